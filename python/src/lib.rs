@@ -0,0 +1,9 @@
+//! The `raster_utils` Python extension module, see
+//! `raster_utils::python`.
+
+use pyo3::prelude::*;
+
+#[pymodule]
+fn raster_utils(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    ::raster_utils::python::register(m)
+}
@@ -10,12 +10,215 @@
 //!
 //! - Extend the above functionality efficiently to work
 //! with chunks of `A`.
+//!
+//! - Estimate the sub-pixel shift between two rasters
+//! whose nominal transforms are slightly off
+//! ([`estimate_shift`]).
+//!
+//! Rasters without a geo. transform (eg. georeferenced by
+//! RPCs or geolocation arrays) are supported through the
+//! [`PixelMapper`] trait.
 
-use super::geometry::{as_f64, as_usize, Offset, PixelPixelTransform, Size};
+#[cfg(feature = "gdal")]
+use super::gdal::utils::pixel_world_transform;
+use super::geometry::{
+    as_f64, signed_as_f64, Offset, PixelPixelTransform, PixelWorldTransform, RasterWindow,
+    SignedOffset, Size,
+};
+use super::reader::ChunkReader;
+use super::{RasterUtilsError, Result};
+#[cfg(feature = "gdal")]
+use gdal::Dataset;
 use geo::{AffineTransform, Coord};
+use ndarray::{s, Array2, ArrayView2};
 
 type ChunkTransform = PixelPixelTransform;
 
+/// Tolerance (in pixels) used when comparing grids.
+const GRID_TOLERANCE: f64 = 1e-6;
+
+/// Reasons two rasters can't be processed together on the
+/// same grid.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum AlignmentError {
+    #[error("CRS mismatch: {0:?} vs {1:?}")]
+    CrsMismatch(String, String),
+    #[error("Resolution mismatch: {0:?} vs {1:?}")]
+    ResolutionMismatch((f64, f64), (f64, f64)),
+    #[error("Rotation or skew mismatch: {0:?} vs {1:?}")]
+    RotationMismatch((f64, f64), (f64, f64)),
+    #[error("Grids are offset by a fraction of a pixel: ({0}, {1})")]
+    SubPixelOffset(f64, f64),
+    #[error("Grids are offset by ({0}, {1}) pixels")]
+    PixelOffset(f64, f64),
+    #[error("Rasters do not overlap")]
+    NoOverlap,
+    #[error("No reliable correlation found between the rasters")]
+    NoCorrelation,
+    #[error("Geolocation arrays differ in shape: {0:?} vs {1:?}")]
+    ShapeMismatch((usize, usize), (usize, usize)),
+}
+
+/// Check that two rasters with given transforms and sizes
+/// lie on the same pixel grid and overlap.
+///
+/// Grids offset by a whole number of pixels are
+/// compatible.
+pub fn check_grid_compatibility(
+    (transform_1, size_1): (&PixelWorldTransform, Size),
+    (transform_2, size_2): (&PixelWorldTransform, Size),
+) -> std::result::Result<(), AlignmentError> {
+    let (t_1, t_2) = (transform_1.affine(), transform_2.affine());
+    let close = |a: f64, b: f64| (a - b).abs() <= GRID_TOLERANCE * a.abs().max(b.abs());
+
+    if !close(t_1.a(), t_2.a()) || !close(t_1.e(), t_2.e()) {
+        return Err(AlignmentError::ResolutionMismatch(
+            (t_1.a(), t_1.e()),
+            (t_2.a(), t_2.e()),
+        ));
+    }
+    if !close(t_1.b(), t_2.b()) || !close(t_1.d(), t_2.d()) {
+        return Err(AlignmentError::RotationMismatch(
+            (t_1.b(), t_1.d()),
+            (t_2.b(), t_2.d()),
+        ));
+    }
+
+    // Origin of the second grid in pixels of the first.
+    let (x, y) = transform_1
+        .world_to_pixel((t_2.xoff(), t_2.yoff()))
+        .map_err(|_| AlignmentError::ResolutionMismatch((t_1.a(), t_1.e()), (t_2.a(), t_2.e())))?;
+    let (dx, dy) = (x - x.round(), y - y.round());
+    if dx.abs() > GRID_TOLERANCE || dy.abs() > GRID_TOLERANCE {
+        return Err(AlignmentError::SubPixelOffset(dx, dy));
+    }
+
+    let extent_1 = RasterWindow::from(((0, 0), size_1)).to_world(t_1);
+    let extent_2 = RasterWindow::from(((0, 0), size_2)).to_world(t_2);
+    let overlaps = extent_1.min().x < extent_2.max().x
+        && extent_2.min().x < extent_1.max().x
+        && extent_1.min().y < extent_2.max().y
+        && extent_2.min().y < extent_1.max().y;
+    if !overlaps {
+        return Err(AlignmentError::NoOverlap);
+    }
+    Ok(())
+}
+
+/// Check that two datasets share CRS and pixel grid, and
+/// overlap. See [`check_grid_compatibility`].
+#[cfg(feature = "gdal")]
+pub fn check_compatibility(dataset_1: &Dataset, dataset_2: &Dataset) -> Result<()> {
+    let crs_1 = dataset_1.spatial_ref().ok();
+    let crs_2 = dataset_2.spatial_ref().ok();
+    if crs_1 != crs_2 {
+        return Err(
+            AlignmentError::CrsMismatch(dataset_1.projection(), dataset_2.projection()).into(),
+        );
+    }
+
+    let transform_1 = pixel_world_transform(dataset_1)?;
+    let transform_2 = pixel_world_transform(dataset_2)?;
+    check_grid_compatibility(
+        (&transform_1, dataset_1.raster_size()),
+        (&transform_2, dataset_2.raster_size()),
+    )?;
+    Ok(())
+}
+
+/// How the pixel grid of a raster relates to the grid of
+/// another one, to pick the cheapest code path able to
+/// process them together. See [`grid_relation`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridRelation {
+    /// Same grid: pixel (x, y) is the same in both rasters.
+    Same,
+    /// Same resolution, with the origin of the second raster
+    /// at pixel (dx, dy) of the first.
+    ShiftedByIntegerPixels(isize, isize),
+    /// Each pixel of the `coarser` raster covers exactly
+    /// `factor` x `factor` pixels of the finer one (`factor` >
+    /// 1), with the origin of the coarser raster at pixel
+    /// `offset` (dx, dy) of the finer one.
+    ResolutionMultiple {
+        factor: usize,
+        coarser: GridOperand,
+        offset: (isize, isize),
+    },
+    /// None of the above: pixels must be resampled.
+    Unrelated,
+}
+
+/// One of the two rasters compared by [`grid_relation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridOperand {
+    First,
+    Second,
+}
+
+/// Relation between the grids of two rasters with given
+/// transforms.
+///
+/// `tolerance` (in pixels of the finer raster) applies to
+/// the entries of the pixel to pixel transform between
+/// them.
+pub fn grid_relation(
+    transform_1: &PixelWorldTransform,
+    transform_2: &PixelWorldTransform,
+    tolerance: f64,
+) -> GridRelation {
+    // Pixels of the coarser raster to pixels of the finer
+    // one.
+    let Ok(mut t) = transform_2.transform_to(transform_1) else {
+        return GridRelation::Unrelated;
+    };
+    let mut coarser = GridOperand::Second;
+    if t.a().abs() < 1. {
+        t = match PixelWorldTransform::new(t).inverse() {
+            Ok(inverse) => inverse,
+            Err(_) => return GridRelation::Unrelated,
+        };
+        coarser = GridOperand::First;
+    }
+    let near = |value: f64, target: f64| (value - target).abs() <= tolerance;
+    let n = t.a().round();
+    if n < 1. || !near(t.a(), n) || !near(t.e(), n) || !near(t.b(), 0.) || !near(t.d(), 0.) {
+        return GridRelation::Unrelated;
+    }
+    let (dx, dy) = (t.xoff().round(), t.yoff().round());
+    if !near(t.xoff(), dx) || !near(t.yoff(), dy) {
+        return GridRelation::Unrelated;
+    }
+    match n as usize {
+        1 if dx == 0. && dy == 0. => GridRelation::Same,
+        1 => GridRelation::ShiftedByIntegerPixels(dx as isize, dy as isize),
+        factor => GridRelation::ResolutionMultiple {
+            factor,
+            coarser,
+            offset: (dx as isize, dy as isize),
+        },
+    }
+}
+
+/// Relation between the grids of two datasets, or
+/// [`GridRelation::Unrelated`] if their CRSs differ. See
+/// [`grid_relation`].
+#[cfg(feature = "gdal")]
+pub fn grids_match(
+    dataset_1: &Dataset,
+    dataset_2: &Dataset,
+    tolerance: f64,
+) -> Result<GridRelation> {
+    if dataset_1.spatial_ref().ok() != dataset_2.spatial_ref().ok() {
+        return Ok(GridRelation::Unrelated);
+    }
+    Ok(grid_relation(
+        &pixel_world_transform(dataset_1)?,
+        &pixel_world_transform(dataset_2)?,
+        tolerance,
+    ))
+}
+
 /// Calculate residue of an transform for a pair of offsets.
 /// This is used to succinctly convert from array
 /// coordinates of a chunk of one raster, to the array
@@ -37,6 +240,10 @@ type ChunkTransform = PixelPixelTransform;
 /// extents of this is typically calculated using
 /// [`transform_window`][crate::prelude::transform_window].
 ///
+/// Both offsets may be negative, eg. for the windows of
+/// [`transform_chunk_extent`] extending outside the target
+/// raster.
+///
 /// Returns a `PixelTransform` that transforms an array
 /// index of the source chunk into array index of the target
 /// chunk. Both indices are floating-point tuples,
@@ -52,77 +259,737 @@ type ChunkTransform = PixelPixelTransform;
 ///
 /// `off_2 + (J, I) = transform(off_1 + (j, i))`
 ///
-/// `(J, I) = transform(off_1) - off_2 + transform(j, i)`
+/// As `transform` is affine, it splits into its linear part
+/// `L` and translation, and thus:
+///
+/// `(J, I) = transform(off_1) - off_2 + L(j, i)`
+///
+/// This holds for general affine transforms, including
+/// rotated or skewed ones.
 pub fn chunk_transform(
     transform: &PixelPixelTransform,
-    off_1: Offset,
-    off_2: Offset,
+    off_1: SignedOffset,
+    off_2: SignedOffset,
 ) -> ChunkTransform {
-    // zero out xoff and yoff and apply transform to off_1.
-    // subtract off_2 from resulting coord.
-    let residue = AffineTransform::translate(-1., -1.)
-        .compose(transform)
-        .apply(Coord::from(as_f64(off_1)))
-        - Coord::from(as_f64(off_2));
-    // summ resedue to xoff and yoff.
-    AffineTransform::translate(residue.x, residue.y).compose(transform)
-}
-
-/// Converts a [`chunk_transform`] into a function that maps
-/// input (integer) indices to indices on the output raster
-/// if it falls within the given dimension (`dim`), and
-/// otherwise `None`.
-pub fn index_transformer(chunk_t: ChunkTransform, dim: Size) -> impl Fn(Size) -> Option<Size> {
+    let residue =
+        transform.apply(Coord::from(signed_as_f64(off_1))) - Coord::from(signed_as_f64(off_2));
+    // keep the linear part, and use the residue as the
+    // translation.
+    AffineTransform::new(
+        transform.a(),
+        transform.b(),
+        residue.x,
+        transform.d(),
+        transform.e(),
+        residue.y,
+    )
+}
+
+/// Inverse of [`chunk_transform`]: maps array indices of
+/// the target chunk (at `off_2`) back to array indices of
+/// the source chunk (at `off_1`), given the same (forward)
+/// `transform` from source to target pixels.
+///
+/// Fails with [`RasterUtilsError::DegenerateTransform`] if
+/// `transform` is not invertible.
+pub fn chunk_transform_inverse(
+    transform: &PixelPixelTransform,
+    off_1: SignedOffset,
+    off_2: SignedOffset,
+) -> Result<ChunkTransform> {
+    let inverse = PixelWorldTransform::new(*transform).inverse()?;
+    Ok(chunk_transform(&inverse, off_2, off_1))
+}
+
+/// Smallest integer window of the target raster enclosing
+/// the image of `window` (eg. a [`Chunk`]) under the pixel
+/// to pixel `transform`.
+///
+/// All four corners are mapped, so this also holds for
+/// rotated transforms. The window may extend outside the
+/// target raster. Use [`PixelWorldTransform::transform_to`]
+/// in the reverse direction to find the pixels of the
+/// source raster mapping into a chunk of the target.
+///
+/// [`Chunk`]: crate::chunking::Chunk
+pub fn transform_chunk_extent<W: Into<RasterWindow>>(
+    window: W,
+    transform: &PixelPixelTransform,
+) -> RasterWindow {
+    let bounds = window.into().to_world(transform);
+    let (min, max) = (bounds.min(), bounds.max());
+    // Ignore rounding noise on corners falling on pixel
+    // boundaries.
+    let (min_x, min_y) = (
+        (min.x + GRID_TOLERANCE).floor(),
+        (min.y + GRID_TOLERANCE).floor(),
+    );
+    let (max_x, max_y) = (
+        (max.x - GRID_TOLERANCE).ceil(),
+        (max.y - GRID_TOLERANCE).ceil(),
+    );
+    RasterWindow::from_signed(
+        (min_x as isize, min_y as isize),
+        ((max_x - min_x) as usize, (max_y - min_y) as usize),
+    )
+}
+
+/// Maps pixel coordinates (x, y) of a source raster to
+/// (fractional) pixel coordinates of a target raster.
+///
+/// Implemented by affine transforms, and by mappers for
+/// rasters georeferenced without a geo. transform.
+pub trait PixelMapper {
+    /// Map `pixel`, or `None` if it has no counterpart.
+    fn map_pixel(&self, pixel: (f64, f64)) -> Option<(f64, f64)>;
+}
+
+impl PixelMapper for AffineTransform {
+    fn map_pixel(&self, pixel: (f64, f64)) -> Option<(f64, f64)> {
+        Some(self.apply(Coord::from(pixel)).x_y())
+    }
+}
+
+impl<M: PixelMapper + ?Sized> PixelMapper for &M {
+    fn map_pixel(&self, pixel: (f64, f64)) -> Option<(f64, f64)> {
+        (**self).map_pixel(pixel)
+    }
+}
+
+/// Generalization of [`chunk_transform`] to any
+/// [`PixelMapper`]: maps array indices of a source chunk at
+/// `off_1` to array indices of a target chunk at `off_2`.
+pub struct ChunkMapper<M> {
+    mapper: M,
+    off_1: (f64, f64),
+    off_2: (f64, f64),
+}
+
+impl<M: PixelMapper> ChunkMapper<M> {
+    pub fn new(mapper: M, off_1: SignedOffset, off_2: SignedOffset) -> Self {
+        ChunkMapper {
+            mapper,
+            off_1: signed_as_f64(off_1),
+            off_2: signed_as_f64(off_2),
+        }
+    }
+}
+
+impl<M: PixelMapper> PixelMapper for ChunkMapper<M> {
+    fn map_pixel(&self, pixel: (f64, f64)) -> Option<(f64, f64)> {
+        let (x, y) = self
+            .mapper
+            .map_pixel((self.off_1.0 + pixel.0, self.off_1.1 + pixel.1))?;
+        Some((x - self.off_2.0, y - self.off_2.1))
+    }
+}
+
+/// Maps pixels of a raster georeferenced by geolocation
+/// arrays onto the pixels of a raster with an affine
+/// transform.
+///
+/// The geolocation arrays hold the world coordinates of
+/// the source raster sampled at pixels `offset + (j, i) *
+/// step` for array index `(i, j)`. Pixels in between samples
+/// are interpolated bilinearly.
+pub struct GeolocationMapper {
+    x: Array2<f64>,
+    y: Array2<f64>,
+    offset: (f64, f64),
+    step: (f64, f64),
+    /// World to target pixel transform.
+    target: AffineTransform,
+}
+
+impl GeolocationMapper {
+    /// Create a mapper from geolocation arrays `x` and `y`
+    /// and the world to pixel transform of the target raster.
+    ///
+    /// Errors if `x` and `y` differ in shape.
+    pub fn new(
+        x: Array2<f64>,
+        y: Array2<f64>,
+        target: AffineTransform,
+    ) -> std::result::Result<Self, AlignmentError> {
+        if x.dim() != y.dim() {
+            return Err(AlignmentError::ShapeMismatch(x.dim(), y.dim()));
+        }
+        Ok(GeolocationMapper {
+            x,
+            y,
+            offset: (0., 0.),
+            step: (1., 1.),
+            target,
+        })
+    }
+
+    /// Set the source pixel (x, y) of the first sample, and
+    /// the distance (x, y) in pixels between samples.
+    pub fn with_sampling(mut self, offset: (f64, f64), step: (f64, f64)) -> Self {
+        self.offset = offset;
+        self.step = step;
+        self
+    }
+
+    /// World coordinates of a source pixel.
+    pub fn world(&self, pixel: (f64, f64)) -> Option<(f64, f64)> {
+        let col = (pixel.0 - self.offset.0) / self.step.0;
+        let row = (pixel.1 - self.offset.1) / self.step.1;
+        Some((bilinear(&self.x, row, col)?, bilinear(&self.y, row, col)?))
+    }
+}
+
+impl PixelMapper for GeolocationMapper {
+    fn map_pixel(&self, pixel: (f64, f64)) -> Option<(f64, f64)> {
+        let world = self.world(pixel)?;
+        Some(self.target.apply(Coord::from(world)).x_y())
+    }
+}
+
+/// Interpolate `array` at fractional index (`row`, `col`),
+/// or `None` outside the array or on non-finite samples.
+pub(crate) fn bilinear(array: &Array2<f64>, row: f64, col: f64) -> Option<f64> {
+    let (rows, cols) = array.dim();
+    if rows == 0 || cols == 0 {
+        return None;
+    }
+    if !(row >= 0. && col >= 0. && row <= (rows - 1) as f64 && col <= (cols - 1) as f64) {
+        return None;
+    }
+    let (i, j) = (
+        (row.floor() as usize).min(rows.saturating_sub(2)),
+        (col.floor() as usize).min(cols.saturating_sub(2)),
+    );
+    let (di, dj) = (row - i as f64, col - j as f64);
+    let at = |i: usize, j: usize| array[[i.min(rows - 1), j.min(cols - 1)]];
+    // Samples with zero weight are skipped, so that they may
+    // be non-finite (eg. outside the raster).
+    let lerp = |a: f64, b: f64, t: f64| if t == 0. { a } else { a * (1. - t) + b * t };
+
+    let top = lerp(at(i, j), at(i, j + 1), dj);
+    let bottom = if di == 0. {
+        top
+    } else {
+        lerp(at(i + 1, j), at(i + 1, j + 1), dj)
+    };
+    let value = lerp(top, bottom, di);
+    value.is_finite().then_some(value)
+}
+
+/// What [`index_transformer_with`] returns for indices
+/// mapped outside the output raster.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutOfBounds {
+    /// `None`.
+    #[default]
+    None,
+    /// The nearest index within the raster.
+    Clamp,
+    /// The index modulo the raster dimension (eg. for global
+    /// rasters wrapping around in longitude).
+    Wrap,
+}
+
+impl OutOfBounds {
+    /// Index of the pixel containing coordinate `value`,
+    /// along an axis of length `len`.
+    fn index(self, value: f64, len: usize) -> Option<usize> {
+        if !value.is_finite() || len == 0 {
+            return None;
+        }
+        let value = value.floor();
+        match self {
+            OutOfBounds::None => (value >= 0. && value < len as f64).then_some(value as usize),
+            OutOfBounds::Clamp => Some(value.clamp(0., (len - 1) as f64) as usize),
+            OutOfBounds::Wrap => Some(value.rem_euclid(len as f64) as usize),
+        }
+    }
+}
+
+/// Converts a [`chunk_transform`] (or any [`PixelMapper`],
+/// eg. a [`ChunkMapper`]) into a function that maps input
+/// (integer) indices to indices on the output raster if it
+/// falls within the given dimension (`dim`), and otherwise
+/// `None`.
+pub fn index_transformer<M: PixelMapper>(chunk_t: M, dim: Size) -> impl Fn(Size) -> Option<Size> {
+    index_transformer_with(chunk_t, dim, OutOfBounds::None)
+}
+
+/// Like [`index_transformer`], with the behaviour for
+/// indices outside the output raster given by
+/// `out_of_bounds`.
+///
+/// Indices that can't be mapped (eg. outside geolocation
+/// arrays) are `None` regardless.
+pub fn index_transformer_with<M: PixelMapper>(
+    chunk_t: M,
+    dim: Size,
+    out_of_bounds: OutOfBounds,
+) -> impl Fn(Size) -> Option<Size> {
     let (cols, rows) = dim;
 
     move |indexes| {
         // Transform indices
-        let pt = chunk_t.apply(Coord::from(as_f64(indexes)));
-        if pt.x < 0. || pt.y < 0. {
-            return None;
+        let (x, y) = chunk_t.map_pixel(as_f64(indexes))?;
+        let j_2 = out_of_bounds.index(x, cols)?;
+        let i_2 = out_of_bounds.index(y, rows)?;
+        Some((i_2, j_2))
+    }
+}
+
+/// Options of [`estimate_shift`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShiftOptions {
+    /// Size (in pixels) of the square windows sampled.
+    pub window: usize,
+    /// Largest shift (in pixels) searched along each axis.
+    pub max_shift: usize,
+    /// Number of windows sampled along each axis.
+    pub samples: usize,
+    /// Minimum normalized cross-correlation of a window for
+    /// its shift to be used.
+    pub min_correlation: f64,
+}
+
+impl Default for ShiftOptions {
+    fn default() -> Self {
+        ShiftOptions {
+            window: 64,
+            max_shift: 4,
+            samples: 4,
+            min_correlation: 0.5,
         }
+    }
+}
 
-        let (j_2, i_2) = as_usize(pt.x_y());
-        if j_2 >= cols || i_2 >= rows {
-            None
-        } else {
-            Some((i_2, j_2))
+/// Outcome of [`estimate_shift`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShiftEstimate {
+    /// Correction (x, y), in pixels of the target raster,
+    /// added to the nominal transform.
+    pub shift: (f64, f64),
+    /// Median correlation of the windows used.
+    pub correlation: f64,
+    /// Number of windows used.
+    pub samples: usize,
+}
+
+/// Normalized cross-correlation of `a` and `b`, ignoring
+/// pairs with non-finite values.
+fn ncc(a: ArrayView2<f64>, b: ArrayView2<f64>) -> Option<f64> {
+    let pairs = || {
+        a.iter()
+            .zip(b.iter())
+            .filter(|(a, b)| a.is_finite() && b.is_finite())
+    };
+    let n = pairs().count();
+    if n < 2 {
+        return None;
+    }
+    let (sum_a, sum_b) = pairs().fold((0., 0.), |(sa, sb), (a, b)| (sa + a, sb + b));
+    let (mean_a, mean_b) = (sum_a / n as f64, sum_b / n as f64);
+    let (mut cov, mut var_a, mut var_b) = (0., 0., 0.);
+    for (a, b) in pairs() {
+        let (da, db) = (a - mean_a, b - mean_b);
+        cov += da * db;
+        var_a += da * da;
+        var_b += db * db;
+    }
+    let denominator = (var_a * var_b).sqrt();
+    (denominator > 0.).then(|| cov / denominator)
+}
+
+/// Offset of the peak of a parabola through `(-1, before)`,
+/// `(0, peak)` and `(1, after)`.
+fn parabolic_peak(before: f64, peak: f64, after: f64) -> f64 {
+    let curvature = before - 2. * peak + after;
+    if curvature < 0. {
+        ((before - after) / (2. * curvature)).clamp(-0.5, 0.5)
+    } else {
+        0.
+    }
+}
+
+/// Median of `values` (which must not be empty).
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.
+    } else {
+        values[mid]
+    }
+}
+
+/// Estimate the sub-pixel translation between raster `a`
+/// and raster `b`, given the nominal pixel to pixel
+/// `transform` from `a` to `b`, and return the corrected
+/// transform (to feed into [`chunk_transform`]) along with
+/// the estimate.
+///
+/// Windows of `a` are sampled on a regular grid and matched
+/// against `b` around their nominal location by normalized
+/// cross-correlation over integer shifts, refined to
+/// sub-pixel precision by a parabolic fit of the peak. The
+/// shift is the median over the windows with a clear peak.
+/// Both rasters are expected to have (about) the same
+/// resolution.
+pub fn estimate_shift<A, B>(
+    a: &A,
+    b: &B,
+    transform: &PixelPixelTransform,
+    options: ShiftOptions,
+) -> Result<(PixelPixelTransform, ShiftEstimate)>
+where
+    A: ChunkReader,
+    B: ChunkReader,
+    A::Error: Into<RasterUtilsError>,
+    B::Error: Into<RasterUtilsError>,
+{
+    let (cols, rows) = a.raster_size().map_err(Into::into)?;
+    let window = options.window.min(cols).min(rows);
+    let max_shift = options.max_shift as isize;
+    let search = window + 2 * options.max_shift;
+    let samples = options.samples.max(1);
+    let position = |extent: usize, k: usize| (extent - window) * (2 * k + 1) / (2 * samples);
+
+    let (mut shifts_x, mut shifts_y, mut correlations) = (vec![], vec![], vec![]);
+    for k_y in 0..samples {
+        for k_x in 0..samples {
+            let origin: Offset = (position(cols, k_x), position(rows, k_y));
+            let values_a = a
+                .read_as_array::<f64>((origin, (window, window)).into())
+                .map_err(Into::into)?;
+
+            let nominal = transform.apply(Coord::from(as_f64(origin)));
+            let (base_x, base_y) = (nominal.x.round() as isize, nominal.y.round() as isize);
+            let search_window = RasterWindow::from_signed(
+                (base_x - max_shift, base_y - max_shift),
+                (search, search),
+            );
+            let values_b = b
+                .read_chunk_or_fill(search_window, f64::NAN)
+                .map_err(Into::into)?;
+
+            let side = 2 * options.max_shift + 1;
+            let mut scores = Array2::from_elem((side, side), f64::NAN);
+            for ((i, j), score) in scores.indexed_iter_mut() {
+                let candidate = values_b.slice(s![i..i + window, j..j + window]);
+                *score = ncc(values_a.view(), candidate).unwrap_or(f64::NAN);
+            }
+            let Some(((i, j), &best)) = scores
+                .indexed_iter()
+                .filter(|(_, score)| score.is_finite())
+                .max_by(|(_, s1), (_, s2)| s1.total_cmp(s2))
+            else {
+                continue;
+            };
+            // Peaks on the border of the search area may lie
+            // beyond it.
+            if best < options.min_correlation || i == 0 || j == 0 || i == side - 1 || j == side - 1
+            {
+                continue;
+            }
+            let refine = |before: f64, after: f64| {
+                if before.is_finite() && after.is_finite() {
+                    parabolic_peak(before, best, after)
+                } else {
+                    0.
+                }
+            };
+            let sub_x = refine(scores[[i, j - 1]], scores[[i, j + 1]]);
+            let sub_y = refine(scores[[i - 1, j]], scores[[i + 1, j]]);
+            shifts_x.push((base_x - max_shift + j as isize) as f64 + sub_x - nominal.x);
+            shifts_y.push((base_y - max_shift + i as isize) as f64 + sub_y - nominal.y);
+            correlations.push(best);
         }
     }
+
+    if correlations.is_empty() {
+        return Err(AlignmentError::NoCorrelation.into());
+    }
+    let estimate = ShiftEstimate {
+        shift: (median(&mut shifts_x), median(&mut shifts_y)),
+        correlation: median(&mut correlations),
+        samples: correlations.len(),
+    };
+    let corrected = AffineTransform::new(
+        transform.a(),
+        transform.b(),
+        transform.xoff() + estimate.shift.0,
+        transform.d(),
+        transform.e(),
+        transform.yoff() + estimate.shift.1,
+    );
+    Ok((corrected, estimate))
 }
 
-/* #[cfg(test)]
+#[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::Path;
-    use gdal::Dataset;
+    use crate::geometry::PixelWorldTransform;
 
-    fn print_mat3x3(t: &AffineTransform) {
-        eprint!("{:?}", t)
+    fn assert_close(actual: Coord, expected: Coord) {
+        assert!(
+            (actual.x - expected.x).abs() < 1e-9 && (actual.y - expected.y).abs() < 1e-9,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    /// Check that `chunk_transform` agrees with applying the
+    /// full transform to the offset indices.
+    fn check_chunk_transform(
+        transform: &PixelPixelTransform,
+        off_1: SignedOffset,
+        off_2: SignedOffset,
+    ) {
+        let chunk_t = chunk_transform(transform, off_1, off_2);
+        for (j, i) in [(0., 0.), (3., 0.), (0., 5.), (7.5, 2.25)] {
+            let expected = transform.apply(Coord::from((off_1.0 as f64 + j, off_1.1 as f64 + i)))
+                - Coord::from(signed_as_f64(off_2));
+            assert_close(chunk_t.apply(Coord::from((j, i))), expected);
+        }
+    }
+
+    #[test]
+    fn test_estimate_shift() {
+        use crate::testing::ArrayReader;
+
+        // Smooth, non-periodic pattern.
+        let pattern = |x: f64, y: f64| (x * 0.31).sin() * (y * 0.17).cos() + (x * y * 0.003).sin();
+        let a = Array2::from_shape_fn((120, 120), |(i, j)| pattern(j as f64, i as f64));
+        // b is a shifted by (2.3, -1.6) pixels.
+        let b = Array2::from_shape_fn((120, 120), |(i, j)| pattern(j as f64 - 2.3, i as f64 + 1.6));
+
+        let options = ShiftOptions {
+            window: 24,
+            samples: 3,
+            ..Default::default()
+        };
+        let (corrected, estimate) = estimate_shift(
+            &ArrayReader(a),
+            &ArrayReader(b),
+            &AffineTransform::identity(),
+            options,
+        )
+        .unwrap();
+        assert!((estimate.shift.0 - 2.3).abs() < 0.2, "{:?}", estimate);
+        assert!((estimate.shift.1 + 1.6).abs() < 0.2, "{:?}", estimate);
+        assert_eq!(corrected.xoff(), estimate.shift.0);
+    }
+
+    #[test]
+    fn test_chunk_transform_axis_aligned() {
+        let a: PixelWorldTransform = AffineTransform::new(10., 0., 1000., 0., -10., 5000.).into();
+        let b: PixelWorldTransform = AffineTransform::new(20., 0., 900., 0., -20., 5100.).into();
+        assert!(a.is_north_up() && b.is_north_up());
+        check_chunk_transform(&a.transform_to(&b).unwrap(), (0, 13), (2, 5));
+    }
+
+    #[test]
+    fn test_chunk_transform_rotated() {
+        let a: PixelWorldTransform = AffineTransform::new(10., 0., 1000., 0., -10., 5000.).into();
+        let b: PixelWorldTransform = AffineTransform::rotate(30., Coord::from((0., 0.)))
+            .compose(&AffineTransform::new(8., 1., 950., 2., -8., 5050.))
+            .into();
+        assert!(!b.is_north_up());
+        check_chunk_transform(&a.transform_to(&b).unwrap(), (4, 17), (1, 3));
+        check_chunk_transform(&b.transform_to(&a).unwrap(), (0, 0), (6, 9));
+    }
+
+    #[test]
+    fn test_chunk_transform_round_trip() {
+        let transform = AffineTransform::rotate(20., Coord::from((0., 0.)))
+            .compose(&AffineTransform::new(2., 0.5, -3., 0.25, 1.5, 7.));
+        let (off_1, off_2) = ((5, 11), (2, 4));
+        let forward = chunk_transform(&transform, off_1, off_2);
+        let inverse = chunk_transform_inverse(&transform, off_1, off_2).unwrap();
+        for pixel in [(0., 0.), (4., 1.), (2.5, 9.)] {
+            let pixel = Coord::from(pixel);
+            assert_close(inverse.apply(forward.apply(pixel)), pixel);
+        }
+        assert!(
+            chunk_transform_inverse(&AffineTransform::scale(0., 1., (0., 0.)), off_1, off_2)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_transform_chunk_extent() {
+        // Half resolution, shifted by a pixel.
+        let transform = AffineTransform::new(0.5, 0., 1., 0., 0.5, -1.);
+        let window: RasterWindow = ((2, 4), (6, 5)).into();
+        let extent = transform_chunk_extent(window.clone(), &transform);
+        let expected: RasterWindow = ((2, 1), (3, 3)).into();
+        assert_eq!(extent, expected);
+
+        // Mapping back encloses the original window.
+        let inverse = PixelWorldTransform::new(transform).inverse().unwrap();
+        let back = transform_chunk_extent(extent, &inverse);
+        assert!(back.contains(&window));
+
+        let rotated = AffineTransform::rotate(45., Coord::from((0., 0.)));
+        let extent = transform_chunk_extent(((0, 0), (2, 2)), &rotated);
+        assert_eq!(extent.size(), (4, 3));
+    }
+
+    #[test]
+    fn test_index_transformer() {
+        // Target raster is shifted by (2, 3) pixels.
+        let transform = AffineTransform::translate(-2., -3.);
+        let index_t = index_transformer(chunk_transform(&transform, (0, 10), (0, 5)), (4, 4));
+        assert_eq!(index_t((2, 0)), Some((2, 0)));
+        assert_eq!(index_t((5, 1)), Some((3, 3)));
+        assert_eq!(index_t((1, 0)), None);
+        assert_eq!(index_t((6, 0)), None);
+
+        let clamp = index_transformer_with(
+            chunk_transform(&transform, (0, 10), (0, 5)),
+            (4, 4),
+            OutOfBounds::Clamp,
+        );
+        assert_eq!(clamp((1, 0)), Some((2, 0)));
+        assert_eq!(clamp((6, 4)), Some((3, 3)));
+
+        let wrap = index_transformer_with(
+            chunk_transform(&transform, (0, 10), (0, 5)),
+            (4, 4),
+            OutOfBounds::Wrap,
+        );
+        assert_eq!(wrap((1, 0)), Some((2, 3)));
+        assert_eq!(wrap((6, 0)), Some((2, 0)));
+    }
+
+    #[test]
+    fn test_chunk_mapper_matches_chunk_transform() {
+        let transform = AffineTransform::rotate(15., Coord::from((0., 0.)))
+            .compose(&AffineTransform::new(2., 0., -3., 0., 2., 7.));
+        let chunk_t = chunk_transform(&transform, (5, 11), (2, 4));
+        let chunk_m = ChunkMapper::new(&transform, (5, 11), (2, 4));
+        for pixel in [(0., 0.), (4., 1.), (2.5, 9.)] {
+            let (x, y) = chunk_m.map_pixel(pixel).unwrap();
+            assert_close(Coord::from((x, y)), chunk_t.apply(Coord::from(pixel)));
+        }
+    }
+
+    #[test]
+    fn test_geolocation_mapper() {
+        // Geolocation arrays sampled every 2 pixels from a
+        // known transform must reproduce it.
+        let world = AffineTransform::new(10., 1., 1000., -1., -10., 5000.);
+        let (x, y): (Vec<_>, Vec<_>) = (0..4)
+            .flat_map(|i| (0..5).map(move |j| (i, j)))
+            .map(|(i, j)| {
+                world
+                    .apply(Coord::from(((2 * j) as f64, (2 * i) as f64)))
+                    .x_y()
+            })
+            .unzip();
+        let x = Array2::from_shape_vec((4, 5), x).unwrap();
+        let y = Array2::from_shape_vec((4, 5), y).unwrap();
+
+        let target = AffineTransform::new(20., 0., 900., 0., -20., 5100.);
+        assert!(matches!(
+            GeolocationMapper::new(x.clone(), Array2::zeros((5, 4)), target),
+            Err(AlignmentError::ShapeMismatch((4, 5), (5, 4)))
+        ));
+        let mapper = GeolocationMapper::new(x, y, target.inverse().unwrap())
+            .unwrap()
+            .with_sampling((0., 0.), (2., 2.));
+        let expected = world.compose(&target.inverse().unwrap());
+        for pixel in [(0., 0.), (3., 5.), (8., 6.)] {
+            let (x, y) = mapper.map_pixel(pixel).unwrap();
+            assert_close(Coord::from((x, y)), expected.apply(Coord::from(pixel)));
+        }
+        assert_eq!(mapper.map_pixel((9., 0.)), None);
+    }
+
+    #[test]
+    fn test_grid_compatibility() {
+        let t = |xoff: f64, yoff: f64, res: f64| -> PixelWorldTransform {
+            AffineTransform::new(res, 0., xoff, 0., -res, yoff).into()
+        };
+        let base = t(1000., 5000., 10.);
+        let check = |other: &PixelWorldTransform, size: Size| {
+            check_grid_compatibility((&base, (100, 100)), (other, size))
+        };
+
+        assert_eq!(check(&t(1030., 4980., 10.), (50, 50)), Ok(()));
+        assert_eq!(
+            check(&t(1000., 5000., 20.), (50, 50)),
+            Err(AlignmentError::ResolutionMismatch((10., -10.), (20., -20.)))
+        );
+        assert!(matches!(
+            check(&t(1005., 5000., 10.), (50, 50)),
+            Err(AlignmentError::SubPixelOffset(dx, dy)) if (dx.abs() - 0.5).abs() < 1e-9 && dy == 0.
+        ));
+        assert_eq!(
+            check(&t(3000., 5000., 10.), (50, 50)),
+            Err(AlignmentError::NoOverlap)
+        );
+    }
+
+    #[test]
+    fn test_grid_relation() {
+        let t = |xoff: f64, yoff: f64, res: f64| -> PixelWorldTransform {
+            AffineTransform::new(res, 0., xoff, 0., -res, yoff).into()
+        };
+        let base = t(1000., 5000., 10.);
+        let relation = |other: &PixelWorldTransform| grid_relation(&base, other, 1e-6);
+
+        assert_eq!(relation(&t(1000., 5000., 10.)), GridRelation::Same);
+        assert_eq!(
+            relation(&t(1030., 4980., 10.)),
+            GridRelation::ShiftedByIntegerPixels(3, 2)
+        );
+        assert_eq!(
+            relation(&t(1040., 4980., 20.)),
+            GridRelation::ResolutionMultiple {
+                factor: 2,
+                coarser: GridOperand::Second,
+                offset: (4, 2),
+            }
+        );
+        assert_eq!(
+            grid_relation(&t(1040., 4980., 20.), &base, 1e-6),
+            GridRelation::ResolutionMultiple {
+                factor: 2,
+                coarser: GridOperand::First,
+                offset: (4, 2),
+            }
+        );
+        assert_eq!(relation(&t(1005., 5000., 20.)), GridRelation::Unrelated);
+        assert_eq!(relation(&t(1000., 5000., 15.)), GridRelation::Unrelated);
+        assert_eq!(
+            grid_relation(&base, &t(1000.05, 5000., 10.), 0.01),
+            GridRelation::Same
+        );
     }
 
     #[test]
     #[ignore]
+    #[cfg(feature = "gdal")]
     fn test_with_input() {
-        use std::env::var;
+        use crate::gdal::utils::pixel_world_transform;
+        use gdal::Dataset;
+        use std::{env::var, path::Path};
+
         let path1 = var("RASTER1").expect("env: RASTER1 not found");
         let path2 = var("RASTER2").expect("env: RASTER2 not found");
         let ds1 = Dataset::open(Path::new(&path1)).unwrap();
         let ds2 = Dataset::open(Path::new(&path2)).unwrap();
 
-        let t1 = transform_from_dataset(&ds1);
-        let t2 = transform_from_dataset(&ds2);
-        eprintln!("ds1 transform: ");
-        print_mat3x3(&t1);
-        eprintln!("ds2 transform: ");
-        print_mat3x3(&t2);
+        let t1 = pixel_world_transform(&ds1).unwrap();
+        let t2 = pixel_world_transform(&ds2).unwrap();
+        eprintln!("ds1 transform: {:?}", t1);
+        eprintln!("ds2 transform: {:?}", t2);
 
-        let tbet = transform_between(&ds1, &ds2).unwrap();
-        eprintln!("transform between: ");
-        print_mat3x3(&tbet);
+        let tbet = t1.transform_to(&t2).unwrap();
+        eprintln!("transform between: {:?}", tbet);
 
-        let tchunk = chunk_transform(&tbet, Vector2::new(0., 0.), Vector2::new(10., 0.));
-        eprintln!("transform chunk: ");
-        print_mat3x3(&tchunk);
+        let tchunk = chunk_transform(&tbet, (0, 0), (10, 0));
+        eprintln!("transform chunk: {:?}", tchunk);
     }
-} */
+}
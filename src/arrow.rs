@@ -0,0 +1,136 @@
+//! Export chunks as Arrow arrays.
+//!
+//! Chunk reads are converted into Arrow [`PrimitiveArray`]s
+//! in row-major order, alongside `x` / `y` coordinate
+//! columns of the pixel centers. [`record_batches`] yields
+//! one [`RecordBatch`] per chunk, ready to be registered with
+//! Arrow based query engines (DataFusion, polars, ...).
+//!
+//! This module is only available with the "arrow" feature.
+
+use super::chunking::ChunkConfig;
+use super::geometry::RasterWindow;
+use super::reader::{ChunkReader, Pixel};
+use super::{RasterUtilsError, Result};
+use arrow_array::{types::ArrowPrimitiveType, Float64Array, PrimitiveArray, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use geo::{AffineTransform, Coord};
+use ndarray::ArrayView2;
+
+use std::sync::Arc;
+
+/// Convert a chunk into an Arrow array, in row-major order.
+pub fn to_primitive_array<T>(values: ArrayView2<T::Native>) -> PrimitiveArray<T>
+where
+    T: ArrowPrimitiveType,
+{
+    PrimitiveArray::from_iter_values(values.iter().copied())
+}
+
+/// World coordinates (x, y) of the centers of the pixels of
+/// `window`, in row-major order.
+pub fn coordinate_arrays(
+    window: &RasterWindow,
+    transform: &AffineTransform,
+) -> (Float64Array, Float64Array) {
+    let (off_x, off_y) = window.signed_offset();
+    let (rows, cols) = window.shape();
+    let (xs, ys): (Vec<f64>, Vec<f64>) = (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (col, row)))
+        .map(|(col, row)| {
+            let pixel = Coord::from((
+                (off_x + col as isize) as f64 + 0.5,
+                (off_y + row as isize) as f64 + 0.5,
+            ));
+            transform.apply(pixel).x_y()
+        })
+        .unzip();
+    (Float64Array::from(xs), Float64Array::from(ys))
+}
+
+/// Schema of the batches: `x`, `y` and a `name` column of
+/// type `T`.
+pub fn schema<T: ArrowPrimitiveType>(name: &str) -> Schema {
+    Schema::new(vec![
+        Field::new("x", DataType::Float64, false),
+        Field::new("y", DataType::Float64, false),
+        Field::new(name, T::DATA_TYPE, false),
+    ])
+}
+
+/// Build a [`RecordBatch`] of a chunk read at `window`.
+pub fn chunk_record_batch<T>(
+    values: ArrayView2<T::Native>,
+    window: &RasterWindow,
+    transform: &AffineTransform,
+    name: &str,
+) -> Result<RecordBatch>
+where
+    T: ArrowPrimitiveType,
+{
+    let (x, y) = coordinate_arrays(window, transform);
+    let values = to_primitive_array::<T>(values);
+    Ok(RecordBatch::try_new(
+        Arc::new(schema::<T>(name)),
+        vec![Arc::new(x), Arc::new(y), Arc::new(values)],
+    )?)
+}
+
+/// Iterate over the chunks of `config`, reading each from
+/// `reader` into a [`RecordBatch`] (see
+/// [`chunk_record_batch`]).
+///
+/// Chunks overlap if the config has padding: use a config
+/// without padding to emit each pixel exactly once.
+pub fn record_batches<'a, T, R>(
+    reader: &'a R,
+    config: &'a ChunkConfig,
+    transform: &'a AffineTransform,
+    name: &'a str,
+) -> impl Iterator<Item = Result<RecordBatch>> + 'a
+where
+    T: ArrowPrimitiveType,
+    T::Native: Pixel,
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    config.iter().map(move |chunk| {
+        let window = RasterWindow::from(chunk);
+        let values = reader
+            .read_as_array::<T::Native>(window.clone())
+            .map_err(Into::into)?;
+        chunk_record_batch::<T>(values.view(), &window, transform, name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{types::UInt16Type, Array};
+    use ndarray::Array2;
+
+    #[test]
+    fn test_chunk_record_batch() {
+        let values = Array2::from_shape_vec((2, 3), vec![1u16, 2, 3, 4, 5, 6]).unwrap();
+        let window = RasterWindow::from(((10, 20), (3, 2)));
+        let transform = AffineTransform::new(2., 0., 100., 0., -2., 50.);
+
+        let batch =
+            chunk_record_batch::<UInt16Type>(values.view(), &window, &transform, "b1").unwrap();
+        assert_eq!(batch.num_rows(), 6);
+        assert_eq!(batch.schema().field(2).name(), "b1");
+
+        let x = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        let y = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        // Center of pixel (11, 21).
+        assert_eq!((x.value(4), y.value(4)), (123., 7.));
+    }
+}
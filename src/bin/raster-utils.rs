@@ -0,0 +1,318 @@
+//! Command line access to the chunked operations of the
+//! crate, for one-off jobs.
+//!
+//! Only built with the "cli" feature:
+//!
+//! ```text
+//! cargo install raster-utils --features cli
+//! raster-utils stats dem.tif --band 1
+//! ```
+
+use clap::{Parser, Subcommand, ValueEnum};
+use gdal::{
+    raster::{GdalDataType, RasterCreationOptions},
+    Dataset, DriverManager,
+};
+use raster_utils::{
+    align::check_compatibility,
+    chunking::{builder::ChunkConfigBuilder, ChunkConfig},
+    gdal::{
+        diff::diff,
+        multi::{MultiArrayReader, MultiReader},
+        readers::{BandIndex, ChunkReader, DatasetReader},
+        translate::{copy_raster, CopyOptions},
+        utils::create_like,
+        writers::{ChunkWriter, DatasetWriter},
+    },
+    nodata::NodataPolicy,
+    stats::{band_stats, Histogram},
+    testing::Tolerance,
+};
+
+use std::{
+    convert::TryFrom,
+    error::Error,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(name = "raster-utils", version, about)]
+struct Cli {
+    /// Rows of data per chunk (default: sized to the GDAL
+    /// block cache).
+    #[arg(long, global = true)]
+    data_height: Option<NonZeroUsize>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Statistics (and optionally a histogram) of a band.
+    Stats {
+        path: PathBuf,
+        #[arg(long, default_value_t = 1)]
+        band: usize,
+        /// Number of histogram bins over the range of the
+        /// band.
+        #[arg(long)]
+        bins: Option<NonZeroUsize>,
+        /// Print as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compare a band of two rasters on the same grid.
+    Diff {
+        a: PathBuf,
+        b: PathBuf,
+        #[arg(long, default_value_t = 1)]
+        band: usize,
+        /// Absolute tolerance.
+        #[arg(long, default_value_t = 0.)]
+        abs: f64,
+        /// Relative tolerance.
+        #[arg(long, default_value_t = 0.)]
+        rel: f64,
+    },
+    /// Check that two rasters share CRS and pixel grid.
+    AlignCheck { a: PathBuf, b: PathBuf },
+    /// Combine a band of two rasters pixel by pixel into a
+    /// `Float64` GeoTIFF.
+    ///
+    /// Pixels that are nodata in either input are `NaN`, the
+    /// nodata value of the output. `Int64` and `UInt64` bands
+    /// are refused, as `Float64` can't hold all their values.
+    Calc {
+        a: PathBuf,
+        b: PathBuf,
+        #[arg(long, value_enum)]
+        op: CalcOp,
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long, default_value_t = 1)]
+        band: usize,
+        /// Relative tolerance of the comparison with the
+        /// nodata values of the inputs (default: exact).
+        #[arg(long)]
+        nodata_epsilon: Option<f64>,
+    },
+    /// Copy a raster, with GDAL or chunk by chunk.
+    Copy {
+        src: PathBuf,
+        dst: PathBuf,
+        /// Copy chunk by chunk with
+        /// [`copy_raster`][raster_utils::gdal::translate::copy_raster]
+        /// instead of `GDALCreateCopy`.
+        #[arg(long)]
+        chunked: bool,
+        #[arg(long, default_value = "GTiff")]
+        driver: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CalcOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+}
+
+impl CalcOp {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            CalcOp::Add => a + b,
+            CalcOp::Sub => a - b,
+            CalcOp::Mul => a * b,
+            CalcOp::Div => a / b,
+            CalcOp::Min => a.min(b),
+            CalcOp::Max => a.max(b),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(cli: &Cli) -> CliResult<ExitCode> {
+    match &cli.command {
+        Command::Stats {
+            path,
+            band,
+            bins,
+            json,
+        } => {
+            let dataset = Dataset::open(path)?;
+            let nodata = dataset.rasterband(*band)?.no_data_value();
+            let config = chunk_config(cli, &dataset)?;
+            let reader = DatasetReader(dataset, BandIndex::try_from(*band)?);
+            let stats = band_stats(&reader, &config, nodata, NodataPolicy::Exact)?;
+            let histogram = match bins {
+                Some(bins) if stats.count > 0 => {
+                    let mut histogram = Histogram::new(stats.min, stats.max, bins.get())?;
+                    for chunk in &config {
+                        let data = reader.read_as_array::<f64>(chunk.data_window())?;
+                        histogram.add_array(data.view(), nodata, NodataPolicy::Exact);
+                    }
+                    Some(histogram)
+                }
+                _ => None,
+            };
+            if *json {
+                let value = serde_json::json!({ "stats": stats, "histogram": histogram });
+                println!("{}", serde_json::to_string_pretty(&value)?);
+            } else {
+                println!("count: {}", stats.count);
+                println!("min: {}", stats.min);
+                println!("max: {}", stats.max);
+                println!("mean: {}", stats.mean().unwrap_or(f64::NAN));
+                println!("std_dev: {}", stats.std_dev().unwrap_or(f64::NAN));
+                if let Some(histogram) = histogram {
+                    let edges = histogram.bin_edges();
+                    for (count, edge) in histogram.counts.iter().zip(&edges) {
+                        println!("{}\t{}", edge, count);
+                    }
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Diff {
+            a,
+            b,
+            band,
+            abs,
+            rel,
+        } => {
+            let readers = open_pair(a, b, *band)?;
+            let config = chunk_config(cli, &readers.readers()[0].0)?;
+            let tolerance = Tolerance {
+                abs: *abs,
+                rel: *rel,
+                ..Tolerance::exact()
+            };
+            let report = diff(&readers, &config, tolerance)?;
+            println!("differing: {}", report.differing());
+            println!("max_abs: {}", report.max_abs());
+            println!("mean_abs: {}", report.mean_abs());
+            for chunk in report.differing_chunks() {
+                let (offset, size) = (chunk.window.signed_offset(), chunk.window.size());
+                println!(
+                    "chunk {} at {:?} (size {:?}): {} differing",
+                    chunk.index, offset, size, chunk.differing
+                );
+            }
+            Ok(if report.is_match() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+        Command::AlignCheck { a, b } => {
+            match check_compatibility(&Dataset::open(a)?, &Dataset::open(b)?) {
+                Ok(()) => {
+                    println!("aligned");
+                    Ok(ExitCode::SUCCESS)
+                }
+                Err(e) => {
+                    println!("not aligned: {}", e);
+                    Ok(ExitCode::FAILURE)
+                }
+            }
+        }
+        Command::Calc {
+            a,
+            b,
+            op,
+            output,
+            band,
+            nodata_epsilon,
+        } => {
+            let readers = open_pair(a, b, *band)?;
+            let mut nodata = vec![];
+            for reader in readers.readers() {
+                let source = reader.0.rasterband(reader.1.get())?;
+                if let GdalDataType::Int64 | GdalDataType::UInt64 = source.band_type() {
+                    return Err("calc computes in Float64, which can't hold every value \
+                                of Int64 and UInt64 bands"
+                        .into());
+                }
+                nodata.push(source.no_data_value());
+            }
+            let policy = nodata_epsilon.map_or(NodataPolicy::Exact, NodataPolicy::Epsilon);
+
+            let template = &readers.readers()[0].0;
+            let config = chunk_config(cli, template)?;
+            let dataset = create_like::<f64>(template, "GTiff", output, 1)?;
+            dataset.rasterband(1)?.set_no_data_value(Some(f64::NAN))?;
+            let mut writer = DatasetWriter(dataset, BandIndex::try_from(1)?);
+            for item in readers.iter_chunks::<f64>(&config) {
+                let (chunk, arrays) = item?;
+                let mut result = arrays[0].clone();
+                result.zip_mut_with(&arrays[1], |a, &b| {
+                    *a = if policy.is_nodata(*a, nodata[0]) || policy.is_nodata(b, nodata[1]) {
+                        f64::NAN
+                    } else {
+                        op.apply(*a, b)
+                    }
+                });
+                writer.write_chunk(&result, chunk)?;
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Copy {
+            src,
+            dst,
+            chunked,
+            driver,
+        } => {
+            let source = Dataset::open(src)?;
+            if !chunked {
+                let driver = DriverManager::get_driver_by_name(driver)?;
+                source.create_copy(&driver, dst, &RasterCreationOptions::new())?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            let mut options = CopyOptions::new().with_driver(driver);
+            if let Some(data_height) = cli.data_height {
+                let width =
+                    NonZeroUsize::new(source.raster_size().0).ok_or("raster without columns")?;
+                options = options.with_data_size(data_height.saturating_mul(width));
+            }
+            copy_raster(src, dst, &options)?;
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+/// Chunking of `dataset`, with the data height of the
+/// command line, if any.
+fn chunk_config(cli: &Cli, dataset: &Dataset) -> CliResult<ChunkConfig> {
+    let builder = ChunkConfigBuilder::from_dataset(dataset)?;
+    let builder = match cli.data_height {
+        Some(data_height) => builder.with_data_height(data_height),
+        None => builder.with_auto_data_height(dataset)?,
+    };
+    Ok(builder.try_build()?)
+}
+
+fn open_pair(a: &Path, b: &Path, band: usize) -> CliResult<MultiReader<DatasetReader>> {
+    let band = BandIndex::try_from(band)?;
+    let readers = vec![
+        DatasetReader(Dataset::open(a)?, band),
+        DatasetReader(Dataset::open(b)?, band),
+    ];
+    Ok(MultiReader::from_datasets(readers)?)
+}
@@ -0,0 +1,326 @@
+//! Compressed in-memory cache of chunk buffers.
+//!
+//! Holding many chunks (eg. re-reading overlapping windows,
+//! or the time steps of a stack) quickly exhausts memory.
+//! [`ChunkCache`] keeps buffers LZ4-compressed within a byte
+//! budget, evicting the least recently used ones, and
+//! decompresses them on access. [`CachedReader`] wraps any
+//! [`ChunkReader`] with such a cache, so it can be used
+//! transparently, eg. by a
+//! [`RasterStack`][crate::gdal::stack::RasterStack] (with
+//! the "gdal" feature).
+//!
+//! Buffers are stored as their raw (native endian) bytes.
+//! Rasters with large uniform areas (nodata, masks) compress
+//! well; noisy float data may not compress at all.
+//!
+//! This module is only available with the "cache" feature.
+
+use super::geometry::{GdalOffset, RasterWindow, Size};
+use super::reader::{ChunkReader, Pixel, ResampleAlg};
+use ndarray::{Array2, ArrayView2};
+use num::NumCast;
+
+use std::{
+    any::type_name,
+    collections::HashMap,
+    hash::Hash,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+struct Entry {
+    data: Vec<u8>,
+    type_name: &'static str,
+    shape: (usize, usize),
+    last_used: u64,
+}
+
+/// LZ4-compressed cache of arrays, with a budget in
+/// (compressed) bytes.
+///
+/// Eviction scans the entries, which is cheap next to
+/// (de)compressing a chunk for the number of chunks that
+/// usually fit in memory.
+pub struct ChunkCache<K> {
+    budget: usize,
+    used: usize,
+    tick: u64,
+    entries: HashMap<K, Entry>,
+}
+
+impl<K: Hash + Eq + Clone> ChunkCache<K> {
+    /// Cache holding at most `budget` compressed bytes.
+    pub fn new(budget: usize) -> Self {
+        ChunkCache {
+            budget,
+            used: 0,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Compressed bytes currently held.
+    pub fn used_bytes(&self) -> usize {
+        self.used
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.used -= entry.data.len();
+        }
+    }
+
+    /// Compress and store `array` under `key`, replacing any
+    /// previous entry. Arrays that don't fit in the budget
+    /// even alone are not stored.
+    pub fn insert<T: Pixel>(&mut self, key: K, array: ArrayView2<T>) {
+        let array = array.as_standard_layout();
+        let values = array.as_slice().expect("standard layout is contiguous");
+        self.insert_slice(key, values, array.dim());
+    }
+
+    /// Decompress the array stored under `key`, if any and if
+    /// it was stored with element type `T`.
+    pub fn get<T: Pixel>(&mut self, key: &K) -> Option<Array2<T>> {
+        let shape = self.entries.get(key)?.shape;
+        let mut values = vec![<T as NumCast>::from(0)?; shape.0 * shape.1];
+        if !self.get_into(key, &mut values) {
+            return None;
+        }
+        Array2::from_shape_vec(shape, values).ok()
+    }
+
+    /// The array stored under `key`, or the result of `read`
+    /// which is then stored.
+    pub fn get_or_insert_with<T, E, F>(&mut self, key: K, read: F) -> Result<Array2<T>, E>
+    where
+        T: Pixel,
+        F: FnOnce() -> Result<Array2<T>, E>,
+    {
+        if let Some(array) = self.get(&key) {
+            return Ok(array);
+        }
+        let array = read()?;
+        self.insert(key, array.view());
+        Ok(array)
+    }
+
+    fn insert_slice<T: Pixel>(&mut self, key: K, values: &[T], shape: (usize, usize)) {
+        self.remove(&key);
+        let data = lz4_flex::block::compress(as_bytes(values));
+        if data.len() > self.budget {
+            return;
+        }
+        while self.used + data.len() > self.budget {
+            self.evict();
+        }
+        self.tick += 1;
+        self.used += data.len();
+        self.entries.insert(
+            key,
+            Entry {
+                data,
+                type_name: type_name::<T>(),
+                shape,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn get_into<T: Pixel>(&mut self, key: &K, out: &mut [T]) -> bool {
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = match self.entries.get_mut(key) {
+            Some(entry) if entry.type_name == type_name::<T>() => entry,
+            _ => return false,
+        };
+        if entry.shape.0 * entry.shape.1 != out.len() {
+            return false;
+        }
+        entry.last_used = tick;
+        let out = as_bytes_mut(out);
+        matches!(
+            lz4_flex::block::decompress_into(&entry.data, out),
+            Ok(len) if len == out.len()
+        )
+    }
+
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            self.remove(&oldest);
+        }
+    }
+}
+
+fn as_bytes<T: Pixel>(values: &[T]) -> &[u8] {
+    // Safety: pixel types are primitive numbers, without
+    // padding or invalid bit patterns.
+    unsafe {
+        std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+    }
+}
+
+fn as_bytes_mut<T: Pixel>(values: &mut [T]) -> &mut [u8] {
+    // Safety: see `as_bytes`.
+    unsafe {
+        std::slice::from_raw_parts_mut(
+            values.as_mut_ptr() as *mut u8,
+            std::mem::size_of_val(values),
+        )
+    }
+}
+
+/// Key of a read: window, buffer size, resampling and pixel
+/// type.
+type ReadKey = (GdalOffset, Size, Size, ResampleAlg, &'static str);
+
+/// A [`ChunkReader`] caching the reads of another in a
+/// [`ChunkCache`].
+///
+/// Reads are cached by window, buffer size and pixel type,
+/// so only reads of the exact same window hit the cache.
+pub struct CachedReader<R> {
+    reader: R,
+    cache: Mutex<ChunkCache<ReadKey>>,
+}
+
+impl<R> CachedReader<R> {
+    /// Cache the reads of `reader`, within `budget`
+    /// compressed bytes.
+    pub fn new(reader: R, budget: usize) -> Self {
+        CachedReader {
+            reader,
+            cache: Mutex::new(ChunkCache::new(budget)),
+        }
+    }
+
+    pub fn reader(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Compressed bytes currently cached.
+    pub fn cached_bytes(&self) -> usize {
+        self.cache().used_bytes()
+    }
+
+    fn cache(&self) -> MutexGuard<'_, ChunkCache<ReadKey>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<R: ChunkReader> ChunkReader for CachedReader<R> {
+    type Error = R::Error;
+
+    fn raster_size(&self) -> Result<Size, Self::Error> {
+        self.reader.raster_size()
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<(), Self::Error>
+    where
+        T: Pixel,
+    {
+        self.read_into_slice_resampled(out, raster_window, buffer_size, ResampleAlg::Nearest)
+    }
+
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<(), Self::Error>
+    where
+        T: Pixel,
+    {
+        let (offset, size): (GdalOffset, Size) = raster_window.clone().into();
+        let key = (offset, size, buffer_size, alg, type_name::<T>());
+        if self.cache().get_into(&key, out) {
+            return Ok(());
+        }
+        self.reader
+            .read_into_slice_resampled(out, raster_window, buffer_size, alg)?;
+        self.cache()
+            .insert_slice(key, out, (buffer_size.1, buffer_size.0));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ArrayReader;
+
+    #[test]
+    fn test_cache() {
+        let zeros = Array2::<f32>::zeros((64, 64));
+        let mut cache = ChunkCache::new(usize::MAX);
+        cache.insert("a", zeros.view());
+        let size = cache.used_bytes();
+        assert!(size < zeros.len());
+        assert_eq!(cache.get::<f32>(&"a"), Some(zeros.clone()));
+        assert_eq!(cache.get::<f64>(&"a"), None);
+
+        // Room for three arrays: inserting a fourth evicts
+        // "b", the least recently used.
+        let mut cache = ChunkCache::new(3 * size);
+        for key in ["a", "b", "c"] {
+            cache.insert(key, zeros.view());
+        }
+        cache.get::<f32>(&"a");
+        cache.insert("d", zeros.view());
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get::<f32>(&"b"), None);
+        assert!(cache.get::<f32>(&"a").is_some());
+
+        let noise =
+            Array2::from_shape_fn((64, 64), |(i, j)| ((i * 7919 + j * 104729) % 251) as f64);
+        cache.insert("noise", noise.view());
+        assert_eq!(cache.get::<f64>(&"noise"), None);
+    }
+
+    #[test]
+    fn test_cached_reader() {
+        let values = Array2::from_shape_fn((8, 8), |(i, j)| (i * 8 + j) as u16);
+        let reader = CachedReader::new(ArrayReader(values.clone()), 1 << 20);
+        let window: RasterWindow = ((2, 1), (4, 3)).into();
+
+        let first = reader.read_as_array::<u16>(window.clone()).unwrap();
+        assert!(reader.cached_bytes() > 0);
+        let second = reader.read_as_array::<u16>(window.clone()).unwrap();
+        let as_float = reader.read_as_array::<f64>(window).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first, values.slice(ndarray::s![1..4, 2..6]));
+        assert_eq!(as_float[[0, 0]], 10.);
+    }
+}
@@ -0,0 +1,360 @@
+//! Fused chains of per-chunk stages.
+//!
+//! A [`ChunkStage`] maps a padded chunk to its data
+//! (unpadded) part, like the operators of [`crate::ops`].
+//! Stages compose with [`then`][ChunkStage::then],
+//! [`map_output`][ChunkStage::map_output] and
+//! [`zip_with`][ChunkStage::zip_with] into a single stage,
+//! eg. scale → focal mean → threshold, so that a chain runs
+//! in one pass over the chunks without writing intermediate
+//! rasters. The [`radius`][ChunkStage::radius] of a chain is
+//! the sum of the radii of its sequential stages: padding
+//! the chunks by it (see
+//! [`ChunkConfigBuilder::with_padding`][crate::chunking::builder::ChunkConfigBuilder::with_padding])
+//! is enough for the whole chain.
+//!
+//! [`StageProcessor`] runs a stage over the chunks read from
+//! a reader, as a [`ChunkProcessor`].
+
+use crate::chunking::{Chunk, Orientation};
+use crate::processing::ChunkProcessor;
+use crate::reader::{ChunkReader, Pixel};
+use crate::{RasterUtilsError, Result};
+use ndarray::{s, Array2, ArrayView2, Zip};
+
+/// Maps padded chunks to their data part.
+pub trait ChunkStage {
+    type Input;
+    type Output;
+
+    /// Padding (x, y) the stage needs on either side of the
+    /// data.
+    fn radius(&self) -> (usize, usize);
+
+    /// Process `input`, and return it without `padding` (x,
+    /// y) pixels on either side. `padding` is at least the
+    /// radius along the chunk direction.
+    fn apply(
+        &mut self,
+        input: ArrayView2<Self::Input>,
+        padding: (usize, usize),
+    ) -> Result<Array2<Self::Output>>;
+
+    /// Feed the output of this stage to `next`.
+    fn then<S>(self, next: S) -> Then<Self, S>
+    where
+        Self: Sized,
+        S: ChunkStage<Input = Self::Output>,
+    {
+        Then(self, next)
+    }
+
+    /// Map each output pixel with `f`.
+    fn map_output<F, U>(self, f: F) -> MapOutput<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Output) -> U,
+    {
+        MapOutput(self, f)
+    }
+
+    /// Run `other` on the same input, and combine the
+    /// outputs pixel by pixel with `f`.
+    fn zip_with<S, F, U>(self, other: S, f: F) -> ZipWith<Self, S, F>
+    where
+        Self: Sized,
+        S: ChunkStage<Input = Self::Input>,
+        F: FnMut(Self::Output, S::Output) -> U,
+    {
+        ZipWith(self, other, f)
+    }
+}
+
+/// See [`ChunkStage::then`].
+pub struct Then<A, B>(A, B);
+
+impl<A, B> ChunkStage for Then<A, B>
+where
+    A: ChunkStage,
+    B: ChunkStage<Input = A::Output>,
+{
+    type Input = A::Input;
+    type Output = B::Output;
+
+    fn radius(&self) -> (usize, usize) {
+        let (a, b) = (self.0.radius(), self.1.radius());
+        (a.0 + b.0, a.1 + b.1)
+    }
+
+    fn apply(
+        &mut self,
+        input: ArrayView2<A::Input>,
+        padding: (usize, usize),
+    ) -> Result<Array2<B::Output>> {
+        // The first stage leaves the padding the second one
+        // needs.
+        let radius = self.1.radius();
+        let first = (
+            padding.0.saturating_sub(radius.0),
+            padding.1.saturating_sub(radius.1),
+        );
+        let middle = self.0.apply(input, first)?;
+        self.1
+            .apply(middle.view(), (padding.0 - first.0, padding.1 - first.1))
+    }
+}
+
+/// See [`ChunkStage::map_output`].
+pub struct MapOutput<S, F>(S, F);
+
+impl<S, F, U> ChunkStage for MapOutput<S, F>
+where
+    S: ChunkStage,
+    F: FnMut(S::Output) -> U,
+{
+    type Input = S::Input;
+    type Output = U;
+
+    fn radius(&self) -> (usize, usize) {
+        self.0.radius()
+    }
+
+    fn apply(&mut self, input: ArrayView2<S::Input>, padding: (usize, usize)) -> Result<Array2<U>> {
+        let output = self.0.apply(input, padding)?;
+        let dim = output.raw_dim();
+        let values = output.into_iter().map(&mut self.1).collect();
+        Ok(Array2::from_shape_vec(dim, values)?)
+    }
+}
+
+/// See [`ChunkStage::zip_with`].
+pub struct ZipWith<A, B, F>(A, B, F);
+
+impl<A, B, F, U> ChunkStage for ZipWith<A, B, F>
+where
+    A: ChunkStage,
+    B: ChunkStage<Input = A::Input>,
+    A::Output: Clone,
+    B::Output: Clone,
+    F: FnMut(A::Output, B::Output) -> U,
+{
+    type Input = A::Input;
+    type Output = U;
+
+    fn radius(&self) -> (usize, usize) {
+        let (a, b) = (self.0.radius(), self.1.radius());
+        (a.0.max(b.0), a.1.max(b.1))
+    }
+
+    fn apply(&mut self, input: ArrayView2<A::Input>, padding: (usize, usize)) -> Result<Array2<U>> {
+        let a = self.0.apply(input.view(), padding)?;
+        let b = self.1.apply(input, padding)?;
+        let f = &mut self.2;
+        Ok(Zip::from(&a)
+            .and(&b)
+            .map_collect(|a, b| f(a.clone(), b.clone())))
+    }
+}
+
+/// A stage computing each output pixel from the same input
+/// pixel. See [`pixelwise`].
+pub struct Pixelwise<F, T>(F, std::marker::PhantomData<fn(T)>);
+
+/// Stage applying `f` to each pixel, without padding.
+pub fn pixelwise<F, T, U>(f: F) -> Pixelwise<F, T>
+where
+    F: FnMut(T) -> U,
+{
+    Pixelwise(f, std::marker::PhantomData)
+}
+
+impl<F, T, U> ChunkStage for Pixelwise<F, T>
+where
+    F: FnMut(T) -> U,
+    T: Clone,
+{
+    type Input = T;
+    type Output = U;
+
+    fn radius(&self) -> (usize, usize) {
+        (0, 0)
+    }
+
+    fn apply(&mut self, input: ArrayView2<T>, padding: (usize, usize)) -> Result<Array2<U>> {
+        let data = trim(input, padding);
+        let f = &mut self.0;
+        Ok(data.map(|value| f(value.clone())))
+    }
+}
+
+/// A stage from a function of the padded input and the
+/// padding to remove, with a fixed radius. See
+/// [`stage_fn`].
+pub struct StageFn<F, T>((usize, usize), F, std::marker::PhantomData<fn(T)>);
+
+/// Stage of radius `radius` (x, y) applying `f`, eg. a
+/// focal operator of [`crate::ops`].
+pub fn stage_fn<F, T, U>(radius: (usize, usize), f: F) -> StageFn<F, T>
+where
+    F: FnMut(ArrayView2<T>, (usize, usize)) -> Result<Array2<U>>,
+{
+    StageFn(radius, f, std::marker::PhantomData)
+}
+
+impl<F, T, U> ChunkStage for StageFn<F, T>
+where
+    F: FnMut(ArrayView2<T>, (usize, usize)) -> Result<Array2<U>>,
+{
+    type Input = T;
+    type Output = U;
+
+    fn radius(&self) -> (usize, usize) {
+        self.0
+    }
+
+    fn apply(&mut self, input: ArrayView2<T>, padding: (usize, usize)) -> Result<Array2<U>> {
+        (self.1)(input, padding)
+    }
+}
+
+/// View of `input` without `padding` (x, y) pixels on either
+/// side.
+pub fn trim<T>(input: ArrayView2<T>, padding: (usize, usize)) -> ArrayView2<T> {
+    let (rows, cols) = input.dim();
+    let (pad_x, pad_y) = (padding.0.min(cols), padding.1.min(rows));
+    let (end_y, end_x) = ((rows - pad_y).max(pad_y), (cols - pad_x).max(pad_x));
+    input.slice_move(s![pad_y..end_y, pad_x..end_x])
+}
+
+/// A [`ChunkProcessor`] applying a [`ChunkStage`] to each
+/// chunk read from a reader, and passing the data
+/// (unpadded) output to a sink.
+pub struct StageProcessor<'a, R, S, F> {
+    reader: &'a R,
+    stage: S,
+    sink: F,
+}
+
+impl<'a, R, S, F> StageProcessor<'a, R, S, F>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    S: ChunkStage,
+    S::Input: Pixel,
+    F: FnMut(usize, Chunk, Array2<S::Output>) -> Result<()>,
+{
+    pub fn new(reader: &'a R, stage: S, sink: F) -> Self {
+        StageProcessor {
+            reader,
+            stage,
+            sink,
+        }
+    }
+
+    pub fn stage(&self) -> &S {
+        &self.stage
+    }
+}
+
+impl<'a, R, S, F> ChunkProcessor for StageProcessor<'a, R, S, F>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    S: ChunkStage,
+    S::Input: Pixel,
+    F: FnMut(usize, Chunk, Array2<S::Output>) -> Result<()>,
+{
+    fn process(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        let padding = chunk.config().padding();
+        let (radius_x, radius_y) = self.stage.radius();
+        let (padding, radius) = match chunk.config().orientation() {
+            Orientation::Rows => ((0, padding), radius_y),
+            Orientation::Columns => ((padding, 0), radius_x),
+        };
+        if padding.0.max(padding.1) < radius {
+            return Err(RasterUtilsError::Unsupported(
+                "chunk padding smaller than the radius of the stage",
+            ));
+        }
+        let input = self
+            .reader
+            .read_chunk::<S::Input>(chunk)
+            .map_err(Into::into)?;
+        let output = self.stage.apply(input.view(), padding)?;
+        (self.sink)(index, chunk, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::testing::ArrayReader;
+    use ndarray::{concatenate, Axis};
+    use std::num::NonZeroUsize;
+
+    /// Mean over a vertical window of `radius` rows on
+    /// either side.
+    fn vertical_mean(radius: usize) -> impl ChunkStage<Input = f64, Output = f64> {
+        stage_fn((0, radius), move |input: ArrayView2<f64>, padding| {
+            let (rows, cols) = input.dim();
+            let (_, pad_y) = padding;
+            Ok(Array2::from_shape_fn((rows - 2 * pad_y, cols), |(i, j)| {
+                let row = i + pad_y;
+                input
+                    .slice(s![row - radius..=row + radius, j])
+                    .mean()
+                    .unwrap()
+            }))
+        })
+    }
+
+    fn chain() -> impl ChunkStage<Input = f64, Output = bool> {
+        pixelwise(|v: f64| v * 2.)
+            .then(vertical_mean(1))
+            .then(vertical_mean(2))
+            .zip_with(pixelwise(|v: f64| v), |mean, v| mean - v)
+            .map_output(|v| v > 1.)
+    }
+
+    #[test]
+    fn test_chained_matches_whole() {
+        let values = Array2::from_shape_fn((20, 3), |(i, j)| ((i * 7 + j * 3) % 5) as f64);
+        assert_eq!(chain().radius(), (0, 3));
+
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(3).unwrap(),
+            NonZeroUsize::new(20).unwrap(),
+        )
+        .with_data_height(NonZeroUsize::new(4).unwrap())
+        .with_padding(3)
+        .build();
+        let reader = ArrayReader(values.clone());
+        let mut chunks = vec![];
+        let mut processor = StageProcessor::new(&reader, chain(), |_, _, output| {
+            chunks.push(output);
+            Ok(())
+        });
+        for (index, chunk) in cfg.iter().enumerate() {
+            processor.process(index, chunk).unwrap();
+        }
+        drop(processor);
+        let views: Vec<_> = chunks.iter().map(|chunk| chunk.view()).collect();
+        let chunked = concatenate(Axis(0), &views).unwrap();
+
+        let whole = chain().apply(values.view(), (0, 3)).unwrap();
+        assert_eq!(chunked.dim(), (14, 3));
+        assert_eq!(chunked, whole);
+
+        let reader = ArrayReader(values);
+        let mut processor = StageProcessor::new(&reader, chain(), |_, _, _| Ok(()));
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(3).unwrap(),
+            NonZeroUsize::new(20).unwrap(),
+        )
+        .with_padding(2)
+        .build();
+        let chunk = cfg.iter().next().unwrap();
+        assert!(processor.process(0, chunk).is_err());
+    }
+}
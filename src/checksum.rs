@@ -0,0 +1,105 @@
+//! Content checksums of chunks and bands.
+//!
+//! Chunks are hashed with XXH3 over their little endian
+//! bytes in row-major order; band checksums combine
+//! the chunk checksums in chunk order. Useful to compare an
+//! output against a golden raster, or to detect silent read
+//! corruption by comparing [`checksum_chunks`] of two reads.
+//!
+//! Checksums depend on the pixel type read and on the chunk
+//! config (padded chunks overlap), so compare only checksums
+//! computed with the same ones.
+//!
+//! This module is only available with the "checksum" feature.
+
+use super::chunking::ChunkConfig;
+use super::reader::{ChunkReader, Pixel};
+use ndarray::ArrayView2;
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+use std::borrow::Cow;
+
+/// Checksum of the values of `array`, the same on little and
+/// big endian targets.
+pub fn checksum_chunk<T: Pixel>(array: ArrayView2<T>) -> u64 {
+    let array = array.as_standard_layout();
+    let values = array.as_slice().expect("standard layout is contiguous");
+    // Safety: pixel types are primitive numbers, without
+    // padding or invalid bit patterns.
+    let bytes = unsafe {
+        std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+    };
+    xxh3_64(&le_bytes(bytes, std::mem::size_of::<T>()))
+}
+
+/// Little endian bytes of the native `bytes` of primitive
+/// numbers of `size` bytes each.
+fn le_bytes(bytes: &[u8], size: usize) -> Cow<'_, [u8]> {
+    if cfg!(target_endian = "little") {
+        return Cow::Borrowed(bytes);
+    }
+    let mut swapped = bytes.to_vec();
+    for value in swapped.chunks_exact_mut(size) {
+        value.reverse();
+    }
+    Cow::Owned(swapped)
+}
+
+/// Combine chunk checksums, in order, into one.
+pub fn combine_checksums<I: IntoIterator<Item = u64>>(checksums: I) -> u64 {
+    let mut hasher = Xxh3::new();
+    for checksum in checksums {
+        hasher.update(&checksum.to_le_bytes());
+    }
+    hasher.digest()
+}
+
+/// Checksums of all chunks of `config` read from `reader`,
+/// in chunk order.
+pub fn checksum_chunks<T, R>(reader: &R, config: &ChunkConfig) -> Result<Vec<u64>, R::Error>
+where
+    T: Pixel,
+    R: ChunkReader,
+{
+    config
+        .iter()
+        .map(|chunk| Ok(checksum_chunk(reader.read_chunk::<T>(chunk)?.view())))
+        .collect()
+}
+
+/// Checksum of the band read by `reader`, combining the
+/// checksums of the chunks of `config`.
+pub fn checksum_band<T, R>(reader: &R, config: &ChunkConfig) -> Result<u64, R::Error>
+where
+    T: Pixel,
+    R: ChunkReader,
+{
+    Ok(combine_checksums(checksum_chunks::<T, R>(reader, config)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, Array2, ShapeBuilder};
+
+    #[test]
+    fn test_checksum_chunk() {
+        let a = array![[1u16, 2, 3], [4, 5, 6]];
+        let f = Array2::from_shape_vec((2, 3).f(), vec![1u16, 4, 2, 5, 3, 6]).unwrap();
+        assert_eq!(checksum_chunk(a.view()), checksum_chunk(f.view()));
+
+        let b = array![[1u16, 2, 3], [4, 5, 7]];
+        assert_ne!(checksum_chunk(a.view()), checksum_chunk(b.view()));
+
+        let (ca, cb) = (checksum_chunk(a.view()), checksum_chunk(b.view()));
+        assert_ne!(combine_checksums([ca, cb]), combine_checksums([cb, ca]));
+
+        // Little endian bytes, whatever the target.
+        let c = array![[1u16, 0x0203]];
+        assert_eq!(checksum_chunk(c.view()), xxh3_64(&[1, 0, 3, 2]));
+        assert_eq!(
+            le_bytes(&0x0102_0304u32.to_ne_bytes(), 4).as_ref(),
+            &0x0102_0304u32.to_le_bytes()
+        );
+    }
+}
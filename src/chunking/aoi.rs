@@ -0,0 +1,193 @@
+//! Area of interest of a chunking.
+//!
+//! An [`Aoi`] is a polygon in pixel coordinates of the
+//! raster. Set with
+//! [`ChunkConfigBuilder::with_aoi`][super::builder::ChunkConfigBuilder::with_aoi],
+//! it restricts the iteration range to the rows (or columns)
+//! of its bounding box, and
+//! [`Chunk::aoi_mask`][super::Chunk::aoi_mask] flags the
+//! pixels of a chunk within it, so processors may skip the
+//! exterior pixels.
+
+use crate::geometry::{PixelWorldTransform, RasterWindow};
+use geo::{AffineOps, AffineTransform, BoundingRect, LineString, Polygon, Rect};
+use ndarray::Array2;
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use std::cmp::Ordering;
+
+/// Polygon in pixel coordinates (x, y) of a raster.
+///
+/// A pixel is within the AOI if its center is (even-odd
+/// rule, so holes are excluded).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Aoi(Polygon<f64>);
+
+impl Aoi {
+    /// AOI of `polygon` (in world coordinates), given the
+    /// pixel to world `transform` of the raster.
+    ///
+    /// Fails with
+    /// [`RasterUtilsError::DegenerateTransform`][crate::RasterUtilsError::DegenerateTransform]
+    /// if the transform is not invertible.
+    pub fn from_world(polygon: &Polygon<f64>, transform: &AffineTransform) -> crate::Result<Self> {
+        let inverse = PixelWorldTransform::new(*transform).inverse()?;
+        Ok(Aoi(polygon.affine_transform(&inverse)))
+    }
+
+    /// AOI of `polygon`, in pixel coordinates.
+    pub fn from_pixels(polygon: Polygon<f64>) -> Self {
+        Aoi(polygon)
+    }
+
+    pub fn polygon(&self) -> &Polygon<f64> {
+        &self.0
+    }
+
+    /// Bounding box in pixel coordinates, if the polygon is
+    /// not empty.
+    pub fn bounds(&self) -> Option<Rect<f64>> {
+        self.0.bounding_rect()
+    }
+
+    /// Mask of the pixels of `window` within the AOI.
+    pub fn mask(&self, window: &RasterWindow) -> Array2<bool> {
+        let (x_off, y_off) = window.signed_offset();
+        let (rows, cols) = window.shape();
+        let mut mask = Array2::from_elem((rows, cols), false);
+        let mut crossings = vec![];
+        for (row, mut line) in mask.rows_mut().into_iter().enumerate() {
+            let y = (y_off + row as isize) as f64 + 0.5;
+            crossings.clear();
+            for ring in self.rings() {
+                for edge in ring.lines() {
+                    let (p, q) = (edge.start, edge.end);
+                    if (p.y <= y) != (q.y <= y) {
+                        crossings.push(p.x + (y - p.y) * (q.x - p.x) / (q.y - p.y));
+                    }
+                }
+            }
+            crossings.sort_by(f64::total_cmp);
+            for span in crossings.chunks_exact(2) {
+                // Columns whose center is in [span[0], span[1]).
+                let first = (span[0] - 0.5).ceil() as isize - x_off;
+                let last = (span[1] - 0.5).ceil() as isize - x_off;
+                let first = first.clamp(0, cols as isize) as usize;
+                let last = last.clamp(0, cols as isize) as usize;
+                for set in line.iter_mut().take(last).skip(first) {
+                    *set = true;
+                }
+            }
+        }
+        mask
+    }
+
+    fn rings(&self) -> impl Iterator<Item = &LineString<f64>> {
+        std::iter::once(self.0.exterior()).chain(self.0.interiors())
+    }
+
+    fn coords(&self) -> impl Iterator<Item = f64> + '_ {
+        self.rings()
+            .flat_map(|ring| ring.coords())
+            .flat_map(|coord| [coord.x, coord.y])
+    }
+}
+
+// Total order on the coordinates, so that `ChunkConfig` may
+// keep its `Eq` and `Ord` impls.
+impl Ord for Aoi {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let rings = self.0.interiors().len().cmp(&other.0.interiors().len());
+        if rings != Ordering::Equal {
+            return rings;
+        }
+        let (mut ours, mut theirs) = (self.coords(), other.coords());
+        loop {
+            match (ours.next(), theirs.next()) {
+                (None, None) => return Ordering::Equal,
+                (None, Some(_)) => return Ordering::Less,
+                (Some(_), None) => return Ordering::Greater,
+                (Some(a), Some(b)) => match a.total_cmp(&b) {
+                    Ordering::Equal => {}
+                    order => return order,
+                },
+            }
+        }
+    }
+}
+
+impl PartialOrd for Aoi {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Aoi {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Aoi {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use geo::polygon;
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn test_aoi_chunking() {
+        // 10 m pixels, origin at (1000, 5000).
+        let transform = AffineTransform::new(10., 0., 1000., 0., -10., 5000.);
+        // Pixels x 2..8, y 3..7, without the hole x 4..6, y 4..6.
+        let aoi = polygon!(
+            exterior: [
+                (x: 1020., y: 4970.),
+                (x: 1080., y: 4970.),
+                (x: 1080., y: 4930.),
+                (x: 1020., y: 4930.),
+            ],
+            interiors: [[
+                (x: 1040., y: 4960.),
+                (x: 1060., y: 4960.),
+                (x: 1060., y: 4940.),
+                (x: 1040., y: 4940.),
+            ]],
+        );
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(10).unwrap(),
+            NonZeroUsize::new(12).unwrap(),
+        )
+        .with_data_height(NonZeroUsize::new(2).unwrap())
+        .with_padding(1)
+        .with_aoi(&aoi, &transform)
+        .build();
+        assert_eq!((cfg.start(), cfg.end()), (3, 7));
+
+        let chunk = cfg.iter().next().unwrap();
+        assert_eq!((chunk.start(), chunk.size()), (2, 4));
+        let mask = chunk.aoi_mask().unwrap();
+        assert_eq!(mask.dim(), (4, 10));
+        let data = chunk.trim_padding(&mask);
+        let expected = |row: usize, col: usize| {
+            (2..8).contains(&col) && !((4..6).contains(&row) && (4..6).contains(&col))
+        };
+        for ((row, col), &set) in data.indexed_iter() {
+            assert_eq!(set, expected(row + 3, col), "pixel ({}, {})", col, row + 3);
+        }
+        assert!(!mask.row(0).iter().any(|&set| set));
+
+        let outside = polygon![(x: 0., y: 0.), (x: 10., y: 0.), (x: 10., y: 10.)];
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(10).unwrap(),
+            NonZeroUsize::new(12).unwrap(),
+        )
+        .with_aoi(&outside, &transform)
+        .build();
+        assert_eq!(cfg.iter().count(), 0);
+    }
+}
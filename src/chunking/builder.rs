@@ -1,10 +1,50 @@
+use geo::{AffineTransform, Polygon};
 use num::Integer;
 use std::num::NonZeroUsize;
 
-use super::{next_multiple, ChunkConfig};
+use super::{aoi::Aoi, next_multiple, ChunkConfig, Orientation};
+
+/// Errors of [`ChunkConfigBuilder::try_build`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ChunkConfigError {
+    #[error("Empty iteration range: start {start} is not before end {end}")]
+    EmptyRange { start: usize, end: usize },
+    #[error("Padding {padding} is not smaller than the raster length {length}")]
+    PaddingTooLarge { padding: usize, length: usize },
+    #[error("Data height {data_height} is not a multiple of the block size {block_size}")]
+    UnalignedDataHeight {
+        data_height: usize,
+        block_size: usize,
+    },
+    #[error("Start {start} is within the padding {padding}")]
+    StartWithinPadding { start: usize, padding: usize },
+    #[error("End {end} is past the raster length {length}")]
+    EndOutOfRange { end: usize, length: usize },
+    #[error("{0} of the chunk config is zero")]
+    Zero(&'static str),
+}
+
+/// Values as given to the builder, before adjustment.
+#[derive(Default)]
+struct Requested {
+    data_height: Option<usize>,
+    start: Option<usize>,
+    end: Option<usize>,
+}
 
 /// Builder for [ChunkConfig].
-pub struct ChunkConfigBuilder(ChunkConfig);
+///
+/// Inconsistent inputs are adjusted: `data_height` is rounded
+/// up to a multiple of the block size, `start` is raised to
+/// the padding and `end` is clamped to the raster. Use
+/// [`try_build`][Self::try_build] to reject invalid configs,
+/// and [`with_strict`][Self::with_strict] to also reject
+/// inputs that had to be adjusted.
+pub struct ChunkConfigBuilder {
+    config: ChunkConfig,
+    requested: Requested,
+    strict: bool,
+}
 impl ChunkConfigBuilder {
     /// Create a [ChunkConfigBuilder] with given raster dimmentions.
     pub fn new(width: NonZeroUsize, height: NonZeroUsize) -> Self {
@@ -19,9 +59,23 @@ impl ChunkConfigBuilder {
 
             start: 0,
             end: height,
+
+            orientation: Orientation::Rows,
+            cross_block_size: 1,
+            aoi: None,
         };
 
-        Self(default_config)
+        Self::from_config(default_config)
+    }
+
+    /// Builder of `config` as is, eg. to validate it with
+    /// [`try_build`][Self::try_build].
+    pub(super) fn from_config(config: ChunkConfig) -> Self {
+        Self {
+            config,
+            requested: Requested::default(),
+            strict: false,
+        }
     }
 
     /// Accumulate `block_size` onto builder.
@@ -29,46 +83,83 @@ impl ChunkConfigBuilder {
     /// Compute least common multiple with existing value and replace it.
     pub fn add_block_size(mut self, block_size: NonZeroUsize) -> Self {
         let block_size = block_size.get();
-        if self.0.block_size != block_size {
-            self.0.block_size = self.0.block_size.lcm(&block_size);
+        if self.config.block_size != block_size {
+            self.config.block_size = self.config.block_size.lcm(&block_size);
             self.adjust_data_height();
         }
         self
     }
 
+    /// Accumulate the block shape (x, y) onto builder.
+    ///
+    /// The block size along the chunks is accumulated as by
+    /// [`add_block_size`][Self::add_block_size], and the one
+    /// across them is used to align windows within a chunk
+    /// (see [`Chunk::split`][super::Chunk::split]). Should be
+    /// called after [`with_orientation`][Self::with_orientation].
+    pub fn add_block_shape(mut self, block_shape: (NonZeroUsize, NonZeroUsize)) -> Self {
+        let (along, across) = match self.config.orientation {
+            Orientation::Rows => (block_shape.1, block_shape.0),
+            Orientation::Columns => (block_shape.0, block_shape.1),
+        };
+        self.config.cross_block_size = self.config.cross_block_size.lcm(&across.get());
+        self.add_block_size(along)
+    }
+
     /// Set `data_height` for the chunking.
     pub fn with_data_height(mut self, data_height: NonZeroUsize) -> Self {
-        self.0.data_height = data_height.get();
-        self.adjust_data_height();
+        self.requested.data_height = Some(data_height.get());
+        self.set_data_height(data_height.get());
         self
     }
 
+    fn set_data_height(&mut self, data_height: usize) {
+        self.config.data_height = data_height;
+        self.adjust_data_height();
+    }
+
     /// Ensure `data_height` is a multiple of block size.
     #[inline]
     fn adjust_data_height(&mut self) {
-        self.0.data_height = next_multiple(self.0.data_height, self.0.block_size);
+        self.config.data_height = next_multiple(self.config.data_height, self.config.block_size);
     }
 
     /// Set `data_height` based on number of data pixels expected in each chunk.
-    pub fn with_data_size(self, data_size: NonZeroUsize) -> Self {
-        // data_height is zero iff data_size + width = 1
-        // but data_size and width are both NonZeroUsize.
-        let data_height = unsafe {
-            NonZeroUsize::new_unchecked((data_size.get() + self.0.width - 1) / self.0.width)
-        };
-        self.with_data_height(data_height)
+    pub fn with_data_size(mut self, data_size: NonZeroUsize) -> Self {
+        // data_height is non zero as data_size and width are
+        // both NonZeroUsize.
+        let data_height = data_size.get().div_ceil(self.config.breadth());
+        self.set_data_height(data_height);
+        self
+    }
+
+    /// Set `data_height` so that a chunk (incl. padding) fits
+    /// in `budget` bytes, given the number of bytes needed
+    /// for each row of the raster (each column for
+    /// [`Orientation::Columns`]).
+    ///
+    /// Should be called after the block size and padding are
+    /// set. The result is rounded down to a multiple of the
+    /// block size, but is at least one block.
+    pub fn with_memory_budget(mut self, budget: usize, bytes_per_row: NonZeroUsize) -> Self {
+        let rows = budget / bytes_per_row.get();
+        let data_height = rows.saturating_sub(2 * self.config.padding);
+        let data_height = data_height - data_height % self.config.block_size;
+        self.config.data_height = data_height.max(self.config.block_size);
+        self
     }
 
     /// Set `padding` required for each chunk.
     pub fn with_padding(mut self, padding: usize) -> Self {
-        self.0.padding = padding;
+        self.config.padding = padding;
         self.adjust_start();
         self
     }
 
     /// Set `start` index of the iteration range.
     pub fn with_start(mut self, start: usize) -> Self {
-        self.0.start = start;
+        self.requested.start = Some(start);
+        self.config.start = start;
         self.adjust_start();
         self
     }
@@ -76,17 +167,151 @@ impl ChunkConfigBuilder {
     /// Ensure `start` is always greater than padding.
     #[inline]
     fn adjust_start(&mut self) {
-        self.0.start = self.0.start.max(self.0.padding);
+        self.config.start = self.config.start.max(self.config.padding);
     }
 
     /// Set `end` index of the iteration range.
     pub fn with_end(mut self, end: usize) -> Self {
-        self.0.end = end.min(self.0.height);
+        self.requested.end = Some(end);
+        self.config.end = end.min(self.config.length());
+        self
+    }
+
+    /// Restrict the iteration range to the rows (or columns)
+    /// of the pixels within `aoi`, given the pixel to world
+    /// `transform` of the raster, and record the AOI for
+    /// [`Chunk::aoi_mask`][super::Chunk::aoi_mask].
+    ///
+    /// Should be called after the orientation and padding are
+    /// set. An AOI outside the raster, or a transform that
+    /// is not invertible, leaves an empty range.
+    pub fn with_aoi(mut self, aoi: &Polygon<f64>, transform: &AffineTransform) -> Self {
+        let aoi = Aoi::from_world(aoi, transform).ok();
+        let range = aoi.as_ref().and_then(Aoi::bounds).map(|bounds| {
+            let (min, max) = match self.config.orientation {
+                Orientation::Rows => (bounds.min().y, bounds.max().y),
+                Orientation::Columns => (bounds.min().x, bounds.max().x),
+            };
+            // Pixels whose center may be within the AOI.
+            let length = self.config.length() as f64;
+            let start = (min - 0.5).ceil().clamp(0., length) as usize;
+            let end = (max - 0.5).ceil().clamp(0., length) as usize;
+            (start, end)
+        });
+        let (start, end) = range.unwrap_or((0, 0));
+        self.config.start = start;
+        self.adjust_start();
+        self.config.end = end.max(self.config.start);
+        self.config.aoi = aoi;
+        self
+    }
+
+    /// [`Orientation`] of the chunks.
+    pub fn orientation(&self) -> Orientation {
+        self.config.orientation
+    }
+
+    /// Set the [`Orientation`] of the chunks.
+    ///
+    /// Resets the iteration range to the full raster, so
+    /// should be called before [`with_start`][Self::with_start]
+    /// and [`with_end`][Self::with_end]. Block size and data
+    /// height are interpreted along the new direction.
+    pub fn with_orientation(mut self, orientation: Orientation) -> Self {
+        self.config.orientation = orientation;
+        self.config.start = self.config.padding;
+        self.config.end = self.config.length();
+        self.requested.start = None;
+        self.requested.end = None;
+        self
+    }
+
+    /// Make [`try_build`][Self::try_build] reject inputs that
+    /// had to be adjusted, instead of adjusting them.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
         self
     }
 
     /// Build [ChunkConfig]
     pub fn build(self) -> ChunkConfig {
-        self.0
+        self.config
+    }
+
+    /// Build [ChunkConfig], failing if its iteration range is
+    /// empty or out of the raster, the padding spans the
+    /// raster, or the data height is not a multiple of the
+    /// block size. In strict mode, also fails if an input was
+    /// adjusted.
+    pub fn try_build(self) -> Result<ChunkConfig, ChunkConfigError> {
+        let config = &self.config;
+        let dimensions = [
+            ("width", config.width),
+            ("height", config.height),
+            ("block_size", config.block_size),
+            ("data_height", config.data_height),
+            ("cross_block_size", config.cross_block_size),
+        ];
+        if let Some((name, _)) = dimensions.iter().find(|(_, value)| *value == 0) {
+            return Err(ChunkConfigError::Zero(name));
+        }
+        let length = config.length();
+        if self.strict {
+            let requested = &self.requested;
+            if let Some(data_height) = requested.data_height {
+                if data_height % config.block_size != 0 {
+                    return Err(ChunkConfigError::UnalignedDataHeight {
+                        data_height,
+                        block_size: config.block_size,
+                    });
+                }
+            }
+            if let Some(start) = requested.start {
+                if start < config.padding {
+                    return Err(ChunkConfigError::StartWithinPadding {
+                        start,
+                        padding: config.padding,
+                    });
+                }
+            }
+            if let Some(end) = requested.end {
+                if end > length {
+                    return Err(ChunkConfigError::EndOutOfRange { end, length });
+                }
+            }
+        }
+        // The builder keeps data heights aligned, but configs
+        // deserialized as is may not be.
+        if config.data_height % config.block_size != 0 {
+            return Err(ChunkConfigError::UnalignedDataHeight {
+                data_height: config.data_height,
+                block_size: config.block_size,
+            });
+        }
+        if config.padding >= length {
+            return Err(ChunkConfigError::PaddingTooLarge {
+                padding: config.padding,
+                length,
+            });
+        }
+        if config.start < config.padding {
+            return Err(ChunkConfigError::StartWithinPadding {
+                start: config.start,
+                padding: config.padding,
+            });
+        }
+        if config.end > length {
+            return Err(ChunkConfigError::EndOutOfRange {
+                end: config.end,
+                length,
+            });
+        }
+        if config.start >= config.end {
+            return Err(ChunkConfigError::EmptyRange {
+                start: config.start,
+                end: config.end,
+            });
+        }
+        Ok(self.config)
     }
 }
@@ -0,0 +1,47 @@
+//! Cooperative cancellation of chunked processing.
+
+use super::super::{RasterUtilsError, Result};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Shared flag to request cancellation of a job.
+///
+/// Clones share the same flag. Cancellation is cooperative:
+/// it is checked between chunks, so the chunk being
+/// processed when [`cancel`][CancellationToken::cancel] is
+/// called still runs to completion.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fail with [`RasterUtilsError::Cancelled`] if
+    /// cancellation was requested.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(RasterUtilsError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancellationToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        CancellationToken(flag)
+    }
+}
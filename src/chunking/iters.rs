@@ -1,11 +1,12 @@
-use super::{next_multiple, ChunkConfig, ChunkWindow};
-use std::{iter::*, ops::Range};
+use super::{next_multiple, Chunk, ChunkConfig};
+use crate::reader::AdviseReader;
+use std::{iter::*, num::NonZeroUsize, ops::Range};
 
 impl<'a> IntoIterator for &'a ChunkConfig {
-    type Item = ChunkWindow<'a>;
+    type Item = Chunk<'a>;
     // TODO: Chnage to impl instead of Box<dyn ...> when supported.
     // https://github.com/rust-lang/rust/issues/63063
-    type IntoIter = Map<Range<usize>, Box<dyn Fn(usize) -> ChunkWindow<'a> + 'a>>;
+    type IntoIter = Map<Range<usize>, Box<dyn Fn(usize) -> Chunk<'a> + 'a>>;
 
     fn into_iter(self) -> Self::IntoIter {
         let (count, func) = self.iter_mapper();
@@ -20,7 +21,7 @@ impl ChunkConfig {
         debug_assert!(
             self.block_size > 0
                 && self.start >= self.padding
-                && self.end <= self.height
+                && self.end <= self.length()
                 && self.data_height % self.block_size == 0,
             "ChunkConfig preconditions failed"
         );
@@ -39,7 +40,8 @@ impl ChunkConfig {
         // For the initial chunk, we ensure the load ends at
         // a chunk boundary. This would increase the size of
         // the chunk, but by at most one block.
-        let mut load_end = next_multiple(data_end + self.padding, self.block_size).min(self.height);
+        let mut load_end =
+            next_multiple(data_end + self.padding, self.block_size).min(self.length());
         // TODO: probably also not needed
         // data_end > start and height >= end > start
         debug_assert!(load_end > self.start);
@@ -61,7 +63,8 @@ impl ChunkConfig {
 
         let count = {
             let dcount = next_multiple(self.end - data_end, self.data_height) / self.data_height;
-            let lcount = next_multiple(self.height - load_end, self.data_height) / self.data_height;
+            let lcount =
+                next_multiple(self.length() - load_end, self.data_height) / self.data_height;
             dcount.min(lcount)
         } + 1;
         debug_assert!(count == 1 || load_end % self.block_size == 0);
@@ -69,7 +72,7 @@ impl ChunkConfig {
         [count, data_end, load_end]
     }
 
-    pub(super) fn iter_mapper<'a>(&'a self) -> (usize, impl Fn(usize) -> ChunkWindow<'a> + 'a) {
+    pub(super) fn iter_mapper<'a>(&'a self) -> (usize, impl Fn(usize) -> Chunk<'a> + 'a) {
         self.check_preconditions();
 
         let [count, initial_data_end, initial_load_end] = self.calc_initial_chunk();
@@ -85,18 +88,93 @@ impl ChunkConfig {
             } else {
                 let data_start = initial_data_end + (i - 1) * self.data_height;
                 let data_end = (data_start + self.data_height).min(self.end);
-                let load_end = (data_end + self.padding).min(self.height);
+                let load_end = (data_end + self.padding).min(self.length());
                 let data_end = load_end - self.padding;
                 (data_start, data_end, load_end)
             };
             let load_start = data_start - self.padding;
-            (self, load_start, (load_end - load_start) as usize)
+            Chunk::new(self, load_start, load_end - load_start)
         })
     }
 
     /// Create an [ExactSizeIterator] from the configuration.
-    pub fn iter(&self) -> impl ExactSizeIterator<Item = ChunkWindow> + '_ {
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = Chunk> + '_ {
         let (count, func) = self.iter_mapper();
         (0..count).map(func)
     }
+
+    /// Index of `chunk` (a chunk of this config) in the
+    /// iteration, computed without iterating.
+    pub fn chunk_index(&self, chunk: &Chunk) -> usize {
+        let [_, initial_data_end, _] = self.calc_initial_chunk();
+        match chunk.data_start().checked_sub(initial_data_end) {
+            Some(offset) => offset / self.data_height + 1,
+            None => 0,
+        }
+    }
+
+    /// Create an [ExactSizeIterator] over every `step`-th
+    /// chunk, starting with the first one.
+    pub fn iter_step(&self, step: NonZeroUsize) -> impl ExactSizeIterator<Item = Chunk> + '_ {
+        let (count, func) = self.iter_mapper();
+        (0..count).step_by(step.get()).map(func)
+    }
+
+    /// Create an [Iterator] over the chunks at `indices` (in
+    /// the order given). Indices past the last chunk are
+    /// skipped.
+    pub fn iter_indices<'a>(
+        &'a self,
+        indices: &'a [usize],
+    ) -> impl Iterator<Item = Chunk<'a>> + 'a {
+        let (count, func) = self.iter_mapper();
+        indices
+            .iter()
+            .copied()
+            .filter(move |&index| index < count)
+            .map(func)
+    }
+
+    /// Create an [Iterator] over the chunks accepted by
+    /// `predicate`.
+    ///
+    /// The predicate is evaluated on each chunk before any
+    /// data is read, so it should only inspect cheap
+    /// information (overview values, previously computed
+    /// per-chunk stats, AOI coverage, ...) to decide whether
+    /// the full-resolution chunk is needed.
+    pub fn iter_filtered<'a, P>(&'a self, predicate: P) -> impl Iterator<Item = Chunk<'a>> + 'a
+    where
+        P: Fn(&Chunk<'a>) -> bool + 'a,
+    {
+        self.iter().filter(move |chunk| predicate(chunk))
+    }
+
+    /// Create an [ExactSizeIterator] that advises `reader` of
+    /// the windows of the next `ahead` chunks before yielding
+    /// each chunk, so backends may fetch them while the
+    /// current one is processed.
+    ///
+    /// Errors of the advice are ignored.
+    pub fn iter_advised<'a, R>(
+        &'a self,
+        reader: &'a R,
+        ahead: usize,
+    ) -> impl ExactSizeIterator<Item = Chunk<'a>> + 'a
+    where
+        R: AdviseReader + ?Sized,
+    {
+        let (count, func) = self.iter_mapper();
+        (0..count).map(move |i| {
+            if ahead > 0 {
+                // The first chunk advises the whole lookahead,
+                // later ones only the chunk entering it.
+                let first = if i == 0 { 1 } else { i + ahead };
+                for j in first..(i + ahead + 1).min(count) {
+                    let _ = reader.advise_read(&func(j).window());
+                }
+            }
+            func(i)
+        })
+    }
 }
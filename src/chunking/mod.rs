@@ -31,21 +31,50 @@
 //! module have the following properties:
 //!
 //! - **Full Width.** Each chunk spans the full width of the
-//! raster. This simplifies the iteration logic, and is
-//! currently the only supported mode.
+//! raster. This simplifies the iteration logic. Chunks may
+//! instead span the full height and iterate across the
+//! width (see [`Orientation`]); everything below then
+//! applies with rows and columns swapped.
 //!
 //! - **Fixed Padding.** Each chunk may additionally use a
 //! fixed number of rows above and below it.
 
+pub mod aoi;
 pub mod builder;
+pub mod cancel;
 mod iters;
 #[cfg(feature = "use-rayon")]
 mod par_iters;
+pub mod progress;
 
 pub use super::{RasterUtilsError, Result};
+use crate::geometry::{Offset, RasterWindow, Size};
+use aoi::Aoi;
+use geo::{AffineTransform, Rect};
+use ndarray::{s, Array2, ArrayView2};
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+/// Direction in which chunks span the raster.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Orientation {
+    /// Chunks span the full width and iterate down the
+    /// rows; padding is applied in y.
+    #[default]
+    Rows,
+    /// Chunks span the full height and iterate across the
+    /// columns; padding is applied in x.
+    Columns,
+}
 
 /// Config for creating chunks within a raster.
+///
+/// Deserialized configs are validated as by
+/// [`ChunkConfigBuilder::try_build`][builder::ChunkConfigBuilder::try_build].
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawChunkConfig"))]
 pub struct ChunkConfig {
     /// Width of raster to be chunked.
     width: usize,
@@ -73,6 +102,62 @@ pub struct ChunkConfig {
     start: usize,
     /// End of processing range.
     end: usize,
+    /// Direction of the chunks. The fields above that refer
+    /// to rows refer to columns for [`Orientation::Columns`].
+    orientation: Orientation,
+    /// Block size across the chunks (along x for
+    /// [`Orientation::Rows`]), used to align windows within a
+    /// chunk.
+    cross_block_size: usize,
+    /// Area of interest the iteration range was restricted
+    /// to, if any.
+    aoi: Option<Aoi>,
+}
+
+/// Fields of a [`ChunkConfig`] as serialized, before
+/// validation.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawChunkConfig {
+    width: usize,
+    height: usize,
+    block_size: usize,
+    data_height: usize,
+    padding: usize,
+    start: usize,
+    end: usize,
+    #[serde(default)]
+    orientation: Orientation,
+    #[serde(default = "one")]
+    cross_block_size: usize,
+    #[serde(default)]
+    aoi: Option<Aoi>,
+}
+
+#[cfg(feature = "serde")]
+fn one() -> usize {
+    1
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<RawChunkConfig> for ChunkConfig {
+    type Error = builder::ChunkConfigError;
+
+    fn try_from(raw: RawChunkConfig) -> std::result::Result<Self, Self::Error> {
+        let config = ChunkConfig {
+            width: raw.width,
+            height: raw.height,
+            block_size: raw.block_size,
+            data_height: raw.data_height,
+            padding: raw.padding,
+            start: raw.start,
+            end: raw.end,
+            orientation: raw.orientation,
+            cross_block_size: raw.cross_block_size,
+            aoi: raw.aoi,
+        };
+        builder::ChunkConfigBuilder::from_config(config).try_build()
+    }
 }
 
 impl ChunkConfig {
@@ -99,15 +184,168 @@ impl ChunkConfig {
     pub fn end(&self) -> usize {
         self.end
     }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Area of interest of the chunking, if any (see
+    /// [`ChunkConfigBuilder::with_aoi`][builder::ChunkConfigBuilder::with_aoi]).
+    pub fn aoi(&self) -> Option<&Aoi> {
+        self.aoi.as_ref()
+    }
+
+    /// Fails with
+    /// [`ChunkOutOfRaster`][crate::RasterUtilsError::ChunkOutOfRaster]
+    /// if the chunks extend beyond a raster of `raster_size`
+    /// (x, y), eg. for a config built for another raster.
+    pub fn check_raster_size(&self, raster_size: Size) -> crate::Result<()> {
+        let config_size = (self.width, self.height);
+        if config_size.0 > raster_size.0 || config_size.1 > raster_size.1 {
+            return Err(crate::RasterUtilsError::ChunkOutOfRaster {
+                config_size,
+                raster_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Block size (x, y) on both axes.
+    pub fn block_shape(&self) -> Size {
+        match self.orientation {
+            Orientation::Rows => (self.cross_block_size, self.block_size),
+            Orientation::Columns => (self.block_size, self.cross_block_size),
+        }
+    }
+
+    /// Extent of the raster along the iteration direction.
+    fn length(&self) -> usize {
+        match self.orientation {
+            Orientation::Rows => self.height,
+            Orientation::Columns => self.width,
+        }
+    }
+
+    /// Extent of the raster spanned by each chunk.
+    fn breadth(&self) -> usize {
+        match self.orientation {
+            Orientation::Rows => self.width,
+            Orientation::Columns => self.height,
+        }
+    }
+
+    /// Owned list of the windows of all chunks (incl.
+    /// padding), in iteration order.
+    ///
+    /// Unlike [`Chunk`], the windows don't borrow the config
+    /// and may be stored or sent elsewhere.
+    pub fn windows(&self) -> Vec<RasterWindow> {
+        self.iter().map(RasterWindow::from).collect()
+    }
 }
 
-/// The type of item produced by the iterations. Consists
-/// of:
+/// The type of item produced by the iterations.
 ///
-/// 0. reference to the underlying `ChunkConfig`
-/// 1. the start index of this chunk
-/// 2. the number of rows (incl. padding) for this chunk
-pub type ChunkWindow<'a> = (&'a ChunkConfig, usize, usize);
+/// Serializes as `start` and `size` only; the config is
+/// expected to be shipped alongside.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Chunk<'a> {
+    /// Reference to the underlying `ChunkConfig`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    config: &'a ChunkConfig,
+    /// Start index of this chunk.
+    start: usize,
+    /// Number of rows (or columns) incl. padding for this
+    /// chunk.
+    size: usize,
+}
+
+impl<'a> Chunk<'a> {
+    pub(crate) fn new(config: &'a ChunkConfig, start: usize, size: usize) -> Self {
+        Chunk {
+            config,
+            start,
+            size,
+        }
+    }
+
+    pub fn config(&self) -> &'a ChunkConfig {
+        self.config
+    }
+    pub fn start(&self) -> usize {
+        self.start
+    }
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Start of the data rows (or columns) of this chunk,
+    /// without padding.
+    pub fn data_start(&self) -> usize {
+        self.start + self.config.padding
+    }
+    /// Number of data rows (or columns) of this chunk,
+    /// without padding.
+    pub fn data_size(&self) -> usize {
+        self.size.saturating_sub(2 * self.config.padding)
+    }
+
+    /// Offset (x, y) of the window of the chunk, incl.
+    /// padding.
+    pub fn offset(&self) -> Offset {
+        match self.config.orientation {
+            Orientation::Rows => (0, self.start),
+            Orientation::Columns => (self.start, 0),
+        }
+    }
+
+    /// Window of the chunk, incl. padding: the window to
+    /// read.
+    pub fn window(&self) -> RasterWindow {
+        RasterWindow::from(*self)
+    }
+
+    /// Window of the data of the chunk, without padding: the
+    /// window results should be written to.
+    pub fn data_window(&self) -> RasterWindow {
+        Chunk::new(self.config, self.data_start(), self.data_size()).into()
+    }
+
+    /// Split the window of the chunk (incl. padding) into
+    /// windows of at most `max_size` (x, y), cut on the block
+    /// boundaries of the config on both axes (see
+    /// [`RasterWindow::split_aligned`]).
+    pub fn split(&self, max_size: Size) -> Vec<RasterWindow> {
+        self.window()
+            .split_aligned(max_size, self.config.block_shape())
+    }
+
+    /// View of the data of `array`, read from the padded
+    /// window of this chunk, without the padding.
+    pub fn trim_padding<'b, T>(&self, array: &'b Array2<T>) -> ArrayView2<'b, T> {
+        let data = self.config.padding..self.config.padding + self.data_size();
+        match self.config.orientation {
+            Orientation::Rows => array.slice(s![data, ..]),
+            Orientation::Columns => array.slice(s![.., data]),
+        }
+    }
+
+    /// Mask of the pixels of the chunk (incl. padding) within
+    /// the area of interest of the config, if any. Use
+    /// [`trim_padding`][Self::trim_padding] for the data
+    /// pixels only.
+    pub fn aoi_mask(&self) -> Option<Array2<bool>> {
+        self.config.aoi().map(|aoi| aoi.mask(&self.window()))
+    }
+
+    /// Bounding box of the chunk (incl. padding) in world
+    /// coordinates, given the pixel to world `transform` of
+    /// the raster.
+    pub fn world_bounds(&self, transform: &AffineTransform) -> Rect<f64> {
+        RasterWindow::from(*self).to_world(transform)
+    }
+}
 
 #[inline]
 /// Find smallest multiple of m that is higher then num.
@@ -125,14 +363,16 @@ mod tests {
 
     fn debug_cfg(cfg: ChunkConfig) {
         eprintln!("{:?}", cfg);
-        for (_, ls, size) in &cfg {
-            eprintln!("{} -> {}", ls, ls + size);
+        for chunk in &cfg {
+            eprintln!("{} -> {}", chunk.start(), chunk.start() + chunk.size());
         }
     }
 
     fn check_cfg(cfg: ChunkConfig, output: Vec<(usize, usize)>) {
         assert_eq!(
-            cfg.into_iter().map(|(_, a, b)| (a, b)).collect::<Vec<_>>(),
+            cfg.into_iter()
+                .map(|chunk| (chunk.start(), chunk.size()))
+                .collect::<Vec<_>>(),
             output
         );
     }
@@ -177,4 +417,282 @@ mod tests {
             vec![(0, 16), (2, 15)],
         )
     }
+
+    #[test]
+    fn test_windows() {
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(32).unwrap(),
+            NonZeroUsize::new(20).unwrap(),
+        )
+        .add_block_size(NonZeroUsize::new(2).unwrap())
+        .with_padding(7)
+        .with_end(10)
+        .build();
+        let windows = cfg.windows();
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[1].offset(), Some((0, 2)));
+        assert_eq!(windows[1].size(), (32, 15));
+    }
+
+    #[test]
+    fn test_columns() {
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(20).unwrap(),
+            NonZeroUsize::new(32).unwrap(),
+        )
+        .with_orientation(Orientation::Columns)
+        .add_block_size(NonZeroUsize::new(2).unwrap())
+        .with_padding(7)
+        .with_end(10)
+        .build();
+        check_cfg(cfg.clone(), vec![(0, 16), (2, 15)]);
+
+        let window = RasterWindow::from(cfg.iter().nth(1).unwrap());
+        assert_eq!(window.offset(), Some((2, 0)));
+        assert_eq!(window.size(), (15, 32));
+    }
+
+    #[test]
+    fn test_subsets() {
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(10).unwrap(),
+            NonZeroUsize::new(100).unwrap(),
+        )
+        .add_block_size(NonZeroUsize::new(10).unwrap())
+        .build();
+        let all: Vec<_> = cfg.iter().collect();
+
+        let stepped: Vec<_> = cfg.iter_step(NonZeroUsize::new(3).unwrap()).collect();
+        assert_eq!(stepped, vec![all[0], all[3], all[6], all[9]]);
+
+        let picked: Vec<_> = cfg.iter_indices(&[5, 1, 42]).collect();
+        assert_eq!(picked, vec![all[5], all[1]]);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let builder = || {
+            ChunkConfigBuilder::new(
+                NonZeroUsize::new(100).unwrap(),
+                NonZeroUsize::new(1000).unwrap(),
+            )
+            .add_block_size(NonZeroUsize::new(16).unwrap())
+            .with_padding(2)
+        };
+        let row = NonZeroUsize::new(400).unwrap();
+        // 100 rows fit, minus padding leaves 96.
+        let cfg = builder().with_memory_budget(40_000, row).build();
+        assert_eq!(cfg.data_height(), 96);
+        // 50 rows fit, rounded down to 32.
+        let cfg = builder().with_memory_budget(20_000, row).build();
+        assert_eq!(cfg.data_height(), 32);
+        // Never below one block.
+        let cfg = builder().with_memory_budget(0, row).build();
+        assert_eq!(cfg.data_height(), 16);
+    }
+
+    #[test]
+    fn test_data_window() {
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(3).unwrap(),
+            NonZeroUsize::new(10).unwrap(),
+        )
+        .with_data_height(NonZeroUsize::new(4).unwrap())
+        .with_padding(1)
+        .build();
+        let chunk = cfg.iter().nth(1).unwrap();
+        assert_eq!((chunk.start(), chunk.size()), (4, 6));
+        assert_eq!((chunk.data_start(), chunk.data_size()), (5, 4));
+        assert_eq!(chunk.data_window(), RasterWindow::from(((0, 5), (3, 4))));
+
+        let array = Array2::from_shape_fn((6, 3), |(i, _)| chunk.start() + i);
+        let data = chunk.trim_padding(&array);
+        assert_eq!(data.dim(), (4, 3));
+        assert_eq!(data[[0, 0]], 5);
+    }
+
+    #[test]
+    fn test_block_shape() {
+        let nz = |n| NonZeroUsize::new(n).unwrap();
+        let cfg = ChunkConfigBuilder::new(nz(10), nz(8))
+            .add_block_shape((nz(4), nz(2)))
+            .add_block_shape((nz(2), nz(4)))
+            .with_data_height(nz(4))
+            .build();
+        assert_eq!(cfg.block_shape(), (4, 4));
+
+        let chunk = cfg.iter().next().unwrap();
+        assert_eq!(
+            chunk.split((6, 6)),
+            vec![
+                RasterWindow::from(((0, 0), (4, 4))),
+                RasterWindow::from(((4, 0), (4, 4))),
+                RasterWindow::from(((8, 0), (2, 4))),
+            ]
+        );
+
+        let cfg = ChunkConfigBuilder::new(nz(10), nz(8))
+            .with_orientation(Orientation::Columns)
+            .add_block_shape((nz(4), nz(2)))
+            .build();
+        assert_eq!((cfg.block_size(), cfg.block_shape()), (4, (4, 2)));
+    }
+
+    #[test]
+    fn test_try_build() {
+        use crate::chunking::builder::ChunkConfigError;
+        let builder = || {
+            ChunkConfigBuilder::new(
+                NonZeroUsize::new(32).unwrap(),
+                NonZeroUsize::new(20).unwrap(),
+            )
+            .add_block_size(NonZeroUsize::new(4).unwrap())
+        };
+        let unaligned = || builder().with_data_height(NonZeroUsize::new(6).unwrap());
+
+        assert_eq!(unaligned().try_build().unwrap().data_height(), 8);
+        assert_eq!(
+            unaligned().with_strict(true).try_build(),
+            Err(ChunkConfigError::UnalignedDataHeight {
+                data_height: 6,
+                block_size: 4
+            })
+        );
+        assert_eq!(
+            builder().with_start(10).with_end(10).try_build(),
+            Err(ChunkConfigError::EmptyRange { start: 10, end: 10 })
+        );
+        assert_eq!(
+            builder().with_padding(20).try_build(),
+            Err(ChunkConfigError::PaddingTooLarge {
+                padding: 20,
+                length: 20
+            })
+        );
+        assert_eq!(
+            builder()
+                .with_padding(2)
+                .with_start(1)
+                .with_strict(true)
+                .try_build(),
+            Err(ChunkConfigError::StartWithinPadding {
+                start: 1,
+                padding: 2
+            })
+        );
+        assert!(builder().with_end(30).try_build().is_ok());
+        assert!(builder()
+            .with_end(30)
+            .with_strict(true)
+            .try_build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_iter_advised() {
+        use crate::reader::{AdviseReader, ChunkReader, Pixel};
+        use crate::testing::ArrayReader;
+        use std::cell::RefCell;
+
+        struct Recorder(ArrayReader<u8>, RefCell<Vec<usize>>);
+        impl ChunkReader for Recorder {
+            type Error = crate::RasterUtilsError;
+            fn raster_size(&self) -> crate::Result<Size> {
+                self.0.raster_size()
+            }
+            fn read_into_slice_sized<T: Pixel>(
+                &self,
+                out: &mut [T],
+                raster_window: RasterWindow,
+                buffer_size: Size,
+            ) -> crate::Result<()> {
+                self.0
+                    .read_into_slice_sized(out, raster_window, buffer_size)
+            }
+        }
+        impl AdviseReader for Recorder {
+            fn advise_read(&self, raster_window: &RasterWindow) -> crate::Result<()> {
+                self.1.borrow_mut().push(raster_window.signed_offset().1);
+                Ok(())
+            }
+        }
+
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(4).unwrap(),
+            NonZeroUsize::new(10).unwrap(),
+        )
+        .with_data_height(NonZeroUsize::new(2).unwrap())
+        .build();
+        let reader = Recorder(ArrayReader(Array2::zeros((10, 4))), RefCell::new(vec![]));
+        let mut advised = vec![];
+        for chunk in cfg.iter_advised(&reader, 2) {
+            advised.push((chunk.start(), reader.1.replace(vec![])));
+        }
+        assert_eq!(
+            advised,
+            vec![
+                (0, vec![2, 4]),
+                (2, vec![6]),
+                (4, vec![8]),
+                (6, vec![]),
+                (8, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chunk_index() {
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(4).unwrap(),
+            NonZeroUsize::new(23).unwrap(),
+        )
+        .add_block_shape((NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(3).unwrap()))
+        .with_data_height(NonZeroUsize::new(6).unwrap())
+        .with_padding(2)
+        .build();
+        for (index, chunk) in cfg.iter().enumerate() {
+            assert_eq!(cfg.chunk_index(&chunk), index);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(32).unwrap(),
+            NonZeroUsize::new(20).unwrap(),
+        )
+        .with_padding(1)
+        .build();
+        let json = serde_json::to_string(&cfg).unwrap();
+        assert_eq!(serde_json::from_str::<ChunkConfig>(&json).unwrap(), cfg);
+
+        let json = serde_json::to_string(&cfg.windows()).unwrap();
+        let windows: Vec<RasterWindow> = serde_json::from_str(&json).unwrap();
+        assert_eq!(windows, cfg.windows());
+
+        // Invalid configs are rejected.
+        let mut value = serde_json::to_value(&cfg).unwrap();
+        value["block_size"] = 0.into();
+        assert!(serde_json::from_value::<ChunkConfig>(value).is_err());
+        let mut value = serde_json::to_value(&cfg).unwrap();
+        value["start"] = 0.into();
+        assert!(serde_json::from_value::<ChunkConfig>(value).is_err());
+        let mut value = serde_json::to_value(&cfg).unwrap();
+        value["end"] = 21.into();
+        assert!(serde_json::from_value::<ChunkConfig>(value).is_err());
+
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(32).unwrap(),
+            NonZeroUsize::new(20).unwrap(),
+        )
+        .add_block_size(NonZeroUsize::new(4).unwrap())
+        .build();
+        let mut value = serde_json::to_value(&cfg).unwrap();
+        value["data_height"] = 6.into();
+        let error = serde_json::from_value::<ChunkConfig>(value).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("not a multiple of the block size"));
+    }
 }
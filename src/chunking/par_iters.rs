@@ -1,19 +1,154 @@
+use super::cancel::CancellationToken;
+use super::progress::{ProgressSink, ProgressTicket, ProgressTracker};
+use super::{Chunk, ChunkConfig};
+use crate::reader::{ChunkReader, ReaderFactory};
+use crate::Result;
 use rayon::iter::Map;
+use rayon::prelude::*;
 use rayon::range::Iter;
+use std::sync::{Arc, Mutex};
 
 impl ChunkConfig {
     /// Create an [`IndexedParallelIterator`] from the configuration.
     ///
     /// This function is only available with the "use-rayon" feature.
-    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = ChunkWindow> {
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = Chunk> {
         let (count, func) = self.iter_mapper();
         (0..count).into_par_iter().map(func)
     }
+
+    /// Create a [`ParallelIterator`] over the chunks accepted
+    /// by `predicate`. See [`ChunkConfig::iter_filtered`].
+    ///
+    /// This function is only available with the "use-rayon" feature.
+    pub fn par_iter_filtered<'a, P>(
+        &'a self,
+        predicate: P,
+    ) -> impl ParallelIterator<Item = Chunk<'a>> + 'a
+    where
+        P: Fn(&Chunk<'a>) -> bool + Send + Sync + 'a,
+    {
+        self.par_iter().filter(move |chunk| predicate(chunk))
+    }
+
+    /// Like [`ChunkConfig::par_iter`], but yields
+    /// [`RasterUtilsError::Cancelled`][crate::RasterUtilsError::Cancelled]
+    /// instead of the chunks that are reached after `token`
+    /// is cancelled. Combine with a short-circuiting
+    /// consumer such as `try_for_each` to stop early.
+    ///
+    /// This function is only available with the "use-rayon" feature.
+    pub fn par_iter_cancellable<'a>(
+        &'a self,
+        token: &'a CancellationToken,
+    ) -> impl IndexedParallelIterator<Item = Result<Chunk<'a>>> + 'a {
+        self.par_iter()
+            .map(move |chunk| token.check().map(|_| chunk))
+    }
+
+    /// Like [`ChunkConfig::par_iter`], but also yields a
+    /// [`ProgressTicket`] per chunk, reporting to `sink`.
+    /// Progress is aggregated across all worker threads.
+    ///
+    /// This function is only available with the "use-rayon" feature.
+    pub fn par_iter_with_progress<'a>(
+        &'a self,
+        sink: &'a dyn ProgressSink,
+    ) -> impl IndexedParallelIterator<Item = (Chunk<'a>, ProgressTicket<'a>)> + 'a {
+        let iter = self.par_iter();
+        let tracker = Arc::new(ProgressTracker::new(sink, iter.len()));
+        iter.map(move |chunk| (chunk, ProgressTicket(tracker.clone())))
+    }
+
+    /// Map `f` over the chunks in parallel, with a reader
+    /// created by `factory` for each worker thread.
+    ///
+    /// Readers are created lazily, on the first chunk of each
+    /// thread of the current rayon pool, and reused for all
+    /// its later chunks. If creating a reader fails, the error
+    /// is yielded for the chunk and creation is retried on the
+    /// next one. Chunks that can't use the reader of their
+    /// thread (when the iterator runs in another pool, or when
+    /// `f` itself runs rayon jobs that reach this iterator)
+    /// get a reader of their own.
+    ///
+    /// This function is only available with the "use-rayon" feature.
+    pub fn par_map_with_reader<'a, RF, F, T, E>(
+        &'a self,
+        factory: &'a RF,
+        f: F,
+    ) -> impl IndexedParallelIterator<Item = std::result::Result<T, E>> + 'a
+    where
+        RF: ReaderFactory,
+        RF::Reader: Send,
+        F: Fn(&RF::Reader, Chunk<'a>) -> std::result::Result<T, E> + Send + Sync + 'a,
+        T: Send,
+        E: From<<RF::Reader as ChunkReader>::Error> + Send,
+    {
+        let readers: Vec<Mutex<Option<RF::Reader>>> = (0..rayon::current_num_threads())
+            .map(|_| Mutex::new(None))
+            .collect();
+        self.par_iter().map(move |chunk| {
+            let slot = rayon::current_thread_index()
+                .and_then(|index| readers.get(index))
+                .and_then(|slot| slot.try_lock().ok());
+            match slot {
+                Some(mut reader) => {
+                    if reader.is_none() {
+                        *reader = Some(factory.create()?);
+                    }
+                    f(reader.as_ref().expect("reader was just created"), chunk)
+                }
+                None => f(&factory.create()?, chunk),
+            }
+        })
+    }
+
+    /// Map each chunk (with its index) to a partial result in
+    /// parallel, and reduce the partials in a fixed tree
+    /// order: pairs of consecutive chunks, then pairs of
+    /// those, and so on.
+    ///
+    /// Unlike `reduce` on a parallel iterator, the order of
+    /// the operations doesn't depend on how rayon splits the
+    /// work, so floating point statistics and checksums are
+    /// reproducible across runs and thread counts. All
+    /// partials are held in memory until reduced. Returns
+    /// `None` if there are no chunks.
+    ///
+    /// This function is only available with the "use-rayon" feature.
+    pub fn reduce_chunks_ordered<'a, T, M, R>(&'a self, map: M, reduce: R) -> Option<T>
+    where
+        T: Send,
+        M: Fn(usize, Chunk<'a>) -> T + Send + Sync,
+        R: Fn(T, T) -> T + Send + Sync,
+    {
+        let mut partials: Vec<T> = self
+            .par_iter()
+            .enumerate()
+            .map(|(index, chunk)| map(index, chunk))
+            .collect();
+        while partials.len() > 1 {
+            let mut pairs: Vec<(T, Option<T>)> = Vec::with_capacity(partials.len().div_ceil(2));
+            let mut iter = partials.into_iter();
+            while let Some(left) = iter.next() {
+                pairs.push((left, iter.next()));
+            }
+            partials = pairs
+                .into_par_iter()
+                .map(|(left, right)| match right {
+                    Some(right) => reduce(left, right),
+                    None => left,
+                })
+                .collect();
+        }
+        partials.pop()
+    }
 }
 
 impl<'a> IntoParallelIterator for &'a ChunkConfig {
-    type Item = ChunkWindow<'a>;
-    type Iter = Map<Iter<usize>, Box<dyn Fn(usize) -> ChunkWindow<'a> + Send + Sync + 'a>>;
+    type Item = Chunk<'a>;
+    type Iter = Map<Iter<usize>, Box<dyn Fn(usize) -> Chunk<'a> + Send + Sync + 'a>>;
 
     fn into_par_iter(self) -> Self::Iter {
         let (count, func) = self.iter_mapper();
@@ -23,16 +158,27 @@ impl<'a> IntoParallelIterator for &'a ChunkConfig {
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroUsize;
+
     use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+
+    fn test_cfg() -> ChunkConfig {
+        ChunkConfigBuilder::new(
+            NonZeroUsize::new(1024).unwrap(),
+            NonZeroUsize::new(1024).unwrap(),
+        )
+        .add_block_size(NonZeroUsize::new(7).unwrap())
+        .with_data_size(NonZeroUsize::new(0x1000).unwrap())
+        .with_padding(3)
+        .with_start(13)
+        .with_end(999)
+        .build()
+    }
 
     #[test]
     fn test_same_output() {
-        let cfg = ChunkConfig::with_dims(1024, 1024)
-            .add_block_size(7)
-            .with_min_data_size(0x1000)
-            .with_padding(3)
-            .with_start(13)
-            .with_end(999);
+        let cfg = test_cfg();
 
         let output1: Vec<_> = cfg
             .into_iter()
@@ -46,4 +192,114 @@ mod tests {
 
         assert_eq!(output1, output2);
     }
+
+    #[test]
+    fn test_same_filtered_output() {
+        let cfg = test_cfg();
+        let predicate = |chunk: &Chunk| chunk.start() % 2 == 0;
+
+        let output1: Vec<_> = cfg.iter_filtered(predicate).collect();
+        let output2: Vec<_> = cfg.par_iter_filtered(predicate).collect();
+
+        assert_eq!(output1, output2);
+    }
+
+    #[test]
+    fn test_par_cancellable() {
+        use crate::RasterUtilsError;
+
+        let cfg = test_cfg();
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = cfg
+            .par_iter_cancellable(&token)
+            .try_for_each(|chunk| chunk.map(|_| ()));
+        assert!(matches!(result, Err(RasterUtilsError::Cancelled)));
+    }
+
+    #[test]
+    fn test_par_map_with_reader() {
+        use crate::geometry::{RasterWindow, Size};
+        use crate::RasterUtilsError;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        /// Reads the row index of each pixel.
+        struct RowReader(Size);
+
+        impl ChunkReader for RowReader {
+            type Error = RasterUtilsError;
+
+            fn raster_size(&self) -> Result<Size> {
+                Ok(self.0)
+            }
+
+            fn read_into_slice_sized<T: crate::reader::Pixel>(
+                &self,
+                out: &mut [T],
+                raster_window: RasterWindow,
+                _buffer_size: Size,
+            ) -> Result<()> {
+                let (cols, _) = raster_window.size();
+                let (_, off_y) = raster_window.validate(self.0)?;
+                for (i, out) in out.iter_mut().enumerate() {
+                    *out = <T as num::NumCast>::from(off_y + i / cols).unwrap();
+                }
+                Ok(())
+            }
+        }
+
+        let cfg = test_cfg();
+        let created = AtomicUsize::new(0);
+        let factory = || {
+            created.fetch_add(1, Ordering::Relaxed);
+            Ok::<_, RasterUtilsError>(RowReader((cfg.width(), cfg.height())))
+        };
+        let starts: Vec<usize> = cfg
+            .par_map_with_reader(&factory, |reader, chunk| {
+                let array = reader.read_chunk::<u32>(chunk)?;
+                Ok::<_, RasterUtilsError>(array[[0, 0]] as usize)
+            })
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(starts, cfg.iter().map(|c| c.start()).collect::<Vec<_>>());
+        assert!(created.into_inner() <= rayon::current_num_threads());
+    }
+
+    #[test]
+    fn test_reduce_chunks_ordered() {
+        let cfg = test_cfg();
+        let partial =
+            |index: usize, chunk: Chunk| 1. / (index as f64 + 1.) + chunk.size() as f64 * 1e-9;
+        let first = cfg.reduce_chunks_ordered(partial, |a, b| a + b).unwrap();
+        for threads in [1, 3, 8] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let sum = pool.install(|| cfg.reduce_chunks_ordered(partial, |a, b| a + b));
+            assert_eq!(sum.map(f64::to_bits), Some(first.to_bits()));
+        }
+
+        let order = cfg
+            .reduce_chunks_ordered(|index, _| vec![index], |a, b| [a, b].concat())
+            .unwrap();
+        assert_eq!(order, (0..cfg.iter().len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_par_progress() {
+        use crate::chunking::progress::Progress;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let cfg = test_cfg();
+        let max_done = AtomicUsize::new(0);
+        let sink = |progress: Progress| {
+            max_done.fetch_max(progress.chunks_done, Ordering::Relaxed);
+        };
+        cfg.par_iter_with_progress(&sink)
+            .for_each(|(_chunk, ticket)| ticket.add_bytes(1));
+
+        assert_eq!(max_done.into_inner(), cfg.iter().len());
+    }
 }
@@ -0,0 +1,158 @@
+//! Progress reporting for chunked processing.
+//!
+//! A [`ProgressSink`] receives a [`Progress`] snapshot
+//! whenever a chunk completes or bytes are read. The counts
+//! are aggregated atomically by a [`ProgressTracker`], so
+//! the same sink may be driven from several (rayon) threads.
+
+use super::{Chunk, ChunkConfig};
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Snapshot of the progress of a chunked job.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of completed chunks.
+    pub chunks_done: usize,
+    /// Total number of chunks.
+    pub chunks_total: usize,
+    /// Number of bytes read so far.
+    pub bytes_read: u64,
+}
+
+/// Receives progress updates.
+///
+/// Updates may arrive concurrently from several threads and
+/// not necessarily in increasing order.
+pub trait ProgressSink: Send + Sync {
+    fn on_progress(&self, progress: Progress);
+}
+
+impl<F> ProgressSink for F
+where
+    F: Fn(Progress) + Send + Sync,
+{
+    fn on_progress(&self, progress: Progress) {
+        self(progress)
+    }
+}
+
+/// Aggregates progress of a job and forwards it to a sink.
+pub struct ProgressTracker<'a> {
+    sink: &'a dyn ProgressSink,
+    chunks_total: usize,
+    chunks_done: AtomicUsize,
+    bytes_read: AtomicU64,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub fn new(sink: &'a dyn ProgressSink, chunks_total: usize) -> Self {
+        ProgressTracker {
+            sink,
+            chunks_total,
+            chunks_done: AtomicUsize::new(0),
+            bytes_read: AtomicU64::new(0),
+        }
+    }
+
+    /// Current progress.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            chunks_done: self.chunks_done.load(Ordering::Relaxed),
+            chunks_total: self.chunks_total,
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Record `count` completed chunks without reporting
+    /// (eg. chunks skipped on resume).
+    pub(crate) fn skip_chunks(&self, count: usize) {
+        self.chunks_done.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Record a completed chunk.
+    pub fn chunk_done(&self) {
+        let chunks_done = self.chunks_done.fetch_add(1, Ordering::Relaxed) + 1;
+        self.sink.on_progress(Progress {
+            chunks_done,
+            ..self.progress()
+        });
+    }
+
+    /// Record `bytes` read.
+    pub fn add_bytes(&self, bytes: u64) {
+        let bytes_read = self.bytes_read.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.sink.on_progress(Progress {
+            bytes_read,
+            ..self.progress()
+        });
+    }
+}
+
+/// Handle yielded with each chunk by the progress
+/// iterators. The chunk is reported as done when the
+/// ticket is dropped.
+pub struct ProgressTicket<'a>(pub(super) Arc<ProgressTracker<'a>>);
+
+impl<'a> ProgressTicket<'a> {
+    /// Record `bytes` read while processing the chunk.
+    pub fn add_bytes(&self, bytes: u64) {
+        self.0.add_bytes(bytes);
+    }
+}
+
+impl<'a> Drop for ProgressTicket<'a> {
+    fn drop(&mut self) {
+        self.0.chunk_done();
+    }
+}
+
+impl ChunkConfig {
+    /// Like [`ChunkConfig::iter`], but also yields a
+    /// [`ProgressTicket`] per chunk, reporting to `sink`.
+    pub fn iter_with_progress<'a>(
+        &'a self,
+        sink: &'a dyn ProgressSink,
+    ) -> impl ExactSizeIterator<Item = (Chunk<'a>, ProgressTicket<'a>)> + 'a {
+        let iter = self.iter();
+        let tracker = Arc::new(ProgressTracker::new(sink, iter.len()));
+        iter.map(move |chunk| (chunk, ProgressTicket(tracker.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use std::{num::NonZeroUsize, sync::Mutex};
+
+    #[test]
+    fn test_iter_with_progress() {
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(10).unwrap(),
+            NonZeroUsize::new(100).unwrap(),
+        )
+        .add_block_size(NonZeroUsize::new(10).unwrap())
+        .build();
+        let total = cfg.iter().len();
+
+        let updates = Mutex::new(vec![]);
+        let sink = |progress: Progress| updates.lock().unwrap().push(progress);
+        for (_chunk, ticket) in cfg.iter_with_progress(&sink) {
+            ticket.add_bytes(100);
+        }
+
+        let updates = updates.into_inner().unwrap();
+        assert_eq!(updates.len(), 2 * total);
+        assert_eq!(
+            updates.last(),
+            Some(&Progress {
+                chunks_done: total,
+                chunks_total: total,
+                bytes_read: 100 * total as u64,
+            })
+        );
+    }
+}
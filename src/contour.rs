@@ -0,0 +1,426 @@
+//! Contour lines of elevation rasters.
+//!
+//! [`for_each_contour`] runs marching squares over the row
+//! chunks of a [`ChunkConfig`], reading one extra row below
+//! each chunk so that cells on chunk boundaries are covered.
+//! Segments are stitched into polylines by the cell edge they
+//! cross, and a polyline is emitted as soon as it can't be
+//! extended by the next chunk: only the open lines ending on
+//! the current chunk boundary are kept in memory.
+//!
+//! Cells are formed by the centers of four neighbouring
+//! pixels; cells with a nodata (or `NaN`) corner are skipped,
+//! so lines stop at nodata. Saddles are resolved with the
+//! mean of the four corners.
+
+use super::chunking::{ChunkConfig, Orientation};
+use super::geometry::{PixelWorldTransform, RasterWindow};
+use super::reader::ChunkReader;
+use super::{RasterUtilsError, Result};
+use geo::{Coord, LineString};
+
+use std::collections::{HashMap, VecDeque};
+
+/// Levels and nodata of [`for_each_contour`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ContourOptions {
+    /// Distance between consecutive levels.
+    pub interval: f64,
+    /// Level from which the intervals are counted: levels are
+    /// `base + k * interval` for integer `k`.
+    pub base: f64,
+    /// Nodata value of the raster, if any.
+    pub nodata: Option<f64>,
+}
+
+impl ContourOptions {
+    /// Levels every `interval` from zero, without nodata.
+    ///
+    /// Errors unless `interval` is finite and positive.
+    pub fn new(interval: f64) -> Result<Self> {
+        let options = ContourOptions {
+            interval,
+            base: 0.,
+            nodata: None,
+        };
+        options.validate()?;
+        Ok(options)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.interval.is_finite() && self.interval > 0. {
+            Ok(())
+        } else {
+            Err(RasterUtilsError::InvalidContourInterval(self.interval))
+        }
+    }
+}
+
+/// A contour line, in world coordinates.
+///
+/// Closed lines have equal first and last points.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contour {
+    pub level: f64,
+    pub line: LineString<f64>,
+}
+
+/// A pixel edge: horizontal between (row, col) and (row, col
+/// + 1), or vertical between (row, col) and (row + 1, col).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Edge {
+    horizontal: bool,
+    row: usize,
+    col: usize,
+}
+
+/// A polyline being built, with the edges of its ends.
+struct OpenLine {
+    level: i64,
+    points: VecDeque<Coord<f64>>,
+    front: Edge,
+    back: Edge,
+}
+
+/// Stitches segments into polylines.
+#[derive(Default)]
+struct Stitcher {
+    lines: HashMap<usize, OpenLine>,
+    ends: HashMap<(i64, Edge), usize>,
+    next_id: usize,
+}
+
+impl Stitcher {
+    /// Add the segment from `a` to `b` at `level`, returning
+    /// the line it closes, if any.
+    fn add(
+        &mut self,
+        level: i64,
+        (a, point_a): (Edge, Coord<f64>),
+        (b, point_b): (Edge, Coord<f64>),
+    ) -> Option<OpenLine> {
+        match (self.ends.remove(&(level, a)), self.ends.remove(&(level, b))) {
+            (None, None) => {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.lines.insert(
+                    id,
+                    OpenLine {
+                        level,
+                        points: VecDeque::from(vec![point_a, point_b]),
+                        front: a,
+                        back: b,
+                    },
+                );
+                self.ends.insert((level, a), id);
+                self.ends.insert((level, b), id);
+            }
+            (Some(id), None) => self.extend(id, a, (b, point_b)),
+            (None, Some(id)) => self.extend(id, b, (a, point_a)),
+            (Some(i), Some(j)) if i == j => {
+                let mut line = self.lines.remove(&i).expect("ends refer to open lines");
+                let first = line.points[0];
+                line.points.push_back(first);
+                return Some(line);
+            }
+            (Some(i), Some(j)) => {
+                let mut line = self.lines.remove(&i).expect("ends refer to open lines");
+                let mut other = self.lines.remove(&j).expect("ends refer to open lines");
+                // Join `line` ending on `a` with `other`
+                // starting on `b`.
+                if line.front == a {
+                    line.points.make_contiguous().reverse();
+                    line.front = line.back;
+                }
+                if other.back == b {
+                    other.points.make_contiguous().reverse();
+                    other.back = other.front;
+                }
+                line.points.extend(other.points);
+                line.back = other.back;
+                self.ends.insert((level, line.back), i);
+                self.lines.insert(i, line);
+            }
+        }
+        None
+    }
+
+    /// Extend line `id` at its end on `edge` to `to`.
+    fn extend(&mut self, id: usize, edge: Edge, (to, point): (Edge, Coord<f64>)) {
+        let line = self.lines.get_mut(&id).expect("ends refer to open lines");
+        if line.front == edge {
+            line.points.push_front(point);
+            line.front = to;
+        } else {
+            line.points.push_back(point);
+            line.back = to;
+        }
+        self.ends.insert((line.level, to), id);
+    }
+
+    /// Remove the lines for which `keep` is false on both
+    /// ends.
+    fn drain<K: Fn(&Edge) -> bool>(&mut self, keep: K) -> Vec<OpenLine> {
+        let done: Vec<usize> = self
+            .lines
+            .iter()
+            .filter(|(_, line)| !keep(&line.front) && !keep(&line.back))
+            .map(|(&id, _)| id)
+            .collect();
+        let mut lines: Vec<(usize, OpenLine)> = done
+            .into_iter()
+            .map(|id| (id, self.lines.remove(&id).expect("listed above")))
+            .collect();
+        // Emit in creation order, for reproducible output.
+        lines.sort_by_key(|(id, _)| *id);
+        lines
+            .into_iter()
+            .map(|(_, line)| {
+                self.ends.remove(&(line.level, line.front));
+                self.ends.remove(&(line.level, line.back));
+                line
+            })
+            .collect()
+    }
+}
+
+/// Call `f` with the contour lines of the raster read by
+/// `reader`, with pixel to world `transform`, over the chunks
+/// of `config`.
+///
+/// The config must iterate over rows without padding, and
+/// the interval of `options` be finite and positive.
+pub fn for_each_contour<R, F>(
+    reader: &R,
+    transform: &PixelWorldTransform,
+    config: &ChunkConfig,
+    options: &ContourOptions,
+    mut f: F,
+) -> Result<()>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    F: FnMut(Contour),
+{
+    options.validate()?;
+    if config.orientation() != Orientation::Rows || config.padding() > 0 {
+        return Err(RasterUtilsError::Unsupported(
+            "contours over column or padded chunks",
+        ));
+    }
+    let (cols, rows) = reader.raster_size().map_err(Into::into)?;
+    let nodata = options.nodata;
+    let mut stitcher = Stitcher::default();
+    let mut emit = |line: OpenLine| {
+        let points = line
+            .points
+            .into_iter()
+            .map(|point| Coord::from(transform.pixel_to_world(point.x_y())));
+        f(Contour {
+            level: options.base + line.level as f64 * options.interval,
+            line: points.collect(),
+        })
+    };
+
+    for chunk in config.iter() {
+        let start = chunk.start();
+        let end = (start + chunk.size()).min(rows);
+        // One more row for the cells on the boundary.
+        let read_end = (end + 1).min(rows);
+        let window: RasterWindow = ((0, start), (cols, read_end - start)).into();
+        let values = reader
+            .read_as_array::<f64>(window)
+            .map_err(Into::into)?
+            .mapv(|v| if Some(v) == nodata { f64::NAN } else { v });
+
+        let (min, max) = values
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            });
+        // Empty for chunks without valid values.
+        let first = ((min - options.base) / options.interval).ceil() as i64;
+        let last = ((max - options.base) / options.interval).floor() as i64;
+
+        for i in 0..read_end.saturating_sub(start + 1) {
+            for j in 0..cols.saturating_sub(1) {
+                let corners = [
+                    values[[i, j]],
+                    values[[i, j + 1]],
+                    values[[i + 1, j + 1]],
+                    values[[i + 1, j]],
+                ];
+                if corners.iter().any(|v| v.is_nan()) {
+                    continue;
+                }
+                let row = start + i;
+                // Corners in (row, col) and the edges between
+                // them: top, right, bottom, left.
+                let positions = [(row, j), (row, j + 1), (row + 1, j + 1), (row + 1, j)];
+                let edges = [
+                    Edge {
+                        horizontal: true,
+                        row,
+                        col: j,
+                    },
+                    Edge {
+                        horizontal: false,
+                        row,
+                        col: j + 1,
+                    },
+                    Edge {
+                        horizontal: true,
+                        row: row + 1,
+                        col: j,
+                    },
+                    Edge {
+                        horizontal: false,
+                        row,
+                        col: j,
+                    },
+                ];
+                let center = corners.iter().sum::<f64>() / 4.;
+
+                for level in first..=last {
+                    let value = options.base + level as f64 * options.interval;
+                    let above = corners.map(|v| v >= value);
+                    let crossing = |k: usize| {
+                        let (a, b) = (k, (k + 1) % 4);
+                        if above[a] == above[b] {
+                            return None;
+                        }
+                        let t = (value - corners[a]) / (corners[b] - corners[a]);
+                        let (pa, pb) = (positions[a], positions[b]);
+                        let x = pa.1 as f64 + t * (pb.1 as f64 - pa.1 as f64) + 0.5;
+                        let y = pa.0 as f64 + t * (pb.0 as f64 - pa.0 as f64) + 0.5;
+                        Some((edges[k], Coord { x, y }))
+                    };
+                    let crossed: Vec<_> = (0..4).filter_map(crossing).collect();
+                    let segments = match crossed.as_slice() {
+                        [a, b] => vec![(*a, *b)],
+                        [top, right, bottom, left] => {
+                            if above[0] == (center >= value) {
+                                // The top left and bottom
+                                // right corners are connected.
+                                vec![(*top, *right), (*bottom, *left)]
+                            } else {
+                                vec![(*left, *top), (*right, *bottom)]
+                            }
+                        }
+                        _ => vec![],
+                    };
+                    for (a, b) in segments {
+                        if let Some(line) = stitcher.add(level, a, b) {
+                            emit(line);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Only lines ending on the boundary row may continue
+        // in the next chunk.
+        for line in stitcher.drain(|edge| edge.horizontal && edge.row == end) {
+            emit(line);
+        }
+    }
+    for line in stitcher.drain(|_| false) {
+        emit(line);
+    }
+    Ok(())
+}
+
+/// Contour lines of the raster read by `reader`, see
+/// [`for_each_contour`].
+pub fn contours<R>(
+    reader: &R,
+    transform: &PixelWorldTransform,
+    config: &ChunkConfig,
+    options: &ContourOptions,
+) -> Result<Vec<Contour>>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    let mut contours = vec![];
+    for_each_contour(reader, transform, config, options, |contour| {
+        contours.push(contour)
+    })?;
+    Ok(contours)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::testing::ArrayReader;
+    use geo::AffineTransform;
+    use ndarray::Array2;
+    use std::num::NonZeroUsize;
+
+    fn test_cfg(width: usize, height: usize) -> ChunkConfig {
+        ChunkConfigBuilder::new(
+            NonZeroUsize::new(width).unwrap(),
+            NonZeroUsize::new(height).unwrap(),
+        )
+        .add_block_size(NonZeroUsize::new(2).unwrap())
+        .build()
+    }
+
+    #[test]
+    fn test_stitched_lines() {
+        let reader = ArrayReader(Array2::from_shape_fn((6, 5), |(_, j)| j as f64));
+        let transform = PixelWorldTransform::new(AffineTransform::identity());
+        let options = ContourOptions {
+            base: 0.5,
+            ..ContourOptions::new(1.).unwrap()
+        };
+        let contours = contours(&reader, &transform, &test_cfg(5, 6), &options).unwrap();
+
+        assert_eq!(contours.len(), 4);
+        for contour in &contours {
+            // One straight line through all the rows.
+            let x = contour.level + 0.5;
+            let mut ys: Vec<f64> = contour.line.coords().map(|c| c.y).collect();
+            ys.sort_by(f64::total_cmp);
+            assert!(contour.line.coords().all(|c| c.x == x));
+            assert_eq!(ys, vec![0.5, 1.5, 2.5, 3.5, 4.5, 5.5]);
+        }
+    }
+
+    #[test]
+    fn test_closed_ring() {
+        let mut dem = Array2::zeros((5, 5));
+        dem[[2, 2]] = 1.;
+        let transform = PixelWorldTransform::new(AffineTransform::identity());
+        let options = ContourOptions {
+            base: 0.5,
+            ..ContourOptions::new(1.).unwrap()
+        };
+        let contours = contours(&ArrayReader(dem), &transform, &test_cfg(5, 5), &options).unwrap();
+
+        assert_eq!(contours.len(), 1);
+        assert!(contours[0].line.is_closed());
+        assert_eq!(contours[0].line.0.len(), 5);
+    }
+
+    #[test]
+    fn test_invalid_interval() {
+        for interval in [0., -1., f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                ContourOptions::new(interval),
+                Err(RasterUtilsError::InvalidContourInterval(_))
+            ));
+        }
+        // Options built field by field are checked too.
+        let reader = ArrayReader(Array2::<f64>::zeros((2, 2)));
+        let transform = PixelWorldTransform::new(AffineTransform::identity());
+        let options = ContourOptions {
+            interval: 0.,
+            base: 0.,
+            nodata: None,
+        };
+        assert!(contours(&reader, &transform, &test_cfg(2, 2), &options).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Chunks of a pixel type only known at run time.
+//!
+//! [`Pixel`][crate::reader::Pixel] covers the real types
+//! that backends read natively. [`DynArray`] holds a chunk
+//! of any of them, of complex types (eg. `CFloat32` SAR
+//! products) as [`Complex`], and with the "f16" feature of
+//! half-precision floats as [`f16`]. Readers dispatching on
+//! the data type of a band return it (see `read_dyn` of the
+//! GDAL and Zarr backends).
+//!
+//! As with GDAL, the nodata value of a complex band applies
+//! to the real part, and the statistics of complex chunks
+//! are those of the magnitudes of the pixels.
+
+use crate::nodata::NodataPolicy;
+use crate::stats::ChunkStats;
+#[cfg(feature = "f16")]
+use half::f16;
+use ndarray::Array2;
+use num::{complex::Complex, ToPrimitive};
+
+/// A chunk of one of the supported pixel types.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DynArray {
+    U8(Array2<u8>),
+    I8(Array2<i8>),
+    U16(Array2<u16>),
+    I16(Array2<i16>),
+    U32(Array2<u32>),
+    I32(Array2<i32>),
+    U64(Array2<u64>),
+    I64(Array2<i64>),
+    #[cfg(feature = "f16")]
+    F16(Array2<f16>),
+    F32(Array2<f32>),
+    F64(Array2<f64>),
+    C32(Array2<Complex<f32>>),
+    C64(Array2<Complex<f64>>),
+}
+
+impl DynArray {
+    /// Shape (rows, cols) of the chunk.
+    pub fn dim(&self) -> (usize, usize) {
+        match self {
+            DynArray::U8(a) => a.dim(),
+            DynArray::I8(a) => a.dim(),
+            DynArray::U16(a) => a.dim(),
+            DynArray::I16(a) => a.dim(),
+            DynArray::U32(a) => a.dim(),
+            DynArray::I32(a) => a.dim(),
+            DynArray::U64(a) => a.dim(),
+            DynArray::I64(a) => a.dim(),
+            #[cfg(feature = "f16")]
+            DynArray::F16(a) => a.dim(),
+            DynArray::F32(a) => a.dim(),
+            DynArray::F64(a) => a.dim(),
+            DynArray::C32(a) => a.dim(),
+            DynArray::C64(a) => a.dim(),
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, DynArray::C32(_) | DynArray::C64(_))
+    }
+
+    /// The values as `f64`, or `None` for complex chunks.
+    pub fn to_real(&self) -> Option<Array2<f64>> {
+        fn real<T: ToPrimitive + Copy>(a: &Array2<T>) -> Array2<f64> {
+            a.mapv(|v| v.to_f64().unwrap_or(f64::NAN))
+        }
+        Some(match self {
+            DynArray::U8(a) => real(a),
+            DynArray::I8(a) => real(a),
+            DynArray::U16(a) => real(a),
+            DynArray::I16(a) => real(a),
+            DynArray::U32(a) => real(a),
+            DynArray::I32(a) => real(a),
+            DynArray::U64(a) => real(a),
+            DynArray::I64(a) => real(a),
+            #[cfg(feature = "f16")]
+            DynArray::F16(a) => real(a),
+            DynArray::F32(a) => real(a),
+            DynArray::F64(a) => a.clone(),
+            DynArray::C32(_) | DynArray::C64(_) => return None,
+        })
+    }
+
+    /// The values as complex numbers, with a zero imaginary
+    /// part for real chunks.
+    pub fn to_complex(&self) -> Array2<Complex<f64>> {
+        match self {
+            DynArray::C32(a) => a.mapv(|v| Complex::new(v.re as f64, v.im as f64)),
+            DynArray::C64(a) => a.clone(),
+            _ => self
+                .to_real()
+                .expect("real chunk")
+                .mapv(|v| Complex::new(v, 0.)),
+        }
+    }
+
+    /// The values of real chunks, and the magnitudes of the
+    /// values of complex ones, as `f64`.
+    pub fn to_magnitude(&self) -> Array2<f64> {
+        self.to_real()
+            .unwrap_or_else(|| self.to_complex().mapv(|v| v.norm()))
+    }
+
+    /// Whether each pixel is nodata under `policy`. Complex
+    /// pixels are nodata if their real part is, or if either
+    /// part is `NaN`.
+    pub fn nodata_mask(&self, nodata: Option<f64>, policy: NodataPolicy) -> Array2<bool> {
+        match self.to_real() {
+            Some(values) => values.mapv(|v| policy.is_nodata(v, nodata)),
+            None => self
+                .to_complex()
+                .mapv(|v| v.im.is_nan() || policy.is_nodata(v.re, nodata)),
+        }
+    }
+
+    /// Statistics of the pixels that are not nodata under
+    /// `policy`; of their magnitudes for complex chunks.
+    pub fn stats(&self, nodata: Option<f64>, policy: NodataPolicy) -> ChunkStats {
+        let mut values = self.to_magnitude();
+        let mask = self.nodata_mask(nodata, policy);
+        values.zip_mut_with(&mask, |v, &masked| {
+            if masked {
+                *v = f64::NAN;
+            }
+        });
+        ChunkStats::from_array(values.view(), None, NodataPolicy::Nan)
+    }
+}
+
+macro_rules! impl_from_array {
+    ($($variant:ident($t:ty)),*) => {
+        $(
+            impl From<Array2<$t>> for DynArray {
+                fn from(array: Array2<$t>) -> Self {
+                    DynArray::$variant(array)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_array!(
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    C32(Complex<f32>),
+    C64(Complex<f64>)
+);
+
+#[cfg(feature = "f16")]
+impl_from_array!(F16(f16));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_complex_stats() {
+        let values = array![
+            [Complex::new(3f32, 4.), Complex::new(-9999., 1.)],
+            [Complex::new(0., f32::NAN), Complex::new(0., 1.)]
+        ];
+        let chunk = DynArray::from(values);
+        assert!(chunk.is_complex());
+        assert_eq!(chunk.to_real(), None);
+        assert_eq!(
+            chunk.nodata_mask(Some(-9999.), NodataPolicy::Exact),
+            array![[false, true], [true, false]]
+        );
+        let stats = chunk.stats(Some(-9999.), NodataPolicy::Exact);
+        assert_eq!((stats.count, stats.min, stats.max), (2, 1., 5.));
+
+        let real = DynArray::from(array![[1u16, 0], [2, 3]]);
+        assert_eq!(real.stats(Some(0.), NodataPolicy::Exact).sum(), 6.);
+        assert_eq!(real.to_complex()[[1, 1]], Complex::new(3., 0.));
+    }
+}
@@ -0,0 +1,545 @@
+//! Lazy, fused raster algebra.
+//!
+//! An [`ExprGraph`] records sources (readers) and the
+//! operations on them as [`Expr`] nodes, built with the
+//! usual operators:
+//!
+//! ```text
+//! let graph = ExprGraph::new();
+//! let (b8, b4) = (graph.source(&nir), graph.source(&red));
+//! let ndvi = (b8 - b4) / (b8 + b4);
+//! ndvi.gt(0.3).write_to(&config, &mut writer)?;
+//! ```
+//!
+//! Nothing is read until an expression is evaluated. Then
+//! each source is read once per window, and the whole
+//! expression is computed in a single loop over the pixels,
+//! without intermediate arrays.
+//!
+//! Values are `f64`. Comparisons and logical operations
+//! return `1` (true) or `0` (false); any non-zero value is
+//! true. NaN is nodata: it propagates through every
+//! operation, comparisons included.
+
+use crate::chunking::Chunk;
+use crate::geometry::RasterWindow;
+use crate::nodata::NodataPolicy;
+use crate::reader::ChunkReader;
+use crate::{RasterUtilsError, Result};
+use ndarray::Array2;
+
+use std::{
+    cell::RefCell,
+    ops::{Add, Div, Mul, Neg, Not, Sub},
+};
+
+type ReadFn<'a> = Box<dyn Fn(RasterWindow) -> Result<Array2<f64>> + 'a>;
+
+/// Arena of the sources and nodes of expressions.
+#[derive(Default)]
+pub struct ExprGraph<'a> {
+    nodes: RefCell<Vec<Node>>,
+    sources: RefCell<Vec<ReadFn<'a>>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Node {
+    Source(usize),
+    Const(f64),
+    Unary(Unary, usize),
+    Binary(Binary, usize, usize),
+    Select(usize, usize, usize),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Unary {
+    Neg,
+    Abs,
+    Sqrt,
+    Not,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Binary {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+impl<'a> ExprGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expression of the values read from `reader`.
+    pub fn source<R>(&self, reader: R) -> Expr<'_, 'a>
+    where
+        R: ChunkReader + 'a,
+        R::Error: Into<RasterUtilsError>,
+    {
+        self.push_source(Box::new(move |window| {
+            reader.read_as_array::<f64>(window).map_err(Into::into)
+        }))
+    }
+
+    /// Like [`source`][Self::source], with the values that
+    /// are `nodata` under `policy` read as NaN (vectorized
+    /// with the "simd" feature).
+    pub fn source_with_nodata<R>(
+        &self,
+        reader: R,
+        nodata: f64,
+        policy: NodataPolicy,
+    ) -> Expr<'_, 'a>
+    where
+        R: ChunkReader + 'a,
+        R::Error: Into<RasterUtilsError>,
+    {
+        self.push_source(Box::new(move |window| {
+            let mut data = reader.read_as_array::<f64>(window).map_err(Into::into)?;
+            #[cfg(feature = "simd")]
+            crate::ops::simd::mask_nodata_array(data.view_mut(), Some(nodata), policy);
+            #[cfg(not(feature = "simd"))]
+            data.mapv_inplace(|v| {
+                if policy.is_nodata(v, Some(nodata)) {
+                    f64::NAN
+                } else {
+                    v
+                }
+            });
+            Ok(data)
+        }))
+    }
+
+    /// Expression of the constant `value`.
+    pub fn constant(&self, value: f64) -> Expr<'_, 'a> {
+        self.push(Node::Const(value))
+    }
+
+    fn push_source(&self, read: ReadFn<'a>) -> Expr<'_, 'a> {
+        let mut sources = self.sources.borrow_mut();
+        sources.push(read);
+        self.push(Node::Source(sources.len() - 1))
+    }
+
+    fn push(&self, node: Node) -> Expr<'_, 'a> {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push(node);
+        Expr {
+            graph: self,
+            id: nodes.len() - 1,
+        }
+    }
+}
+
+/// Node of an [`ExprGraph`].
+///
+/// A cheap handle (`Copy`): operations add nodes to the
+/// graph and return their handle.
+#[derive(Clone, Copy)]
+pub struct Expr<'g, 'a> {
+    graph: &'g ExprGraph<'a>,
+    id: usize,
+}
+
+/// Values usable as operands of an [`Expr`]: expressions of
+/// the same graph and constants.
+pub trait IntoExpr<'g, 'a> {
+    fn into_expr(self, graph: &'g ExprGraph<'a>) -> Expr<'g, 'a>;
+}
+
+impl<'g, 'a> IntoExpr<'g, 'a> for Expr<'g, 'a> {
+    fn into_expr(self, graph: &'g ExprGraph<'a>) -> Expr<'g, 'a> {
+        assert!(
+            std::ptr::eq(self.graph, graph),
+            "expressions of different graphs"
+        );
+        self
+    }
+}
+
+impl<'g, 'a> IntoExpr<'g, 'a> for f64 {
+    fn into_expr(self, graph: &'g ExprGraph<'a>) -> Expr<'g, 'a> {
+        graph.constant(self)
+    }
+}
+
+impl<'g, 'a> Expr<'g, 'a> {
+    fn unary(self, op: Unary) -> Self {
+        self.graph.push(Node::Unary(op, self.id))
+    }
+
+    fn binary(self, op: Binary, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        let rhs = rhs.into_expr(self.graph);
+        self.graph.push(Node::Binary(op, self.id, rhs.id))
+    }
+
+    pub fn abs(self) -> Self {
+        self.unary(Unary::Abs)
+    }
+
+    pub fn sqrt(self) -> Self {
+        self.unary(Unary::Sqrt)
+    }
+
+    pub fn min(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::Min, rhs)
+    }
+
+    pub fn max(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::Max, rhs)
+    }
+
+    pub fn gt(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::Gt, rhs)
+    }
+
+    pub fn ge(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::Ge, rhs)
+    }
+
+    pub fn lt(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::Lt, rhs)
+    }
+
+    pub fn le(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::Le, rhs)
+    }
+
+    pub fn eq(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::Eq, rhs)
+    }
+
+    pub fn ne(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::Ne, rhs)
+    }
+
+    pub fn and(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::And, rhs)
+    }
+
+    pub fn or(self, rhs: impl IntoExpr<'g, 'a>) -> Self {
+        self.binary(Binary::Or, rhs)
+    }
+
+    /// `then` where this expression is true, `otherwise`
+    /// elsewhere.
+    pub fn select(self, then: impl IntoExpr<'g, 'a>, otherwise: impl IntoExpr<'g, 'a>) -> Self {
+        let then = then.into_expr(self.graph);
+        let otherwise = otherwise.into_expr(self.graph);
+        self.graph
+            .push(Node::Select(self.id, then.id, otherwise.id))
+    }
+
+    /// Evaluate the expression over `window` of the sources.
+    pub fn eval(&self, window: RasterWindow) -> Result<Array2<f64>> {
+        let program = Program::compile(self);
+        let sources = self.graph.sources.borrow();
+        let inputs = program
+            .sources
+            .iter()
+            .map(|&source| sources[source](window.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        for input in &inputs {
+            if input.dim() != window.shape() {
+                return Err(RasterUtilsError::Unsupported("sources of different shapes"));
+            }
+        }
+
+        let mut stack = Vec::with_capacity(program.depth);
+        Ok(Array2::from_shape_fn(window.shape(), |index| {
+            program.run(&mut stack, |slot| inputs[slot][index])
+        }))
+    }
+
+    /// Evaluate the expression over the data window of
+    /// `chunk`.
+    pub fn eval_chunk(&self, chunk: Chunk) -> Result<Array2<f64>> {
+        self.eval(chunk.data_window())
+    }
+
+    /// Evaluate the expression over the chunks of `config`,
+    /// writing the data windows to `writer`.
+    #[cfg(feature = "gdal")]
+    pub fn write_to<W>(&self, config: &crate::chunking::ChunkConfig, writer: &mut W) -> Result<()>
+    where
+        W: crate::gdal::writers::ChunkWriter,
+    {
+        for chunk in config {
+            let window = chunk.data_window();
+            let data = self.eval(window.clone())?;
+            writer.write_array(data.view(), window)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'g, 'a, R: IntoExpr<'g, 'a>> Add<R> for Expr<'g, 'a> {
+    type Output = Self;
+
+    fn add(self, rhs: R) -> Self {
+        self.binary(Binary::Add, rhs)
+    }
+}
+
+impl<'g, 'a, R: IntoExpr<'g, 'a>> Sub<R> for Expr<'g, 'a> {
+    type Output = Self;
+
+    fn sub(self, rhs: R) -> Self {
+        self.binary(Binary::Sub, rhs)
+    }
+}
+
+impl<'g, 'a, R: IntoExpr<'g, 'a>> Mul<R> for Expr<'g, 'a> {
+    type Output = Self;
+
+    fn mul(self, rhs: R) -> Self {
+        self.binary(Binary::Mul, rhs)
+    }
+}
+
+impl<'g, 'a, R: IntoExpr<'g, 'a>> Div<R> for Expr<'g, 'a> {
+    type Output = Self;
+
+    fn div(self, rhs: R) -> Self {
+        self.binary(Binary::Div, rhs)
+    }
+}
+
+impl<'g, 'a> Neg for Expr<'g, 'a> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self.unary(Unary::Neg)
+    }
+}
+
+/// Logical negation.
+impl<'g, 'a> Not for Expr<'g, 'a> {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        self.unary(Unary::Not)
+    }
+}
+
+impl<'g, 'a> Add<Expr<'g, 'a>> for f64 {
+    type Output = Expr<'g, 'a>;
+
+    fn add(self, rhs: Expr<'g, 'a>) -> Expr<'g, 'a> {
+        rhs.graph.constant(self) + rhs
+    }
+}
+
+impl<'g, 'a> Sub<Expr<'g, 'a>> for f64 {
+    type Output = Expr<'g, 'a>;
+
+    fn sub(self, rhs: Expr<'g, 'a>) -> Expr<'g, 'a> {
+        rhs.graph.constant(self) - rhs
+    }
+}
+
+impl<'g, 'a> Mul<Expr<'g, 'a>> for f64 {
+    type Output = Expr<'g, 'a>;
+
+    fn mul(self, rhs: Expr<'g, 'a>) -> Expr<'g, 'a> {
+        rhs.graph.constant(self) * rhs
+    }
+}
+
+impl<'g, 'a> Div<Expr<'g, 'a>> for f64 {
+    type Output = Expr<'g, 'a>;
+
+    fn div(self, rhs: Expr<'g, 'a>) -> Expr<'g, 'a> {
+        rhs.graph.constant(self) / rhs
+    }
+}
+
+/// Instruction of a compiled expression, run on a value
+/// stack.
+#[derive(Clone, Copy, Debug)]
+enum Instr {
+    /// Push the pixel of an input slot.
+    Load(usize),
+    Const(f64),
+    Unary(Unary),
+    Binary(Binary),
+    Select,
+}
+
+/// An expression compiled to postfix order, with the
+/// sources it reads.
+struct Program {
+    instrs: Vec<Instr>,
+    /// Sources (by index in the graph) of the input slots.
+    sources: Vec<usize>,
+    /// Maximum stack depth.
+    depth: usize,
+}
+
+impl Program {
+    fn compile(expr: &Expr) -> Self {
+        let nodes = expr.graph.nodes.borrow();
+        let mut program = Program {
+            instrs: vec![],
+            sources: vec![],
+            depth: 0,
+        };
+        program.emit(&nodes, expr.id, 0);
+        program
+    }
+
+    /// Emit node `id` with `depth` values already on the
+    /// stack.
+    fn emit(&mut self, nodes: &[Node], id: usize, depth: usize) {
+        self.depth = self.depth.max(depth + 1);
+        match nodes[id] {
+            Node::Source(source) => {
+                let slot = match self.sources.iter().position(|&s| s == source) {
+                    Some(slot) => slot,
+                    None => {
+                        self.sources.push(source);
+                        self.sources.len() - 1
+                    }
+                };
+                self.instrs.push(Instr::Load(slot));
+            }
+            Node::Const(value) => self.instrs.push(Instr::Const(value)),
+            Node::Unary(op, arg) => {
+                self.emit(nodes, arg, depth);
+                self.instrs.push(Instr::Unary(op));
+            }
+            Node::Binary(op, lhs, rhs) => {
+                self.emit(nodes, lhs, depth);
+                self.emit(nodes, rhs, depth + 1);
+                self.instrs.push(Instr::Binary(op));
+            }
+            Node::Select(cond, then, otherwise) => {
+                self.emit(nodes, cond, depth);
+                self.emit(nodes, then, depth + 1);
+                self.emit(nodes, otherwise, depth + 2);
+                self.instrs.push(Instr::Select);
+            }
+        }
+    }
+
+    /// Value of the expression at a pixel, with the inputs
+    /// given by `load`.
+    fn run<F: Fn(usize) -> f64>(&self, stack: &mut Vec<f64>, load: F) -> f64 {
+        let boolean = |value: bool| if value { 1. } else { 0. };
+        let truth = |v: f64| {
+            if v.is_nan() {
+                f64::NAN
+            } else {
+                boolean(v != 0.)
+            }
+        };
+        // NaN if either operand is, `value` otherwise.
+        let nodata_or = |a: f64, b: f64, value: f64| {
+            if a.is_nan() || b.is_nan() {
+                f64::NAN
+            } else {
+                value
+            }
+        };
+        stack.clear();
+        for instr in &self.instrs {
+            let value = match *instr {
+                Instr::Load(slot) => load(slot),
+                Instr::Const(value) => value,
+                Instr::Unary(op) => {
+                    let v = stack.pop().expect("compiled stack");
+                    match op {
+                        Unary::Neg => -v,
+                        Unary::Abs => v.abs(),
+                        Unary::Sqrt => v.sqrt(),
+                        Unary::Not => 1. - truth(v),
+                    }
+                }
+                Instr::Binary(op) => {
+                    let b = stack.pop().expect("compiled stack");
+                    let a = stack.pop().expect("compiled stack");
+                    match op {
+                        Binary::Add => a + b,
+                        Binary::Sub => a - b,
+                        Binary::Mul => a * b,
+                        Binary::Div => a / b,
+                        Binary::Min => nodata_or(a, b, a.min(b)),
+                        Binary::Max => nodata_or(a, b, a.max(b)),
+                        Binary::Gt => nodata_or(a, b, boolean(a > b)),
+                        Binary::Ge => nodata_or(a, b, boolean(a >= b)),
+                        Binary::Lt => nodata_or(a, b, boolean(a < b)),
+                        Binary::Le => nodata_or(a, b, boolean(a <= b)),
+                        Binary::Eq => nodata_or(a, b, boolean(a == b)),
+                        Binary::Ne => nodata_or(a, b, boolean(a != b)),
+                        Binary::And => truth(a) * truth(b),
+                        Binary::Or => (truth(a) + truth(b)).min(1.),
+                    }
+                }
+                Instr::Select => {
+                    let otherwise = stack.pop().expect("compiled stack");
+                    let then = stack.pop().expect("compiled stack");
+                    let cond = truth(stack.pop().expect("compiled stack"));
+                    if cond.is_nan() {
+                        f64::NAN
+                    } else if cond != 0. {
+                        then
+                    } else {
+                        otherwise
+                    }
+                }
+            };
+            stack.push(value);
+        }
+        stack.pop().expect("compiled stack")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ArrayReader;
+    use ndarray::array;
+
+    #[test]
+    fn test_ndvi() {
+        let nir = ArrayReader(array![[5., 8.], [0., 3.]]);
+        let red = ArrayReader(array![[3., 2.], [0., -1.]]);
+        let graph = ExprGraph::new();
+        let (b8, b4) = (
+            graph.source(&nir),
+            graph.source_with_nodata(&red, -1., NodataPolicy::Exact),
+        );
+        let ndvi = (b8 - b4) / (b8 + b4);
+        let window: RasterWindow = ((0, 0), (2, 2)).into();
+
+        let values = ndvi.eval(window.clone()).unwrap();
+        assert_eq!(values[[0, 0]], 0.25);
+        assert_eq!(values[[0, 1]], 0.6);
+        assert!(values[[1, 0]].is_nan() && values[[1, 1]].is_nan());
+
+        let mask = ndvi.gt(0.3).eval(window.clone()).unwrap();
+        assert_eq!(mask.row(0).to_vec(), vec![0., 1.]);
+        assert!(mask[[1, 1]].is_nan());
+
+        let clamped = (2. * b8 - 1.).min(b4.select(b4, 0.).max(4.));
+        let values = clamped.eval(window.clone()).unwrap();
+        assert_eq!(values.row(0).to_vec(), vec![4., 4.]);
+        assert_eq!(values[[1, 0]], -1.);
+        assert!(values[[1, 1]].is_nan());
+
+        let valid = !ndvi.ne(ndvi);
+        assert_eq!(valid.eval(window).unwrap().row(0).to_vec(), vec![1., 1.]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Chunked comparison of two rasters on the same grid.
+//!
+//! [`diff`] reads both rasters with a [`MultiReader`] and
+//! summarizes the differences of each chunk in a
+//! [`DiffReport`]. [`diff_with_output`] also writes the
+//! per-pixel delta (`a - b`) with a [`ChunkWriter`].
+//!
+//! Use a config without padding, so that each pixel is
+//! compared once.
+
+use super::multi::{MultiArrayReader, MultiReader};
+use super::readers::ChunkReader;
+use super::writers::ChunkWriter;
+use super::{RasterUtilsGdalError, Result};
+use crate::chunking::ChunkConfig;
+use crate::geometry::RasterWindow;
+use crate::testing::Tolerance;
+use crate::RasterUtilsError;
+use gdal::raster::RasterBand;
+use ndarray::{Array2, Zip};
+
+/// Differences within a chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkDiff {
+    /// Index of the chunk in the iteration order.
+    pub index: usize,
+    pub window: RasterWindow,
+    /// Number of pixels that don't match within tolerance.
+    pub differing: usize,
+    /// Number of pixels valid in both rasters.
+    pub compared: usize,
+    /// Maximum absolute difference of the compared pixels.
+    pub max_abs: f64,
+    /// Sum of the absolute differences of the compared
+    /// pixels.
+    pub sum_abs: f64,
+}
+
+impl ChunkDiff {
+    /// Mean absolute difference of the compared pixels (`NaN`
+    /// if none).
+    pub fn mean_abs(&self) -> f64 {
+        self.sum_abs / self.compared as f64
+    }
+}
+
+/// Per-chunk differences between two rasters.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiffReport {
+    pub chunks: Vec<ChunkDiff>,
+}
+
+impl DiffReport {
+    /// Total number of differing pixels.
+    pub fn differing(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.differing).sum()
+    }
+
+    /// Whether all pixels match within tolerance.
+    pub fn is_match(&self) -> bool {
+        self.differing() == 0
+    }
+
+    /// Maximum absolute difference over all chunks.
+    pub fn max_abs(&self) -> f64 {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.max_abs)
+            .fold(0., f64::max)
+    }
+
+    /// Mean absolute difference over all compared pixels.
+    pub fn mean_abs(&self) -> f64 {
+        let (sum, count) = self.chunks.iter().fold((0., 0), |(sum, count), chunk| {
+            (sum + chunk.sum_abs, count + chunk.compared)
+        });
+        sum / count as f64
+    }
+
+    /// Chunks with differing pixels.
+    pub fn differing_chunks(&self) -> impl Iterator<Item = &ChunkDiff> {
+        self.chunks.iter().filter(|chunk| chunk.differing > 0)
+    }
+}
+
+/// Compare the two rasters of `readers` over the chunks of
+/// `config`, reading values as `f64`.
+pub fn diff<R>(
+    readers: &MultiReader<R>,
+    config: &ChunkConfig,
+    tolerance: Tolerance,
+) -> Result<DiffReport>
+where
+    R: ChunkReader<Error = RasterUtilsGdalError>,
+{
+    diff_impl::<R, RasterBand>(readers, config, tolerance, None)
+}
+
+/// Like [`diff`], also writing the per-pixel delta `a - b`
+/// (`f64`, `NaN` where either value is nodata) to `output`.
+pub fn diff_with_output<R, W>(
+    readers: &MultiReader<R>,
+    config: &ChunkConfig,
+    tolerance: Tolerance,
+    output: &mut W,
+) -> Result<DiffReport>
+where
+    R: ChunkReader<Error = RasterUtilsGdalError>,
+    W: ChunkWriter,
+{
+    diff_impl(readers, config, tolerance, Some(output))
+}
+
+fn diff_impl<R, W>(
+    readers: &MultiReader<R>,
+    config: &ChunkConfig,
+    tolerance: Tolerance,
+    mut output: Option<&mut W>,
+) -> Result<DiffReport>
+where
+    R: ChunkReader<Error = RasterUtilsGdalError>,
+    W: ChunkWriter,
+{
+    if readers.readers().len() != 2 {
+        return Err(RasterUtilsError::Unsupported("diff of other than two rasters").into());
+    }
+    let mut report = DiffReport::default();
+    for (index, item) in readers.iter_chunks::<f64>(config).enumerate() {
+        let (chunk, arrays) = item?;
+        let (a, b) = (&arrays[0], &arrays[1]);
+        let mut chunk_diff = ChunkDiff {
+            index,
+            window: chunk.into(),
+            differing: 0,
+            compared: 0,
+            max_abs: 0.,
+            sum_abs: 0.,
+        };
+        let delta: Array2<f64> = Zip::from(a).and(b).map_collect(|&a, &b| {
+            if !tolerance.matches(a, b) {
+                chunk_diff.differing += 1;
+            }
+            if tolerance.is_nodata(a) || tolerance.is_nodata(b) {
+                return f64::NAN;
+            }
+            let delta = a - b;
+            chunk_diff.compared += 1;
+            chunk_diff.max_abs = chunk_diff.max_abs.max(delta.abs());
+            chunk_diff.sum_abs += delta.abs();
+            delta
+        });
+        if let Some(output) = output.as_deref_mut() {
+            output.write_chunk(&delta, chunk)?;
+        }
+        report.chunks.push(chunk_diff);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::gdal::readers::{BandIndex, DatasetReader};
+    use crate::gdal::testing::mem_dataset;
+    use crate::gdal::writers::DatasetWriter;
+    use std::convert::TryFrom;
+    use std::num::NonZeroUsize;
+
+    fn reader(array: &Array2<f64>) -> DatasetReader {
+        DatasetReader(
+            mem_dataset(&[array.clone()]),
+            BandIndex::try_from(1).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = Array2::from_shape_fn((4, 4), |(i, j)| (i * 4 + j) as f64);
+        let mut b = a.clone();
+        b[[2, 1]] += 0.5;
+        b[[3, 3]] -= 2.;
+        b[[3, 0]] = f64::NAN;
+        let multi = MultiReader::from_datasets(vec![reader(&a), reader(&b)]).unwrap();
+        let config =
+            ChunkConfigBuilder::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(4).unwrap())
+                .with_data_height(NonZeroUsize::new(2).unwrap())
+                .build();
+
+        let mut output = DatasetWriter(
+            mem_dataset(&[Array2::<f64>::zeros((4, 4))]),
+            BandIndex::try_from(1).unwrap(),
+        );
+        let report = diff_with_output(&multi, &config, Tolerance::exact(), &mut output).unwrap();
+        assert_eq!(report.chunks.len(), 2);
+        assert_eq!(report.chunks[0].differing, 0);
+        assert_eq!(report.chunks[0].compared, 8);
+        assert_eq!(report.chunks[0].max_abs, 0.);
+        assert_eq!(report.chunks[1].differing, 3);
+        assert_eq!(report.chunks[1].compared, 7);
+        assert_eq!(report.chunks[1].max_abs, 2.);
+        assert_eq!(report.chunks[1].sum_abs, 2.5);
+        assert!(!report.is_match());
+        assert_eq!(report.differing(), 3);
+        assert_eq!(report.max_abs(), 2.);
+        assert_eq!(report.mean_abs(), 2.5 / 15.);
+        let differing: Vec<_> = report.differing_chunks().map(|chunk| chunk.index).collect();
+        assert_eq!(differing, vec![1]);
+
+        // The delta is a - b, NaN where either value is nodata.
+        let written = DatasetReader(output.0, output.1)
+            .read_as_array::<f64>(RasterWindow::from(((0, 0), (4, 4))))
+            .unwrap();
+        let mut expected = Array2::<f64>::zeros((4, 4));
+        expected[[2, 1]] = -0.5;
+        expected[[3, 3]] = 2.;
+        expected[[3, 0]] = f64::NAN;
+        assert!(Zip::from(&written)
+            .and(&expected)
+            .all(|&w, &e| w == e || (w.is_nan() && e.is_nan())));
+
+        // Same rasters, within tolerance.
+        let tolerance = Tolerance {
+            abs: 2.,
+            nodata_equal: true,
+            ..Default::default()
+        };
+        let same = MultiReader::from_datasets(vec![reader(&a), reader(&a)]).unwrap();
+        assert!(diff(&same, &config, tolerance).unwrap().is_match());
+
+        let three = MultiReader::from_datasets(vec![reader(&a), reader(&a), reader(&b)]).unwrap();
+        assert!(matches!(
+            diff(&three, &config, tolerance)
+                .err()
+                .unwrap()
+                .raster_utils_error(),
+            Some(RasterUtilsError::Unsupported(_))
+        ));
+    }
+}
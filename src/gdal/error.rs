@@ -1,12 +1,241 @@
-use gdal::errors::GdalError;
+use super::writers::WriteError;
+use crate::align::AlignmentError;
+use crate::geometry::{RasterWindow, Size, WindowOutOfBounds};
+use crate::ops::convert::ConvertError;
+use crate::RasterUtilsError;
+use gdal::{errors::GdalError, Dataset, Metadata};
 use ndarray::ShapeError;
 
+use std::{fmt, path::PathBuf};
+
 #[derive(thiserror::Error, Debug)]
 pub enum RasterUtilsGdalError {
     #[error(transparent)]
     GdalError(#[from] GdalError),
     #[error(transparent)]
     NdarrayShapeError(#[from] ShapeError),
+    #[error(transparent)]
+    Convert(#[from] ConvertError),
+    /// Errors not specific to GDAL.
+    #[error(transparent)]
+    RasterUtils(Box<RasterUtilsError>),
+    #[error(transparent)]
+    Write(#[from] WriteError),
+    #[error("{source} ({context})")]
+    WithContext {
+        context: ErrorContext,
+        #[source]
+        source: Box<RasterUtilsGdalError>,
+    },
+    #[error("Invalid slice {0:?} of multidimensional array")]
+    InvalidSlice(Vec<u64>),
+    #[error("No subdataset matching {0}")]
+    UnknownSubdataset(String),
+    #[error("Raster {index} has size {found:?}, expected {expected:?}")]
+    SizeMismatch {
+        index: usize,
+        expected: Size,
+        found: Size,
+    },
+    #[error("Raster {index} is not on the grid of the first raster")]
+    GridMismatch {
+        index: usize,
+        #[source]
+        source: AlignmentError,
+    },
+}
+
+impl From<RasterUtilsError> for RasterUtilsGdalError {
+    fn from(error: RasterUtilsError) -> Self {
+        match error {
+            RasterUtilsError::Gdal(error) => error,
+            error => RasterUtilsGdalError::RasterUtils(Box::new(error)),
+        }
+    }
+}
+
+impl From<WindowOutOfBounds> for RasterUtilsGdalError {
+    fn from(error: WindowOutOfBounds) -> Self {
+        RasterUtilsError::from(error).into()
+    }
+}
+
+/// Where an error occurred: dataset path (when known), band
+/// index and requested window.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ErrorContext {
+    pub path: Option<PathBuf>,
+    pub band: Option<usize>,
+    pub window: Option<RasterWindow>,
+}
+
+impl ErrorContext {
+    /// Context of a band of `dataset`. The path is taken from
+    /// the dataset description, when set.
+    pub fn dataset(dataset: &Dataset, band: usize) -> Self {
+        let path = dataset
+            .description()
+            .ok()
+            .filter(|description| !description.is_empty())
+            .map(PathBuf::from);
+        ErrorContext {
+            path,
+            band: Some(band),
+            window: None,
+        }
+    }
+
+    /// Context of a window.
+    pub fn window(window: &RasterWindow) -> Self {
+        ErrorContext {
+            window: Some(window.clone()),
+            ..Default::default()
+        }
+    }
+
+    /// Fill the fields missing in `self` from `other`.
+    fn merge(&mut self, other: ErrorContext) {
+        self.path = self.path.take().or(other.path);
+        self.band = self.band.or(other.band);
+        self.window = self.window.take().or(other.window);
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        if let Some(path) = &self.path {
+            write!(f, "path: {}", path.display())?;
+            sep = ", ";
+        }
+        if let Some(band) = self.band {
+            write!(f, "{}band: {}", sep, band)?;
+            sep = ", ";
+        }
+        if let Some(window) = &self.window {
+            write!(
+                f,
+                "{}window: offset {:?}, size {:?}",
+                sep,
+                window.signed_offset(),
+                window.size()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl RasterUtilsGdalError {
+    /// Attach `context` to the error. Contexts attached by
+    /// outer layers only fill in fields that are missing, so
+    /// errors are never wrapped more than once.
+    pub fn with_context(self, context: ErrorContext) -> Self {
+        match self {
+            RasterUtilsGdalError::WithContext {
+                context: mut inner,
+                source,
+            } => {
+                inner.merge(context);
+                RasterUtilsGdalError::WithContext {
+                    context: inner,
+                    source,
+                }
+            }
+            error => RasterUtilsGdalError::WithContext {
+                context,
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// Context attached to the error, if any.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            RasterUtilsGdalError::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Underlying [`GdalError`], if any (looking through
+    /// attached context).
+    pub fn gdal_error(&self) -> Option<&GdalError> {
+        match self {
+            RasterUtilsGdalError::GdalError(error) => Some(error),
+            RasterUtilsGdalError::WithContext { source, .. } => source.gdal_error(),
+            _ => None,
+        }
+    }
+
+    /// Underlying [`RasterUtilsError`], if any (looking
+    /// through attached context).
+    pub fn raster_utils_error(&self) -> Option<&RasterUtilsError> {
+        match self {
+            RasterUtilsGdalError::RasterUtils(error) => Some(error),
+            RasterUtilsGdalError::WithContext { source, .. } => source.raster_utils_error(),
+            _ => None,
+        }
+    }
+}
+
+/// Attach an [`ErrorContext`] to the error of a result.
+pub trait ResultExt<T> {
+    /// Lazily attach the context returned by `f`.
+    fn context<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> ErrorContext;
+}
+
+impl<T, E> ResultExt<T> for std::result::Result<T, E>
+where
+    E: Into<RasterUtilsGdalError>,
+{
+    fn context<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> ErrorContext,
+    {
+        self.map_err(|error| error.into().with_context(f()))
+    }
 }
 
 pub type Result<T> = std::result::Result<T, RasterUtilsGdalError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_display() {
+        let error: Result<()> = Err(RasterUtilsError::ZeroDimention.into());
+        let error = error
+            .context(|| ErrorContext {
+                window: Some(((0, 64), (256, 32)).into()),
+                ..Default::default()
+            })
+            .context(|| ErrorContext {
+                path: Some("/data/b04.tif".into()),
+                band: Some(1),
+                window: Some(((0, 64), (1, 1)).into()),
+            })
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Encountered an object with zero dimention \
+             (path: /data/b04.tif, band: 1, window: offset (0, 64), size (256, 32))"
+        );
+    }
+
+    #[test]
+    fn test_raster_utils_error() {
+        let error: Result<()> = Err(RasterUtilsError::InvalidBandIndex(0).into());
+        let error = error.context(ErrorContext::default).unwrap_err();
+        assert!(matches!(
+            error.raster_utils_error(),
+            Some(RasterUtilsError::InvalidBandIndex(0))
+        ));
+
+        // Conversions back and forth don't nest the errors.
+        let error =
+            RasterUtilsError::from(RasterUtilsGdalError::from(RasterUtilsError::ZeroDimention));
+        assert!(matches!(error, RasterUtilsError::ZeroDimention));
+    }
+}
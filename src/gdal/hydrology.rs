@@ -0,0 +1,208 @@
+//! Chunked D8 flow direction and flow accumulation.
+//!
+//! Both work over the row chunks of a config without
+//! padding. [`flow_direction`] reads each chunk with a row of
+//! context on either side. [`flow_accumulation`] makes
+//! several passes over the chunks of a flow direction
+//! raster: each pass accumulates the chunks whose inflow
+//! changed, and hands the flow leaving across their first
+//! and last rows to the neighbouring chunks. Once no inflow
+//! changes, a last pass writes the result.
+//!
+//! Flow paths crossing chunk boundaries back and forth need
+//! more passes, so prefer large chunks.
+
+use super::readers::ChunkReader;
+use super::writers::ChunkWriter;
+use super::{RasterUtilsGdalError, Result};
+use crate::chunking::{Chunk, ChunkConfig, Orientation};
+use crate::geometry::RasterWindow;
+use crate::ops::hydrology::{accumulate, flow_direction as flow_direction_kernel, outflow};
+use crate::RasterUtilsError;
+
+fn check_config(config: &ChunkConfig) -> Result<()> {
+    if config.orientation() != Orientation::Rows || config.padding() > 0 {
+        return Err(RasterUtilsError::Unsupported("hydrology over column or padded chunks").into());
+    }
+    Ok(())
+}
+
+/// Compute the D8 flow direction (see
+/// [`ops::hydrology`][crate::ops::hydrology]) of the
+/// elevations read by `dem` into `output` (`u8`), over the
+/// chunks of `config`.
+///
+/// `cell_size` is the pixel size (x, y) in the units of the
+/// elevations.
+pub fn flow_direction<R, W>(
+    dem: &R,
+    config: &ChunkConfig,
+    cell_size: (f64, f64),
+    output: &mut W,
+) -> Result<()>
+where
+    R: ChunkReader<Error = RasterUtilsGdalError>,
+    W: ChunkWriter,
+{
+    check_config(config)?;
+    let width = config.width();
+    for chunk in config {
+        let window =
+            RasterWindow::from_signed((0, chunk.start() as isize - 1), (width, chunk.size() + 2));
+        let elevations = dem.read_chunk_or_fill(window, f64::NAN)?;
+        let directions = flow_direction_kernel(elevations.view(), cell_size);
+        output.write_chunk(&directions, chunk)?;
+    }
+    Ok(())
+}
+
+/// Compute the flow accumulation of the D8 flow directions
+/// read by `directions` into `output` (`f64`), over the
+/// chunks of `config`.
+///
+/// Fails with [`RasterUtilsError::NoConvergence`] if the
+/// boundary flows haven't settled after `max_passes` passes.
+/// Returns the number of passes made, excluding the one
+/// writing the result.
+pub fn flow_accumulation<R, W>(
+    directions: &R,
+    config: &ChunkConfig,
+    output: &mut W,
+    max_passes: usize,
+) -> Result<usize>
+where
+    R: ChunkReader<Error = RasterUtilsGdalError>,
+    W: ChunkWriter,
+{
+    check_config(config)?;
+    let chunks: Vec<Chunk> = config.iter().collect();
+    let width = config.width();
+    // Flow entering each chunk across its first and last
+    // rows.
+    let mut top = vec![vec![0.; width]; chunks.len()];
+    let mut bottom = vec![vec![0.; width]; chunks.len()];
+    let mut dirty = vec![true; chunks.len()];
+
+    let mut passes = 0;
+    while dirty.contains(&true) {
+        if passes == max_passes {
+            return Err(RasterUtilsError::NoConvergence(max_passes).into());
+        }
+        passes += 1;
+        for (k, chunk) in chunks.iter().enumerate() {
+            if !dirty[k] {
+                continue;
+            }
+            dirty[k] = false;
+            let codes = directions.read_chunk::<u8>(*chunk)?;
+            let acc = accumulate(codes.view(), &top[k], &bottom[k]);
+            let (up, down) = outflow(codes.view(), acc.view());
+            if k > 0 && up != bottom[k - 1] {
+                bottom[k - 1] = up;
+                dirty[k - 1] = true;
+            }
+            if k + 1 < chunks.len() && down != top[k + 1] {
+                top[k + 1] = down;
+                dirty[k + 1] = true;
+            }
+        }
+    }
+
+    for (k, chunk) in chunks.iter().enumerate() {
+        let codes = directions.read_chunk::<u8>(*chunk)?;
+        let acc = accumulate(codes.view(), &top[k], &bottom[k]);
+        output.write_chunk(&acc, *chunk)?;
+    }
+    Ok(passes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::gdal::readers::{BandIndex, DatasetReader};
+    use crate::gdal::testing::mem_dataset;
+    use crate::gdal::writers::DatasetWriter;
+    use crate::ops::hydrology::NO_FLOW;
+    use ndarray::{array, Array2};
+    use std::convert::TryFrom;
+    use std::num::NonZeroUsize;
+
+    fn config(data_height: usize) -> ChunkConfigBuilder {
+        ChunkConfigBuilder::new(NonZeroUsize::new(2).unwrap(), NonZeroUsize::new(4).unwrap())
+            .with_data_height(NonZeroUsize::new(data_height).unwrap())
+    }
+
+    fn output() -> DatasetWriter {
+        DatasetWriter(
+            mem_dataset(&[Array2::<f64>::zeros((4, 2))]),
+            BandIndex::try_from(1).unwrap(),
+        )
+    }
+
+    fn read(writer: DatasetWriter) -> Array2<f64> {
+        DatasetReader(writer.0, writer.1)
+            .read_as_array(RasterWindow::from(((0, 0), (2, 4))))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_flow_accumulation() {
+        // The first column drains south into the last row,
+        // then east and north up the second column: the path
+        // crosses the boundary between the chunks twice.
+        let codes = array![[4, NO_FLOW], [4, 64], [4, 64], [1, 64]];
+        let directions = DatasetReader(mem_dataset(&[codes]), BandIndex::try_from(1).unwrap());
+        let expected = array![[1., 8.], [2., 7.], [3., 6.], [4., 5.]];
+
+        let mut writer = output();
+        let passes = flow_accumulation(&directions, &config(2).build(), &mut writer, 10).unwrap();
+        assert_eq!(passes, 2);
+        assert_eq!(read(writer), expected);
+
+        // Same result in a single chunk, in one pass.
+        let mut writer = output();
+        let passes = flow_accumulation(&directions, &config(4).build(), &mut writer, 10).unwrap();
+        assert_eq!(passes, 1);
+        assert_eq!(read(writer), expected);
+
+        assert!(matches!(
+            flow_accumulation(&directions, &config(2).build(), &mut output(), 1)
+                .err()
+                .unwrap()
+                .raster_utils_error(),
+            Some(RasterUtilsError::NoConvergence(1))
+        ));
+        assert!(matches!(
+            flow_accumulation(
+                &directions,
+                &config(2).with_padding(1).build(),
+                &mut output(),
+                10
+            )
+            .err()
+            .unwrap()
+            .raster_utils_error(),
+            Some(RasterUtilsError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_flow_direction() {
+        // Descending from the top left corner, with the
+        // steepest drop to the south east.
+        let dem = Array2::from_shape_fn((4, 2), |(i, j)| 10. - 2. * i as f64 - j as f64);
+        let dem = DatasetReader(mem_dataset(&[dem]), BandIndex::try_from(1).unwrap());
+        let mut writer = DatasetWriter(
+            mem_dataset(&[Array2::<u8>::zeros((4, 2))]),
+            BandIndex::try_from(1).unwrap(),
+        );
+        flow_direction(&dem, &config(2).build(), (1., 1.), &mut writer).unwrap();
+        let directions: Array2<u8> = DatasetReader(writer.0, writer.1)
+            .read_as_array(RasterWindow::from(((0, 0), (2, 4))))
+            .unwrap();
+        // The last row has no lower neighbour: it flows east,
+        // except for its last cell.
+        assert_eq!(directions, array![[2, 4], [2, 4], [2, 4], [1, NO_FLOW]]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Typed snapshot of the metadata of a dataset.
+//!
+//! [`RasterInfo::from_dataset`] gathers, in one call, what
+//! pipelines need to plan chunking and alignment: sizes,
+//! band types and block sizes, nodata, scale and offset,
+//! the geo transform, the CRS and the overview levels.
+
+use super::readers::BandIndex;
+use super::utils::geo_affine_from;
+use super::Result;
+use crate::geometry::{PixelWorldTransform, Size};
+use gdal::{
+    raster::{GdalDataType, RasterBand},
+    Dataset,
+};
+use geo::AffineTransform;
+
+use std::{convert::TryFrom, num::NonZeroUsize};
+
+/// Metadata of a band.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BandInfo {
+    pub index: BandIndex,
+    pub data_type: GdalDataType,
+    pub nodata: Option<f64>,
+    /// Natural block size (x, y).
+    pub block_size: Size,
+    /// Scale of the stored values, if set.
+    pub scale: Option<f64>,
+    /// Offset of the stored values, if set.
+    pub offset: Option<f64>,
+    /// Sizes (x, y) of the overviews, from the largest.
+    pub overviews: Vec<Size>,
+}
+
+impl BandInfo {
+    /// Metadata of `band`, the band `index` of its dataset.
+    pub fn from_band(band: &RasterBand, index: BandIndex) -> Result<Self> {
+        let mut overviews = vec![];
+        for level in 0..band.overview_count()?.max(0) {
+            overviews.push(band.overview(level as usize)?.size());
+        }
+        Ok(BandInfo {
+            index,
+            data_type: band.band_type(),
+            nodata: band.no_data_value(),
+            block_size: band.block_size(),
+            scale: band.scale(),
+            offset: band.offset(),
+            overviews,
+        })
+    }
+}
+
+/// Metadata of a dataset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RasterInfo {
+    /// Raster size (x, y).
+    pub size: Size,
+    pub bands: Vec<BandInfo>,
+    /// Pixel to world transform, if the dataset has one.
+    pub transform: Option<AffineTransform>,
+    /// WKT of the CRS, if any.
+    pub crs_wkt: Option<String>,
+    /// EPSG code of the CRS, if known.
+    pub epsg: Option<u32>,
+}
+
+impl RasterInfo {
+    pub fn from_dataset(dataset: &Dataset) -> Result<Self> {
+        let mut bands = vec![];
+        for index in 1..=dataset.raster_count() {
+            let band = dataset.rasterband(index)?;
+            let index = BandIndex::new(NonZeroUsize::new(index).expect("bands are 1-indexed"));
+            bands.push(BandInfo::from_band(&band, index)?);
+        }
+        let srs = dataset.spatial_ref().ok();
+        let epsg = srs.as_ref().and_then(|srs| {
+            match (srs.auth_name().ok()?.as_str(), srs.auth_code().ok()?) {
+                ("EPSG", code) => u32::try_from(code).ok(),
+                _ => None,
+            }
+        });
+        Ok(RasterInfo {
+            size: dataset.raster_size(),
+            bands,
+            transform: dataset
+                .geo_transform()
+                .ok()
+                .map(|geo_transform| geo_affine_from(&geo_transform)),
+            crs_wkt: srs.and_then(|srs| srs.to_wkt().ok()),
+            epsg,
+        })
+    }
+
+    pub fn band_count(&self) -> usize {
+        self.bands.len()
+    }
+
+    /// Metadata of band `index`, if it exists.
+    pub fn band(&self, index: BandIndex) -> Option<&BandInfo> {
+        self.bands.get(index.get() - 1)
+    }
+
+    /// The transform as a [`PixelWorldTransform`].
+    pub fn pixel_world_transform(&self) -> Option<PixelWorldTransform> {
+        self.transform.map(PixelWorldTransform::new)
+    }
+
+    /// Whether all bands share data type and block size, so
+    /// that a single chunk config fits them all.
+    pub fn is_uniform(&self) -> bool {
+        self.bands.windows(2).all(|pair| {
+            pair[0].data_type == pair[1].data_type && pair[0].block_size == pair[1].block_size
+        })
+    }
+}
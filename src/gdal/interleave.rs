@@ -0,0 +1,279 @@
+//! Reads of several bands of a dataset at once.
+//!
+//! Pixel interleaved (BIP) datasets store all bands of a
+//! pixel together, so each block holds every band: reading
+//! the bands one at a time (eg. with one [`DatasetReader`]
+//! per band) decodes the same blocks once per band.
+//! [`BandsReader`] reads all the requested bands of a window
+//! with a single dataset level `RasterIO` call instead,
+//! when the `INTERLEAVE` metadata of the dataset says it
+//! pays off.
+//!
+//! [`DatasetReader`]: super::readers::DatasetReader
+
+use super::multi::MultiArrayReader;
+use super::readers::{last_cpl_error, BandIndex, ChunkReader, Pixel};
+use super::{ErrorContext, Result, ResultExt};
+use crate::geometry::{RasterWindow, Size};
+use crate::RasterUtilsError;
+use gdal::{raster::GdalType, Dataset, Metadata};
+use ndarray::Array2;
+
+use std::convert::TryFrom;
+
+/// Layout of the bands of a dataset, from its `INTERLEAVE`
+/// metadata (domain `IMAGE_STRUCTURE`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interleave {
+    /// The bands of each pixel are stored together (BIP).
+    Pixel,
+    /// The bands of each row are stored together (BIL).
+    Line,
+    /// Each band is stored separately (BSQ).
+    Band,
+}
+
+impl Interleave {
+    /// The interleaving of `dataset`, if reported by the
+    /// driver.
+    pub fn of(dataset: &Dataset) -> Option<Self> {
+        let value = dataset.metadata_item("INTERLEAVE", "IMAGE_STRUCTURE")?;
+        match value.to_ascii_uppercase().as_str() {
+            "PIXEL" => Some(Interleave::Pixel),
+            "LINE" => Some(Interleave::Line),
+            "BAND" => Some(Interleave::Band),
+            _ => None,
+        }
+    }
+}
+
+/// How a [`BandsReader`] reads its bands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BandReadStrategy {
+    /// [`Interleaved`][Self::Interleaved] for pixel and line
+    /// interleaved datasets, [`PerBand`][Self::PerBand]
+    /// otherwise.
+    #[default]
+    Auto,
+    /// One `RasterIO` call per band.
+    PerBand,
+    /// One dataset level `RasterIO` call for all bands.
+    Interleaved,
+}
+
+/// Reads several bands of a [`Dataset`] on the same window.
+///
+/// Each read (see [`MultiArrayReader`]) returns one array per
+/// band, in the order the bands were given.
+pub struct BandsReader {
+    dataset: Dataset,
+    bands: Vec<BandIndex>,
+    strategy: BandReadStrategy,
+}
+
+impl BandsReader {
+    /// Read `bands` of `dataset`, or all of its bands if
+    /// `bands` is empty, with the
+    /// [`Auto`][BandReadStrategy::Auto] strategy.
+    pub fn new(dataset: Dataset, bands: Vec<BandIndex>) -> Result<Self> {
+        let bands = if bands.is_empty() {
+            (1..=dataset.raster_count())
+                .map(BandIndex::try_from)
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            for band in &bands {
+                band.band_of(&dataset)?;
+            }
+            bands
+        };
+        if bands.is_empty() {
+            return Err(RasterUtilsError::Unsupported("reads without bands").into());
+        }
+        let mut reader = BandsReader {
+            dataset,
+            bands,
+            strategy: BandReadStrategy::Auto,
+        };
+        reader.strategy = reader.resolve(BandReadStrategy::Auto);
+        Ok(reader)
+    }
+
+    /// Force the strategy, eg. to compare their performance.
+    pub fn with_strategy(mut self, strategy: BandReadStrategy) -> Self {
+        self.strategy = self.resolve(strategy);
+        self
+    }
+
+    /// The strategy used, never [`Auto`][BandReadStrategy::Auto].
+    pub fn strategy(&self) -> BandReadStrategy {
+        self.strategy
+    }
+
+    fn resolve(&self, strategy: BandReadStrategy) -> BandReadStrategy {
+        match strategy {
+            BandReadStrategy::Auto => match Interleave::of(&self.dataset) {
+                Some(Interleave::Pixel | Interleave::Line) if self.bands.len() > 1 => {
+                    BandReadStrategy::Interleaved
+                }
+                _ => BandReadStrategy::PerBand,
+            },
+            strategy => strategy,
+        }
+    }
+
+    pub fn dataset(&self) -> &Dataset {
+        &self.dataset
+    }
+
+    pub fn bands(&self) -> &[BandIndex] {
+        &self.bands
+    }
+
+    /// Size (x, y) of the dataset.
+    pub fn raster_size(&self) -> Size {
+        self.dataset.raster_size()
+    }
+
+    /// Read all bands with `GDALDatasetRasterIO`, into a band
+    /// sequential buffer.
+    fn read_interleaved<T>(&self, raster_window: RasterWindow) -> Result<Vec<Array2<T>>>
+    where
+        T: Pixel,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "read_interleaved",
+            window = ?raster_window,
+            bands = self.bands.len(),
+        )
+        .entered();
+
+        let (off_x, off_y) = raster_window.validate(self.raster_size())?;
+        let (size_x, size_y) = raster_window.size();
+        let pixels = size_x * size_y;
+        if pixels == 0 {
+            let empty = Array2::from_shape_vec((size_y, size_x), vec![])?;
+            return Ok(vec![empty; self.bands.len()]);
+        }
+        let band_map: Vec<i32> = self.bands.iter().map(|band| band.get() as i32).collect();
+        let mut data: Vec<T> = Vec::with_capacity(pixels * self.bands.len());
+        let rv = unsafe {
+            gdal_sys::GDALDatasetRasterIO(
+                self.dataset.c_dataset(),
+                gdal_sys::GDALRWFlag::GF_Read,
+                off_x as i32,
+                off_y as i32,
+                size_x as i32,
+                size_y as i32,
+                data.as_mut_ptr() as *mut std::ffi::c_void,
+                size_x as i32,
+                size_y as i32,
+                T::gdal_ordinal(),
+                band_map.len() as i32,
+                band_map.as_ptr() as _,
+                0,
+                0,
+                0,
+            )
+        };
+        if rv != gdal_sys::CPLErr::CE_None {
+            return Err(last_cpl_error(rv)).context(|| ErrorContext::window(&raster_window));
+        }
+        // SAFETY: GDAL filled the whole buffer on success.
+        unsafe { data.set_len(pixels * self.bands.len()) };
+        // Split the bands off the end of the buffer, so the
+        // first one keeps it and each other is moved once.
+        let mut bands = Vec::with_capacity(self.bands.len());
+        for index in (1..self.bands.len()).rev() {
+            bands.push(data.split_off(index * pixels));
+        }
+        bands.push(data);
+        bands
+            .into_iter()
+            .rev()
+            .map(|band| Ok(Array2::from_shape_vec((size_y, size_x), band)?))
+            .collect()
+    }
+}
+
+impl MultiArrayReader for BandsReader {
+    /// Read `raster_window` from every band.
+    fn read_as_arrays<T>(&self, raster_window: RasterWindow) -> Result<Vec<Array2<T>>>
+    where
+        T: Pixel,
+    {
+        match self.strategy {
+            BandReadStrategy::Interleaved => self.read_interleaved(raster_window),
+            _ => self
+                .bands
+                .iter()
+                .map(|band| {
+                    let context = || ErrorContext::dataset(&self.dataset, band.get());
+                    band.band_of(&self.dataset)
+                        .and_then(|band| band.read_as_array(raster_window.clone()))
+                        .context(context)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::gdal::writers::ChunkWriter;
+    use gdal::{cpl::CslStringList, raster::RasterCreationOptions, DriverManager};
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn test_pixel_interleaved() {
+        let path = "/vsimem/interleave_pixel.tif";
+        let (cols, rows) = (7, 5);
+        let bands: Vec<Array2<u16>> = (0..3)
+            .map(|band| {
+                Array2::from_shape_fn((rows, cols), |(i, j)| (band * 100 + i * cols + j) as u16)
+            })
+            .collect();
+        {
+            let driver = DriverManager::get_driver_by_name("GTiff").unwrap();
+            let mut options = CslStringList::new();
+            options.set_name_value("INTERLEAVE", "PIXEL").unwrap();
+            let options: RasterCreationOptions = options;
+            let mut dataset = driver
+                .create_with_band_type_with_options::<u16, _>(path, cols, rows, 3, &options)
+                .unwrap();
+            for (index, array) in bands.iter().enumerate() {
+                let mut band = dataset.rasterband(index + 1).unwrap();
+                band.write_array(array.view(), ((0, 0), (cols, rows)).into())
+                    .unwrap();
+            }
+        }
+
+        let reader = BandsReader::new(Dataset::open(path).unwrap(), vec![]).unwrap();
+        assert_eq!(Interleave::of(reader.dataset()), Some(Interleave::Pixel));
+        assert_eq!(reader.strategy(), BandReadStrategy::Interleaved);
+        let per_band = BandsReader::new(Dataset::open(path).unwrap(), vec![])
+            .unwrap()
+            .with_strategy(BandReadStrategy::PerBand);
+        assert_eq!(per_band.strategy(), BandReadStrategy::PerBand);
+
+        let window: RasterWindow = ((1, 2), (4, 3)).into();
+        let interleaved = reader.read_as_arrays::<u16>(window.clone()).unwrap();
+        assert_eq!(interleaved, per_band.read_as_arrays::<u16>(window).unwrap());
+        assert_eq!(interleaved[2], bands[2].slice(ndarray::s![2..5, 1..5]));
+
+        let config = ChunkConfigBuilder::new(
+            NonZeroUsize::new(cols).unwrap(),
+            NonZeroUsize::new(rows).unwrap(),
+        )
+        .with_data_height(NonZeroUsize::new(2).unwrap())
+        .build();
+        for (item, expected) in reader
+            .iter_chunks::<u16>(&config)
+            .zip(per_band.iter_chunks::<u16>(&config))
+        {
+            assert_eq!(item.unwrap().1, expected.unwrap().1);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! GDAL-backed [`PixelMapper`] for rasters without a geo.
+//! transform.
+//!
+//! Uses GDAL's generic image-to-image transformer, which
+//! supports geo. transforms, RPCs, geolocation arrays and
+//! GCPs on either side.
+
+use super::{RasterUtilsGdalError, Result};
+use crate::align::PixelMapper;
+use gdal::{cpl::CslStringList, errors::GdalError, Dataset};
+
+use std::{cell::RefCell, ffi::c_void, marker::PhantomData, ptr};
+
+/// Georeferencing used to map the pixels of a dataset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Georeferencing {
+    /// Let GDAL pick (geo. transform, then GCPs, RPCs, ...).
+    Auto,
+    GeoTransform,
+    /// Rational polynomial coefficients.
+    Rpc,
+    /// Geolocation arrays.
+    GeolocArray,
+    /// Polynomial fit of the ground control points.
+    GcpPolynomial,
+}
+
+impl Georeferencing {
+    fn method(&self) -> Option<&'static str> {
+        match self {
+            Georeferencing::Auto => None,
+            Georeferencing::GeoTransform => Some("GEOTRANSFORM"),
+            Georeferencing::Rpc => Some("RPC"),
+            Georeferencing::GeolocArray => Some("GEOLOC_ARRAY"),
+            Georeferencing::GcpPolynomial => Some("GCP_POLYNOMIAL"),
+        }
+    }
+}
+
+/// Maps pixels of a source dataset to pixels of a target
+/// dataset using `GDALGenImgProjTransform`.
+///
+/// The transformer is neither [`Send`] nor [`Sync`]; create
+/// one per thread.
+pub struct GdalPixelMapper<'a> {
+    transformer: *mut c_void,
+    /// Scratch buffers for the single-point calls.
+    scratch: RefCell<[f64; 3]>,
+    _datasets: PhantomData<&'a Dataset>,
+}
+
+impl<'a> GdalPixelMapper<'a> {
+    /// Create a mapper from pixels of `source` to pixels of
+    /// `target`, using the given georeferencing on each side.
+    pub fn new(
+        source: &'a Dataset,
+        source_method: Georeferencing,
+        target: &'a Dataset,
+        target_method: Georeferencing,
+    ) -> Result<Self> {
+        let mut options = CslStringList::new();
+        if let Some(method) = source_method.method() {
+            options.set_name_value("SRC_METHOD", method)?;
+        }
+        if let Some(method) = target_method.method() {
+            options.set_name_value("DST_METHOD", method)?;
+        }
+
+        let transformer = unsafe {
+            gdal_sys::GDALCreateGenImgProjTransformer2(
+                source.c_dataset(),
+                target.c_dataset(),
+                options.as_ptr(),
+            )
+        };
+        if transformer.is_null() {
+            return Err(RasterUtilsGdalError::GdalError(GdalError::NullPointer {
+                method_name: "GDALCreateGenImgProjTransformer2",
+                msg: "could not create pixel transformer".into(),
+            }));
+        }
+        Ok(GdalPixelMapper {
+            transformer,
+            scratch: RefCell::new([0.; 3]),
+            _datasets: PhantomData,
+        })
+    }
+
+    /// Map a batch of pixels in place. Returns, for each
+    /// pixel, whether the transformation succeeded.
+    pub fn map_pixels(&self, xs: &mut [f64], ys: &mut [f64]) -> Vec<bool> {
+        assert_eq!(xs.len(), ys.len());
+        let mut zs = vec![0.; xs.len()];
+        let mut success = vec![0; xs.len()];
+        unsafe {
+            gdal_sys::GDALGenImgProjTransform(
+                self.transformer,
+                0,
+                xs.len() as i32,
+                xs.as_mut_ptr(),
+                ys.as_mut_ptr(),
+                zs.as_mut_ptr(),
+                success.as_mut_ptr(),
+            );
+        }
+        success.into_iter().map(|s| s != 0).collect()
+    }
+}
+
+impl<'a> PixelMapper for GdalPixelMapper<'a> {
+    fn map_pixel(&self, pixel: (f64, f64)) -> Option<(f64, f64)> {
+        let mut scratch = self.scratch.borrow_mut();
+        let [x, y, z] = &mut *scratch;
+        *x = pixel.0;
+        *y = pixel.1;
+        *z = 0.;
+        let mut success = 0;
+        unsafe {
+            gdal_sys::GDALGenImgProjTransform(self.transformer, 0, 1, x, y, z, &mut success);
+        }
+        (success != 0).then_some((*x, *y))
+    }
+}
+
+impl<'a> Drop for GdalPixelMapper<'a> {
+    fn drop(&mut self) {
+        unsafe { gdal_sys::GDALDestroyGenImgProjTransformer(self.transformer) };
+        self.transformer = ptr::null_mut();
+    }
+}
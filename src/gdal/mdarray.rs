@@ -0,0 +1,270 @@
+//! Chunked reads of 2D slices of multidimensional arrays.
+//!
+//! Formats such as NetCDF and Zarr are exposed by GDAL's
+//! multidimensional API as [`MDArray`]s rather than bands.
+//! [`MdArrayReader`] selects a 2D slice of such an array by
+//! fixing an index on every other dimension, and reads it
+//! like a band.
+
+use super::readers::{transpose_into, ChunkReader, Pixel};
+use super::{RasterUtilsGdalError, Result};
+use crate::chunking::builder::ChunkConfigBuilder;
+use crate::geometry::{RasterWindow, Size};
+use crate::RasterUtilsError;
+use gdal::{errors::GdalError, raster::MDArray};
+
+use std::num::NonZeroUsize;
+
+/// A [`ChunkReader`] over a 2D slice of an [`MDArray`].
+pub struct MdArrayReader<'a> {
+    array: MDArray<'a>,
+    /// Index of the dimension used as rows.
+    y_dim: usize,
+    /// Index of the dimension used as columns.
+    x_dim: usize,
+    /// Fixed index on every dimension. Entries of the x and
+    /// y dimensions are ignored.
+    index: Vec<u64>,
+    /// Sizes of the dimensions.
+    sizes: Vec<usize>,
+}
+
+impl<'a> MdArrayReader<'a> {
+    /// Read the slice of `array` at `index` (one entry per
+    /// dimension), using the last two dimensions as rows
+    /// and columns.
+    pub fn new(array: MDArray<'a>, index: Vec<u64>) -> Result<Self> {
+        let sizes: Vec<usize> = array
+            .dimensions()?
+            .iter()
+            .map(|dimension| dimension.size())
+            .collect();
+        let ndims = sizes.len();
+        if ndims < 2 || index.len() != ndims {
+            return Err(RasterUtilsGdalError::InvalidSlice(index));
+        }
+        MdArrayReader {
+            array,
+            y_dim: ndims - 2,
+            x_dim: ndims - 1,
+            index,
+            sizes,
+        }
+        .with_axes(ndims - 2, ndims - 1)
+    }
+
+    /// Use dimensions `y_dim` and `x_dim` as rows and
+    /// columns.
+    pub fn with_axes(mut self, y_dim: usize, x_dim: usize) -> Result<Self> {
+        let ndims = self.sizes.len();
+        if y_dim == x_dim || y_dim >= ndims || x_dim >= ndims {
+            return Err(RasterUtilsGdalError::InvalidSlice(self.index));
+        }
+        let in_bounds = self
+            .index
+            .iter()
+            .zip(&self.sizes)
+            .enumerate()
+            .all(|(dim, (&index, &size))| dim == y_dim || dim == x_dim || (index as usize) < size);
+        if !in_bounds {
+            return Err(RasterUtilsGdalError::InvalidSlice(self.index));
+        }
+        self.y_dim = y_dim;
+        self.x_dim = x_dim;
+        Ok(self)
+    }
+
+    /// Native block sizes of the array, per dimension (`0`
+    /// where unknown).
+    pub fn block_size(&self) -> Result<Vec<usize>> {
+        let mut count = 0;
+        let sizes =
+            unsafe { gdal_sys::GDALMDArrayGetBlockSize(self.array.c_mdarray(), &mut count) };
+        if sizes.is_null() {
+            return Err(GdalError::NullPointer {
+                method_name: "GDALMDArrayGetBlockSize",
+                msg: "could not get block size".into(),
+            }
+            .into());
+        }
+        let block_size = unsafe { std::slice::from_raw_parts(sizes, count) }
+            .iter()
+            .map(|&size| size as usize)
+            .collect();
+        unsafe { gdal_sys::VSIFree(sizes as *mut std::ffi::c_void) };
+        Ok(block_size)
+    }
+
+    /// Create a [`ChunkConfigBuilder`] for the slice, using the
+    /// native block sizes of the x and y dimensions.
+    pub fn chunk_config_builder(&self) -> Result<ChunkConfigBuilder> {
+        let (width, height) = NonZeroUsize::new(self.sizes[self.x_dim])
+            .zip(NonZeroUsize::new(self.sizes[self.y_dim]))
+            .ok_or(RasterUtilsError::ZeroDimention)?;
+        let mut builder = ChunkConfigBuilder::new(width, height);
+        let block_size = self.block_size()?;
+        if let Some(block_shape) =
+            NonZeroUsize::new(block_size[self.x_dim]).zip(NonZeroUsize::new(block_size[self.y_dim]))
+        {
+            builder = builder.add_block_shape(block_shape);
+        }
+        Ok(builder)
+    }
+}
+
+impl<'a> ChunkReader for MdArrayReader<'a> {
+    type Error = RasterUtilsGdalError;
+
+    fn raster_size(&self) -> Result<Size> {
+        Ok((self.sizes[self.x_dim], self.sizes[self.y_dim]))
+    }
+
+    /// Resampling is not supported by the multidimensional
+    /// API: `buffer_size` must match the window size.
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        let (off_x, off_y) = raster_window.validate(self.raster_size()?)?;
+        let (cols, rows) = raster_window.size();
+        if buffer_size != (cols, rows) {
+            return Err(RasterUtilsError::Unsupported(
+                "resampled reads of multidimensional arrays",
+            )
+            .into());
+        }
+
+        let mut start = self.index.clone();
+        let mut count = vec![1; start.len()];
+        start[self.y_dim] = off_y as u64;
+        start[self.x_dim] = off_x as u64;
+        count[self.y_dim] = rows;
+        count[self.x_dim] = cols;
+
+        if self.y_dim < self.x_dim {
+            self.array.read_into_slice(out, start, count)?;
+        } else {
+            // Slice is read column-major.
+            let mut buf = out.to_vec();
+            self.array.read_into_slice(&mut buf, start, count)?;
+            transpose_into(&buf, out, (cols, rows));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gdal::{cpl::CslStringList, Dataset, DriverManager};
+    use ndarray::{array, Array2};
+    use std::ffi::{c_void, CString};
+    use std::ptr::null;
+
+    /// MEM dataset with a `UInt16` array `v` of dimensions
+    /// (time, y, x) = (2, 3, 4), holding `0..24`.
+    fn multidim_dataset() -> Dataset {
+        let driver = DriverManager::get_driver_by_name("MEM").unwrap();
+        let name = CString::new("").unwrap();
+        unsafe {
+            let c_dataset = gdal_sys::GDALCreateMultiDimensional(
+                driver.c_driver(),
+                name.as_ptr(),
+                null::<c_void>() as _,
+                null::<c_void>() as _,
+            );
+            assert!(!c_dataset.is_null());
+            let dataset = Dataset::from_c_dataset(c_dataset);
+
+            let group = gdal_sys::GDALDatasetGetRootGroup(c_dataset);
+            let mut dimensions: Vec<_> = [("time", 2u64), ("y", 3), ("x", 4)]
+                .iter()
+                .map(|&(name, size)| {
+                    let name = CString::new(name).unwrap();
+                    gdal_sys::GDALGroupCreateDimension(
+                        group,
+                        name.as_ptr(),
+                        null::<c_void>() as _,
+                        null::<c_void>() as _,
+                        size,
+                        null::<c_void>() as _,
+                    )
+                })
+                .collect();
+            let data_type =
+                gdal_sys::GDALExtendedDataTypeCreate(gdal_sys::GDALDataType::GDT_UInt16);
+            let name = CString::new("v").unwrap();
+            let array = gdal_sys::GDALGroupCreateMDArray(
+                group,
+                name.as_ptr(),
+                dimensions.len(),
+                dimensions.as_mut_ptr(),
+                data_type,
+                null::<c_void>() as _,
+            );
+            assert!(!array.is_null());
+
+            let values: Vec<u16> = (0..24).collect();
+            let start = [0u64; 3];
+            let count = [2usize, 3, 4];
+            let written = gdal_sys::GDALMDArrayWrite(
+                array,
+                start.as_ptr() as _,
+                count.as_ptr() as _,
+                null::<c_void>() as _,
+                null::<c_void>() as _,
+                data_type,
+                values.as_ptr() as _,
+                values.as_ptr() as _,
+                values.len() * 2,
+            );
+            assert!(written != 0);
+
+            gdal_sys::GDALMDArrayRelease(array);
+            gdal_sys::GDALExtendedDataTypeRelease(data_type);
+            for dimension in dimensions {
+                gdal_sys::GDALDimensionRelease(dimension);
+            }
+            gdal_sys::GDALGroupRelease(group);
+            dataset
+        }
+    }
+
+    #[test]
+    fn test_read_slice() {
+        let dataset = multidim_dataset();
+        let group = dataset.root_group().unwrap();
+        let open = || group.open_md_array("v", CslStringList::new()).unwrap();
+
+        let reader = MdArrayReader::new(open(), vec![1, 0, 0]).unwrap();
+        assert_eq!(reader.raster_size().unwrap(), (4, 3));
+        let window: RasterWindow = ((1, 1), (2, 2)).into();
+        let values = reader.read_as_array::<u16>(window).unwrap();
+        assert_eq!(values, array![[17, 18], [21, 22]]);
+
+        // Rows along x: the slice is transposed.
+        let transposed = MdArrayReader::new(open(), vec![0, 0, 0])
+            .unwrap()
+            .with_axes(2, 1)
+            .unwrap();
+        assert_eq!(transposed.raster_size().unwrap(), (3, 4));
+        let window: RasterWindow = ((0, 0), (3, 4)).into();
+        let values = transposed.read_as_array::<u16>(window).unwrap();
+        let expected = Array2::from_shape_fn((4, 3), |(i, j)| (j * 4 + i) as u16);
+        assert_eq!(values, expected);
+
+        assert!(matches!(
+            MdArrayReader::new(open(), vec![0, 0]),
+            Err(RasterUtilsGdalError::InvalidSlice(_))
+        ));
+        assert!(matches!(
+            MdArrayReader::new(open(), vec![2, 0, 0]),
+            Err(RasterUtilsGdalError::InvalidSlice(_))
+        ));
+    }
+}
@@ -1,5 +1,28 @@
+pub mod diff;
 pub mod error;
+pub mod hydrology;
+pub mod info;
+pub mod interleave;
+pub mod mapper;
+pub mod mdarray;
+pub mod multi;
+pub mod open;
+pub mod presets;
+pub mod processors;
 pub mod readers;
+pub mod registry;
+pub mod retry;
+pub mod scan;
+pub mod stack;
+pub mod subdatasets;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod translate;
 pub mod utils;
+pub mod vrt;
+pub mod writers;
 
-pub use error::{RasterUtilsGdalError, Result};
+pub use error::{ErrorContext, RasterUtilsGdalError, Result, ResultExt};
+pub use subdatasets::{open_subdataset, subdatasets, SubdatasetInfo};
+pub use translate::{copy_raster, CopyOptions};
+pub use vrt::{build_vrt, build_vrt_with_nodata, VrtOptions};
@@ -0,0 +1,217 @@
+//! Synchronized reads from several rasters on the same grid.
+
+use super::readers::{ChunkReader, DatasetReader, Pixel};
+use super::utils::pixel_world_transform;
+use super::{RasterUtilsGdalError, Result};
+use crate::align::{check_grid_compatibility, AlignmentError};
+use crate::chunking::{Chunk, ChunkConfig};
+use crate::geometry::{RasterWindow, Size};
+use crate::RasterUtilsError;
+use ndarray::Array2;
+
+/// Tolerance (in pixels) on the offset between grids.
+const GRID_TOLERANCE: f64 = 1e-6;
+
+/// Reads of several arrays on the same window, eg. one per
+/// raster of a [`MultiReader`] or one per band of a
+/// [`BandsReader`][super::interleave::BandsReader].
+pub trait MultiArrayReader {
+    /// Read `raster_window` into one array per raster (or
+    /// band).
+    fn read_as_arrays<T>(&self, raster_window: RasterWindow) -> Result<Vec<Array2<T>>>
+    where
+        T: Pixel;
+
+    /// Helper to read every array at the location of an
+    /// output of [`ChunkConfig`] iterator.
+    fn read_chunk<T>(&self, chunk: Chunk) -> Result<Vec<Array2<T>>>
+    where
+        T: Pixel,
+    {
+        self.read_as_arrays(chunk.into())
+    }
+
+    /// Iterate over the chunks of `config`, reading every
+    /// array for each chunk.
+    fn iter_chunks<'a, T>(
+        &'a self,
+        config: &'a ChunkConfig,
+    ) -> impl Iterator<Item = Result<(Chunk<'a>, Vec<Array2<T>>)>> + 'a
+    where
+        T: Pixel,
+    {
+        config
+            .iter()
+            .map(move |chunk| Ok((chunk, self.read_chunk(chunk)?)))
+    }
+}
+
+/// Group of [`ChunkReader`]s sharing the same grid.
+///
+/// Each read returns one array per reader, in the order the
+/// readers were given.
+pub struct MultiReader<R> {
+    readers: Vec<R>,
+    size: Size,
+}
+
+impl<R: ChunkReader<Error = RasterUtilsGdalError>> MultiReader<R> {
+    /// Group `readers`, checking that they all have the same
+    /// raster size.
+    pub fn new(readers: Vec<R>) -> Result<Self> {
+        let mut size = None;
+        for (index, reader) in readers.iter().enumerate() {
+            let found = reader.raster_size()?;
+            match size {
+                None => size = Some(found),
+                Some(expected) if expected != found => {
+                    return Err(RasterUtilsGdalError::SizeMismatch {
+                        index,
+                        expected,
+                        found,
+                    })
+                }
+                _ => {}
+            }
+        }
+        let size = size.ok_or(RasterUtilsError::ZeroDimention)?;
+        Ok(MultiReader { readers, size })
+    }
+
+    pub fn readers(&self) -> &[R] {
+        &self.readers
+    }
+
+    /// Size (x, y) shared by all rasters.
+    pub fn raster_size(&self) -> Size {
+        self.size
+    }
+}
+
+impl<R: ChunkReader<Error = RasterUtilsGdalError>> MultiArrayReader for MultiReader<R> {
+    /// Read `raster_window` from every reader.
+    fn read_as_arrays<T>(&self, raster_window: RasterWindow) -> Result<Vec<Array2<T>>>
+    where
+        T: Pixel,
+    {
+        self.readers
+            .iter()
+            .map(|reader| reader.read_as_array(raster_window.clone()))
+            .collect()
+    }
+}
+
+impl MultiReader<DatasetReader> {
+    /// Group dataset readers, checking that they have the same
+    /// raster size and geo transform.
+    pub fn from_datasets(readers: Vec<DatasetReader>) -> Result<Self> {
+        let multi = Self::new(readers)?;
+        let mut transforms = multi
+            .readers
+            .iter()
+            .map(|reader| pixel_world_transform(&reader.0));
+        if let Some(first) = transforms.next() {
+            let first = first?;
+            for (index, transform) in transforms.enumerate() {
+                let transform = transform?;
+                let index = index + 1;
+                check_grid_compatibility((&first, multi.size), (&transform, multi.size)).map_err(
+                    |source: AlignmentError| RasterUtilsGdalError::GridMismatch { index, source },
+                )?;
+
+                // Same grid, but possibly shifted by whole
+                // pixels. The inverse exists, as checked above.
+                let origin = transform.affine();
+                if let Ok((x, y)) = first.world_to_pixel((origin.xoff(), origin.yoff())) {
+                    if x.abs() > GRID_TOLERANCE || y.abs() > GRID_TOLERANCE {
+                        return Err(RasterUtilsGdalError::GridMismatch {
+                            index,
+                            source: AlignmentError::PixelOffset(x, y),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(multi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::gdal::readers::BandIndex;
+    use crate::gdal::testing::mem_dataset;
+    use std::convert::TryFrom;
+    use std::num::NonZeroUsize;
+
+    fn reader(array: &Array2<u8>) -> DatasetReader {
+        DatasetReader(
+            mem_dataset(&[array.clone()]),
+            BandIndex::try_from(1).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let a = Array2::from_shape_fn((5, 4), |(i, j)| (i * 4 + j) as u8);
+        let b = a.mapv(|v| v + 100);
+        let multi = MultiReader::from_datasets(vec![reader(&a), reader(&b)]).unwrap();
+        assert_eq!(multi.raster_size(), (4, 5));
+
+        let config =
+            ChunkConfigBuilder::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(5).unwrap())
+                .with_data_height(NonZeroUsize::new(2).unwrap())
+                .build();
+        let mut rows = 0;
+        for item in multi.iter_chunks::<u8>(&config) {
+            let (chunk, arrays) = item.unwrap();
+            let window = RasterWindow::from(chunk);
+            let (_, off_y) = chunk.offset();
+            let (size_x, size_y) = window.size();
+            let expected = a.slice(ndarray::s![off_y..off_y + size_y, ..size_x]);
+            assert_eq!(arrays.len(), 2);
+            assert_eq!(arrays[0], expected);
+            assert_eq!(arrays[1], expected.mapv(|v| v + 100));
+            rows += size_y;
+        }
+        assert_eq!(rows, 5);
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let a = Array2::<u8>::zeros((5, 4));
+        assert!(matches!(
+            MultiReader::new(vec![reader(&a), reader(&Array2::zeros((5, 3)))]),
+            Err(RasterUtilsGdalError::SizeMismatch {
+                index: 1,
+                expected: (4, 5),
+                found: (3, 5),
+            })
+        ));
+
+        // Same grid, shifted by one pixel.
+        let mut shifted = reader(&a);
+        shifted
+            .0
+            .set_geo_transform(&[1., 1., 0., 5., 0., -1.])
+            .unwrap();
+        assert!(matches!(
+            MultiReader::from_datasets(vec![reader(&a), shifted]),
+            Err(RasterUtilsGdalError::GridMismatch {
+                index: 1,
+                source: AlignmentError::PixelOffset(..)
+            })
+        ));
+
+        let mut coarser = reader(&a);
+        coarser
+            .0
+            .set_geo_transform(&[0., 2., 0., 5., 0., -2.])
+            .unwrap();
+        assert!(matches!(
+            MultiReader::from_datasets(vec![reader(&a), coarser]),
+            Err(RasterUtilsGdalError::GridMismatch { index: 1, .. })
+        ));
+    }
+}
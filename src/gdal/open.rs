@@ -0,0 +1,110 @@
+//! Open datasets with GDAL open and config options.
+//!
+//! Remote sources (`/vsicurl/`, `/vsis3/`, ...) are usually
+//! tuned through GDAL config options. Instead of mutating the
+//! process-global configuration, [`OpenOptions`] sets them as
+//! _thread-local_ config options for the duration of an
+//! operation, and restores the previous values afterwards.
+
+use super::{ErrorContext, Result, ResultExt};
+use gdal::{config, Dataset, DatasetOptions, GdalOpenFlags};
+
+use std::path::Path;
+
+/// Options used to open (and read) a dataset.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct OpenOptions {
+    /// Driver specific open options, as `KEY=VALUE`.
+    open_options: Vec<String>,
+    /// Thread-local config options (key, value).
+    config_options: Vec<(String, String)>,
+    /// Drivers allowed to open the dataset (all if empty).
+    allowed_drivers: Vec<String>,
+}
+
+impl OpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a driver specific open option.
+    pub fn with_open_option(mut self, key: &str, value: &str) -> Self {
+        self.open_options.push(format!("{}={}", key, value));
+        self
+    }
+
+    /// Add a config option (eg. `GDAL_HTTP_MAX_RETRY`,
+    /// `AWS_PROFILE`), set for the calling thread only.
+    pub fn with_config_option(mut self, key: &str, value: &str) -> Self {
+        self.config_options.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Restrict the drivers used to open the dataset.
+    pub fn with_allowed_driver(mut self, driver: &str) -> Self {
+        self.allowed_drivers.push(driver.to_owned());
+        self
+    }
+
+    /// Run `f` with the config options set on the calling
+    /// thread. Previous thread-local values are restored
+    /// afterwards, even if `f` panics.
+    pub fn scoped<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _guard = ConfigGuard::set(&self.config_options);
+        f()
+    }
+
+    /// Open the dataset at `path` (read-only, raster mode).
+    ///
+    /// Only the open runs with the config options set. Reads
+    /// from remote datasets may need them too: wrap those in
+    /// [`OpenOptions::scoped`].
+    pub fn open(&self, path: &Path) -> Result<Dataset> {
+        let open_options: Vec<&str> = self.open_options.iter().map(String::as_str).collect();
+        let allowed_drivers: Vec<&str> = self.allowed_drivers.iter().map(String::as_str).collect();
+        let options = DatasetOptions {
+            open_flags: GdalOpenFlags::GDAL_OF_RASTER | GdalOpenFlags::GDAL_OF_READONLY,
+            allowed_drivers: (!allowed_drivers.is_empty()).then_some(&allowed_drivers[..]),
+            open_options: (!open_options.is_empty()).then_some(&open_options[..]),
+            sibling_files: None,
+        };
+        self.scoped(|| Dataset::open_ex(path, options))
+            .context(|| ErrorContext {
+                path: Some(path.to_path_buf()),
+                ..Default::default()
+            })
+    }
+}
+
+/// Restores thread-local config options on drop.
+struct ConfigGuard {
+    /// Previous (key, value), empty if unset.
+    previous: Vec<(String, String)>,
+}
+
+impl ConfigGuard {
+    fn set(options: &[(String, String)]) -> Self {
+        let mut previous = Vec::with_capacity(options.len());
+        for (key, value) in options {
+            let old = config::get_thread_local_config_option(key, "").unwrap_or_default();
+            previous.push((key.clone(), old));
+            // Failures leave the option unset, which surfaces
+            // when the dataset is used.
+            let _ = config::set_thread_local_config_option(key, value);
+        }
+        ConfigGuard { previous }
+    }
+}
+
+impl Drop for ConfigGuard {
+    fn drop(&mut self) {
+        // Restore in reverse, in case a key was set twice.
+        for (key, value) in self.previous.iter().rev() {
+            let _ = if value.is_empty() {
+                config::clear_thread_local_config_option(key)
+            } else {
+                config::set_thread_local_config_option(key, value)
+            };
+        }
+    }
+}
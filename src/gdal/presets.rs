@@ -0,0 +1,165 @@
+//! Ready-to-use processing pipelines.
+//!
+//! Each preset is built only on the public API of this
+//! crate ([`ChunkConfigBuilder`], [`ChunkReader`],
+//! [`ChunkWriter`] and [`ops`][crate::ops]), and doubles as
+//! documentation of how these compose.
+
+use super::readers::{BandIndex, ChunkReader};
+use super::utils::{create_like, geo_affine_from};
+use super::writers::{ChunkWriter, DatasetWriter};
+use super::Result;
+use crate::chunking::builder::ChunkConfigBuilder;
+use crate::ops::terrain::{hillshade as hillshade_kernel, HillshadeParams};
+use gdal::{cpl::CslStringList, raster::RasterCreationOptions, Dataset, DriverManager};
+use ndarray::Zip;
+
+use std::{num::NonZeroUsize, path::Path};
+
+/// Number of pixels processed per chunk by the presets.
+const DATA_SIZE: usize = 1 << 22;
+
+/// Compute the NDVI `(nir - red) / (nir + red)` of the given
+/// bands of `input`, into a single band `Float32` GeoTIFF.
+///
+/// Pixels where `nir + red` is zero are set to `NaN`, which
+/// is also the nodata value of the output.
+pub fn ndvi(input: &Path, red: BandIndex, nir: BandIndex, output: &Path) -> Result<()> {
+    let dataset = Dataset::open(input)?;
+    let cfg = ChunkConfigBuilder::from_dataset(&dataset)?
+        .with_data_size(NonZeroUsize::new(DATA_SIZE).unwrap())
+        .build();
+
+    let out = create_like::<f32>(&dataset, "GTiff", output, 1)?;
+    out.rasterband(BandIndex::FIRST.get())?
+        .set_no_data_value(Some(f64::NAN))?;
+    let mut writer = DatasetWriter(out, BandIndex::FIRST);
+
+    let red = dataset.rasterband(red.get())?;
+    let nir = dataset.rasterband(nir.get())?;
+    for chunk in &cfg {
+        let red = ChunkReader::read_chunk::<f32>(&red, chunk)?;
+        let nir = ChunkReader::read_chunk::<f32>(&nir, chunk)?;
+        let ndvi = Zip::from(&nir).and(&red).map_collect(|&nir, &red| {
+            let sum = nir + red;
+            if sum == 0. {
+                f32::NAN
+            } else {
+                (nir - red) / sum
+            }
+        });
+        writer.write_chunk(&ndvi, chunk)?;
+    }
+    Ok(())
+}
+
+/// Compute the hillshade of band `band` of the elevation
+/// raster `dem` into a single band `Byte` GeoTIFF.
+///
+/// The first and last rows, which lack neighbours, are set
+/// to `0`, which is also the nodata value of the output.
+pub fn hillshade(
+    dem: &Path,
+    band: BandIndex,
+    output: &Path,
+    azimuth: f64,
+    altitude: f64,
+) -> Result<()> {
+    let dataset = Dataset::open(dem)?;
+    let transform = geo_affine_from(&dataset.geo_transform()?);
+    let params = HillshadeParams {
+        azimuth,
+        altitude,
+        cell_size: (transform.a(), transform.e()),
+        ..Default::default()
+    };
+    let cfg = ChunkConfigBuilder::from_dataset(&dataset)?
+        .with_data_size(NonZeroUsize::new(DATA_SIZE).unwrap())
+        .with_padding(1)
+        .build();
+
+    let out = create_like::<u8>(&dataset, "GTiff", output, 1)?;
+    out.rasterband(BandIndex::FIRST.get())?
+        .set_no_data_value(Some(0.))?;
+    let mut writer = DatasetWriter(out, BandIndex::FIRST);
+
+    let band = dataset.rasterband(band.get())?;
+    for chunk in &cfg {
+        let dem = ChunkReader::read_chunk::<f64>(&band, chunk)?;
+        let shade = hillshade_kernel(dem.view(), &params);
+        writer.write_array(shade.view(), chunk.data_window())?;
+    }
+    Ok(())
+}
+
+/// Convert `input` into a Cloud Optimized GeoTIFF with
+/// `DEFLATE` compression.
+///
+/// GDAL's `COG` driver streams the data and builds the
+/// overviews itself, so no chunking is needed here.
+pub fn cog_convert(input: &Path, output: &Path) -> Result<()> {
+    let dataset = Dataset::open(input)?;
+    let driver = DriverManager::get_driver_by_name("COG")?;
+
+    let mut options = CslStringList::new();
+    options.set_name_value("COMPRESS", "DEFLATE")?;
+    options.set_name_value("BIGTIFF", "IF_SAFER")?;
+    let options: RasterCreationOptions = options;
+
+    dataset.create_copy(&driver, output, &options)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdal::testing::vsimem_raster;
+    use ndarray::Array2;
+    use std::convert::TryFrom;
+
+    fn read_output<T: crate::gdal::readers::Pixel>(path: &Path) -> Array2<T> {
+        let dataset = Dataset::open(path).unwrap();
+        let band = dataset.rasterband(1).unwrap();
+        ChunkReader::read_as_array(&band, ((0, 0), dataset.raster_size()).into()).unwrap()
+    }
+
+    #[test]
+    fn test_ndvi() {
+        let red = Array2::from_shape_fn((3, 4), |(i, j)| (i + j) as f32);
+        let nir = Array2::from_shape_fn((3, 4), |(i, j)| (2 * i + j) as f32);
+        let input = vsimem_raster("presets_ndvi_input.tif", &[red.clone(), nir.clone()]);
+        let output = Path::new("/vsimem/presets_ndvi_output.tif");
+        let bands = (
+            BandIndex::try_from(1).unwrap(),
+            BandIndex::try_from(2).unwrap(),
+        );
+        ndvi(&input, bands.0, bands.1, output).unwrap();
+
+        let values = read_output::<f32>(output);
+        assert!(values[[0, 0]].is_nan());
+        assert_eq!(values[[2, 1]], (5. - 3.) / (5. + 3.));
+        assert_eq!(values[[0, 3]], 0.);
+        let dataset = Dataset::open(output).unwrap();
+        let nodata = dataset.rasterband(1).unwrap().no_data_value();
+        assert!(nodata.unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_hillshade() {
+        let dem = Array2::from_elem((5, 6), 100f32);
+        let input = vsimem_raster("presets_hillshade_input.tif", &[dem]);
+        let output = Path::new("/vsimem/presets_hillshade_output.tif");
+        hillshade(&input, BandIndex::FIRST, output, 315., 45.).unwrap();
+
+        let shade = read_output::<u8>(output);
+        assert_eq!(shade.dim(), (5, 6));
+        // Flat terrain is lit evenly, except the edge rows.
+        assert!(shade.row(0).iter().chain(shade.row(4)).all(|&v| v == 0));
+        let lit = shade[[2, 2]];
+        assert!(lit > 0);
+        assert!(shade
+            .slice(ndarray::s![1..4, 1..5])
+            .iter()
+            .all(|&v| v == lit));
+    }
+}
@@ -0,0 +1,128 @@
+//! Ready-made [`ChunkProcessor`]s over GDAL bands.
+//!
+//! Processors read their inputs and write their output
+//! chunk by chunk, so they run under a
+//! [`ProcessingDriver`][crate::processing::ProcessingDriver]
+//! with its checkpointing, progress and cancellation.
+
+use super::readers::{BandIndex, ChunkReader, Pixel};
+use super::writers::ChunkWriter;
+use super::Result;
+use crate::chunking::Chunk;
+use crate::nodata::NodataPolicy;
+use crate::ops::spectral::NormalizedDifferenceIndex;
+use crate::processing::ChunkProcessor;
+use gdal::{raster::RasterBand, Dataset};
+use ndarray::Zip;
+use num::{NumCast, ToPrimitive};
+
+/// Computes `(a - b) / (a + b)` of two bands (eg. NDVI with
+/// `a` NIR and `b` red, NDWI with `a` green and `b` NIR).
+///
+/// Inputs are read as `f64`. Pixels where either input is
+/// nodata (or `NaN`), or where `a + b` is zero, are set to
+/// the output nodata value. The output is `f32` with `NaN`
+/// nodata, unless scaled to another type with
+/// [`with_scaling`][Self::with_scaling].
+pub struct NormalizedDifference<'a, W, T = f32> {
+    a: RasterBand<'a>,
+    b: RasterBand<'a>,
+    index: NormalizedDifferenceIndex,
+    writer: W,
+    scale: f64,
+    nodata: T,
+    round: bool,
+}
+
+/// Normalized difference of band `band_a` of `dataset_a` and
+/// band `band_b` of `dataset_b`, written to `writer`.
+///
+/// The input nodata values are taken from the bands.
+pub fn normalized_difference<'a, W: ChunkWriter>(
+    dataset_a: &'a Dataset,
+    band_a: BandIndex,
+    dataset_b: &'a Dataset,
+    band_b: BandIndex,
+    writer: W,
+) -> Result<NormalizedDifference<'a, W>> {
+    let a = dataset_a.rasterband(band_a.get())?;
+    let b = dataset_b.rasterband(band_b.get())?;
+    Ok(NormalizedDifference {
+        index: NormalizedDifferenceIndex::new(
+            a.no_data_value(),
+            b.no_data_value(),
+            NodataPolicy::Exact,
+        ),
+        a,
+        b,
+        writer,
+        scale: 1.,
+        nodata: f32::NAN,
+        round: false,
+    })
+}
+
+impl<'a, W, T> NormalizedDifference<'a, W, T>
+where
+    W: ChunkWriter,
+    T: Pixel,
+{
+    /// Override the nodata values of the inputs.
+    pub fn with_input_nodata(mut self, nodata_a: Option<f64>, nodata_b: Option<f64>) -> Self {
+        self.index.nodata_a = nodata_a;
+        self.index.nodata_b = nodata_b;
+        self
+    }
+
+    /// How input values are compared with their nodata
+    /// values (default: exact).
+    pub fn with_nodata_policy(mut self, policy: NodataPolicy) -> Self {
+        self.index.policy = policy;
+        self
+    }
+
+    /// Write values multiplied by `scale` as `U`, with
+    /// `nodata` as nodata value: eg. `i16` with a scale of
+    /// `10000.` and nodata `i16::MIN`. Values are rounded if
+    /// `U` is an integer type.
+    pub fn with_scaling<U: Pixel>(self, scale: f64, nodata: U) -> NormalizedDifference<'a, W, U> {
+        let half = <U as NumCast>::from(0.5).and_then(|v| v.to_f64());
+        NormalizedDifference {
+            a: self.a,
+            b: self.b,
+            index: self.index,
+            writer: self.writer,
+            scale,
+            nodata,
+            round: half != Some(0.5),
+        }
+    }
+
+    /// The output writer.
+    pub fn into_writer(self) -> W {
+        self.writer
+    }
+
+    fn value(&self, a: f64, b: f64) -> T {
+        let Some(value) = self.index.value(a, b) else {
+            return self.nodata;
+        };
+        let value = value * self.scale;
+        let value = if self.round { value.round() } else { value };
+        <T as NumCast>::from(value).unwrap_or(self.nodata)
+    }
+}
+
+impl<'a, W, T> ChunkProcessor for NormalizedDifference<'a, W, T>
+where
+    W: ChunkWriter,
+    T: Pixel,
+{
+    fn process(&mut self, _index: usize, chunk: Chunk) -> crate::Result<()> {
+        let a = ChunkReader::read_chunk::<f64>(&self.a, chunk)?;
+        let b = ChunkReader::read_chunk::<f64>(&self.b, chunk)?;
+        let output = Zip::from(&a).and(&b).map_collect(|&a, &b| self.value(a, b));
+        self.writer.write_chunk(&output, chunk)?;
+        Ok(())
+    }
+}
@@ -1,86 +1,447 @@
 //! Abstractions to safely read GDAL datasets from multiple
 //! threads.
 
-use super::{RasterUtilsGdalError, Result};
-use crate::chunking::ChunkWindow;
-use crate::geometry::RasterWindow;
+use super::open::OpenOptions;
+use super::{ErrorContext, RasterUtilsGdalError, Result, ResultExt};
+use crate::chunking::Chunk;
+use crate::dynamic::DynArray;
+use crate::geometry::{RasterWindow, Size};
+use crate::RasterUtilsError;
 use gdal::{
-    raster::{GdalType, RasterBand},
+    errors::GdalError,
+    raster::{GdalDataType, RasterBand},
     Dataset,
 };
 use ndarray::Array2;
+use num::complex::Complex;
 
-use std::{num::NonZeroUsize, path::Path};
+pub(crate) use crate::reader::transpose_into;
+pub use crate::reader::{
+    AdviseReader, ChunkReader, Coverage, CoverageReader, MemoryOrder, Pixel, ReaderFactory,
+    ResampleAlg,
+};
 
-/// Abstracts reading chunks from raster.
-pub trait ChunkReader {
-    /// Emulate [`RasterBand::read_into_slice`].
-    fn read_into_slice<T>(&self, out: &mut [T], raster_window: RasterWindow) -> Result<()>
+use std::{cell::RefCell, collections::HashMap, ffi::CStr, rc::Rc};
+
+use std::{
+    convert::TryFrom,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+impl From<ResampleAlg> for gdal::raster::ResampleAlg {
+    fn from(alg: ResampleAlg) -> Self {
+        use gdal::raster::ResampleAlg as Gdal;
+        match alg {
+            ResampleAlg::Nearest => Gdal::NearestNeighbour,
+            ResampleAlg::Bilinear => Gdal::Bilinear,
+            ResampleAlg::Cubic => Gdal::Cubic,
+            ResampleAlg::CubicSpline => Gdal::CubicSpline,
+            ResampleAlg::Lanczos => Gdal::Lanczos,
+            ResampleAlg::Average => Gdal::Average,
+            ResampleAlg::Mode => Gdal::Mode,
+            ResampleAlg::Gauss => Gdal::Gauss,
+        }
+    }
+}
+
+impl<'a> ChunkReader for RasterBand<'a> {
+    type Error = RasterUtilsGdalError;
+
+    fn raster_size(&self) -> Result<Size> {
+        Ok(self.size())
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
     where
-        T: GdalType + Copy;
+        T: Pixel,
+    {
+        self.read_into_slice_resampled(out, raster_window, buffer_size, ResampleAlg::Nearest)
+    }
 
-    /// Helper to read into an ndarray.
-    fn read_as_array<T>(&self, raster_window: RasterWindow) -> Result<Array2<T>>
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<()>
     where
-        T: GdalType + Copy,
+        T: Pixel,
     {
-        let bufsize = raster_window.num_pixels();
-        let mut buf = Vec::with_capacity(bufsize);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "read_chunk",
+            window = ?raster_window,
+            bytes = std::mem::size_of_val(out),
+        )
+        .entered();
+
+        let context = || ErrorContext::window(&raster_window);
+        raster_window.validate(self.size())?;
+        let (off, size) = raster_window.clone().into();
+        self.read_into_slice(off, size, buffer_size, out, Some(alg.into()))
+            .context(context)
+    }
+}
+
+/// Coverage from `GDALGetDataCoverageStatus`, implemented by
+/// drivers of sparse formats (eg. GeoTIFF files whose blocks
+/// were never written).
+impl<'a> CoverageReader for RasterBand<'a> {
+    type Error = RasterUtilsGdalError;
 
-        // Safety: paradigm suggested in std docs
-        // https://doc.rust-lang.org/std/vec/struct.Vec.html#examples-18
-        unsafe {
-            buf.set_len(bufsize);
+    fn coverage(&self, raster_window: &RasterWindow) -> Result<Coverage> {
+        let ((off_x, off_y), (size_x, size_y), _) = match raster_window.read_window(self.size()) {
+            Some(window) => window,
+            None => return Ok(Coverage::Empty),
+        };
+        let mut percent = 0.;
+        let status = unsafe {
+            gdal_sys::GDALGetDataCoverageStatus(
+                self.c_rasterband(),
+                off_x as i32,
+                off_y as i32,
+                size_x as i32,
+                size_y as i32,
+                0,
+                &mut percent,
+            )
+        } as u32;
+        let has_data = status & gdal_sys::GDAL_DATA_COVERAGE_STATUS_DATA != 0;
+        let has_empty = status & gdal_sys::GDAL_DATA_COVERAGE_STATUS_EMPTY != 0;
+        Ok(
+            if status & gdal_sys::GDAL_DATA_COVERAGE_STATUS_UNIMPLEMENTED != 0 {
+                Coverage::Unknown
+            } else {
+                match (has_data, has_empty) {
+                    (true, true) => Coverage::Partial,
+                    (true, false) => Coverage::Data,
+                    (false, true) => Coverage::Empty,
+                    (false, false) => Coverage::Unknown,
+                }
+            },
+        )
+    }
+}
+
+/// Advice with `GDALRasterAdviseRead`, for the window read
+/// at full resolution. Drivers of network formats (eg.
+/// GeoTIFF over `/vsicurl`) fetch the intersecting blocks
+/// in a single request; others ignore it.
+impl<'a> AdviseReader for RasterBand<'a> {
+    fn advise_read(&self, raster_window: &RasterWindow) -> Result<()> {
+        let (off_x, off_y) = raster_window.validate(self.size())?;
+        let (size_x, size_y) = raster_window.size();
+        let rv = unsafe {
+            gdal_sys::GDALRasterAdviseRead(
+                self.c_rasterband(),
+                off_x as i32,
+                off_y as i32,
+                size_x as i32,
+                size_y as i32,
+                size_x as i32,
+                size_y as i32,
+                gdal_sys::GDALDataType::GDT_Unknown,
+                std::ptr::null_mut(),
+            )
+        };
+        if rv != gdal_sys::CPLErr::CE_None {
+            return Err(last_cpl_error(rv)).context(|| ErrorContext::window(raster_window));
         }
+        Ok(())
+    }
+}
 
-        let array_shape = raster_window.shape();
-        self.read_into_slice(&mut buf[..], raster_window)?;
-        Array2::from_shape_vec(array_shape, buf).map_err(RasterUtilsGdalError::NdarrayShapeError)
+/// The last error of GDAL, raised with class `class`.
+pub(crate) fn last_cpl_error(class: gdal_sys::CPLErr::Type) -> GdalError {
+    let msg = unsafe { CStr::from_ptr(gdal_sys::CPLGetLastErrorMsg()) };
+    GdalError::CplError {
+        class,
+        number: unsafe { gdal_sys::CPLGetLastErrorNo() },
+        msg: msg.to_string_lossy().into_owned(),
     }
+}
+
+/// Read `raster_window` of `band` in its native data type.
+///
+/// Unlike [`ChunkReader`] reads, complex bands are read as
+/// complex values: `CInt16` and `CFloat32` bands as
+/// `Complex<f32>`, and `CInt32` and `CFloat64` bands as
+/// `Complex<f64>`.
+pub fn read_dyn(band: &RasterBand, raster_window: RasterWindow) -> Result<DynArray> {
+    use gdal_sys::GDALDataType::*;
 
-    /* /// Helper to read into slice from output of
-    /// [`ChunkConfig`] iterator
-    fn read_chunk_into_slice<T>(
+    raster_window.validate(band.size())?;
+    let data_type = unsafe { gdal_sys::GDALGetRasterDataType(band.c_rasterband()) };
+    Ok(match data_type {
+        GDT_CInt16 | GDT_CFloat32 => {
+            read_complex::<f32>(band, &raster_window, GDT_CFloat32)?.into()
+        }
+        GDT_CInt32 | GDT_CFloat64 => {
+            read_complex::<f64>(band, &raster_window, GDT_CFloat64)?.into()
+        }
+        _ => match band.band_type() {
+            GdalDataType::UInt8 => band.read_as_array::<u8>(raster_window)?.into(),
+            GdalDataType::Int8 => band.read_as_array::<i8>(raster_window)?.into(),
+            GdalDataType::UInt16 => band.read_as_array::<u16>(raster_window)?.into(),
+            GdalDataType::Int16 => band.read_as_array::<i16>(raster_window)?.into(),
+            GdalDataType::UInt32 => band.read_as_array::<u32>(raster_window)?.into(),
+            GdalDataType::Int32 => band.read_as_array::<i32>(raster_window)?.into(),
+            GdalDataType::UInt64 => band.read_as_array::<u64>(raster_window)?.into(),
+            GdalDataType::Int64 => band.read_as_array::<i64>(raster_window)?.into(),
+            GdalDataType::Float32 => band.read_as_array::<f32>(raster_window)?.into(),
+            GdalDataType::Float64 => band.read_as_array::<f64>(raster_window)?.into(),
+            _ => return Err(RasterUtilsError::Unsupported("data type of the band").into()),
+        },
+    })
+}
+
+/// Read `raster_window` of `band` with `RasterIO` into a
+/// buffer of `buffer_type`, the complex type of `T`.
+fn read_complex<T: Copy + Default>(
+    band: &RasterBand,
+    raster_window: &RasterWindow,
+    buffer_type: gdal_sys::GDALDataType::Type,
+) -> Result<Array2<Complex<T>>> {
+    let (off_x, off_y) = raster_window.validate(band.size())?;
+    let (size_x, size_y) = raster_window.size();
+    let mut data = vec![Complex::<T>::default(); size_x * size_y];
+    // `Complex` is `repr(C)`, with the layout of GDAL complex
+    // types.
+    let rv = unsafe {
+        gdal_sys::GDALRasterIO(
+            band.c_rasterband(),
+            gdal_sys::GDALRWFlag::GF_Read,
+            off_x as i32,
+            off_y as i32,
+            size_x as i32,
+            size_y as i32,
+            data.as_mut_ptr() as *mut std::ffi::c_void,
+            size_x as i32,
+            size_y as i32,
+            buffer_type,
+            0,
+            0,
+        )
+    };
+    if rv != gdal_sys::CPLErr::CE_None {
+        return Err(last_cpl_error(rv)).context(|| ErrorContext::window(raster_window));
+    }
+    Ok(Array2::from_shape_vec((size_y, size_x), data)?)
+}
+
+/// A [`ChunkReader`] that reads native blocks directly.
+///
+/// Uses [`RasterBand::read_block`] when the requested window
+/// is aligned to the band's block grid (the window may end
+/// at the raster edge instead of a block boundary), which
+/// avoids the extra copies and re-decoding of `RasterIO`.
+/// Falls back to [`RasterBand::read_into_slice`] otherwise.
+pub struct BlockReader<'a>(pub RasterBand<'a>);
+
+impl<'a> BlockReader<'a> {
+    /// Check if `raster_window` starts and ends on block
+    /// boundaries (or the raster edge).
+    pub fn is_block_aligned(&self, raster_window: &RasterWindow) -> bool {
+        let (block_x, block_y) = self.0.block_size();
+        let (cols, rows) = self.0.size();
+        let Ok((off_x, off_y)) = raster_window.validate((cols, rows)) else {
+            return false;
+        };
+        let (size_x, size_y) = raster_window.size();
+        let (end_x, end_y) = (off_x + size_x, off_y + size_y);
+
+        off_x % block_x == 0
+            && off_y % block_y == 0
+            && (end_x % block_x == 0 || end_x == cols)
+            && (end_y % block_y == 0 || end_y == rows)
+            && end_x <= cols
+            && end_y <= rows
+    }
+}
+
+impl<'a> ChunkReader for BlockReader<'a> {
+    type Error = RasterUtilsGdalError;
+
+    fn raster_size(&self) -> Result<Size> {
+        Ok(self.0.size())
+    }
+
+    fn read_into_slice_sized<T>(
         &self,
         out: &mut [T],
-        chunk: ChunkWindow,
+        raster_window: RasterWindow,
+        buffer_size: Size,
     ) -> Result<()>
     where
-        T: GdalType + Copy,
+        T: Pixel,
     {
-        self.read_into_slice(out, chunk.into())
-    } */
+        if buffer_size == raster_window.size() {
+            self.read_into_slice(out, raster_window)
+        } else {
+            self.0
+                .read_into_slice_sized(out, raster_window, buffer_size)
+        }
+    }
 
-    /// Helper to read ndarray from output of
-    /// [`ChunkConfig`] iterator
-    fn read_chunk<T>(&self, chunk: ChunkWindow) -> Result<Array2<T>>
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<()>
     where
-        T: GdalType + Copy,
+        T: Pixel,
     {
-        self.read_as_array(chunk.into())
+        if buffer_size == raster_window.size() {
+            self.read_into_slice(out, raster_window)
+        } else {
+            self.0
+                .read_into_slice_resampled(out, raster_window, buffer_size, alg)
+        }
     }
 
-    // TODO: read using gdal read_chunk faster?
-}
-
-impl<'a> ChunkReader for RasterBand<'a> {
     fn read_into_slice<T>(&self, out: &mut [T], raster_window: RasterWindow) -> Result<()>
     where
-        T: GdalType + Copy,
+        T: Pixel,
     {
-        let (off, size) = raster_window.into();
-        self.read_into_slice(off.into(), size, size, out, None)
-            .map_err(RasterUtilsGdalError::GdalError)
+        if !self.is_block_aligned(&raster_window) {
+            let size = raster_window.size();
+            return self.0.read_into_slice_sized(out, raster_window, size);
+        }
+
+        // Blocks are only read in the band type.
+        let band_type = self.0.band_type();
+        if T::datatype() != band_type {
+            return Err(RasterUtilsError::TypeMismatch {
+                expected: band_type.name(),
+                found: T::datatype().name(),
+            }
+            .into());
+        }
+
+        let (block_x, block_y) = self.0.block_size();
+        let (off_x, off_y) = raster_window.validate(self.0.size())?;
+        let (size_x, size_y) = raster_window.size();
+        let (end_x, end_y) = (off_x + size_x, off_y + size_y);
+
+        for y_block in off_y / block_y..end_y.div_ceil(block_y) {
+            for x_block in off_x / block_x..end_x.div_ceil(block_x) {
+                let block = self
+                    .0
+                    .read_block::<T>((x_block, y_block))
+                    .context(|| ErrorContext::window(&raster_window))?;
+                let data = block.data();
+
+                // Blocks on the raster edge are only partially
+                // valid: copy just the rows and columns that
+                // fall within the window.
+                let (start_x, start_y) = (x_block * block_x, y_block * block_y);
+                let cols = block_x.min(end_x - start_x);
+                let rows = block_y.min(end_y - start_y);
+                for row in 0..rows {
+                    let src = &data[row * block_x..row * block_x + cols];
+                    let dst = (start_y - off_y + row) * size_x + (start_x - off_x);
+                    out[dst..dst + cols].copy_from_slice(src);
+                }
+            }
+        }
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy)]
+impl<'a> CoverageReader for BlockReader<'a> {
+    type Error = RasterUtilsGdalError;
+
+    fn coverage(&self, raster_window: &RasterWindow) -> Result<Coverage> {
+        self.0.coverage(raster_window)
+    }
+}
+
+impl<'a> AdviseReader for BlockReader<'a> {
+    fn advise_read(&self, raster_window: &RasterWindow) -> Result<()> {
+        self.0.advise_read(raster_window)
+    }
+}
+
+impl<'a> From<RasterBand<'a>> for BlockReader<'a> {
+    fn from(band: RasterBand<'a>) -> Self {
+        BlockReader(band)
+    }
+}
+
+impl<'a> From<BlockReader<'a>> for RasterBand<'a> {
+    fn from(reader: BlockReader<'a>) -> Self {
+        reader.0
+    }
+}
+
+/// 1-based index of a band within a [`Dataset`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct BandIndex(NonZeroUsize);
 
 impl BandIndex {
-    fn get(&self) -> usize {
+    /// Index of the first band.
+    pub const FIRST: BandIndex = BandIndex(NonZeroUsize::MIN);
+
+    /// Create a [`BandIndex`] from a non-zero (1-based) index.
+    pub fn new(index: NonZeroUsize) -> Self {
+        BandIndex(index)
+    }
+
+    /// The (1-based) index as expected by
+    /// [`Dataset::rasterband`].
+    pub fn get(&self) -> usize {
         self.0.get()
     }
+
+    /// The band of `dataset` at this index.
+    ///
+    /// Fails with
+    /// [`BandOutOfRange`][RasterUtilsError::BandOutOfRange]
+    /// if the dataset has fewer bands, instead of calling
+    /// into GDAL.
+    pub fn band_of(self, dataset: &Dataset) -> Result<RasterBand<'_>> {
+        let count = dataset.raster_count();
+        if self.get() > count {
+            return Err(RasterUtilsError::BandOutOfRange {
+                band: self.get(),
+                count,
+            }
+            .into());
+        }
+        Ok(dataset.rasterband(self.get())?)
+    }
+}
+
+impl From<NonZeroUsize> for BandIndex {
+    fn from(index: NonZeroUsize) -> Self {
+        BandIndex(index)
+    }
+}
+
+impl TryFrom<usize> for BandIndex {
+    type Error = RasterUtilsGdalError;
+
+    fn try_from(index: usize) -> Result<Self> {
+        NonZeroUsize::new(index)
+            .map(BandIndex)
+            .ok_or(RasterUtilsError::InvalidBandIndex(index).into())
+    }
+}
+
+impl From<BandIndex> for usize {
+    fn from(index: BandIndex) -> Self {
+        index.get()
+    }
 }
 
 /// A [`ChunkReader`] that is [`Send`], but not [`Sync`].
@@ -89,28 +450,576 @@ impl BandIndex {
 pub struct DatasetReader(pub Dataset, pub BandIndex);
 
 impl ChunkReader for DatasetReader {
-    fn read_into_slice<T>(&self, out: &mut [T], raster_window: RasterWindow) -> Result<()>
+    type Error = RasterUtilsGdalError;
+
+    fn raster_size(&self) -> Result<Size> {
+        Ok(self.0.raster_size())
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        self.read_into_slice_resampled(out, raster_window, buffer_size, ResampleAlg::Nearest)
+    }
+
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<()>
     where
-        T: GdalType + Copy,
+        T: Pixel,
     {
-        let band = self.0.rasterband(self.1.get())?;
-        ChunkReader::read_into_slice(&band, out, raster_window)
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("read_band", band = self.1.get()).entered();
+
+        let context = || ErrorContext::dataset(&self.0, self.1.get());
+        let band = self.1.band_of(&self.0).context(context)?;
+        band.read_into_slice_resampled(out, raster_window, buffer_size, alg)
+            .context(context)
+    }
+}
+
+impl CoverageReader for DatasetReader {
+    type Error = RasterUtilsGdalError;
+
+    fn coverage(&self, raster_window: &RasterWindow) -> Result<Coverage> {
+        let context = || ErrorContext::dataset(&self.0, self.1.get());
+        let band = self.1.band_of(&self.0).context(context)?;
+        band.coverage(raster_window).context(context)
+    }
+}
+
+impl AdviseReader for DatasetReader {
+    fn advise_read(&self, raster_window: &RasterWindow) -> Result<()> {
+        let context = || ErrorContext::dataset(&self.0, self.1.get());
+        let band = self.1.band_of(&self.0).context(context)?;
+        band.advise_read(raster_window).context(context)
+    }
+}
+
+impl From<(Dataset, BandIndex)> for DatasetReader {
+    fn from((dataset, band): (Dataset, BandIndex)) -> Self {
+        DatasetReader(dataset, band)
+    }
+}
+
+/// Reads the first band of the dataset.
+impl From<Dataset> for DatasetReader {
+    fn from(dataset: Dataset) -> Self {
+        DatasetReader(dataset, BandIndex::FIRST)
     }
 }
 
+impl From<DatasetReader> for Dataset {
+    fn from(reader: DatasetReader) -> Self {
+        reader.0
+    }
+}
+
+/// Maximum number of datasets cached per thread by
+/// [`RasterPathReader`]s and [`PathReader`]s. The least
+/// recently used one is closed to open another.
+pub const PATH_DATASETS_CAPACITY: usize = 16;
+
+type PathKey = (PathBuf, OpenOptions);
+
+/// Modification time and length of a file, to notice that it
+/// was rewritten. `None` for paths that are not local files
+/// (eg. `/vsimem/` or `/vsicurl/` paths).
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+struct CachedDataset {
+    dataset: Rc<Dataset>,
+    version: Option<(SystemTime, u64)>,
+    last_used: u64,
+}
+
+/// Least recently used cache of datasets.
+#[derive(Default)]
+struct PathDatasets {
+    entries: HashMap<PathKey, CachedDataset>,
+    clock: u64,
+}
+
+impl PathDatasets {
+    /// The dataset of `key`, unless its file changed since
+    /// it was opened.
+    fn get(&mut self, key: &PathKey, version: Option<(SystemTime, u64)>) -> Option<Rc<Dataset>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        if entry.version != version {
+            self.entries.remove(key);
+            return None;
+        }
+        entry.last_used = self.clock;
+        Some(entry.dataset.clone())
+    }
+
+    fn insert(&mut self, key: PathKey, dataset: Rc<Dataset>, version: Option<(SystemTime, u64)>) {
+        self.clock += 1;
+        self.entries.insert(
+            key,
+            CachedDataset {
+                dataset,
+                version,
+                last_used: self.clock,
+            },
+        );
+        while self.entries.len() > PATH_DATASETS_CAPACITY {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// Datasets opened by [`RasterPathReader`]s and
+    /// [`PathReader`]s on this thread, by path and open
+    /// options. They are closed when evicted, when their
+    /// file changes, by [`clear_path_datasets`], or when the
+    /// thread exits.
+    static PATH_DATASETS: RefCell<PathDatasets> = RefCell::new(PathDatasets::default());
+}
+
+/// Close the datasets cached by [`RasterPathReader`]s and
+/// [`PathReader`]s on the calling thread, eg. after files
+/// that are not local (see [`RasterPathReader`]) were
+/// rewritten.
+pub fn clear_path_datasets() {
+    PATH_DATASETS.with(|datasets| datasets.borrow_mut().entries.clear());
+}
+
+/// Run `f` with the dataset at `path` opened with `options`
+/// on this thread, opening it if needed. The handle is
+/// dropped if `f` fails.
+fn with_path_dataset<F, R>(path: &Path, options: &OpenOptions, f: F) -> Result<R>
+where
+    F: FnOnce(&Dataset) -> Result<R>,
+{
+    let key = (path.to_path_buf(), options.clone());
+    let version = file_version(path);
+    // The `Rc` is cloned out, so that the cache isn't
+    // borrowed while reading.
+    let cached = PATH_DATASETS.with(|datasets| datasets.borrow_mut().get(&key, version));
+    let dataset = match cached {
+        Some(dataset) => dataset,
+        None => {
+            let dataset = Rc::new(options.open(path)?);
+            PATH_DATASETS.with(|datasets| {
+                datasets
+                    .borrow_mut()
+                    .insert(key.clone(), dataset.clone(), version)
+            });
+            dataset
+        }
+    };
+    let result = f(&dataset);
+    if result.is_err() {
+        PATH_DATASETS.with(|datasets| datasets.borrow_mut().entries.remove(&key));
+    }
+    result
+}
+
 /// A [`ChunkReader`] that is [`Send`] + [`Sync`].
 ///
-/// Opens the dataset for each read.
+/// Opens the dataset lazily, once per thread and path: the
+/// handle is kept in a thread-local cache of up to
+/// [`PATH_DATASETS_CAPACITY`] datasets and reused by later
+/// reads of any reader of the same path on that thread. A
+/// handle is dropped (and reopened on the next read) if a
+/// read with it fails, or if the modification time or length
+/// of a local file changed. Rewrites of other paths are not
+/// noticed, see [`clear_path_datasets`].
 pub struct RasterPathReader<'a, P: AsRef<Path> + ?Sized>(pub &'a P, pub BandIndex);
 
 impl<'a, P> ChunkReader for RasterPathReader<'a, P>
 where
     P: AsRef<Path> + ?Sized,
 {
-    fn read_into_slice<T>(&self, out: &mut [T], raster_window: RasterWindow) -> Result<()>
+    type Error = RasterUtilsGdalError;
+
+    fn raster_size(&self) -> Result<Size> {
+        self.with_dataset(|dataset| Ok(dataset.raster_size()))
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
     where
-        T: GdalType + Copy,
+        T: Pixel,
     {
-        DatasetReader(Dataset::open(self.0)?, self.1).read_into_slice(out, raster_window)
+        self.read_into_slice_resampled(out, raster_window, buffer_size, ResampleAlg::Nearest)
+    }
+
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        self.with_dataset(|dataset| {
+            let context = || ErrorContext::dataset(dataset, self.1.get());
+            let band = self.1.band_of(dataset).context(context)?;
+            band.read_into_slice_resampled(out, raster_window, buffer_size, alg)
+                .context(context)
+        })
+    }
+}
+
+impl<'a, P> RasterPathReader<'a, P>
+where
+    P: AsRef<Path> + ?Sized,
+{
+    /// Run `f` with the dataset of this thread, opening it
+    /// if needed.
+    fn with_dataset<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&Dataset) -> Result<R>,
+    {
+        with_path_dataset(self.0.as_ref(), &OpenOptions::new(), f).context(|| ErrorContext {
+            path: Some(self.0.as_ref().to_path_buf()),
+            band: Some(self.1.get()),
+            window: None,
+        })
+    }
+}
+
+impl<'a, P> From<(&'a P, BandIndex)> for RasterPathReader<'a, P>
+where
+    P: AsRef<Path> + ?Sized,
+{
+    fn from((path, band): (&'a P, BandIndex)) -> Self {
+        RasterPathReader(path, band)
+    }
+}
+
+/// A [`ChunkReader`] over a path (incl. `/vsi*` paths),
+/// opened with [`OpenOptions`].
+///
+/// As with [`RasterPathReader`], the dataset is opened once
+/// per thread (and path and options), and the handle kept
+/// in a thread-local cache. The config options are applied
+/// to the calling thread around each open and read, so
+/// readers with different options (eg. credentials) may be
+/// used concurrently. Build with [`PathReaderBuilder`].
+#[derive(Clone, Debug)]
+pub struct PathReader {
+    path: PathBuf,
+    band: BandIndex,
+    options: OpenOptions,
+    advise_ahead: usize,
+}
+
+impl PathReader {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn band(&self) -> BandIndex {
+        self.band
+    }
+
+    pub fn options(&self) -> &OpenOptions {
+        &self.options
+    }
+
+    /// Number of chunks advised ahead of each chunk read (see
+    /// [`PathReaderBuilder::with_advise_ahead`]).
+    pub fn advise_ahead(&self) -> usize {
+        self.advise_ahead
+    }
+}
+
+impl ChunkReader for PathReader {
+    type Error = RasterUtilsGdalError;
+
+    fn raster_size(&self) -> Result<Size> {
+        self.with_band(|band| Ok(band.size()))
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        self.read_into_slice_resampled(out, raster_window, buffer_size, ResampleAlg::Nearest)
+    }
+
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        self.with_band(|band| band.read_into_slice_resampled(out, raster_window, buffer_size, alg))
+    }
+
+    /// Advises the next chunks of the config of `chunk` (if
+    /// enabled) on the dataset of this thread, which later
+    /// reads reuse.
+    fn read_chunk<T>(&self, chunk: Chunk) -> Result<Array2<T>>
+    where
+        T: Pixel,
+    {
+        if self.advise_ahead == 0 {
+            return self.read_as_array(chunk.into());
+        }
+        let config = chunk.config();
+        let index = config.chunk_index(&chunk);
+        let ahead: Vec<usize> = (index + 1..=index + self.advise_ahead).collect();
+        self.with_band(|band| {
+            for next in config.iter_indices(&ahead) {
+                // Advice is only a hint.
+                let _ = band.advise_read(&next.window());
+            }
+            band.read_as_array(chunk.into())
+        })
+    }
+}
+
+impl AdviseReader for PathReader {
+    fn advise_read(&self, raster_window: &RasterWindow) -> Result<()> {
+        self.with_band(|band| band.advise_read(raster_window))
+    }
+}
+
+impl PathReader {
+    /// Run `f` with the band of the dataset of this thread,
+    /// with the config options set.
+    fn with_band<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(RasterBand) -> Result<R>,
+    {
+        self.options.scoped(|| {
+            with_path_dataset(&self.path, &self.options, |dataset| {
+                let context = || ErrorContext::dataset(dataset, self.band.get());
+                let band = self.band.band_of(dataset).context(context)?;
+                f(band).context(context)
+            })
+        })
+    }
+}
+
+/// Opens one [`DatasetReader`] per worker, to read without
+/// reopening the dataset for each chunk. Only the open runs
+/// with the config options set.
+impl ReaderFactory for PathReader {
+    type Reader = DatasetReader;
+
+    fn create(&self) -> Result<DatasetReader> {
+        Ok(DatasetReader(self.options.open(&self.path)?, self.band))
+    }
+}
+
+/// Builder for [`PathReader`].
+pub struct PathReaderBuilder(PathReader);
+
+impl PathReaderBuilder {
+    /// Read the first band of the raster at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        PathReaderBuilder(PathReader {
+            path: path.as_ref().to_path_buf(),
+            band: BandIndex::FIRST,
+            options: OpenOptions::new(),
+            advise_ahead: 0,
+        })
+    }
+
+    /// Set the band to read.
+    pub fn with_band(mut self, band: BandIndex) -> Self {
+        self.0.band = band;
+        self
+    }
+
+    /// Replace all open and config options.
+    pub fn with_options(mut self, options: OpenOptions) -> Self {
+        self.0.options = options;
+        self
+    }
+
+    /// See [`OpenOptions::with_open_option`].
+    pub fn with_open_option(mut self, key: &str, value: &str) -> Self {
+        self.0.options = self.0.options.with_open_option(key, value);
+        self
+    }
+
+    /// See [`OpenOptions::with_config_option`].
+    pub fn with_config_option(mut self, key: &str, value: &str) -> Self {
+        self.0.options = self.0.options.with_config_option(key, value);
+        self
+    }
+
+    /// Advise GDAL (`AdviseRead`) of the windows of the next
+    /// `ahead` chunks on each
+    /// [`read_chunk`][ChunkReader::read_chunk], so network
+    /// drivers fetch them in advance. Disabled (`0`) by
+    /// default.
+    ///
+    /// Readers created by the [`ReaderFactory`] impl don't
+    /// advise; use
+    /// [`ChunkConfig::iter_advised`][crate::chunking::ChunkConfig::iter_advised]
+    /// with them instead.
+    pub fn with_advise_ahead(mut self, ahead: usize) -> Self {
+        self.0.advise_ahead = ahead;
+        self
+    }
+
+    /// Build [`PathReader`].
+    pub fn build(self) -> PathReader {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdal::testing::{gtiff_raster, mem_dataset, vsimem_raster};
+    use ndarray::array;
+
+    fn cached_datasets() -> usize {
+        PATH_DATASETS.with(|datasets| datasets.borrow().entries.len())
+    }
+
+    #[test]
+    fn test_path_datasets_capacity() {
+        let paths: Vec<PathBuf> = (0..PATH_DATASETS_CAPACITY + 2)
+            .map(|index| {
+                let name = format!("readers_capacity_{}.tif", index);
+                vsimem_raster(&name, &[Array2::<u8>::zeros((2, 3))])
+            })
+            .collect();
+        for path in &paths {
+            let reader = RasterPathReader(path, BandIndex::FIRST);
+            assert_eq!(reader.raster_size().unwrap(), (3, 2));
+        }
+        assert_eq!(cached_datasets(), PATH_DATASETS_CAPACITY);
+
+        // The least recently used datasets were closed.
+        let is_cached = |path: &PathBuf| {
+            let key = (path.clone(), OpenOptions::new());
+            PATH_DATASETS.with(|datasets| datasets.borrow().entries.contains_key(&key))
+        };
+        assert!(!is_cached(&paths[0]) && !is_cached(&paths[1]));
+        assert!(is_cached(&paths[2]));
+        clear_path_datasets();
+        assert_eq!(cached_datasets(), 0);
+    }
+
+    #[test]
+    fn test_path_datasets_rewritten() {
+        let path = std::env::temp_dir().join("raster_utils_readers_rewritten.tif");
+        gtiff_raster(&path, &[Array2::<u8>::zeros((2, 3))]);
+        let reader = RasterPathReader(&path, BandIndex::FIRST);
+        assert_eq!(reader.raster_size().unwrap(), (3, 2));
+
+        // A larger file, so that the length changes whatever
+        // the resolution of modification times.
+        gtiff_raster(&path, &[Array2::<u8>::ones((20, 30))]);
+        assert_eq!(reader.raster_size().unwrap(), (30, 20));
+        assert_eq!(cached_datasets(), 1);
+        clear_path_datasets();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_path_reader_context() {
+        let path = Path::new("/vsimem/readers_missing.tif");
+        let reader = RasterPathReader(path, BandIndex::FIRST);
+        let error = reader
+            .read_as_array::<u8>(((0, 0), (1, 1)).into())
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("path: /vsimem/readers_missing.tif"));
+    }
+
+    #[test]
+    fn test_read_resampled() {
+        let array = Array2::from_shape_fn((4, 4), |(i, j)| (i * 4 + j) as f64);
+        let dataset = mem_dataset(&[array.clone()]);
+        let band = dataset.rasterband(1).unwrap();
+        let window = RasterWindow::from(((0, 0), (4, 4)));
+
+        // Each pixel of the buffer averages 2x2 pixels.
+        let averaged: Array2<f64> = ChunkReader::read_as_array_resampled(
+            &band,
+            window.clone(),
+            (2, 2),
+            ResampleAlg::Average,
+        )
+        .unwrap();
+        assert_eq!(averaged, array![[2.5, 4.5], [10.5, 12.5]]);
+
+        // Upsampling a part of the band repeats its pixels,
+        // also through the default (nearest) sized read.
+        let part = RasterWindow::from(((2, 1), (2, 2)));
+        let expected = array![
+            [6., 6., 7., 7.],
+            [6., 6., 7., 7.],
+            [10., 10., 11., 11.],
+            [10., 10., 11., 11.]
+        ];
+        let nearest: Array2<f64> =
+            ChunkReader::read_as_array_resampled(&band, part.clone(), (4, 4), ResampleAlg::Nearest)
+                .unwrap();
+        assert_eq!(nearest, expected);
+        assert_eq!(
+            ChunkReader::read_as_array_sized::<f64>(&band, part, (4, 4)).unwrap(),
+            expected
+        );
+
+        // Dataset readers forward the algorithm.
+        let reader = DatasetReader(mem_dataset(&[array]), BandIndex::FIRST);
+        assert_eq!(
+            reader
+                .read_as_array_resampled::<f64>(window, (2, 2), ResampleAlg::Average)
+                .unwrap(),
+            averaged
+        );
+        assert!(matches!(
+            reader
+                .read_as_array_resampled::<f64>(
+                    RasterWindow::from(((3, 3), (2, 2))),
+                    (1, 1),
+                    ResampleAlg::Average
+                )
+                .err()
+                .unwrap()
+                .raster_utils_error(),
+            Some(RasterUtilsError::WindowOutOfBounds(_))
+        ));
     }
 }
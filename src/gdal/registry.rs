@@ -0,0 +1,320 @@
+//! Registry of pooled readers for long-running services.
+//!
+//! A [`ReaderRegistry`] maps raster keys to a path and band,
+//! and keeps a pool of open [`Dataset`] handles for each.
+//! Request handlers grab a [`PooledReader`] by key, which
+//! returns its handle to the pool on drop. Handles that
+//! fail a read are considered corrupt and are dropped, so
+//! that the next reader reopens the dataset. Idle handles
+//! of keys unused for longer than the TTL are closed by
+//! [`ReaderRegistry::evict_expired`]. Handles checked out
+//! before their key was replaced or unregistered are closed
+//! when returned, instead of joining the pool of the new
+//! entry.
+
+use super::readers::{BandIndex, ChunkReader, Pixel, ResampleAlg};
+use super::{RasterUtilsGdalError, Result};
+use crate::geometry::{RasterWindow, Size};
+use crate::RasterUtilsError;
+use gdal::Dataset;
+
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, OnceLock, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+/// TTL used by [`ReaderRegistry::global`].
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+struct Entry {
+    /// Distinguishes the registrations of a key.
+    generation: u64,
+    path: PathBuf,
+    band: BandIndex,
+    idle: Vec<Dataset>,
+    last_used: Instant,
+}
+
+/// Thread-safe registry of pooled readers, keyed by name.
+pub struct ReaderRegistry {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+    generations: AtomicU64,
+}
+
+impl ReaderRegistry {
+    /// Create a registry closing idle handles after `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        ReaderRegistry {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            generations: AtomicU64::new(0),
+        }
+    }
+
+    /// Process-wide registry with [`DEFAULT_TTL`].
+    pub fn global() -> &'static ReaderRegistry {
+        static GLOBAL: OnceLock<ReaderRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(|| ReaderRegistry::new(DEFAULT_TTL))
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Register (or replace) the raster at `path` under
+    /// `key`. Replacing a key closes its idle handles.
+    pub fn register<K, P>(&self, key: K, path: P, band: BandIndex)
+    where
+        K: Into<String>,
+        P: Into<PathBuf>,
+    {
+        self.entries().insert(
+            key.into(),
+            Entry {
+                generation: self.generations.fetch_add(1, Ordering::Relaxed),
+                path: path.into(),
+                band,
+                idle: vec![],
+                last_used: Instant::now(),
+            },
+        );
+    }
+
+    /// Remove `key` from the registry. Returns whether it was
+    /// registered. Readers already handed out stay usable,
+    /// and close their handle on drop.
+    pub fn unregister(&self, key: &str) -> bool {
+        self.entries().remove(key).is_some()
+    }
+
+    /// Obtain a reader for `key`, reusing an idle handle if
+    /// available.
+    pub fn get(&self, key: &str) -> Result<PooledReader<'_>> {
+        let (generation, path, band, dataset) = {
+            let mut entries = self.entries();
+            let entry = entries
+                .get_mut(key)
+                .ok_or_else(|| RasterUtilsError::UnknownReader(key.to_owned()))?;
+            entry.last_used = Instant::now();
+            (
+                entry.generation,
+                entry.path.clone(),
+                entry.band,
+                entry.idle.pop(),
+            )
+        };
+
+        // Open outside the lock: opening may be slow (eg.
+        // remote datasets).
+        let dataset = match dataset {
+            Some(dataset) => dataset,
+            None => Dataset::open(&path)?,
+        };
+        Ok(PooledReader {
+            registry: self,
+            key: key.to_owned(),
+            generation,
+            dataset: Some(dataset),
+            band,
+            healthy: Cell::new(true),
+        })
+    }
+
+    /// Close idle handles of keys not used within the TTL.
+    /// Returns the number of closed handles.
+    pub fn evict_expired(&self) -> usize {
+        let now = Instant::now();
+        let mut evicted = 0;
+        for entry in self.entries().values_mut() {
+            if now.duration_since(entry.last_used) >= self.ttl {
+                evicted += entry.idle.len();
+                entry.idle.clear();
+            }
+        }
+        evicted
+    }
+
+    /// Check the idle handles of `key` by reading a single
+    /// pixel from each, and drop the ones that fail.
+    /// Returns the number of dropped handles.
+    pub fn health_check(&self, key: &str) -> Result<usize> {
+        let (generation, band, idle) = {
+            let mut entries = self.entries();
+            let entry = entries
+                .get_mut(key)
+                .ok_or_else(|| RasterUtilsError::UnknownReader(key.to_owned()))?;
+            (
+                entry.generation,
+                entry.band,
+                std::mem::take(&mut entry.idle),
+            )
+        };
+
+        let total = idle.len();
+        let healthy: Vec<_> = idle
+            .into_iter()
+            .filter(|dataset| {
+                let mut pixel = [0u8];
+                dataset
+                    .rasterband(band.get())
+                    .map_err(RasterUtilsGdalError::from)
+                    .and_then(|band| {
+                        ChunkReader::read_into_slice(&band, &mut pixel, ((0, 0), (1, 1)).into())
+                    })
+                    .is_ok()
+            })
+            .collect();
+        let dropped = total - healthy.len();
+
+        if let Some(entry) = self.entries().get_mut(key) {
+            if entry.generation == generation {
+                entry.idle.extend(healthy);
+            }
+        }
+        Ok(dropped)
+    }
+
+    /// Return `dataset` to the pool of `key`, unless the key
+    /// was unregistered or replaced since `generation`.
+    fn release(&self, key: &str, generation: u64, dataset: Dataset) {
+        if let Some(entry) = self.entries().get_mut(key) {
+            if entry.generation == generation {
+                entry.idle.push(dataset);
+            }
+        }
+    }
+
+    /// Number of idle handles of `key`.
+    pub fn idle(&self, key: &str) -> usize {
+        self.entries().get(key).map_or(0, |entry| entry.idle.len())
+    }
+}
+
+/// A [`ChunkReader`] checked out of a [`ReaderRegistry`].
+///
+/// The dataset handle is returned to the pool on drop,
+/// unless a read failed in which case it is closed.
+pub struct PooledReader<'r> {
+    registry: &'r ReaderRegistry,
+    key: String,
+    generation: u64,
+    dataset: Option<Dataset>,
+    band: BandIndex,
+    healthy: Cell<bool>,
+}
+
+impl<'r> PooledReader<'r> {
+    fn dataset(&self) -> &Dataset {
+        self.dataset
+            .as_ref()
+            .expect("dataset is only taken on drop")
+    }
+}
+
+impl<'r> ChunkReader for PooledReader<'r> {
+    type Error = RasterUtilsGdalError;
+
+    fn raster_size(&self) -> Result<Size> {
+        Ok(self.dataset().raster_size())
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        self.read_into_slice_resampled(out, raster_window, buffer_size, ResampleAlg::Nearest)
+    }
+
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        let result = self
+            .dataset()
+            .rasterband(self.band.get())
+            .map_err(RasterUtilsGdalError::from)
+            .and_then(|band| band.read_into_slice_resampled(out, raster_window, buffer_size, alg));
+        if result.is_err() {
+            self.healthy.set(false);
+        }
+        result
+    }
+}
+
+impl<'r> Drop for PooledReader<'r> {
+    fn drop(&mut self) {
+        if let Some(dataset) = self.dataset.take() {
+            if self.healthy.get() {
+                self.registry.release(&self.key, self.generation, dataset);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdal::testing::vsimem_raster;
+    use ndarray::Array2;
+
+    fn read_pixel(reader: &PooledReader) -> u8 {
+        reader.read_as_array::<u8>(((0, 0), (1, 1)).into()).unwrap()[[0, 0]]
+    }
+
+    #[test]
+    fn test_replaced_while_leased() {
+        let first = vsimem_raster("registry_first.tif", &[Array2::from_elem((2, 2), 1u8)]);
+        let second = vsimem_raster("registry_second.tif", &[Array2::from_elem((2, 2), 2u8)]);
+        let registry = ReaderRegistry::new(DEFAULT_TTL);
+        registry.register("dem", &first, BandIndex::FIRST);
+
+        let leased = registry.get("dem").unwrap();
+        assert_eq!(read_pixel(&leased), 1);
+        registry.register("dem", &second, BandIndex::FIRST);
+        drop(leased);
+        assert_eq!(registry.idle("dem"), 0);
+
+        let reader = registry.get("dem").unwrap();
+        assert_eq!(read_pixel(&reader), 2);
+        drop(reader);
+        assert_eq!(registry.idle("dem"), 1);
+    }
+
+    #[test]
+    fn test_unregistered_while_leased() {
+        let first = vsimem_raster(
+            "registry_unregistered.tif",
+            &[Array2::from_elem((2, 2), 1u8)],
+        );
+        let second = vsimem_raster("registry_new.tif", &[Array2::from_elem((2, 2), 3u8)]);
+        let registry = ReaderRegistry::new(DEFAULT_TTL);
+        registry.register("dem", &first, BandIndex::FIRST);
+
+        let leased = registry.get("dem").unwrap();
+        assert!(registry.unregister("dem"));
+        // Still usable after the key is gone.
+        assert_eq!(read_pixel(&leased), 1);
+        registry.register("dem", &second, BandIndex::FIRST);
+        drop(leased);
+        assert_eq!(registry.idle("dem"), 0);
+        assert_eq!(read_pixel(&registry.get("dem").unwrap()), 3);
+    }
+}
@@ -0,0 +1,199 @@
+//! Retry transient read failures.
+
+use super::readers::{ChunkReader, Pixel, ResampleAlg};
+use super::{RasterUtilsGdalError, Result};
+use crate::geometry::{RasterWindow, Size};
+use gdal::errors::GdalError;
+
+use std::{thread, time::Duration};
+
+/// Default [`RetryingReader`] predicate: I/O and HTTP
+/// errors, and HTTP responses that are usually transient
+/// (`429`, `5xx`) or timeouts.
+pub fn is_transient(error: &GdalError) -> bool {
+    match error {
+        GdalError::CplError { number, msg, .. } => {
+            let number = *number as u32;
+            number == gdal_sys::CPLE_FileIO
+                || number == gdal_sys::CPLE_HttpResponse
+                || msg.contains("HTTP response code: 429")
+                || msg.contains("HTTP response code: 5")
+                || msg.contains("timed out")
+        }
+        _ => false,
+    }
+}
+
+/// A [`ChunkReader`] that retries failed reads with
+/// exponential backoff.
+///
+/// Only errors accepted by the predicate (see
+/// [`is_transient`]) are retried; others are returned
+/// immediately.
+pub struct RetryingReader<R, F = fn(&GdalError) -> bool> {
+    inner: R,
+    retries: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retryable: F,
+}
+
+impl<R: ChunkReader<Error = RasterUtilsGdalError>> RetryingReader<R> {
+    /// Retry up to 3 times, waiting 100ms, 200ms, 400ms, ...
+    /// (at most 10s) between attempts.
+    pub fn new(inner: R) -> Self {
+        RetryingReader {
+            inner,
+            retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            retryable: is_transient,
+        }
+    }
+}
+
+impl<R, F> RetryingReader<R, F>
+where
+    R: ChunkReader<Error = RasterUtilsGdalError>,
+    F: Fn(&GdalError) -> bool,
+{
+    /// Maximum number of retries after the first attempt.
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Wait before the first retry; doubled for each retry.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Retry the errors accepted by `retryable` instead.
+    pub fn with_predicate<G>(self, retryable: G) -> RetryingReader<R, G>
+    where
+        G: Fn(&GdalError) -> bool,
+    {
+        RetryingReader {
+            inner: self.inner,
+            retries: self.retries,
+            initial_backoff: self.initial_backoff,
+            max_backoff: self.max_backoff,
+            retryable,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn retry<T>(&self, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if attempt < self.retries && e.gdal_error().is_some_and(&self.retryable) => {
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<R, F> ChunkReader for RetryingReader<R, F>
+where
+    R: ChunkReader<Error = RasterUtilsGdalError>,
+    F: Fn(&GdalError) -> bool,
+{
+    type Error = RasterUtilsGdalError;
+
+    fn raster_size(&self) -> Result<Size> {
+        self.retry(|| self.inner.raster_size())
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        self.read_into_slice_resampled(out, raster_window, buffer_size, ResampleAlg::Nearest)
+    }
+
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        self.retry(|| {
+            self.inner
+                .read_into_slice_resampled(out, raster_window.clone(), buffer_size, alg)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Fails the first `failures` reads with `number`.
+    struct FlakyReader {
+        failures: Cell<usize>,
+        number: i32,
+    }
+
+    impl ChunkReader for FlakyReader {
+        type Error = RasterUtilsGdalError;
+
+        fn raster_size(&self) -> Result<Size> {
+            Ok((1, 1))
+        }
+
+        fn read_into_slice_sized<T>(&self, _: &mut [T], _: RasterWindow, _: Size) -> Result<()>
+        where
+            T: Pixel,
+        {
+            if self.failures.get() == 0 {
+                return Ok(());
+            }
+            self.failures.set(self.failures.get() - 1);
+            Err(RasterUtilsGdalError::GdalError(GdalError::CplError {
+                class: gdal_sys::CPLErr::CE_Failure,
+                number: self.number,
+                msg: "HTTP response code: 503".into(),
+            }))
+        }
+    }
+
+    fn reader(failures: usize, number: i32) -> RetryingReader<FlakyReader> {
+        RetryingReader::new(FlakyReader {
+            failures: Cell::new(failures),
+            number,
+        })
+        .with_backoff(Duration::ZERO, Duration::ZERO)
+    }
+
+    #[test]
+    fn test_retry() {
+        let window = || RasterWindow::from(((0, 0), (1, 1)));
+
+        assert!(reader(3, 1).read_as_array::<u8>(window()).is_ok());
+        assert!(reader(4, 1).read_as_array::<u8>(window()).is_err());
+
+        let reader = reader(1, 1).with_predicate(|_: &GdalError| false);
+        assert!(reader.read_as_array::<u8>(window()).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Detect unreadable blocks before a long job starts.
+//!
+//! A raster may look fine to `gdalinfo` and still contain
+//! blocks that fail to decode. [`scan`] walks every band
+//! chunk-by-chunk (one row of blocks at a time), and when a
+//! chunk fails, re-reads it block-by-block to pinpoint the
+//! offending windows.
+
+use super::readers::{BandIndex, ChunkReader};
+use super::{RasterUtilsGdalError, Result};
+use crate::chunking::builder::ChunkConfigBuilder;
+use crate::geometry::RasterWindow;
+use gdal::{raster::RasterBand, Dataset};
+
+use std::num::NonZeroUsize;
+
+/// A block that could not be read.
+#[derive(Debug)]
+pub struct CorruptBlock {
+    /// Band containing the block.
+    pub band: BandIndex,
+    /// Window of the block within the raster.
+    pub window: RasterWindow,
+    /// Error returned while decoding the block.
+    pub error: RasterUtilsGdalError,
+}
+
+/// Result of [`scan`].
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// Number of blocks that were read.
+    pub blocks_scanned: usize,
+    /// Blocks that failed to decode.
+    pub corrupt_blocks: Vec<CorruptBlock>,
+}
+
+impl ScanReport {
+    /// Whether every block was read successfully.
+    pub fn is_ok(&self) -> bool {
+        self.corrupt_blocks.is_empty()
+    }
+}
+
+/// Attempt to decode every block of every band of `dataset`.
+///
+/// Errors are only returned if a band can't be accessed at
+/// all; unreadable blocks are collected in the report.
+pub fn scan(dataset: &Dataset) -> Result<ScanReport> {
+    let mut report = ScanReport::default();
+    for index in 1..=dataset.raster_count() {
+        let band = dataset.rasterband(index)?;
+        let band_index = BandIndex::new(NonZeroUsize::new(index).expect("bands are 1-indexed"));
+        scan_band(&band, band_index, &mut report);
+    }
+    Ok(report)
+}
+
+/// Scan a single band, accumulating into `report`.
+pub fn scan_band(band: &RasterBand, band_index: BandIndex, report: &mut ScanReport) {
+    let (cols, rows) = band.size();
+    let (block_x, block_y) = band.block_size();
+    let (width, height, block_x, block_y) = match (
+        NonZeroUsize::new(cols),
+        NonZeroUsize::new(rows),
+        NonZeroUsize::new(block_x),
+        NonZeroUsize::new(block_y),
+    ) {
+        (Some(w), Some(h), Some(bx), Some(by)) => (w, h, bx, by),
+        _ => return,
+    };
+
+    let cfg = ChunkConfigBuilder::new(width, height)
+        .add_block_size(block_y)
+        .with_data_height(block_y)
+        .build();
+    let blocks_per_row = cols.div_ceil(block_x.get());
+
+    for chunk in &cfg {
+        let (start, size) = (chunk.start(), chunk.size());
+        report.blocks_scanned += blocks_per_row * size.div_ceil(block_y.get());
+        if ChunkReader::read_as_array::<f64>(band, chunk.into()).is_ok() {
+            continue;
+        }
+
+        // Pinpoint the failing blocks within the chunk.
+        for y in (start..start + size).step_by(block_y.get()) {
+            for x in (0..cols).step_by(block_x.get()) {
+                let size_x = block_x.get().min(cols - x);
+                let size_y = block_y.get().min(start + size - y);
+                let window: RasterWindow = ((x, y), (size_x, size_y)).into();
+                if let Err(error) = ChunkReader::read_as_array::<f64>(band, window.clone()) {
+                    report.corrupt_blocks.push(CorruptBlock {
+                        band: band_index,
+                        window,
+                        error,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdal::writers::ChunkWriter;
+    use gdal::{cpl::CslStringList, raster::RasterCreationOptions, DriverManager, Metadata};
+    use ndarray::Array2;
+
+    #[test]
+    fn test_scan() {
+        // 2 x 2 deflated tiles of 16 pixels.
+        let path = std::env::temp_dir().join("raster_utils_scan.tif");
+        {
+            let driver = DriverManager::get_driver_by_name("GTiff").unwrap();
+            let mut options = CslStringList::new();
+            for (key, value) in [
+                ("TILED", "YES"),
+                ("BLOCKXSIZE", "16"),
+                ("BLOCKYSIZE", "16"),
+                ("COMPRESS", "DEFLATE"),
+            ] {
+                options.set_name_value(key, value).unwrap();
+            }
+            let options: RasterCreationOptions = options;
+            let dataset = driver
+                .create_with_band_type_with_options::<u16, _>(&path, 32, 32, 1, &options)
+                .unwrap();
+            let array = Array2::from_shape_fn((32, 32), |(i, j)| (i * 32 + j) as u16);
+            dataset
+                .rasterband(1)
+                .unwrap()
+                .write_array(array.view(), ((0, 0), (32, 32)).into())
+                .unwrap();
+        }
+
+        let report = scan(&Dataset::open(&path).unwrap()).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.blocks_scanned, 4);
+
+        // Overwrite the data of the tile at (1, 0).
+        let (offset, size) = {
+            let dataset = Dataset::open(&path).unwrap();
+            let band = dataset.rasterband(1).unwrap();
+            let item =
+                |key: &str| -> usize { band.metadata_item(key, "TIFF").unwrap().parse().unwrap() };
+            (item("BLOCK_OFFSET_1_0"), item("BLOCK_SIZE_1_0"))
+        };
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[offset..offset + size].fill(0xFF);
+        std::fs::write(&path, bytes).unwrap();
+
+        let report = scan(&Dataset::open(&path).unwrap()).unwrap();
+        assert_eq!(report.blocks_scanned, 4);
+        assert_eq!(report.corrupt_blocks.len(), 1);
+        let corrupt = &report.corrupt_blocks[0];
+        assert_eq!(corrupt.band, BandIndex::FIRST);
+        assert_eq!(corrupt.window, RasterWindow::from(((16, 0), (16, 16))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+//! Time-series stacks of rasters on the same grid.
+//!
+//! A [`RasterStack`] reads the same window from an ordered
+//! list of rasters (eg. one file per date) into an
+//! [`Array3`] of shape (time, rows, cols). The temporal
+//! reducers in [`crate::ops::temporal`] turn those into
+//! per-pixel composites.
+//!
+//! Stacks that are read several times (eg. overlapping
+//! windows) can keep their reads in memory, compressed, by
+//! wrapping the readers in a
+//! [`CachedReader`][crate::cache::CachedReader] (with the
+//! "cache" feature).
+
+use super::multi::{MultiArrayReader, MultiReader};
+use super::readers::{BandIndex, ChunkReader, DatasetReader, Pixel};
+use super::{ErrorContext, RasterUtilsGdalError, Result, ResultExt};
+use crate::chunking::{Chunk, ChunkConfig};
+use crate::geometry::{RasterWindow, Size};
+use gdal::Dataset;
+use ndarray::{stack, Array3, ArrayView2, Axis};
+
+use std::path::Path;
+
+/// Ordered stack of readers sharing the same grid.
+pub struct RasterStack<R>(MultiReader<R>);
+
+impl<R: ChunkReader<Error = RasterUtilsGdalError>> RasterStack<R> {
+    /// Stack `readers`, in time order. See [`MultiReader::new`].
+    pub fn new(readers: Vec<R>) -> Result<Self> {
+        MultiReader::new(readers).map(RasterStack)
+    }
+
+    /// Number of rasters (time steps) in the stack.
+    pub fn len(&self) -> usize {
+        self.0.readers().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Size (x, y) shared by all rasters.
+    pub fn raster_size(&self) -> Size {
+        self.0.raster_size()
+    }
+
+    /// Read `raster_window` from every raster, stacked along
+    /// the first axis.
+    pub fn read_as_array<T>(&self, raster_window: RasterWindow) -> Result<Array3<T>>
+    where
+        T: Pixel,
+    {
+        let arrays = self.0.read_as_arrays::<T>(raster_window)?;
+        let views: Vec<ArrayView2<T>> = arrays.iter().map(|array| array.view()).collect();
+        Ok(stack(Axis(0), &views)?)
+    }
+
+    /// Helper to read the stack at the location of an output
+    /// of [`ChunkConfig`] iterator.
+    pub fn read_chunk<T>(&self, chunk: Chunk) -> Result<Array3<T>>
+    where
+        T: Pixel,
+    {
+        self.read_as_array(chunk.into())
+    }
+
+    /// Iterate over the chunks of `config`, reading the stack
+    /// for each chunk.
+    pub fn iter_chunks<'a, T>(
+        &'a self,
+        config: &'a ChunkConfig,
+    ) -> impl Iterator<Item = Result<(Chunk<'a>, Array3<T>)>> + 'a
+    where
+        T: Pixel,
+    {
+        config
+            .iter()
+            .map(move |chunk| Ok((chunk, self.read_chunk(chunk)?)))
+    }
+}
+
+impl RasterStack<DatasetReader> {
+    /// Open the rasters at `paths`, in time order, reading
+    /// `band` of each. Checks that they share the same grid.
+    pub fn open<P: AsRef<Path>>(paths: &[P], band: BandIndex) -> Result<Self> {
+        let readers = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                Dataset::open(path)
+                    .context(|| ErrorContext {
+                        path: Some(path.to_path_buf()),
+                        band: Some(band.get()),
+                        window: None,
+                    })
+                    .map(|dataset| DatasetReader(dataset, band))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        MultiReader::from_datasets(readers).map(RasterStack)
+    }
+}
@@ -0,0 +1,125 @@
+//! Discover and open subdatasets of container formats
+//! (HDF5, NetCDF, ...).
+
+use super::{ErrorContext, RasterUtilsGdalError, Result, ResultExt};
+use gdal::{Dataset, Metadata};
+
+use std::path::Path;
+
+/// A subdataset, as listed in the `SUBDATASETS` metadata
+/// domain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubdatasetInfo {
+    /// 1-based index of the subdataset.
+    pub index: usize,
+    /// Connection string to open the subdataset with.
+    pub name: String,
+    pub description: String,
+}
+
+impl SubdatasetInfo {
+    /// Whether `name` refers to this subdataset: either the
+    /// full connection string, or its last `:`-separated
+    /// component (usually the variable name).
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || self.name.rsplit(':').next() == Some(name)
+    }
+}
+
+/// List the subdatasets of `dataset`, in index order.
+pub fn subdatasets(dataset: &Dataset) -> Vec<SubdatasetInfo> {
+    let mut infos: Vec<SubdatasetInfo> = vec![];
+    for item in dataset.metadata_domain("SUBDATASETS").unwrap_or_default() {
+        let Some((key, value)) = item.split_once('=') else {
+            continue;
+        };
+        let Some(rest) = key.strip_prefix("SUBDATASET_") else {
+            continue;
+        };
+        let Some((index, field)) = rest.split_once('_') else {
+            continue;
+        };
+        let Ok(index) = index.parse() else {
+            continue;
+        };
+
+        let position = match infos.iter().position(|info| info.index == index) {
+            Some(position) => position,
+            None => {
+                infos.push(SubdatasetInfo {
+                    index,
+                    name: String::new(),
+                    description: String::new(),
+                });
+                infos.len() - 1
+            }
+        };
+        match field {
+            "NAME" => infos[position].name = value.to_owned(),
+            "DESC" => infos[position].description = value.to_owned(),
+            _ => {}
+        }
+    }
+    infos.retain(|info| !info.name.is_empty());
+    infos.sort_by_key(|info| info.index);
+    infos
+}
+
+/// Selects a subdataset by 1-based index or by name (see
+/// [`SubdatasetInfo::matches`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubdatasetRef<'a> {
+    Index(usize),
+    Name(&'a str),
+}
+
+impl<'a> From<usize> for SubdatasetRef<'a> {
+    fn from(index: usize) -> Self {
+        SubdatasetRef::Index(index)
+    }
+}
+
+impl<'a> From<&'a str> for SubdatasetRef<'a> {
+    fn from(name: &'a str) -> Self {
+        SubdatasetRef::Name(name)
+    }
+}
+
+/// Open a subdataset of the container at `path`.
+pub fn open_subdataset<'a, S: Into<SubdatasetRef<'a>>>(
+    path: &Path,
+    subdataset: S,
+) -> Result<Dataset> {
+    let context = || ErrorContext {
+        path: Some(path.to_path_buf()),
+        ..Default::default()
+    };
+    let container = Dataset::open(path).context(context)?;
+    let subdataset = subdataset.into();
+    let info = subdatasets(&container)
+        .into_iter()
+        .find(|info| match subdataset {
+            SubdatasetRef::Index(index) => info.index == index,
+            SubdatasetRef::Name(name) => info.matches(name),
+        })
+        .ok_or_else(|| RasterUtilsGdalError::UnknownSubdataset(format!("{:?}", subdataset)))
+        .context(context)?;
+    Dataset::open(&info.name).context(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let info = SubdatasetInfo {
+            index: 1,
+            name: r#"NETCDF:"data.nc":tas"#.into(),
+            description: "[12x180x360] tas (32-bit floating-point)".into(),
+        };
+        assert!(info.matches("tas"));
+        assert!(info.matches(r#"NETCDF:"data.nc":tas"#));
+        assert!(!info.matches("pr"));
+    }
+}
@@ -0,0 +1,44 @@
+//! Fixtures of the tests of the GDAL backend.
+
+use super::writers::ChunkWriter;
+use gdal::{raster::GdalType, Dataset, DriverManager};
+use ndarray::Array2;
+
+use std::path::{Path, PathBuf};
+
+/// In-memory dataset (MEM driver) with one band per array,
+/// on a grid of unit pixels at the origin.
+pub(crate) fn mem_dataset<T: GdalType + Copy>(bands: &[Array2<T>]) -> Dataset {
+    create("MEM", "", bands)
+}
+
+/// GeoTIFF at `/vsimem/<name>` with one band per array, on a
+/// grid of unit pixels at the origin. The dataset is closed,
+/// so that readers open it by path.
+pub(crate) fn vsimem_raster<T: GdalType + Copy>(name: &str, bands: &[Array2<T>]) -> PathBuf {
+    let path = PathBuf::from(format!("/vsimem/{}", name));
+    create("GTiff", path.to_str().unwrap(), bands);
+    path
+}
+
+/// GeoTIFF at `path`, as [`vsimem_raster`].
+pub(crate) fn gtiff_raster<T: GdalType + Copy>(path: &Path, bands: &[Array2<T>]) {
+    create("GTiff", path.to_str().unwrap(), bands);
+}
+
+fn create<T: GdalType + Copy>(driver: &str, path: &str, bands: &[Array2<T>]) -> Dataset {
+    let (rows, cols) = bands[0].dim();
+    let driver = DriverManager::get_driver_by_name(driver).unwrap();
+    let mut dataset = driver
+        .create_with_band_type::<T, _>(path, cols, rows, bands.len())
+        .unwrap();
+    dataset
+        .set_geo_transform(&[0., 1., 0., rows as f64, 0., -1.])
+        .unwrap();
+    for (index, array) in bands.iter().enumerate() {
+        let mut band = dataset.rasterband(index + 1).unwrap();
+        band.write_array(array.view(), ((0, 0), (cols, rows)).into())
+            .unwrap();
+    }
+    dataset
+}
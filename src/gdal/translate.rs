@@ -0,0 +1,317 @@
+//! Chunked copies of rasters, like `gdal_translate`.
+//!
+//! [`copy_raster`] copies a subset of the bands of a raster,
+//! optionally cropped to a window and converted to another
+//! data type (see [`convert_chunk`]), into a new dataset
+//! with the given driver and creation options (eg.
+//! compression). The data is streamed chunk by chunk through
+//! a [`ChunkConfig`], so the raster is never loaded whole.
+//! With the "use-rayon" feature, the chunks are read in
+//! parallel batches, and written in order by the calling
+//! thread.
+//!
+//! Besides its use as is, it is the reference example of a
+//! [`ChunkReader`] and a [`ChunkWriter`] working together.
+//!
+//! [`ChunkConfig`]: crate::chunking::ChunkConfig
+
+use super::readers::{BandIndex, ChunkReader, Pixel, RasterPathReader};
+use super::writers::ChunkWriter;
+use super::Result;
+use crate::chunking::{builder::ChunkConfigBuilder, Chunk};
+use crate::geometry::{RasterWindow, Size};
+use crate::ops::convert::{convert_chunk, ConvertOptions, ConvertPolicy};
+use crate::RasterUtilsError;
+use gdal::{
+    cpl::CslStringList,
+    raster::{GdalDataType, RasterCreationOptions},
+    Dataset, DriverManager,
+};
+use ndarray::Array2;
+use num::{Bounded, NumCast};
+#[cfg(feature = "use-rayon")]
+use rayon::prelude::*;
+
+use std::{convert::TryFrom, num::NonZeroUsize, path::Path};
+
+/// Options of [`copy_raster`], after the flags of
+/// `gdal_translate`.
+#[derive(Clone, Debug)]
+pub struct CopyOptions {
+    driver: String,
+    bands: Vec<BandIndex>,
+    window: Option<RasterWindow>,
+    data_type: Option<GdalDataType>,
+    conversion: Option<ConvertPolicy>,
+    nodata: Option<f64>,
+    creation_options: Vec<(String, String)>,
+    data_size: NonZeroUsize,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            driver: "GTiff".to_string(),
+            bands: vec![],
+            window: None,
+            data_type: None,
+            conversion: None,
+            nodata: None,
+            creation_options: vec![],
+            data_size: NonZeroUsize::new(1 << 22).unwrap(),
+        }
+    }
+}
+
+impl CopyOptions {
+    /// Copy all bands of the whole raster to a GeoTIFF, in
+    /// the data type of the first band.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Driver of the output (`-of`).
+    pub fn with_driver(mut self, driver: &str) -> Self {
+        self.driver = driver.to_string();
+        self
+    }
+
+    /// Bands of the source to copy, in order (`-b`).
+    pub fn with_bands(mut self, bands: Vec<BandIndex>) -> Self {
+        self.bands = bands;
+        self
+    }
+
+    /// Window of the source to copy (`-srcwin`).
+    pub fn with_window(mut self, window: RasterWindow) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    /// Data type of the output (`-ot`). Values are converted
+    /// by GDAL on read, unless a conversion policy is set
+    /// with [`with_conversion`][Self::with_conversion].
+    pub fn with_data_type(mut self, data_type: GdalDataType) -> Self {
+        self.data_type = Some(data_type);
+        self
+    }
+
+    /// Convert the values to the data type of the output
+    /// under `policy` (`-scale`), mapping the nodata values of
+    /// the source to that of the output.
+    pub fn with_conversion(mut self, policy: ConvertPolicy) -> Self {
+        self.conversion = Some(policy);
+        self
+    }
+
+    /// Nodata value of the output bands (`-a_nodata`).
+    /// Defaults to the nodata values of the source.
+    pub fn with_nodata(mut self, nodata: f64) -> Self {
+        self.nodata = Some(nodata);
+        self
+    }
+
+    /// Add a creation option of the driver (`-co`), eg.
+    /// `COMPRESS=DEFLATE`.
+    pub fn with_creation_option(mut self, key: &str, value: &str) -> Self {
+        self.creation_options
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Number of pixels per chunk.
+    pub fn with_data_size(mut self, data_size: NonZeroUsize) -> Self {
+        self.data_size = data_size;
+        self
+    }
+}
+
+/// Copy the raster at `src` to a new dataset at `dst`, as
+/// described by `options`, chunk by chunk.
+///
+/// The geo transform of the output is shifted to the copied
+/// window, and the projection and nodata values of the
+/// copied bands are kept. Returns the output dataset.
+pub fn copy_raster(src: &Path, dst: &Path, options: &CopyOptions) -> Result<Dataset> {
+    let source = Dataset::open(src)?;
+    let raster_size = source.raster_size();
+    let bands = if options.bands.is_empty() {
+        (1..=source.raster_count())
+            .map(BandIndex::try_from)
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        options.bands.clone()
+    };
+    let window = options
+        .window
+        .clone()
+        .unwrap_or_else(|| ((0, 0), raster_size).into());
+    window.validate(raster_size)?;
+
+    let first = match bands.first() {
+        Some(band) => band.band_of(&source)?.band_type(),
+        None => return Err(RasterUtilsError::Unsupported("copy without bands").into()),
+    };
+    let copy = CopyJob {
+        source: &source,
+        src,
+        bands: &bands,
+        window: &window,
+        options,
+    };
+    match options.data_type.unwrap_or(first) {
+        GdalDataType::UInt8 => copy.run::<u8>(dst),
+        GdalDataType::UInt16 => copy.run::<u16>(dst),
+        GdalDataType::Int16 => copy.run::<i16>(dst),
+        GdalDataType::UInt32 => copy.run::<u32>(dst),
+        GdalDataType::Int32 => copy.run::<i32>(dst),
+        GdalDataType::Float32 => copy.run::<f32>(dst),
+        GdalDataType::Float64 => copy.run::<f64>(dst),
+        _ => Err(RasterUtilsError::Unsupported("data type of the copy").into()),
+    }
+}
+
+/// Validated arguments of [`copy_raster`].
+struct CopyJob<'a> {
+    source: &'a Dataset,
+    src: &'a Path,
+    bands: &'a [BandIndex],
+    window: &'a RasterWindow,
+    options: &'a CopyOptions,
+}
+
+impl<'a> CopyJob<'a> {
+    fn run<T: Pixel + Bounded + Send>(&self, dst: &Path) -> Result<Dataset> {
+        let (cols, rows): Size = self.window.size();
+        let output = self.create::<T>(dst, (cols, rows))?;
+        let (Some(width), Some(height)) = (NonZeroUsize::new(cols), NonZeroUsize::new(rows)) else {
+            return Ok(output);
+        };
+        let cfg = ChunkConfigBuilder::new(width, height)
+            .with_data_size(self.options.data_size)
+            .build();
+        let chunks: Vec<Chunk> = cfg.iter().collect();
+        #[cfg(feature = "use-rayon")]
+        let batch_size = rayon::current_num_threads();
+        #[cfg(not(feature = "use-rayon"))]
+        let batch_size = 1;
+
+        let offset = self.window.signed_offset();
+        for (index, &band) in self.bands.iter().enumerate() {
+            let reader = RasterPathReader(self.src, band);
+            let conversion = self.conversion::<T>(band)?;
+            let read = |chunk: &Chunk| -> Result<Array2<T>> {
+                let window = RasterWindow::from(*chunk).translate(offset);
+                match &conversion {
+                    Some(options) => {
+                        let data = reader.read_as_array::<f64>(window)?;
+                        Ok(convert_chunk(data.view(), options)?)
+                    }
+                    None => reader.read_as_array(window),
+                }
+            };
+            let mut writer = output.rasterband(index + 1)?;
+            for batch in chunks.chunks(batch_size) {
+                #[cfg(feature = "use-rayon")]
+                let arrays = batch.par_iter().map(read).collect::<Result<Vec<_>>>()?;
+                #[cfg(not(feature = "use-rayon"))]
+                let arrays = batch.iter().map(read).collect::<Result<Vec<_>>>()?;
+                for (chunk, array) in batch.iter().zip(&arrays) {
+                    writer.write_chunk(array, *chunk)?;
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    /// Options to convert band `band` to `T`, if a conversion
+    /// policy is set.
+    fn conversion<T: Pixel + Bounded>(
+        &self,
+        band: BandIndex,
+    ) -> Result<Option<ConvertOptions<f64, T>>> {
+        let Some(policy) = self.options.conversion else {
+            return Ok(None);
+        };
+        let mut options = ConvertOptions::new(policy);
+        let src_nodata = band.band_of(self.source)?.no_data_value();
+        if let Some(src_nodata) = src_nodata {
+            let nodata = self.options.nodata.unwrap_or(src_nodata);
+            let nodata = <T as NumCast>::from(nodata).ok_or(RasterUtilsError::Unsupported(
+                "nodata value out of the range of the data type",
+            ))?;
+            options = options.with_nodata(src_nodata, nodata);
+        }
+        Ok(Some(options))
+    }
+
+    /// Create the output dataset of `size` (x, y), with the
+    /// georeferencing and nodata values of the source.
+    fn create<T: Pixel>(&self, dst: &Path, size: Size) -> Result<Dataset> {
+        let driver = DriverManager::get_driver_by_name(&self.options.driver)?;
+        let mut creation_options = CslStringList::new();
+        for (key, value) in &self.options.creation_options {
+            creation_options.set_name_value(key, value)?;
+        }
+        let creation_options: RasterCreationOptions = creation_options;
+        let mut output = driver.create_with_band_type_with_options::<T, _>(
+            dst,
+            size.0,
+            size.1,
+            self.bands.len(),
+            &creation_options,
+        )?;
+
+        if let Ok(mut geo_transform) = self.source.geo_transform() {
+            let (x, y) = self.window.signed_offset();
+            let (x, y) = (x as f64, y as f64);
+            geo_transform[0] += x * geo_transform[1] + y * geo_transform[2];
+            geo_transform[3] += x * geo_transform[4] + y * geo_transform[5];
+            output.set_geo_transform(&geo_transform)?;
+        }
+        output.set_projection(&self.source.projection())?;
+        for (index, band) in self.bands.iter().enumerate() {
+            let nodata = self
+                .options
+                .nodata
+                .or(band.band_of(self.source)?.no_data_value());
+            if let Some(nodata) = nodata {
+                output
+                    .rasterband(index + 1)?
+                    .set_no_data_value(Some(nodata))?;
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdal::testing::vsimem_raster;
+
+    #[test]
+    fn test_copy_raster() {
+        let a = Array2::from_shape_fn((6, 5), |(i, j)| (i * 5 + j) as u16);
+        let b = a.mapv(|v| v * 2);
+        let src = vsimem_raster("translate_copy.tif", &[a.clone(), b]);
+        let window: RasterWindow = ((1, 2), (3, 4)).into();
+        let options = CopyOptions::new()
+            .with_driver("MEM")
+            .with_bands(vec![BandIndex::try_from(1).unwrap()])
+            .with_window(window.clone())
+            .with_data_type(GdalDataType::Float32)
+            .with_data_size(NonZeroUsize::new(4).unwrap());
+        let output = copy_raster(&src, Path::new(""), &options).unwrap();
+
+        assert_eq!(output.raster_count(), 1);
+        assert_eq!(output.raster_size(), (3, 4));
+        let geo_transform = output.geo_transform().unwrap();
+        assert_eq!((geo_transform[0], geo_transform[3]), (1., 4.));
+        let band = output.rasterband(1).unwrap();
+        assert_eq!(band.band_type(), GdalDataType::Float32);
+        let copied = ChunkReader::read_as_array::<f32>(&band, ((0, 0), (3, 4)).into()).unwrap();
+        let expected = a.slice(ndarray::s![2..6, 1..4]).mapv(|v| v as f32);
+        assert_eq!(copied, expected);
+    }
+}
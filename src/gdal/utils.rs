@@ -1,5 +1,11 @@
-use gdal::GeoTransform;
-use geo::AffineTransform;
+use super::Result;
+use crate::chunking::{builder::ChunkConfigBuilder, Chunk, Orientation};
+use crate::geometry::{AreaWeights, Crs, Ellipsoid, PixelWorldTransform};
+use crate::RasterUtilsError;
+use gdal::{raster::GdalType, Dataset, DriverManager, GeoTransform, Metadata};
+use geo::{AffineTransform, Rect};
+
+use std::{convert::TryFrom, num::NonZeroUsize, path::Path};
 
 // TODO: Add other gdal utils from original crate
 
@@ -16,14 +22,181 @@ pub fn geo_affine_from(geo_transform: &GeoTransform) -> AffineTransform {
     )
 }
 
+/// Converts Geo [AffineTransform] into raw GDAL
+/// [GeoTransform] information.
+pub fn gdal_transform_from(transform: &AffineTransform) -> GeoTransform {
+    [
+        transform.xoff(),
+        transform.a(),
+        transform.b(),
+        transform.yoff(),
+        transform.d(),
+        transform.e(),
+    ]
+}
+
+/// Read the [GeoTransform] of a dataset as a Geo
+/// [AffineTransform].
+pub fn transform_from_dataset(dataset: &Dataset) -> Result<AffineTransform> {
+    Ok(geo_affine_from(&dataset.geo_transform()?))
+}
+
+/// Read the [GeoTransform] of a dataset as a
+/// [PixelWorldTransform].
+pub fn pixel_world_transform(dataset: &Dataset) -> Result<PixelWorldTransform> {
+    transform_from_dataset(dataset).map(PixelWorldTransform::new)
+}
+
+/// Kind of the CRS of a dataset, with its ellipsoid if
+/// geographic. Datasets without CRS are treated as
+/// projected.
+pub fn crs_from_dataset(dataset: &Dataset) -> Result<Crs> {
+    match dataset.spatial_ref() {
+        Ok(srs) if srs.is_geographic() => Ok(Crs::Geographic(Ellipsoid {
+            semi_major: srs.semi_major()?,
+            semi_minor: srs.semi_minor()?,
+        })),
+        _ => Ok(Crs::Projected),
+    }
+}
+
+/// [`AreaWeights`] of the pixels of a dataset.
+pub fn area_weights(dataset: &Dataset) -> Result<AreaWeights> {
+    Ok(AreaWeights {
+        transform: pixel_world_transform(dataset)?,
+        crs: crs_from_dataset(dataset)?,
+    })
+}
+
+/// Create a dataset with `bands` bands of type `T`, and
+/// the size, geo transform and projection of `template`.
+pub fn create_like<T: GdalType>(
+    template: &Dataset,
+    driver: &str,
+    path: &Path,
+    bands: usize,
+) -> Result<Dataset> {
+    let driver = DriverManager::get_driver_by_name(driver)?;
+    let (cols, rows) = template.raster_size();
+    let mut dataset = driver.create_with_band_type::<T, _>(path, cols, rows, bands)?;
+    if let Ok(geo_transform) = template.geo_transform() {
+        dataset.set_geo_transform(&geo_transform)?;
+    }
+    dataset.set_projection(&template.projection())?;
+    Ok(dataset)
+}
+
+impl ChunkConfigBuilder {
+    /// Create a [ChunkConfigBuilder] with the dimensions of
+    /// `dataset`, accumulating the block shapes of all its
+    /// bands.
+    pub fn from_dataset(dataset: &Dataset) -> Result<Self> {
+        Self::from_dataset_oriented(dataset, Orientation::Rows)
+    }
+
+    /// Like [`ChunkConfigBuilder::from_dataset`], for chunks
+    /// of the given `orientation`. For
+    /// [`Orientation::Columns`] the chunks are aligned on the
+    /// (x) block sizes instead.
+    pub fn from_dataset_oriented(dataset: &Dataset, orientation: Orientation) -> Result<Self> {
+        let (cols, rows) = dataset.raster_size();
+        let (width, height) = NonZeroUsize::new(cols)
+            .zip(NonZeroUsize::new(rows))
+            .ok_or(RasterUtilsError::ZeroDimention)?;
+
+        let mut builder = ChunkConfigBuilder::new(width, height).with_orientation(orientation);
+        for index in 1..=dataset.raster_count() {
+            let (block_x, block_y) = dataset.rasterband(index)?.block_size();
+            if let Some(block_shape) = NonZeroUsize::new(block_x).zip(NonZeroUsize::new(block_y)) {
+                builder = builder.add_block_shape(block_shape);
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Set `data_height` so that the blocks touched by a chunk
+    /// of `dataset` (all bands, incl. padding) stay within
+    /// the GDAL block cache.
+    ///
+    /// Only a share of the cache is used, to leave room for
+    /// other datasets (eg. the output). Compressed datasets
+    /// get a larger share, as evicted blocks are costly to
+    /// decode again. See [`ChunkConfigBuilder::with_memory_budget`].
+    ///
+    /// For [`Orientation::Columns`], the data height is a
+    /// number of columns, each touching the blocks of the
+    /// full height of the raster: set the orientation first.
+    pub fn with_auto_data_height(self, dataset: &Dataset) -> Result<Self> {
+        let cache = usize::try_from(unsafe { gdal_sys::GDALGetCacheMax64() }).unwrap_or(0);
+        let compressed = dataset
+            .metadata_item("COMPRESSION", "IMAGE_STRUCTURE")
+            .is_some();
+        let budget = if compressed { cache / 2 } else { cache / 4 };
+        let bytes_per_line = bytes_per_line(dataset, self.orientation())?;
+        Ok(self.with_memory_budget(budget, bytes_per_line))
+    }
+}
+
+/// Bytes of the blocks touched by a row of `dataset` (a
+/// column for [`Orientation::Columns`]), over all bands.
+fn bytes_per_line(dataset: &Dataset, orientation: Orientation) -> Result<NonZeroUsize> {
+    let (cols, rows) = dataset.raster_size();
+    let mut bytes = 0;
+    for index in 1..=dataset.raster_count() {
+        let band = dataset.rasterband(index)?;
+        let (block_x, block_y) = band.block_size();
+        let (breadth, block) = match orientation {
+            Orientation::Rows => (cols, block_x.max(1)),
+            Orientation::Columns => (rows, block_y.max(1)),
+        };
+        bytes += breadth.div_ceil(block) * block * band.band_type().bytes() as usize;
+    }
+    NonZeroUsize::new(bytes).ok_or(RasterUtilsError::ZeroDimention.into())
+}
+
+impl<'a> Chunk<'a> {
+    /// Bounding box of the chunk (incl. padding) in the world
+    /// coordinates of `dataset`.
+    pub fn bounds(&self, dataset: &Dataset) -> Result<Rect<f64>> {
+        Ok(self.world_bounds(&transform_from_dataset(dataset)?))
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use super::geo_affine_from;
+    use super::{bytes_per_line, gdal_transform_from, geo_affine_from};
+    use crate::chunking::Orientation;
+    use crate::gdal::testing::mem_dataset;
     use gdal::Dataset;
     use geo::{AffineOps, Point};
+    use ndarray::Array2;
     use std::path::Path;
 
+    #[test]
+    fn test_transform_round_trip() {
+        let geo_transform = [440720.0, 60.0, 0.5, 3751320.0, -0.25, -60.0];
+        assert_eq!(
+            gdal_transform_from(&geo_affine_from(&geo_transform)),
+            geo_transform
+        );
+    }
+
+    #[test]
+    fn test_bytes_per_line() {
+        // MEM blocks are single rows.
+        let dataset = mem_dataset(&[Array2::<f64>::zeros((10, 1000)), Array2::zeros((10, 1000))]);
+        assert_eq!(
+            bytes_per_line(&dataset, Orientation::Rows).unwrap().get(),
+            2 * 8000
+        );
+        assert_eq!(
+            bytes_per_line(&dataset, Orientation::Columns)
+                .unwrap()
+                .get(),
+            2 * 80
+        );
+    }
+
     #[test]
     #[ignore]
     fn test_with_input() {
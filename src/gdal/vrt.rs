@@ -0,0 +1,250 @@
+//! In-memory VRT mosaics of many rasters.
+//!
+//! [`build_vrt`] mosaics a set of rasters (eg. the files of
+//! a tile grid) into a single virtual dataset, as
+//! `gdalbuildvrt` does, without writing a file. The result
+//! is a regular [`Dataset`], so it can be read with a
+//! [`DatasetReader`][super::readers::DatasetReader] and
+//! chunked with
+//! [`ChunkConfigBuilder::from_dataset`][crate::chunking::builder::ChunkConfigBuilder::from_dataset].
+
+use super::{ErrorContext, Result, ResultExt};
+use crate::RasterUtilsError;
+use gdal::{
+    programs::raster::{build_vrt as gdal_build_vrt, BuildVRTOptions},
+    Dataset,
+};
+use geo::Rect;
+
+use std::path::Path;
+
+/// Resolution of the mosaic, when the inputs differ.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VrtResolution {
+    #[default]
+    Average,
+    Highest,
+    Lowest,
+}
+
+/// Options of [`build_vrt`], mirroring those of
+/// `gdalbuildvrt`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VrtOptions {
+    resolution: VrtResolution,
+    target_resolution: Option<(f64, f64)>,
+    bounds: Option<Rect<f64>>,
+    src_nodata: Option<f64>,
+    vrt_nodata: Option<f64>,
+    separate: bool,
+}
+
+impl VrtOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_resolution(mut self, resolution: VrtResolution) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Set the pixel size (x, y) of the mosaic, overriding
+    /// the resolution.
+    pub fn with_target_resolution(mut self, x: f64, y: f64) -> Self {
+        self.target_resolution = Some((x, y));
+        self
+    }
+
+    /// Restrict the mosaic to `bounds` (in the CRS of the
+    /// inputs).
+    pub fn with_bounds(mut self, bounds: Rect<f64>) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    /// Value of the input pixels to treat as transparent.
+    pub fn with_src_nodata(mut self, nodata: f64) -> Self {
+        self.src_nodata = Some(nodata);
+        self
+    }
+
+    /// Nodata value of the mosaic bands.
+    pub fn with_vrt_nodata(mut self, nodata: f64) -> Self {
+        self.vrt_nodata = Some(nodata);
+        self
+    }
+
+    /// Put each input in a separate band, instead of
+    /// mosaicking them.
+    pub fn with_separate(mut self, separate: bool) -> Self {
+        self.separate = separate;
+        self
+    }
+
+    /// Command line arguments of `gdalbuildvrt`.
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        match self.target_resolution {
+            Some((x, y)) => {
+                args.extend(["-tr".to_owned(), x.to_string(), y.to_string()]);
+            }
+            None => {
+                let resolution = match self.resolution {
+                    VrtResolution::Average => "average",
+                    VrtResolution::Highest => "highest",
+                    VrtResolution::Lowest => "lowest",
+                };
+                args.extend(["-resolution".to_owned(), resolution.to_owned()]);
+            }
+        }
+        if let Some(bounds) = self.bounds {
+            let (min, max) = (bounds.min(), bounds.max());
+            args.push("-te".to_owned());
+            args.extend([min.x, min.y, max.x, max.y].iter().map(f64::to_string));
+        }
+        if let Some(nodata) = self.src_nodata {
+            args.extend(["-srcnodata".to_owned(), nodata.to_string()]);
+        }
+        if let Some(nodata) = self.vrt_nodata {
+            args.extend(["-vrtnodata".to_owned(), nodata.to_string()]);
+        }
+        if self.separate {
+            args.push("-separate".to_owned());
+        }
+        args
+    }
+}
+
+/// Mosaic the rasters at `paths` into an in-memory VRT.
+///
+/// Where inputs overlap, those later in `paths` are drawn on
+/// top.
+pub fn build_vrt<P: AsRef<Path>>(paths: &[P], options: &VrtOptions) -> Result<Dataset> {
+    if paths.is_empty() {
+        return Err(RasterUtilsError::Unsupported("VRT mosaic of no rasters").into());
+    }
+    let datasets = paths
+        .iter()
+        .map(|path| {
+            let path = path.as_ref();
+            Dataset::open(path).context(|| ErrorContext {
+                path: Some(path.to_path_buf()),
+                ..Default::default()
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let options = BuildVRTOptions::new(options.args())?;
+    Ok(gdal_build_vrt(None::<&Path>, &datasets, Some(options))?)
+}
+
+/// Like [`build_vrt`], for overlapping rasters whose
+/// `nodata` pixels should not hide the data of the rasters
+/// below: each raster only overrides the ones before it
+/// where it has data.
+///
+/// The order of `paths` thus sets the priority along seams
+/// (eg. least cloudy last). `nodata` is also the nodata value
+/// of the mosaic, unless set in `options`.
+pub fn build_vrt_with_nodata<P: AsRef<Path>>(
+    paths: &[P],
+    nodata: f64,
+    options: &VrtOptions,
+) -> Result<Dataset> {
+    let mut options = options.clone().with_src_nodata(nodata);
+    if options.vrt_nodata.is_none() {
+        options.vrt_nodata = Some(nodata);
+    }
+    build_vrt(paths, &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdal::readers::{BandIndex, ChunkReader, DatasetReader};
+    use crate::gdal::testing::mem_dataset;
+    use crate::geometry::RasterWindow;
+    use gdal::{raster::RasterCreationOptions, DriverManager};
+    use ndarray::{array, Array2};
+    use std::convert::TryFrom;
+    use std::path::PathBuf;
+
+    /// GeoTIFF tile at `/vsimem/<name>`, with unit pixels and
+    /// its top left corner at (`x`, 2).
+    fn tile(name: &str, array: Array2<u8>, x: f64) -> PathBuf {
+        let path = PathBuf::from(format!("/vsimem/{}", name));
+        let mut dataset = mem_dataset(&[array]);
+        dataset
+            .set_geo_transform(&[x, 1., 0., 2., 0., -1.])
+            .unwrap();
+        let driver = DriverManager::get_driver_by_name("GTiff").unwrap();
+        dataset
+            .create_copy(&driver, &path, &RasterCreationOptions::new())
+            .unwrap();
+        path
+    }
+
+    fn read(dataset: Dataset) -> Array2<u8> {
+        let reader = DatasetReader(dataset, BandIndex::try_from(1).unwrap());
+        let size = reader.raster_size().unwrap();
+        reader
+            .read_as_array(RasterWindow::from(((0, 0), size)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_build_vrt() {
+        let left = tile("vrt_left.tif", Array2::from_elem((2, 2), 1), 0.);
+        let right = tile("vrt_right.tif", Array2::from_elem((2, 2), 2), 2.);
+        let overlay = tile("vrt_overlay.tif", array![[0, 5], [0, 5]], 0.);
+
+        let mosaic = build_vrt(&[&left, &right], &VrtOptions::new()).unwrap();
+        assert_eq!(mosaic.raster_size(), (4, 2));
+        assert_eq!(read(mosaic), array![[1, 1, 2, 2], [1, 1, 2, 2]]);
+
+        // Later rasters are drawn on top, nodata included
+        // unless set.
+        let mosaic = build_vrt(&[&left, &overlay], &VrtOptions::new()).unwrap();
+        assert_eq!(read(mosaic), array![[0, 5], [0, 5]]);
+        let mosaic = build_vrt_with_nodata(&[&left, &overlay], 0., &VrtOptions::new()).unwrap();
+        assert_eq!(mosaic.rasterband(1).unwrap().no_data_value(), Some(0.));
+        assert_eq!(read(mosaic), array![[1, 5], [1, 5]]);
+
+        let separate = VrtOptions::new().with_separate(true);
+        let mosaic = build_vrt(&[&left, &right], &separate).unwrap();
+        assert_eq!(mosaic.raster_count(), 2);
+
+        assert!(matches!(
+            build_vrt::<&Path>(&[], &VrtOptions::new())
+                .err()
+                .unwrap()
+                .raster_utils_error(),
+            Some(RasterUtilsError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_args() {
+        let options = VrtOptions::new()
+            .with_target_resolution(0.5, 0.5)
+            .with_bounds(Rect::new((0., 1.), (2., 3.)))
+            .with_vrt_nodata(-1.);
+        assert_eq!(
+            options.args(),
+            [
+                "-tr",
+                "0.5",
+                "0.5",
+                "-te",
+                "0",
+                "1",
+                "2",
+                "3",
+                "-vrtnodata",
+                "-1"
+            ]
+        );
+        let options = VrtOptions::new().with_resolution(VrtResolution::Highest);
+        assert_eq!(options.args(), ["-resolution", "highest"]);
+    }
+}
@@ -0,0 +1,860 @@
+//! Abstractions to write chunks into GDAL datasets.
+
+use super::readers::{last_cpl_error, BandIndex};
+use super::{ErrorContext, Result, ResultExt};
+use crate::chunking::Chunk;
+use crate::dynamic::DynArray;
+use crate::geometry::RasterWindow;
+use crate::nodata::NodataPolicy;
+use crate::ops::convert::{convert_chunk, ConvertOptions};
+use crate::ops::sparse::MaskedChunk;
+use crate::RasterUtilsError;
+use gdal::{
+    raster::{Buffer, ColorInterpretation, GdalType, RasterBand},
+    Dataset,
+};
+use gdal_sys::GDALDataType::{GDT_CFloat32, GDT_CFloat64};
+use ndarray::{Array2, ArrayView2, Axis};
+use num::{complex::Complex, Bounded, NumCast, ToPrimitive};
+
+use std::{
+    collections::BTreeMap,
+    num::NonZeroUsize,
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+/// Abstracts writing chunks into a raster.
+pub trait ChunkWriter {
+    /// Emulate [`RasterBand::write`].
+    ///
+    /// `data` is expected in row-major order and must hold
+    /// exactly the number of pixels in `raster_window`.
+    fn write_from_vec<T>(&mut self, data: Vec<T>, raster_window: RasterWindow) -> Result<()>
+    where
+        T: GdalType + Copy;
+
+    /// Helper to write an ndarray.
+    fn write_array<T>(&mut self, array: ArrayView2<T>, raster_window: RasterWindow) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        self.write_from_vec(array.iter().copied().collect(), raster_window)
+    }
+
+    /// Helper to write an ndarray at the location of an
+    /// output of [`ChunkConfig`][crate::chunking::ChunkConfig]
+    /// iterator.
+    fn write_chunk<T>(&mut self, array: &Array2<T>, chunk: Chunk) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        self.write_array(array.view(), chunk.into())
+    }
+
+    /// Like [`write_chunk`][Self::write_chunk], first setting
+    /// the values that are nodata under `policy` to exactly
+    /// `nodata`, so that readers comparing exactly agree.
+    fn write_chunk_with_nodata<T>(
+        &mut self,
+        array: &Array2<T>,
+        chunk: Chunk,
+        nodata: T,
+        policy: NodataPolicy,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy + ToPrimitive,
+    {
+        let mut array = array.clone();
+        policy.normalize(&mut array, nodata);
+        self.write_chunk(&array, chunk)
+    }
+
+    /// Like [`write_chunk`][Self::write_chunk], first
+    /// converting the chunk to the pixel type `U` of the
+    /// output under `options` (see [`convert_chunk`]),
+    /// instead of casting it.
+    fn write_chunk_converted<T, U>(
+        &mut self,
+        array: &Array2<T>,
+        chunk: Chunk,
+        options: &ConvertOptions<T, U>,
+    ) -> Result<()>
+    where
+        T: ToPrimitive + Copy,
+        U: GdalType + NumCast + Bounded + Copy,
+    {
+        let array = convert_chunk(array.view(), options)?;
+        self.write_chunk(&array, chunk)
+    }
+}
+
+impl<'a> ChunkWriter for RasterBand<'a> {
+    fn write_from_vec<T>(&mut self, data: Vec<T>, raster_window: RasterWindow) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "write_chunk",
+            window = ?raster_window,
+            bytes = std::mem::size_of_val(&data[..]),
+        )
+        .entered();
+
+        let context = || ErrorContext::window(&raster_window);
+        let (off, size) = raster_window.clone().into();
+        let mut buffer = Buffer::new(size, data);
+        self.write(off, size, &mut buffer).context(context)
+    }
+}
+
+/// A [`ChunkWriter`] over a band of an owned [`Dataset`].
+///
+/// Obtains a `RasterBand` handle for each write.
+pub struct DatasetWriter(pub Dataset, pub BandIndex);
+
+impl ChunkWriter for DatasetWriter {
+    fn write_from_vec<T>(&mut self, data: Vec<T>, raster_window: RasterWindow) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("write_band", band = self.1.get()).entered();
+
+        let context = || ErrorContext::dataset(&self.0, self.1.get());
+        let mut band = self.0.rasterband(self.1.get()).context(context)?;
+        ChunkWriter::write_from_vec(&mut band, data, raster_window).context(context)
+    }
+}
+
+impl From<(Dataset, BandIndex)> for DatasetWriter {
+    fn from((dataset, band): (Dataset, BandIndex)) -> Self {
+        DatasetWriter(dataset, band)
+    }
+}
+
+impl From<DatasetWriter> for Dataset {
+    fn from(writer: DatasetWriter) -> Self {
+        writer.0
+    }
+}
+
+/// Write `array` into `raster_window` of `band`, converted
+/// to the data type of the band by GDAL.
+///
+/// Complex chunks are written as complex values (into the
+/// real part for real bands). Half-precision chunks are
+/// written as `f32`, as GDAL bands don't hold `f16`.
+pub fn write_dyn(
+    band: &mut RasterBand,
+    array: &DynArray,
+    raster_window: RasterWindow,
+) -> Result<()> {
+    match array {
+        DynArray::U8(a) => band.write_array(a.view(), raster_window),
+        DynArray::I8(a) => band.write_array(a.view(), raster_window),
+        DynArray::U16(a) => band.write_array(a.view(), raster_window),
+        DynArray::I16(a) => band.write_array(a.view(), raster_window),
+        DynArray::U32(a) => band.write_array(a.view(), raster_window),
+        DynArray::I32(a) => band.write_array(a.view(), raster_window),
+        DynArray::U64(a) => band.write_array(a.view(), raster_window),
+        DynArray::I64(a) => band.write_array(a.view(), raster_window),
+        #[cfg(feature = "f16")]
+        DynArray::F16(a) => band.write_array(a.mapv(f32::from).view(), raster_window),
+        DynArray::F32(a) => band.write_array(a.view(), raster_window),
+        DynArray::F64(a) => band.write_array(a.view(), raster_window),
+        DynArray::C32(a) => write_complex(band, a, raster_window, GDT_CFloat32),
+        DynArray::C64(a) => write_complex(band, a, raster_window, GDT_CFloat64),
+    }
+}
+
+/// Write `array` into `raster_window` of `band` with
+/// `RasterIO`, from a buffer of `buffer_type`, the complex
+/// type of `T`.
+fn write_complex<T: Copy>(
+    band: &mut RasterBand,
+    array: &Array2<Complex<T>>,
+    raster_window: RasterWindow,
+    buffer_type: gdal_sys::GDALDataType::Type,
+) -> Result<()> {
+    let (off_x, off_y) = raster_window.validate(band.size())?;
+    let (size_x, size_y) = raster_window.size();
+    if array.dim() != (size_y, size_x) {
+        return Err(
+            RasterUtilsError::Unsupported("chunk of a different size than the window").into(),
+        );
+    }
+    let mut data: Vec<Complex<T>> = array.iter().copied().collect();
+    // `Complex` is `repr(C)`, with the layout of GDAL complex
+    // types.
+    let rv = unsafe {
+        gdal_sys::GDALRasterIO(
+            band.c_rasterband(),
+            gdal_sys::GDALRWFlag::GF_Write,
+            off_x as i32,
+            off_y as i32,
+            size_x as i32,
+            size_y as i32,
+            data.as_mut_ptr() as *mut std::ffi::c_void,
+            size_x as i32,
+            size_y as i32,
+            buffer_type,
+            0,
+            0,
+        )
+    };
+    if rv != gdal_sys::CPLErr::CE_None {
+        return Err(last_cpl_error(rv)).context(|| ErrorContext::window(&raster_window));
+    }
+    Ok(())
+}
+
+/// Where a [`MaskedWriter`] writes the validity of pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskTarget {
+    /// Nowhere: only the nodata value of the band is set.
+    None,
+    /// The GDAL mask band of the data band.
+    MaskBand,
+    /// An alpha band (of type `Byte`) of the dataset.
+    Alpha(BandIndex),
+}
+
+/// Writes a band of an owned [`Dataset`] along with its
+/// nodata value and, optionally, a mask or alpha band.
+///
+/// Pixels that are nodata under the [`NodataPolicy`] are
+/// written as exactly the nodata value of the band, and as
+/// `0` in the mask (`255` elsewhere), so that the data, the
+/// nodata value and the mask always agree.
+pub struct MaskedWriter {
+    dataset: Dataset,
+    band: BandIndex,
+    nodata: f64,
+    policy: NodataPolicy,
+    mask: MaskTarget,
+}
+
+impl MaskedWriter {
+    /// Write band `band` of `dataset`, setting its nodata
+    /// value to `nodata` (`NaN` under [`NodataPolicy::Nan`]).
+    pub fn new(
+        dataset: Dataset,
+        band: BandIndex,
+        nodata: f64,
+        policy: NodataPolicy,
+    ) -> Result<Self> {
+        let nodata = match policy {
+            NodataPolicy::Nan => f64::NAN,
+            _ => nodata,
+        };
+        dataset
+            .rasterband(band.get())?
+            .set_no_data_value(Some(nodata))?;
+        Ok(MaskedWriter {
+            dataset,
+            band,
+            nodata,
+            policy,
+            mask: MaskTarget::None,
+        })
+    }
+
+    /// Also write the GDAL mask band of the band, which is
+    /// created.
+    pub fn with_mask_band(mut self) -> Result<Self> {
+        self.dataset
+            .rasterband(self.band.get())?
+            .create_mask_band(false)?;
+        self.mask = MaskTarget::MaskBand;
+        Ok(self)
+    }
+
+    /// Also write band `alpha` of the dataset, which is
+    /// marked as alpha band.
+    pub fn with_alpha_band(mut self, alpha: BandIndex) -> Result<Self> {
+        self.dataset
+            .rasterband(alpha.get())?
+            .set_color_interpretation(ColorInterpretation::AlphaBand)?;
+        self.mask = MaskTarget::Alpha(alpha);
+        Ok(self)
+    }
+
+    /// Nodata value of the band.
+    pub fn nodata(&self) -> f64 {
+        self.nodata
+    }
+
+    pub fn mask(&self) -> MaskTarget {
+        self.mask
+    }
+
+    pub fn into_dataset(self) -> Dataset {
+        self.dataset
+    }
+
+    /// Write `array`, with its nodata pixels (under the
+    /// policy) set to the nodata value of the band.
+    pub fn write_array<T>(
+        &mut self,
+        array: ArrayView2<T>,
+        raster_window: RasterWindow,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy + NumCast,
+    {
+        let nodata = self.typed_nodata::<T>()?;
+        let policy = self.policy;
+        let values = array
+            .iter()
+            .map(|&value| (!policy.is_nodata(value, Some(nodata))).then_some(value));
+        self.write_values(values, nodata, raster_window)
+    }
+
+    /// Helper to write an ndarray at the location of an
+    /// output of [`ChunkConfig`][crate::chunking::ChunkConfig]
+    /// iterator.
+    pub fn write_chunk<T>(&mut self, array: &Array2<T>, chunk: Chunk) -> Result<()>
+    where
+        T: GdalType + Copy + NumCast,
+    {
+        self.write_array(array.view(), chunk.into())
+    }
+
+    /// Write `masked`, whose nodata pixels (with its own
+    /// nodata value) are written as the nodata value of the
+    /// band.
+    pub fn write_masked<T>(
+        &mut self,
+        masked: MaskedChunk<T>,
+        raster_window: RasterWindow,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy + NumCast + PartialEq,
+    {
+        let nodata = self.typed_nodata::<T>()?;
+        let policy = self.policy;
+        let masked_policy = masked.policy();
+        let nodata_in = masked.nodata();
+        let data = masked.into_dense();
+        let values = data.iter().map(|&value| {
+            let invalid = masked_policy.is_nodata(value, Some(nodata_in))
+                || policy.is_nodata(value, Some(nodata));
+            (!invalid).then_some(value)
+        });
+        self.write_values(values, nodata, raster_window)
+    }
+
+    fn typed_nodata<T: NumCast>(&self) -> Result<T> {
+        <T as NumCast>::from(self.nodata).ok_or(
+            RasterUtilsError::Unsupported("nodata value not representable in the output type")
+                .into(),
+        )
+    }
+
+    /// Write `values` (`None` for nodata) and the mask.
+    fn write_values<T, I>(
+        &mut self,
+        values: I,
+        nodata: T,
+        raster_window: RasterWindow,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+        I: Iterator<Item = Option<T>>,
+    {
+        let (data, mask): (Vec<T>, Vec<u8>) = values
+            .map(|value| match value {
+                Some(value) => (value, 255),
+                None => (nodata, 0),
+            })
+            .unzip();
+
+        let context = || ErrorContext::dataset(&self.dataset, self.band.get());
+        let mut band = self.dataset.rasterband(self.band.get()).context(context)?;
+        ChunkWriter::write_from_vec(&mut band, data, raster_window.clone()).context(context)?;
+        match self.mask {
+            MaskTarget::None => {}
+            MaskTarget::MaskBand => {
+                let mut mask_band = band.open_mask_band().context(context)?;
+                ChunkWriter::write_from_vec(&mut mask_band, mask, raster_window)
+                    .context(context)?;
+            }
+            MaskTarget::Alpha(alpha) => {
+                let context = || ErrorContext::dataset(&self.dataset, alpha.get());
+                let mut alpha = self.dataset.rasterband(alpha.get()).context(context)?;
+                ChunkWriter::write_from_vec(&mut alpha, mask, raster_window).context(context)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Errors of the [`StreamingWriter`] and the
+/// [`OrderedWriter`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum WriteError {
+    #[error("Rows written out of order: expected row {expected}, found {found}")]
+    OutOfOrderWrite { expected: usize, found: usize },
+    #[error("Width mismatch: expected {expected} columns, found {found}")]
+    WidthMismatch { expected: usize, found: usize },
+    #[error("Chunk {found} submitted out of order: chunk {expected} is next")]
+    ChunkOutOfOrder { expected: usize, found: usize },
+    #[error("Chunk {next} was never submitted ({pending} later chunks pending)")]
+    MissingChunk { next: usize, pending: usize },
+    #[error("An earlier ordered write failed")]
+    OrderedWriteFailed,
+}
+
+/// Writes outputs strictly top-to-bottom.
+///
+/// Rows are accepted in order and buffered until a full
+/// group of `block_height` rows is available, which is then
+/// written immediately and released. Writer-side memory is
+/// thus bounded by one row group (plus the rows being
+/// pushed), regardless of the output size.
+pub struct StreamingWriter<W: ChunkWriter, T> {
+    writer: W,
+    width: usize,
+    block_height: usize,
+    /// Index of the first row in `buffer`.
+    next_row: usize,
+    /// Buffered rows, in row-major order.
+    buffer: Vec<T>,
+}
+
+impl<W, T> StreamingWriter<W, T>
+where
+    W: ChunkWriter,
+    T: GdalType + Copy,
+{
+    /// Create a streaming writer for a raster of given
+    /// `width` that flushes `block_height` rows at a time.
+    ///
+    /// `block_height` should be the (y) block size of the
+    /// output band, or a multiple of it.
+    pub fn new(writer: W, width: NonZeroUsize, block_height: NonZeroUsize) -> Self {
+        let (width, block_height) = (width.get(), block_height.get());
+        StreamingWriter {
+            writer,
+            width,
+            block_height,
+            next_row: 0,
+            buffer: Vec::with_capacity(width * block_height),
+        }
+    }
+
+    /// Index of the next row expected by the writer.
+    pub fn next_row(&self) -> usize {
+        self.next_row + self.buffer.len() / self.width
+    }
+
+    /// Append `rows` starting at row `start`.
+    ///
+    /// Fails if `start` is not the next expected row or if
+    /// the width of `rows` doesn't match the output.
+    pub fn push_rows(&mut self, start: usize, rows: ArrayView2<T>) -> Result<()> {
+        let expected = self.next_row();
+        if start != expected {
+            return Err(WriteError::OutOfOrderWrite {
+                expected,
+                found: start,
+            }
+            .into());
+        }
+        if rows.ncols() != self.width {
+            return Err(WriteError::WidthMismatch {
+                expected: self.width,
+                found: rows.ncols(),
+            }
+            .into());
+        }
+
+        for row in rows.axis_iter(Axis(0)) {
+            self.buffer.extend(row.iter().copied());
+            if self.buffer.len() == self.width * self.block_height {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write all buffered rows.
+    fn flush(&mut self) -> Result<()> {
+        let rows = self.buffer.len() / self.width;
+        if rows == 0 {
+            return Ok(());
+        }
+        let data = std::mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(self.width * self.block_height),
+        );
+        let window = ((0, self.next_row), (self.width, rows)).into();
+        self.writer.write_from_vec(data, window)?;
+        self.next_row += rows;
+        Ok(())
+    }
+
+    /// Flush remaining rows and return the underlying
+    /// writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes chunks submitted from any thread strictly in chunk
+/// order.
+///
+/// Parallel processing completes chunks out of order, while
+/// some outputs (streamed COGs, pipes, [`StreamingWriter`])
+/// must be written sequentially. Chunks are submitted with
+/// their index (as enumerated from the config iterator): the
+/// next chunk is written immediately, along with any
+/// following chunks it unblocks; later ones are held until
+/// then. At most `capacity` chunks are held: submitting
+/// another blocks until the next chunk arrives.
+///
+/// To avoid blocking every worker, `capacity` should be at
+/// least the number of threads submitting.
+pub struct OrderedWriter<W, T> {
+    state: Mutex<OrderedState<W, T>>,
+    written: Condvar,
+    capacity: usize,
+}
+
+struct OrderedState<W, T> {
+    writer: W,
+    /// Index of the next chunk to write.
+    next: usize,
+    /// Held chunks, by index.
+    pending: BTreeMap<usize, (Vec<T>, RasterWindow)>,
+    failed: bool,
+}
+
+impl<W, T> OrderedWriter<W, T>
+where
+    W: ChunkWriter,
+    T: GdalType + Copy,
+{
+    /// Write to `writer`, holding at most `capacity`
+    /// out-of-order chunks (at least one).
+    pub fn new(writer: W, capacity: usize) -> Self {
+        OrderedWriter {
+            state: Mutex::new(OrderedState {
+                writer,
+                next: 0,
+                pending: BTreeMap::new(),
+                failed: false,
+            }),
+            written: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Index of the next chunk to write.
+    pub fn next_index(&self) -> usize {
+        self.state().next
+    }
+
+    /// Number of chunks held, waiting for earlier ones.
+    pub fn pending(&self) -> usize {
+        self.state().pending.len()
+    }
+
+    /// Submit `array` of chunk `index`, to be written at
+    /// `raster_window`.
+    ///
+    /// Blocks while the buffer is full, unless `index` is the
+    /// next chunk. Fails if chunk `index` was already
+    /// submitted, or if writing it (or the chunks it
+    /// unblocks) fails; later submissions then fail with
+    /// [`OrderedWriteFailed`][WriteError::OrderedWriteFailed].
+    pub fn submit_array(
+        &self,
+        index: usize,
+        array: ArrayView2<T>,
+        raster_window: RasterWindow,
+    ) -> Result<()> {
+        let data = array.iter().copied().collect();
+        let mut state = self.state();
+        loop {
+            if state.failed {
+                return Err(WriteError::OrderedWriteFailed.into());
+            }
+            if index < state.next || state.pending.contains_key(&index) {
+                return Err(WriteError::ChunkOutOfOrder {
+                    expected: state.next,
+                    found: index,
+                }
+                .into());
+            }
+            if index == state.next || state.pending.len() < self.capacity {
+                break;
+            }
+            state = self
+                .written
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        state.pending.insert(index, (data, raster_window));
+        let mut result = Ok(());
+        loop {
+            let next = state.next;
+            let Some((data, raster_window)) = state.pending.remove(&next) else {
+                break;
+            };
+            if let Err(e) = state.writer.write_from_vec(data, raster_window) {
+                state.failed = true;
+                result = Err(e);
+                break;
+            }
+            state.next += 1;
+        }
+        drop(state);
+        self.written.notify_all();
+        result
+    }
+
+    /// Submit `array` of chunk `index` at the location of
+    /// `chunk` (see [`ChunkWriter::write_chunk`]).
+    pub fn submit_chunk(&self, index: usize, array: &Array2<T>, chunk: Chunk) -> Result<()> {
+        self.submit_array(index, array.view(), chunk.into())
+    }
+
+    /// Return the underlying writer, once all submitted
+    /// chunks are written.
+    ///
+    /// Fails if chunks are still held, ie. an earlier chunk
+    /// was never submitted.
+    pub fn finish(self) -> Result<W> {
+        let state = self
+            .state
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        if state.failed {
+            return Err(WriteError::OrderedWriteFailed.into());
+        }
+        if !state.pending.is_empty() {
+            return Err(WriteError::MissingChunk {
+                next: state.next,
+                pending: state.pending.len(),
+            }
+            .into());
+        }
+        Ok(state.writer)
+    }
+
+    fn state(&self) -> MutexGuard<'_, OrderedState<W, T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gdal::readers::{read_dyn, ChunkReader};
+    use crate::gdal::testing::mem_dataset;
+    use crate::gdal::RasterUtilsGdalError;
+    use gdal::DriverManager;
+    use ndarray::array;
+    use std::{convert::TryFrom, ffi::CString, thread};
+
+    /// Records the row offset of each write.
+    struct RecordingWriter(Vec<usize>);
+
+    impl ChunkWriter for RecordingWriter {
+        fn write_from_vec<T>(&mut self, _: Vec<T>, raster_window: RasterWindow) -> Result<()>
+        where
+            T: GdalType + Copy,
+        {
+            self.0.push(raster_window.offset().unwrap().1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_streaming_writer() {
+        let non_zero = |value| NonZeroUsize::new(value).unwrap();
+        let mut writer = StreamingWriter::new(RecordingWriter(vec![]), non_zero(3), non_zero(2));
+        let rows = Array2::<u8>::zeros((3, 3));
+        writer.push_rows(0, rows.view()).unwrap();
+        assert_eq!(writer.next_row(), 3);
+        assert!(matches!(
+            writer.push_rows(4, rows.view()),
+            Err(RasterUtilsGdalError::Write(WriteError::OutOfOrderWrite {
+                expected: 3,
+                found: 4
+            }))
+        ));
+        assert!(matches!(
+            writer.push_rows(3, Array2::<u8>::zeros((1, 2)).view()),
+            Err(RasterUtilsGdalError::Write(WriteError::WidthMismatch {
+                expected: 3,
+                found: 2
+            }))
+        ));
+        writer.push_rows(3, rows.view()).unwrap();
+        writer
+            .push_rows(6, rows.slice(ndarray::s![..1, ..]))
+            .unwrap();
+        // Groups of two rows, then the remaining row.
+        let writer = writer.finish().unwrap();
+        assert_eq!(writer.0, vec![0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_ordered_writer() {
+        let writer = OrderedWriter::new(RecordingWriter(vec![]), 4);
+        let array = Array2::<u8>::zeros((1, 2));
+        let window = |index: usize| ((0, index), (2, 1)).into();
+
+        writer.submit_array(2, array.view(), window(2)).unwrap();
+        writer.submit_array(1, array.view(), window(1)).unwrap();
+        assert_eq!((writer.next_index(), writer.pending()), (0, 2));
+        writer.submit_array(0, array.view(), window(0)).unwrap();
+        assert_eq!((writer.next_index(), writer.pending()), (3, 0));
+        assert!(matches!(
+            writer.submit_array(1, array.view(), window(1)),
+            Err(RasterUtilsGdalError::Write(WriteError::ChunkOutOfOrder {
+                expected: 3,
+                found: 1
+            }))
+        ));
+
+        // Submitters block on a full buffer until the next
+        // chunk arrives.
+        thread::scope(|scope| {
+            for index in (3..40).rev() {
+                let (writer, array) = (&writer, &array);
+                scope.spawn(move || {
+                    writer
+                        .submit_array(index, array.view(), window(index))
+                        .unwrap()
+                });
+            }
+        });
+        let written = writer.finish().unwrap().0;
+        assert_eq!(written, (0..40).collect::<Vec<_>>());
+
+        let writer = OrderedWriter::new(RecordingWriter(vec![]), 4);
+        writer.submit_array(1, array.view(), window(1)).unwrap();
+        assert!(matches!(
+            writer.finish(),
+            Err(RasterUtilsGdalError::Write(WriteError::MissingChunk {
+                next: 0,
+                pending: 1
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_dyn_complex_round_trip() {
+        // `create_with_band_type` needs a `GdalType`, which
+        // complex types are not.
+        let driver = DriverManager::get_driver_by_name("MEM").unwrap();
+        let name = CString::new("").unwrap();
+        let dataset = unsafe {
+            let c_dataset = gdal_sys::GDALCreate(
+                driver.c_driver(),
+                name.as_ptr(),
+                3,
+                2,
+                1,
+                GDT_CFloat32,
+                std::ptr::null_mut(),
+            );
+            assert!(!c_dataset.is_null());
+            Dataset::from_c_dataset(c_dataset)
+        };
+        let mut band = dataset.rasterband(1).unwrap();
+        let values = array![
+            [
+                Complex::new(1f32, -1.),
+                Complex::new(2., 0.5),
+                Complex::new(0., 3.)
+            ],
+            [
+                Complex::new(-4., 4.),
+                Complex::new(5., -0.25),
+                Complex::new(6., 6.)
+            ]
+        ];
+        let window: RasterWindow = ((0, 0), (3, 2)).into();
+        write_dyn(&mut band, &DynArray::from(values.clone()), window.clone()).unwrap();
+        assert_eq!(read_dyn(&band, window).unwrap(), DynArray::from(values));
+
+        // A part of the band.
+        let window: RasterWindow = ((1, 1), (2, 1)).into();
+        let read = read_dyn(&band, window).unwrap();
+        assert_eq!(
+            read.to_complex(),
+            array![[Complex::new(5., -0.25), Complex::new(6., 6.)]]
+        );
+    }
+
+    #[test]
+    fn test_masked_writer() {
+        let band_index = |index| BandIndex::try_from(index).unwrap();
+        let window: RasterWindow = ((0, 0), (3, 2)).into();
+
+        // Values within epsilon of the nodata value, and NaNs,
+        // are written as exactly the nodata value, and masked.
+        let dataset = mem_dataset(&[Array2::<f64>::zeros((2, 3))]);
+        let mut writer =
+            MaskedWriter::new(dataset, band_index(1), -9999., NodataPolicy::Epsilon(1e-6))
+                .unwrap()
+                .with_mask_band()
+                .unwrap();
+        assert_eq!(writer.mask(), MaskTarget::MaskBand);
+        let values = array![[1., -9999.001, 3.], [f64::NAN, 5., 6.]];
+        writer.write_array(values.view(), window.clone()).unwrap();
+        let dataset = writer.into_dataset();
+        let band = dataset.rasterband(1).unwrap();
+        assert_eq!(band.no_data_value(), Some(-9999.));
+        assert_eq!(
+            ChunkReader::read_as_array::<f64>(&band, window.clone()).unwrap(),
+            array![[1., -9999., 3.], [-9999., 5., 6.]]
+        );
+        let mask = band.open_mask_band().unwrap();
+        assert_eq!(
+            ChunkReader::read_as_array::<u8>(&mask, window.clone()).unwrap(),
+            array![[255, 0, 255], [0, 255, 255]]
+        );
+
+        // An alpha band, written from a masked chunk with its
+        // own nodata value.
+        let dataset = mem_dataset(&[Array2::<u8>::zeros((2, 3)), Array2::zeros((2, 3))]);
+        let mut writer = MaskedWriter::new(dataset, band_index(1), 0., NodataPolicy::Exact)
+            .unwrap()
+            .with_alpha_band(band_index(2))
+            .unwrap();
+        let masked = MaskedChunk::new(array![[0, 7, 9], [9, 1, 2]], 9, 0.5, NodataPolicy::Exact);
+        writer.write_masked(masked, window.clone()).unwrap();
+        let dataset = writer.into_dataset();
+        let band = dataset.rasterband(1).unwrap();
+        assert_eq!(
+            ChunkReader::read_as_array::<u8>(&band, window.clone()).unwrap(),
+            array![[0, 7, 0], [0, 1, 2]]
+        );
+        let alpha = dataset.rasterband(2).unwrap();
+        assert_eq!(alpha.color_interpretation(), ColorInterpretation::AlphaBand);
+        assert_eq!(
+            ChunkReader::read_as_array::<u8>(&alpha, window.clone()).unwrap(),
+            array![[0, 255, 0], [0, 255, 255]]
+        );
+
+        // The NaN nodata of the policy has no integer value.
+        let dataset = mem_dataset(&[Array2::<u8>::zeros((2, 3))]);
+        let mut writer = MaskedWriter::new(dataset, band_index(1), 0., NodataPolicy::Nan).unwrap();
+        assert!(writer.nodata().is_nan());
+        let error = writer
+            .write_array(Array2::<u8>::zeros((2, 3)).view(), window)
+            .unwrap_err();
+        assert!(matches!(
+            error.raster_utils_error(),
+            Some(RasterUtilsError::Unsupported(_))
+        ));
+    }
+}
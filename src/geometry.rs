@@ -3,12 +3,29 @@
 use std::usize;
 
 use geo::{AffineOps, AffineTransform, Coord, Rect};
+use ndarray::Array2;
 
-use super::chunking::ChunkWindow;
+use super::chunking::{Chunk, Orientation};
+use super::{RasterUtilsError, Result};
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
 
 /// Represents size (x, y) of a raster or a window in pixels.
 pub type Size = (usize, usize);
 
+/// A window extending beyond its raster, see
+/// [`RasterWindow::validate`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error(
+    "Window at offset {:?} of size {:?} extends beyond the raster of size {raster_size:?}",
+    .window.signed_offset(),
+    .window.size()
+)]
+pub struct WindowOutOfBounds {
+    pub window: RasterWindow,
+    pub raster_size: Size,
+}
+
 /// Represents offset (x, y) in pixels, within a raster.
 pub type Offset = (usize, usize);
 
@@ -20,16 +37,104 @@ pub fn as_f64(tuple: (usize, usize)) -> (f64, f64) {
     (tuple.0 as f64, tuple.1 as f64)
 }
 
-/// Same as [Offset](Offset) but for Gdal.
-pub type GdalOffset = (isize, isize);
+/// Offset (x, y) in pixels, which may be negative for
+/// windows extending left of or above the raster origin.
+///
+/// Convert to an in-raster [`Offset`] with
+/// [`RasterWindow::clip`] or [`RasterWindow::read_window`],
+/// which clamp the window to the raster explicitly.
+pub type SignedOffset = (isize, isize);
+
+pub fn signed_as_f64(tuple: SignedOffset) -> (f64, f64) {
+    (tuple.0 as f64, tuple.1 as f64)
+}
 
-/* /// Represents transform from pixel coordinates to "world" coordinates.
-pub type PixelWorldTransform = AffineTransform; */
+/// Same as [SignedOffset](SignedOffset), as used by the
+/// windows of Gdal.
+pub type GdalOffset = SignedOffset;
 
 /// Represents transform from a pixel coordinate to another pixel coordinate.
 pub type PixelPixelTransform = AffineTransform;
 
+/// Represents transform from pixel coordinates to "world" coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelWorldTransform(AffineTransform);
+
+impl PixelWorldTransform {
+    pub fn new(transform: AffineTransform) -> Self {
+        Self(transform)
+    }
+
+    /// The underlying affine transform.
+    pub fn affine(&self) -> &AffineTransform {
+        &self.0
+    }
+
+    /// Whether the transform is "north-up": no rotation or
+    /// skew, and rows running from north to south.
+    pub fn is_north_up(&self) -> bool {
+        self.0.b() == 0. && self.0.d() == 0. && self.0.a() > 0. && self.0.e() < 0.
+    }
+
+    /// Map pixel coordinates (x, y) to world coordinates.
+    pub fn pixel_to_world(&self, pixel: (f64, f64)) -> (f64, f64) {
+        self.0.apply(Coord::from(pixel)).x_y()
+    }
+
+    /// Map world coordinates to pixel coordinates (x, y).
+    pub fn world_to_pixel(&self, world: (f64, f64)) -> Result<(f64, f64)> {
+        Ok(self.inverse()?.apply(Coord::from(world)).x_y())
+    }
+
+    /// Transform from world coordinates to pixel
+    /// coordinates.
+    ///
+    /// Fails with [`RasterUtilsError::DegenerateTransform`]
+    /// if the transform is not invertible, instead of
+    /// producing non-finite values downstream.
+    pub fn inverse(&self) -> Result<AffineTransform> {
+        let t = &self.0;
+        let determinant = t.a() * t.e() - t.b() * t.d();
+        let inverse = if determinant.is_normal() {
+            t.inverse()
+        } else {
+            None
+        };
+        match inverse {
+            Some(inverse)
+                if [inverse.a(), inverse.b(), inverse.xoff()]
+                    .iter()
+                    .chain(&[inverse.d(), inverse.e(), inverse.yoff()])
+                    .all(|v| v.is_finite()) =>
+            {
+                Ok(inverse)
+            }
+            _ => Err(RasterUtilsError::DegenerateTransform),
+        }
+    }
+
+    /// Transform from pixel coordinates of this raster to
+    /// pixel coordinates of the raster with transform `other`.
+    pub fn transform_to(&self, other: &Self) -> Result<PixelPixelTransform> {
+        Ok(self.0.compose(&other.inverse()?))
+    }
+}
+
+impl From<AffineTransform> for PixelWorldTransform {
+    fn from(transform: AffineTransform) -> Self {
+        Self(transform)
+    }
+}
+
+impl From<PixelWorldTransform> for AffineTransform {
+    fn from(transform: PixelWorldTransform) -> Self {
+        transform.0
+    }
+}
+
 ///A block of contiguous data in a raster.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RasterWindow(Rect<f64>);
 
 impl RasterWindow {
@@ -39,9 +144,30 @@ impl RasterWindow {
         self.0.unsigned_area() as usize
     }
 
-    /// Window offset.
-    pub fn offset(&self) -> Offset {
-        as_usize(self.0.min().x_y())
+    /// Window with a possibly negative `offset` (x, y), eg.
+    /// to be read with
+    /// [`read_chunk_or_fill`][crate::reader::ChunkReader::read_chunk_or_fill].
+    /// Plain reads reject windows extending beyond the raster
+    /// with [`WindowOutOfBounds`].
+    pub fn from_signed(offset: SignedOffset, size: Size) -> Self {
+        let min = Coord::from(signed_as_f64(offset));
+        let max = min + Coord::from(as_f64(size));
+        Self(Rect::new(min, max))
+    }
+
+    /// Window offset, or `None` if the window starts left of
+    /// or above the raster origin (see
+    /// [`signed_offset`][Self::signed_offset]).
+    pub fn offset(&self) -> Option<Offset> {
+        let (x, y) = self.signed_offset();
+        (x >= 0 && y >= 0).then_some((x as usize, y as usize))
+    }
+
+    /// Window offset, which may be negative for windows
+    /// extending above or left of the raster origin.
+    pub fn signed_offset(&self) -> SignedOffset {
+        let (x, y) = self.0.min().x_y();
+        (x.floor() as isize, y.floor() as isize)
     }
 
     /// Window size (x, y)
@@ -59,6 +185,181 @@ impl RasterWindow {
     pub fn affine_transform(&self, transform: &AffineTransform) -> Self {
         Self(self.0.affine_transform(transform))
     }
+
+    /// Bounding box of the window in world coordinates, given
+    /// the pixel to world `transform` of its raster.
+    ///
+    /// All four corners are transformed, so the box is also
+    /// correct for rotated transforms.
+    pub fn to_world(&self, transform: &AffineTransform) -> Rect<f64> {
+        let (min, max) = (self.0.min(), self.0.max());
+        let corners = [
+            min,
+            Coord::from((max.x, min.y)),
+            max,
+            Coord::from((min.x, max.y)),
+        ]
+        .map(|corner| transform.apply(corner));
+
+        let (mut lower, mut upper) = (corners[0], corners[0]);
+        for corner in &corners[1..] {
+            lower.x = lower.x.min(corner.x);
+            lower.y = lower.y.min(corner.y);
+            upper.x = upper.x.max(corner.x);
+            upper.y = upper.y.max(corner.y);
+        }
+        Rect::new(lower, upper)
+    }
+
+    /// Window covered by both `self` and `other`, if they
+    /// overlap on a non-empty area.
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let min = Coord {
+            x: self.0.min().x.max(other.0.min().x),
+            y: self.0.min().y.max(other.0.min().y),
+        };
+        let max = Coord {
+            x: self.0.max().x.min(other.0.max().x),
+            y: self.0.max().y.min(other.0.max().y),
+        };
+        if min.x < max.x && min.y < max.y {
+            Some(Self(Rect::new(min, max)))
+        } else {
+            None
+        }
+    }
+
+    /// Smallest window covering both `self` and `other`.
+    pub fn union(&self, other: &Self) -> Self {
+        let min = Coord {
+            x: self.0.min().x.min(other.0.min().x),
+            y: self.0.min().y.min(other.0.min().y),
+        };
+        let max = Coord {
+            x: self.0.max().x.max(other.0.max().x),
+            y: self.0.max().y.max(other.0.max().y),
+        };
+        Self(Rect::new(min, max))
+    }
+
+    /// Whether `other` lies completely within `self`.
+    pub fn contains(&self, other: &Self) -> bool {
+        self.0.min().x <= other.0.min().x
+            && self.0.min().y <= other.0.min().y
+            && self.0.max().x >= other.0.max().x
+            && self.0.max().y >= other.0.max().y
+    }
+
+    /// Whether the window lies within a raster of
+    /// `raster_size` (x, y).
+    pub fn is_within(&self, raster_size: Size) -> bool {
+        let (off_x, off_y) = self.signed_offset();
+        let (size_x, size_y) = self.size();
+        off_x >= 0
+            && off_y >= 0
+            && off_x as usize + size_x <= raster_size.0
+            && off_y as usize + size_y <= raster_size.1
+    }
+
+    /// Offset of the window, if it lies within a raster of
+    /// `raster_size` (x, y); fails with [`WindowOutOfBounds`],
+    /// naming the window and the raster extent, otherwise.
+    ///
+    /// The readers of the crate (of every backend) validate
+    /// windows before reading, instead of forwarding them to
+    /// the underlying library.
+    pub fn validate(&self, raster_size: Size) -> std::result::Result<Offset, WindowOutOfBounds> {
+        match self.offset() {
+            Some(offset) if self.is_within(raster_size) => Ok(offset),
+            _ => Err(WindowOutOfBounds {
+                window: self.clone(),
+                raster_size,
+            }),
+        }
+    }
+
+    /// Part of the window within a raster of `raster_size`
+    /// (x, y), if any.
+    pub fn clip(&self, raster_size: Size) -> Option<Self> {
+        self.intersection(&((0, 0), raster_size).into())
+    }
+
+    /// Window to read from a raster of `raster_size` (x, y):
+    /// the offset and size of [`clip`][Self::clip], and the
+    /// offset (x, y) of that part within the window. `None`
+    /// if the window does not overlap the raster.
+    pub fn read_window(&self, raster_size: Size) -> Option<(Offset, Size, Offset)> {
+        let valid = self.clip(raster_size)?;
+        let (off_x, off_y) = self.signed_offset();
+        let (valid_x, valid_y) = valid.signed_offset();
+        Some((
+            (valid_x as usize, valid_y as usize),
+            valid.size(),
+            ((valid_x - off_x) as usize, (valid_y - off_y) as usize),
+        ))
+    }
+
+    /// Shift window by `offset` (x, y) pixels.
+    pub fn translate(&self, offset: SignedOffset) -> Self {
+        let delta = Coord::from((offset.0 as f64, offset.1 as f64));
+        Self(Rect::new(self.0.min() + delta, self.0.max() + delta))
+    }
+
+    /// Split window into consecutive windows of at most
+    /// `max_size` (x, y), in row-major order.
+    ///
+    /// Windows on the right and bottom edges may be smaller.
+    pub fn split(&self, max_size: Size) -> Vec<Self> {
+        let (off_x, off_y) = self.signed_offset();
+        let (size_x, size_y) = self.size();
+        let (max_x, max_y) = (max_size.0.max(1), max_size.1.max(1));
+
+        let mut windows = Vec::with_capacity(size_x.div_ceil(max_x) * size_y.div_ceil(max_y));
+        for y in (0..size_y).step_by(max_y) {
+            for x in (0..size_x).step_by(max_x) {
+                let offset = (off_x + x as isize, off_y + y as isize);
+                let size = (max_x.min(size_x - x), max_y.min(size_y - y));
+                windows.push(Self::from_signed(offset, size));
+            }
+        }
+        windows
+    }
+
+    /// Split window into windows of at most `max_size` (x, y),
+    /// in row-major order, cut on the boundaries of the blocks
+    /// of `block_shape` (x, y) of the raster.
+    ///
+    /// Sizes are rounded down to a multiple of the block
+    /// size (but at least one block), so windows may be
+    /// larger than `max_size` if it is smaller than a block.
+    /// Windows on the edges may be smaller.
+    pub fn split_aligned(&self, max_size: Size, block_shape: Size) -> Vec<Self> {
+        let (off_x, off_y) = self.signed_offset();
+        let (size_x, size_y) = self.size();
+        let cuts = |offset: isize, size: usize, max: usize, block: usize| {
+            let block = block.max(1);
+            let step = ((max / block).max(1) * block) as isize;
+            let end = offset + size as isize;
+            let mut cuts = vec![];
+            let mut start = offset;
+            while start < end {
+                let stop = ((start.div_euclid(step) + 1) * step).min(end);
+                cuts.push((start, (stop - start) as usize));
+                start = stop;
+            }
+            cuts
+        };
+        let xs = cuts(off_x, size_x, max_size.0, block_shape.0);
+        let ys = cuts(off_y, size_y, max_size.1, block_shape.1);
+
+        let mut windows = Vec::with_capacity(xs.len() * ys.len());
+        for &(y, height) in &ys {
+            for &(x, width) in &xs {
+                windows.push(Self::from_signed((x, y), (width, height)));
+            }
+        }
+        windows
+    }
 }
 
 impl From<(Offset, Size)> for RasterWindow {
@@ -69,16 +370,403 @@ impl From<(Offset, Size)> for RasterWindow {
     }
 }
 
-impl From<RasterWindow> for (GdalOffset, Size) {
+impl From<RasterWindow> for (SignedOffset, Size) {
     fn from(value: RasterWindow) -> Self {
-        let (x, y) = value.offset();
-        ((x as isize, y as isize), value.size())
+        (value.signed_offset(), value.size())
     }
 }
 
-impl<'a> From<ChunkWindow<'a>> for RasterWindow {
-    fn from(value: ChunkWindow<'a>) -> Self {
-        let (cfg, start, end) = value;
-        ((0 as usize, start), (cfg.width(), end - start)).into()
+impl<'a> From<Chunk<'a>> for RasterWindow {
+    fn from(value: Chunk<'a>) -> Self {
+        let config = value.config();
+        match config.orientation() {
+            Orientation::Rows => ((0, value.start()), (config.width(), value.size())).into(),
+            Orientation::Columns => ((value.start(), 0), (value.size(), config.height())).into(),
+        }
+    }
+}
+
+/// A window with fractional offset and size, to be read
+/// (resampled) on a grid of `buffer_size` (x, y) pixels.
+///
+/// Unlike [`RasterWindow`], the offset is not floored to
+/// whole pixels, so windows defined in world coordinates
+/// keep their sub-pixel precision.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FractionalWindow {
+    bounds: Rect<f64>,
+    buffer_size: Size,
+}
+
+impl FractionalWindow {
+    /// Window at fractional pixel `offset` (x, y) of `size`
+    /// (x, y) pixels.
+    pub fn new(offset: (f64, f64), size: (f64, f64), buffer_size: Size) -> Self {
+        let min = Coord::from(offset);
+        FractionalWindow {
+            bounds: Rect::new(min, min + Coord::from(size)),
+            buffer_size,
+        }
+    }
+
+    /// Window covering `bounds` (in world coordinates) of the
+    /// raster with pixel to world `transform`.
+    pub fn from_world(
+        bounds: Rect<f64>,
+        transform: &PixelWorldTransform,
+        buffer_size: Size,
+    ) -> Result<Self> {
+        let (x_1, y_1) = transform.world_to_pixel(bounds.min().x_y())?;
+        let (x_2, y_2) = transform.world_to_pixel(bounds.max().x_y())?;
+        Ok(FractionalWindow {
+            bounds: Rect::new(Coord::from((x_1, y_1)), Coord::from((x_2, y_2))),
+            buffer_size,
+        })
+    }
+
+    /// Fractional offset (x, y).
+    pub fn offset(&self) -> (f64, f64) {
+        self.bounds.min().x_y()
+    }
+
+    /// Fractional size (x, y).
+    pub fn size(&self) -> (f64, f64) {
+        (self.bounds.max() - self.bounds.min()).x_y()
+    }
+
+    /// Size (x, y) of the output grid.
+    pub fn buffer_size(&self) -> Size {
+        self.buffer_size
+    }
+
+    /// Pixel coordinates (x, y) of the center of output pixel
+    /// (`col`, `row`).
+    pub fn sample_point(&self, col: usize, row: usize) -> (f64, f64) {
+        let (off_x, off_y) = self.offset();
+        let (size_x, size_y) = self.size();
+        let (buf_x, buf_y) = as_f64(self.buffer_size);
+        (
+            off_x + (col as f64 + 0.5) * size_x / buf_x,
+            off_y + (row as f64 + 0.5) * size_y / buf_y,
+        )
+    }
+
+    /// Smallest integer window holding the pixels needed to
+    /// interpolate all sample points bilinearly. It may
+    /// extend outside the raster.
+    pub fn enclosing(&self) -> RasterWindow {
+        // Pixel centers lie at half-integer coordinates.
+        let (min, max) = (self.bounds.min(), self.bounds.max());
+        let (min_x, min_y) = ((min.x - 0.5).floor(), (min.y - 0.5).floor());
+        let (max_x, max_y) = ((max.x - 0.5).floor() + 2., (max.y - 0.5).floor() + 2.);
+        RasterWindow::from_signed(
+            (min_x as isize, min_y as isize),
+            ((max_x - min_x) as usize, (max_y - min_y) as usize),
+        )
+    }
+}
+
+/// An ellipsoid of revolution, for areas in geographic
+/// coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ellipsoid {
+    pub semi_major: f64,
+    pub semi_minor: f64,
+}
+
+impl Ellipsoid {
+    pub const WGS84: Ellipsoid = Ellipsoid {
+        semi_major: 6_378_137.,
+        semi_minor: 6_356_752.314_245_179,
+    };
+
+    pub fn sphere(radius: f64) -> Self {
+        Ellipsoid {
+            semi_major: radius,
+            semi_minor: radius,
+        }
+    }
+
+    fn eccentricity_squared(&self) -> f64 {
+        1. - (self.semi_minor / self.semi_major).powi(2)
+    }
+
+    /// Area of the cell between latitudes `lat_1` and `lat_2`
+    /// spanning `lon_span` of longitude (all in degrees).
+    pub fn cell_area(&self, lat_1: f64, lat_2: f64, lon_span: f64) -> f64 {
+        let e = self.eccentricity_squared().sqrt();
+        // Twice the authalic latitude term, integrated.
+        let q = |lat: f64| {
+            let sin = lat.clamp(-90., 90.).to_radians().sin();
+            if e < 1e-12 {
+                2. * sin
+            } else {
+                sin / (1. - e * e * sin * sin) + (e * sin).atanh() / e
+            }
+        };
+        (self.semi_minor.powi(2) * lon_span.to_radians() / 2. * (q(lat_2) - q(lat_1))).abs()
+    }
+
+    /// Area of a square degree at latitude `lat` (degrees),
+    /// for cells small enough to neglect the curvature.
+    pub fn square_degree_area(&self, lat: f64) -> f64 {
+        let e2 = self.eccentricity_squared();
+        let (sin, cos) = lat.to_radians().sin_cos();
+        let w = 1. - e2 * sin * sin;
+        let meridian = self.semi_major * (1. - e2) / w.powf(1.5);
+        let normal = self.semi_major / w.sqrt();
+        meridian * normal * cos * 1f64.to_radians().powi(2)
+    }
+}
+
+/// Kind of coordinate reference system, as needed to compute
+/// pixel areas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crs {
+    /// Longitude and latitude, in degrees, on an ellipsoid.
+    Geographic(Ellipsoid),
+    /// Projected, in linear units.
+    Projected,
+}
+
+/// Area of each pixel of `window`, in squared linear units
+/// (of the ellipsoid for geographic CRSs), as (rows, cols).
+///
+/// Areas are constant in projected CRSs. In geographic CRSs
+/// they are exact on the ellipsoid for north-up transforms,
+/// and computed at the pixel centers otherwise.
+pub fn pixel_areas(
+    transform: &PixelWorldTransform,
+    crs: &Crs,
+    window: &RasterWindow,
+) -> Array2<f64> {
+    let t = transform.affine();
+    let determinant = (t.a() * t.e() - t.b() * t.d()).abs();
+    let (off_x, off_y) = window.signed_offset();
+    let shape = window.shape();
+    match crs {
+        Crs::Projected => Array2::from_elem(shape, determinant),
+        Crs::Geographic(ellipsoid) if t.b() == 0. && t.d() == 0. => {
+            // Pixels of a row share their area.
+            let areas: Vec<f64> = (0..shape.0)
+                .map(|i| {
+                    let lat_1 = t.yoff() + t.e() * (off_y + i as isize) as f64;
+                    ellipsoid.cell_area(lat_1, lat_1 + t.e(), t.a())
+                })
+                .collect();
+            Array2::from_shape_fn(shape, |(i, _)| areas[i])
+        }
+        Crs::Geographic(ellipsoid) => Array2::from_shape_fn(shape, |(i, j)| {
+            let center = (
+                (off_x + j as isize) as f64 + 0.5,
+                (off_y + i as isize) as f64 + 0.5,
+            );
+            let (_, lat) = transform.pixel_to_world(center);
+            determinant * ellipsoid.square_degree_area(lat)
+        }),
+    }
+}
+
+/// Per-pixel weights of windows, eg. for area weighted
+/// statistics.
+pub trait WeightProvider {
+    /// Weights of the pixels of `window`, as (rows, cols).
+    fn weights(&self, window: &RasterWindow) -> Array2<f64>;
+}
+
+/// Weights pixels by their area, see [`pixel_areas`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AreaWeights {
+    pub transform: PixelWorldTransform,
+    pub crs: Crs,
+}
+
+impl WeightProvider for AreaWeights {
+    fn weights(&self, window: &RasterWindow) -> Array2<f64> {
+        pixel_areas(&self.transform, &self.crs, window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(offset: Offset, size: Size) -> RasterWindow {
+        (offset, size).into()
+    }
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-9 && (actual.1 - expected.1).abs() < 1e-9,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_intersection() {
+        let a = window((0, 0), (10, 10));
+        let b = window((5, 8), (10, 10));
+        assert_eq!(a.intersection(&b), Some(window((5, 8), (5, 2))));
+        assert_eq!(a.intersection(&window((10, 0), (5, 5))), None);
+    }
+
+    #[test]
+    fn test_union_and_contains() {
+        let a = window((0, 0), (10, 10));
+        let b = window((5, 8), (10, 10));
+        let union = a.union(&b);
+        assert_eq!(union, window((0, 0), (15, 18)));
+        assert!(union.contains(&a) && union.contains(&b));
+        assert!(!a.contains(&b));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(window((5, 8), (5, 2)).validate((10, 10)), Ok((5, 8)));
+        let negative = RasterWindow::from_signed((-1, 0), (5, 5));
+        assert!(!negative.is_within((10, 10)));
+        assert!(matches!(
+            window((5, 8), (5, 3)).validate((10, 10)),
+            Err(WindowOutOfBounds {
+                raster_size: (10, 10),
+                ..
+            })
+        ));
+        assert_eq!(
+            negative.validate((10, 10)).unwrap_err().to_string(),
+            "Window at offset (-1, 0) of size (5, 5) extends beyond the raster of size (10, 10)"
+        );
+    }
+
+    #[test]
+    fn test_read_window() {
+        let window = RasterWindow::from_signed((-3, 2), (6, 10));
+        assert_eq!(window.read_window((10, 8)), Some(((0, 2), (3, 6), (3, 0))));
+        assert_eq!(window.clip((10, 8)).unwrap().signed_offset(), (0, 2));
+        let outside = RasterWindow::from_signed((-6, 0), (6, 4));
+        assert_eq!(outside.read_window((10, 8)), None);
+    }
+
+    #[test]
+    fn test_translate() {
+        let a = window((5, 5), (10, 10));
+        assert_eq!(a.translate((-5, 2)), window((0, 7), (10, 10)));
+    }
+
+    #[test]
+    fn test_fractional_window() {
+        let window = FractionalWindow::new((2.25, 3.5), (2., 1.), (4, 2));
+        assert_close(window.sample_point(0, 0), (2.5, 3.75));
+        assert_close(window.sample_point(3, 1), (4., 4.25));
+        assert_eq!(window.enclosing(), RasterWindow::from(((1, 3), (4, 3))));
+    }
+
+    #[test]
+    fn test_pixel_areas() {
+        let window = window((0, 0), (2, 90));
+        // 1 degree pixels from the north pole.
+        let transform = PixelWorldTransform::new(AffineTransform::new(1., 0., 0., 0., -1., 90.));
+        let sphere = Crs::Geographic(Ellipsoid::sphere(1.));
+        let areas = pixel_areas(&transform, &sphere, &window);
+        // A whole hemisphere is 2 pi.
+        let total = areas.sum() * 180.;
+        assert!((total - 2. * std::f64::consts::PI).abs() < 1e-9);
+        assert!(areas[[0, 0]] < areas[[89, 0]]);
+
+        // About 12308.5 km2 for a degree at the equator.
+        let wgs84 = pixel_areas(&transform, &Crs::Geographic(Ellipsoid::WGS84), &window);
+        assert!((wgs84[[89, 1]] / 1e6 - 12_308.5).abs() < 0.1);
+
+        let projected = pixel_areas(&transform, &Crs::Projected, &window);
+        assert!(projected.iter().all(|&area| area == 1.));
+    }
+
+    #[test]
+    fn test_signed_offset() {
+        let a = RasterWindow::from_signed((-3, -1), (10, 10));
+        assert_eq!(a.signed_offset(), (-3, -1));
+        assert_eq!(a.offset(), None);
+        assert_eq!(a.size(), (10, 10));
+        assert_eq!(
+            a.split((8, 10)),
+            vec![
+                RasterWindow::from_signed((-3, -1), (8, 10)),
+                RasterWindow::from_signed((5, -1), (2, 10))
+            ]
+        );
+        assert_eq!(
+            a.intersection(&window((0, 0), (5, 20))),
+            Some(window((0, 0), (5, 9)))
+        );
+    }
+
+    #[test]
+    fn test_to_world() {
+        // North-up, 10m pixels.
+        let transform = AffineTransform::new(10., 0., 1000., 0., -10., 5000.);
+        let bounds = window((2, 3), (4, 5)).to_world(&transform);
+        assert_eq!(bounds.min().x_y(), (1020., 4920.));
+        assert_eq!(bounds.max().x_y(), (1060., 4970.));
+
+        // Rotated by 90 degrees.
+        let transform = AffineTransform::new(0., -1., 0., 1., 0., 0.);
+        let bounds = window((0, 0), (4, 2)).to_world(&transform);
+        assert_eq!(bounds.min().x_y(), (-2., 0.));
+        assert_eq!(bounds.max().x_y(), (0., 4.));
+    }
+
+    #[test]
+    fn test_pixel_world_transform() {
+        let transform: PixelWorldTransform =
+            AffineTransform::new(10., 0., 1000., 0., -10., 5000.).into();
+        assert_eq!(transform.pixel_to_world((2., 3.)), (1020., 4970.));
+        assert_close(transform.world_to_pixel((1020., 4970.)).unwrap(), (2., 3.));
+
+        let other: PixelWorldTransform =
+            AffineTransform::new(20., 0., 1000., 0., -20., 5000.).into();
+        let between = transform.transform_to(&other).unwrap();
+        assert_close(between.apply(Coord::from((4., 6.))).x_y(), (2., 3.));
+    }
+
+    #[test]
+    fn test_degenerate_inverse() {
+        let transform: PixelWorldTransform = AffineTransform::new(10., 20., 0., 1., 2., 0.).into();
+        assert!(matches!(
+            transform.inverse(),
+            Err(RasterUtilsError::DegenerateTransform)
+        ));
+        assert!(transform.world_to_pixel((0., 0.)).is_err());
+    }
+
+    #[test]
+    fn test_split() {
+        let windows = window((1, 2), (5, 3)).split((2, 2));
+        let expected = vec![
+            window((1, 2), (2, 2)),
+            window((3, 2), (2, 2)),
+            window((5, 2), (1, 2)),
+            window((1, 4), (2, 1)),
+            window((3, 4), (2, 1)),
+            window((5, 4), (1, 1)),
+        ];
+        assert_eq!(windows, expected);
+        assert_eq!(
+            windows.iter().map(RasterWindow::num_pixels).sum::<usize>(),
+            15
+        );
+    }
+
+    #[test]
+    fn test_split_aligned() {
+        let windows = window((1, 2), (5, 3)).split_aligned((4, 4), (2, 3));
+        let expected = vec![
+            window((1, 2), (3, 1)),
+            window((4, 2), (2, 1)),
+            window((1, 3), (3, 2)),
+            window((4, 3), (2, 2)),
+        ];
+        assert_eq!(windows, expected);
     }
 }
@@ -0,0 +1,282 @@
+//! Pure-Rust GeoTIFF backend.
+//!
+//! [`TiffReader`] implements [`ChunkReader`] for tiled and
+//! striped GeoTIFFs with the [`tiff`] crate, for deployments
+//! where linking GDAL is not possible. Compressions are
+//! those supported by `tiff` (LZW, Deflate, PackBits, ...).
+//!
+//! This module is only available with the "tiff" feature.
+
+use super::chunking::builder::ChunkConfigBuilder;
+use super::geometry::{PixelWorldTransform, RasterWindow, Size};
+use super::reader::{ChunkReader, Pixel};
+use super::{RasterUtilsError, Result};
+use geo::AffineTransform;
+use num::{NumCast, ToPrimitive};
+use tiff::{
+    decoder::{Decoder, DecodingResult},
+    tags::Tag,
+};
+
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek},
+    num::NonZeroUsize,
+    path::Path,
+    sync::Mutex,
+};
+
+/// GeoTIFF tags used to georeference the raster.
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const MODEL_TRANSFORMATION: u16 = 34264;
+
+/// A [`ChunkReader`] over one sample (band) of a TIFF.
+///
+/// Reads decode the strips or tiles intersecting the
+/// window. Resampling on read is not supported.
+pub struct TiffReader<R> {
+    decoder: Mutex<Decoder<R>>,
+    size: Size,
+    /// Size (x, y) of the strips or tiles.
+    chunk_size: Size,
+    samples: usize,
+    /// Whether samples are stored in separate planes.
+    planar: bool,
+    /// Index (0-based) of the sample to read.
+    sample: usize,
+    transform: Option<PixelWorldTransform>,
+}
+
+impl TiffReader<BufReader<File>> {
+    /// Open the TIFF at `path`, reading its first sample.
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> TiffReader<R> {
+    /// Read the first sample of the TIFF in `reader`.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut decoder = Decoder::new(reader)?;
+        let (width, height) = decoder.dimensions()?;
+        let (chunk_x, chunk_y) = decoder.chunk_dimensions();
+        let samples = decoder
+            .find_tag_unsigned::<u16>(Tag::SamplesPerPixel)?
+            .unwrap_or(1);
+        let planar = decoder
+            .find_tag_unsigned::<u16>(Tag::PlanarConfiguration)?
+            .unwrap_or(1)
+            == 2;
+        let transform = read_transform(&mut decoder);
+
+        Ok(TiffReader {
+            decoder: Mutex::new(decoder),
+            size: (width as usize, height as usize),
+            chunk_size: (chunk_x as usize, chunk_y as usize),
+            samples: samples as usize,
+            planar,
+            sample: 0,
+            transform,
+        })
+    }
+
+    /// Read sample (band) `sample` (0-based) instead.
+    pub fn with_sample(mut self, sample: usize) -> Result<Self> {
+        if sample >= self.samples {
+            return Err(RasterUtilsError::BandOutOfRange {
+                band: sample + 1,
+                count: self.samples,
+            });
+        }
+        self.sample = sample;
+        Ok(self)
+    }
+
+    /// Number of samples (bands) per pixel.
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Pixel to world transform from the GeoTIFF tags, if
+    /// georeferenced.
+    pub fn transform(&self) -> Option<&PixelWorldTransform> {
+        self.transform.as_ref()
+    }
+
+    /// Create a [`ChunkConfigBuilder`] with the dimensions of
+    /// the raster and the shape of its strips or tiles as
+    /// block shape.
+    pub fn chunk_config_builder(&self) -> Result<ChunkConfigBuilder> {
+        let (width, height) = NonZeroUsize::new(self.size.0)
+            .zip(NonZeroUsize::new(self.size.1))
+            .ok_or(RasterUtilsError::ZeroDimention)?;
+        let mut builder = ChunkConfigBuilder::new(width, height);
+        if let Some(block_shape) =
+            NonZeroUsize::new(self.chunk_size.0).zip(NonZeroUsize::new(self.chunk_size.1))
+        {
+            builder = builder.add_block_shape(block_shape);
+        }
+        Ok(builder)
+    }
+}
+
+/// Read the pixel to world transform from the GeoTIFF tags.
+fn read_transform<R: Read + Seek>(decoder: &mut Decoder<R>) -> Option<PixelWorldTransform> {
+    let mut tag = |code| decoder.get_tag_f64_vec(Tag::Unknown(code)).ok();
+
+    if let Some(m) = tag(MODEL_TRANSFORMATION).filter(|m| m.len() >= 8) {
+        return Some(PixelWorldTransform::new(AffineTransform::new(
+            m[0], m[1], m[3], m[4], m[5], m[7],
+        )));
+    }
+    let scale = tag(MODEL_PIXEL_SCALE).filter(|s| s.len() >= 2)?;
+    let tiepoint = tag(MODEL_TIEPOINT).filter(|t| t.len() >= 6)?;
+    let (i, j, x, y) = (tiepoint[0], tiepoint[1], tiepoint[3], tiepoint[4]);
+    Some(PixelWorldTransform::new(AffineTransform::new(
+        scale[0],
+        0.,
+        x - i * scale[0],
+        0.,
+        -scale[1],
+        y + j * scale[1],
+    )))
+}
+
+/// Copy the window of `sample` in a decoded strip or tile
+/// into `out`.
+struct ChunkCopy {
+    /// Window (offset, size) requested, in pixels.
+    window: (Size, Size),
+    /// Origin of the strip or tile, in pixels.
+    origin: Size,
+    /// Size of the decoded strip or tile.
+    data_size: Size,
+    /// Number of interleaved samples and the sample to read.
+    samples: usize,
+    sample: usize,
+}
+
+impl ChunkCopy {
+    fn copy<S, T>(&self, data: &[S], out: &mut [T]) -> Result<()>
+    where
+        S: ToPrimitive + Copy,
+        T: Pixel,
+    {
+        let ((off_x, off_y), (size_x, size_y)) = self.window;
+        let (origin_x, origin_y) = self.origin;
+        let (data_x, data_y) = self.data_size;
+
+        let (start_x, end_x) = (off_x.max(origin_x), (off_x + size_x).min(origin_x + data_x));
+        let (start_y, end_y) = (off_y.max(origin_y), (off_y + size_y).min(origin_y + data_y));
+        for y in start_y..end_y {
+            for x in start_x..end_x {
+                let src = ((y - origin_y) * data_x + (x - origin_x)) * self.samples + self.sample;
+                let value =
+                    <T as NumCast>::from(data[src]).ok_or(RasterUtilsError::Unsupported(
+                        "sample value not representable in the requested type",
+                    ))?;
+                out[(y - off_y) * size_x + (x - off_x)] = value;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> ChunkReader for TiffReader<R> {
+    type Error = RasterUtilsError;
+
+    fn raster_size(&self) -> Result<Size> {
+        Ok(self.size)
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        let size = raster_window.size();
+        if buffer_size != size {
+            return Err(RasterUtilsError::Unsupported("resampled reads of TIFFs"));
+        }
+        let (off_x, off_y) = raster_window.validate(self.size)?;
+        let (size_x, size_y) = size;
+        if size_x == 0 || size_y == 0 {
+            return Ok(());
+        }
+
+        let (chunk_x, chunk_y) = self.chunk_size;
+        let across = self.size.0.div_ceil(chunk_x);
+        let down = self.size.1.div_ceil(chunk_y);
+        // With separate planes, each sample has its own set
+        // of chunks and a single sample per pixel.
+        let (plane, samples, sample) = if self.planar {
+            (self.sample * across * down, 1, 0)
+        } else {
+            (0, self.samples, self.sample)
+        };
+
+        let mut decoder = self.decoder.lock().expect("TIFF decoder lock poisoned");
+        for y_chunk in off_y / chunk_y..(off_y + size_y).div_ceil(chunk_y) {
+            for x_chunk in off_x / chunk_x..(off_x + size_x).div_ceil(chunk_x) {
+                let index = (plane + y_chunk * across + x_chunk) as u32;
+                let (data_x, data_y) = decoder.chunk_data_dimensions(index);
+                let copy = ChunkCopy {
+                    window: (offset, size),
+                    origin: (x_chunk * chunk_x, y_chunk * chunk_y),
+                    data_size: (data_x as usize, data_y as usize),
+                    samples,
+                    sample,
+                };
+                match decoder.read_chunk(index)? {
+                    DecodingResult::U8(data) => copy.copy(&data, out)?,
+                    DecodingResult::U16(data) => copy.copy(&data, out)?,
+                    DecodingResult::U32(data) => copy.copy(&data, out)?,
+                    DecodingResult::U64(data) => copy.copy(&data, out)?,
+                    DecodingResult::I8(data) => copy.copy(&data, out)?,
+                    DecodingResult::I16(data) => copy.copy(&data, out)?,
+                    DecodingResult::I32(data) => copy.copy(&data, out)?,
+                    DecodingResult::I64(data) => copy.copy(&data, out)?,
+                    DecodingResult::F32(data) => copy.copy(&data, out)?,
+                    DecodingResult::F64(data) => copy.copy(&data, out)?,
+                    #[allow(unreachable_patterns)]
+                    _ => return Err(RasterUtilsError::Unsupported("TIFF sample format")),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tiff::encoder::{colortype::Gray16, TiffEncoder};
+
+    #[test]
+    fn test_read_window() {
+        let (width, height) = (7u32, 5u32);
+        let data: Vec<u16> = (0..(width * height) as u16).collect();
+        let mut buffer = Cursor::new(vec![]);
+        TiffEncoder::new(&mut buffer)
+            .unwrap()
+            .write_image::<Gray16>(width, height, &data)
+            .unwrap();
+        buffer.set_position(0);
+
+        let reader = TiffReader::new(buffer).unwrap();
+        assert_eq!(reader.raster_size().unwrap(), (7, 5));
+
+        let array = reader
+            .read_as_array::<f32>(((2, 1), (3, 4)).into())
+            .unwrap();
+        assert_eq!(array.shape(), &[4, 3]);
+        assert_eq!(array[[0, 0]], 9.);
+        assert_eq!(array[[3, 2]], 32.);
+    }
+}
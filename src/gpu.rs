@@ -0,0 +1,380 @@
+//! GPU offload of per-chunk operations with [`wgpu`].
+//!
+//! A [`GpuKernel`] wraps a WGSL compute shader run over a
+//! padded `f32` chunk. [`GpuProcessor`] reads each chunk,
+//! runs the kernel and hands the result to a sink, and
+//! implements [`ChunkProcessor`] so it slots into
+//! [`ProcessingDriver`][crate::processing::ProcessingDriver].
+//!
+//! # Kernel interface
+//!
+//! Kernels are entry point `main` with workgroup size
+//! `(8, 8)`, one invocation per output pixel, and bindings:
+//!
+//! ```wgsl
+//! struct Params {
+//!     in_width: u32,   // size of the padded input
+//!     in_height: u32,
+//!     pad_x: u32,      // padding on either side
+//!     pad_y: u32,
+//!     args: vec4<f32>, // kernel specific arguments
+//! }
+//! @group(0) @binding(0) var<storage, read> input: array<f32>;
+//! @group(0) @binding(1) var<storage, read_write> output: array<f32>;
+//! @group(0) @binding(2) var<uniform> params: Params;
+//! ```
+//!
+//! Both arrays are row-major; the output is the input
+//! without the padding.
+//!
+//! This module is only available with the "gpu" feature.
+
+use super::chunking::{Chunk, Orientation};
+use super::processing::ChunkProcessor;
+use super::reader::ChunkReader;
+use super::{RasterUtilsError, Result};
+use ndarray::{Array2, ArrayView2};
+use wgpu::util::DeviceExt;
+
+use std::{borrow::Cow, sync::mpsc};
+
+/// Size of the (square) workgroups of the kernels.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Common declarations of the built-in shaders.
+const PRELUDE: &str = r#"
+struct Params {
+    in_width: u32,
+    in_height: u32,
+    pad_x: u32,
+    pad_y: u32,
+    args: vec4<f32>,
+}
+@group(0) @binding(0) var<storage, read> input: array<f32>;
+@group(0) @binding(1) var<storage, read_write> output: array<f32>;
+@group(0) @binding(2) var<uniform> params: Params;
+"#;
+
+/// `output = args.x * input + args.y`.
+const SCALE_SHADER: &str = r#"
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let width = params.in_width - 2u * params.pad_x;
+    let height = params.in_height - 2u * params.pad_y;
+    if (id.x >= width || id.y >= height) {
+        return;
+    }
+    let value = input[(id.y + params.pad_y) * params.in_width + id.x + params.pad_x];
+    output[id.y * width + id.x] = params.args.x * value + params.args.y;
+}
+"#;
+
+/// Mean over a square window of radius `args.x`, clamped to
+/// the input.
+const FOCAL_MEAN_SHADER: &str = r#"
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let width = params.in_width - 2u * params.pad_x;
+    let height = params.in_height - 2u * params.pad_y;
+    if (id.x >= width || id.y >= height) {
+        return;
+    }
+    let radius = i32(params.args.x);
+    let x = i32(id.x + params.pad_x);
+    let y = i32(id.y + params.pad_y);
+    var sum = 0.0;
+    var count = 0.0;
+    for (var dy = -radius; dy <= radius; dy++) {
+        for (var dx = -radius; dx <= radius; dx++) {
+            let sx = clamp(x + dx, 0, i32(params.in_width) - 1);
+            let sy = clamp(y + dy, 0, i32(params.in_height) - 1);
+            sum += input[u32(sy) * params.in_width + u32(sx)];
+            count += 1.0;
+        }
+    }
+    output[id.y * width + id.x] = sum / count;
+}
+"#;
+
+fn gpu_error<E: ToString>(e: E) -> RasterUtilsError {
+    RasterUtilsError::Gpu(e.to_string())
+}
+
+/// A GPU device and its queue.
+pub struct GpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl GpuContext {
+    /// Request the default adapter and a device from it.
+    pub fn new() -> Result<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+                .ok_or_else(|| gpu_error("no suitable GPU adapter found"))?;
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+                .map_err(gpu_error)?;
+        Ok(GpuContext { device, queue })
+    }
+
+    /// Use an existing `device` and `queue`.
+    pub fn from_device(device: wgpu::Device, queue: wgpu::Queue) -> Self {
+        GpuContext { device, queue }
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+}
+
+/// A compiled compute kernel (see the [module
+/// docs][self] for the interface).
+pub struct GpuKernel {
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuKernel {
+    /// Compile the WGSL `source` of a kernel.
+    pub fn from_wgsl(context: &GpuContext, source: &str) -> Result<Self> {
+        let device = &context.device;
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        if let Some(e) = pollster::block_on(device.pop_error_scope()) {
+            return Err(gpu_error(e));
+        }
+        Ok(GpuKernel { pipeline })
+    }
+
+    /// Built-in kernel computing `factor * value + offset`.
+    /// Arguments are set by [`GpuKernel::scale_args`].
+    pub fn scale(context: &GpuContext) -> Result<Self> {
+        Self::from_wgsl(context, &[PRELUDE, SCALE_SHADER].concat())
+    }
+
+    /// Arguments of the [`scale`][GpuKernel::scale] kernel.
+    pub fn scale_args(factor: f32, offset: f32) -> [f32; 4] {
+        [factor, offset, 0., 0.]
+    }
+
+    /// Built-in kernel computing the mean over a square
+    /// window. Arguments are set by
+    /// [`GpuKernel::focal_mean_args`]; the chunk padding
+    /// should be at least the radius.
+    pub fn focal_mean(context: &GpuContext) -> Result<Self> {
+        Self::from_wgsl(context, &[PRELUDE, FOCAL_MEAN_SHADER].concat())
+    }
+
+    /// Arguments of the [`focal_mean`][GpuKernel::focal_mean]
+    /// kernel.
+    pub fn focal_mean_args(radius: u32) -> [f32; 4] {
+        [radius as f32, 0., 0., 0.]
+    }
+
+    /// Run the kernel over `input`, padded by `padding` (x,
+    /// y) on either side, and download the unpadded result.
+    pub fn run(
+        &self,
+        context: &GpuContext,
+        input: ArrayView2<f32>,
+        padding: (usize, usize),
+        args: [f32; 4],
+    ) -> Result<Array2<f32>> {
+        let (device, queue) = (&context.device, &context.queue);
+        let (in_height, in_width) = input.dim();
+        let (pad_x, pad_y) = padding;
+        if in_width < 2 * pad_x || in_height < 2 * pad_y {
+            return Err(RasterUtilsError::ZeroDimention);
+        }
+        let (width, height) = (in_width - 2 * pad_x, in_height - 2 * pad_y);
+        if width == 0 || height == 0 {
+            return Ok(Array2::zeros((height, width)));
+        }
+
+        let values: Vec<f32> = input.iter().copied().collect();
+        let params = [
+            in_width as u32,
+            in_height as u32,
+            pad_x as u32,
+            pad_y as u32,
+            args[0].to_bits(),
+            args[1].to_bits(),
+            args[2].to_bits(),
+            args[3].to_bits(),
+        ];
+        let output_size = (width * height * std::mem::size_of::<f32>()) as u64;
+
+        let input_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("input"),
+            contents: bytemuck::cast_slice(&values),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: bytemuck::cast_slice(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output"),
+            size: output_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size: output_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: input_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: output_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                (width as u32).div_ceil(WORKGROUP_SIZE),
+                (height as u32).div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, output_size);
+        queue.submit([encoder.finish()]);
+
+        let slice = staging_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(gpu_error)?.map_err(gpu_error)?;
+
+        let output = bytemuck::cast_slice::<u8, f32>(&slice.get_mapped_range()).to_vec();
+        staging_buffer.unmap();
+        Ok(Array2::from_shape_vec((height, width), output)?)
+    }
+}
+
+/// A [`ChunkProcessor`] that runs a [`GpuKernel`] over each
+/// (padded) chunk read from `reader` and passes the
+/// unpadded result to `sink`.
+pub struct GpuProcessor<'a, R, F> {
+    context: &'a GpuContext,
+    kernel: &'a GpuKernel,
+    reader: &'a R,
+    args: [f32; 4],
+    sink: F,
+}
+
+impl<'a, R, F> GpuProcessor<'a, R, F>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    F: FnMut(usize, Chunk, Array2<f32>) -> Result<()>,
+{
+    pub fn new(context: &'a GpuContext, kernel: &'a GpuKernel, reader: &'a R, sink: F) -> Self {
+        GpuProcessor {
+            context,
+            kernel,
+            reader,
+            args: [0.; 4],
+            sink,
+        }
+    }
+
+    /// Arguments passed to the kernel (`params.args`).
+    pub fn with_args(mut self, args: [f32; 4]) -> Self {
+        self.args = args;
+        self
+    }
+}
+
+impl<'a, R, F> ChunkProcessor for GpuProcessor<'a, R, F>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    F: FnMut(usize, Chunk, Array2<f32>) -> Result<()>,
+{
+    fn process(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        let input = self.reader.read_chunk::<f32>(chunk).map_err(Into::into)?;
+        let padding = chunk.config().padding();
+        let padding = match chunk.config().orientation() {
+            Orientation::Rows => (0, padding),
+            Orientation::Columns => (padding, 0),
+        };
+        let output = self
+            .kernel
+            .run(self.context, input.view(), padding, self.args)?;
+        (self.sink)(index, chunk, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, s};
+
+    /// Runs only where a GPU adapter is available.
+    #[test]
+    #[ignore]
+    fn test_kernels() {
+        let context = GpuContext::new().unwrap();
+        let input = array![[1f32, 2., 3.], [4., 5., 6.], [7., 8., 9.], [10., 11., 12.]];
+
+        let scale = GpuKernel::scale(&context).unwrap();
+        let output = scale
+            .run(
+                &context,
+                input.view(),
+                (0, 1),
+                GpuKernel::scale_args(2., 1.),
+            )
+            .unwrap();
+        assert_eq!(output, input.slice(s![1..3, ..]).mapv(|v| 2. * v + 1.));
+
+        let mean = GpuKernel::focal_mean(&context).unwrap();
+        let output = mean
+            .run(
+                &context,
+                input.view(),
+                (0, 1),
+                GpuKernel::focal_mean_args(1),
+            )
+            .unwrap();
+        // Center pixel: mean of the 3x3 block.
+        assert_eq!(output[[0, 1]], 5.);
+    }
+}
@@ -0,0 +1,158 @@
+//! Interop with the [`image`] crate.
+//!
+//! Conversions between chunk arrays and [`ImageBuffer`]s,
+//! and helpers to dump a chunk or a decimated preview of a
+//! whole band as PNG, to inspect intermediate results
+//! visually.
+//!
+//! Multi-band arrays are in (band, row, col) order, as
+//! produced by stacking band reads.
+//!
+//! This module is only available with the "image" feature.
+
+use super::chunking::Chunk;
+use super::geometry::RasterWindow;
+use super::reader::ChunkReader;
+use super::{RasterUtilsError, Result};
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Pixel, Rgb, Rgba};
+use ndarray::{Array2, Array3, ArrayView2, ArrayView3, Axis, ErrorKind, ShapeError};
+
+use std::{num::NonZeroUsize, path::Path};
+
+/// Convert a single band chunk into a grayscale image.
+pub fn to_gray_image(array: ArrayView2<u8>) -> GrayImage {
+    let (rows, cols) = array.dim();
+    ImageBuffer::from_raw(cols as u32, rows as u32, array.iter().copied().collect())
+        .expect("buffer matches the image dimensions")
+}
+
+/// Convert a (band, row, col) array of 1, 3 or 4 bands into a
+/// grayscale, RGB or RGBA image respectively.
+pub fn to_image(array: ArrayView3<u8>) -> Result<DynamicImage> {
+    let (bands, rows, cols) = array.dim();
+    // Interleave the bands, as expected by `ImageBuffer`.
+    let raw: Vec<u8> = array.permuted_axes([1, 2, 0]).iter().copied().collect();
+    let (width, height) = (cols as u32, rows as u32);
+    let image = match bands {
+        1 => ImageBuffer::<Luma<u8>, _>::from_raw(width, height, raw).map(DynamicImage::from),
+        3 => ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, raw).map(DynamicImage::from),
+        4 => ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, raw).map(DynamicImage::from),
+        _ => {
+            return Err(RasterUtilsError::Unsupported(
+                "images with other than 1, 3 or 4 bands",
+            ))
+        }
+    };
+    Ok(image.expect("buffer matches the image dimensions"))
+}
+
+/// Convert a grayscale image into a single band array.
+pub fn from_gray_image(image: &GrayImage) -> Array2<u8> {
+    let (width, height) = image.dimensions();
+    Array2::from_shape_vec((height as usize, width as usize), image.as_raw().clone())
+        .expect("buffer matches the image dimensions")
+}
+
+/// Convert an image into a (band, row, col) array, with one
+/// band per channel.
+pub fn from_image<P>(image: &ImageBuffer<P, Vec<u8>>) -> Array3<u8>
+where
+    P: Pixel<Subpixel = u8>,
+{
+    let (width, height) = image.dimensions();
+    let shape = (height as usize, width as usize, P::CHANNEL_COUNT as usize);
+    Array3::from_shape_vec(shape, image.as_raw().clone())
+        .expect("buffer matches the image dimensions")
+        .permuted_axes([2, 0, 1])
+        .as_standard_layout()
+        .into_owned()
+}
+
+/// Linearly stretch `array` to the full `u8` range, mapping
+/// its minimum to `0` and maximum to `255`. Non-finite values
+/// are mapped to `0`.
+pub fn stretch_to_u8(array: ArrayView2<f64>) -> Array2<u8> {
+    let (min, max) = array
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        });
+    let range = if max > min { max - min } else { 1. };
+    array.mapv(|v| {
+        if v.is_finite() {
+            ((v - min) / range * 255.).round() as u8
+        } else {
+            0
+        }
+    })
+}
+
+/// Save a single band chunk as PNG.
+pub fn save_png(array: ArrayView2<u8>, path: &Path) -> Result<()> {
+    Ok(to_gray_image(array).save_with_format(path, image::ImageFormat::Png)?)
+}
+
+/// Read `chunk` from `reader` and save it as a stretched
+/// (see [`stretch_to_u8`]) grayscale PNG.
+pub fn save_chunk_png<R>(reader: &R, chunk: Chunk, path: &Path) -> Result<()>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    let array = reader.read_chunk::<f64>(chunk).map_err(Into::into)?;
+    save_png(stretch_to_u8(array.view()).view(), path)
+}
+
+/// Save a preview of the whole band of `reader`, decimated
+/// by `factor` along each axis, as a stretched (see
+/// [`stretch_to_u8`]) grayscale PNG.
+pub fn save_band_preview<R>(reader: &R, factor: NonZeroUsize, path: &Path) -> Result<()>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    let (cols, rows) = reader.raster_size().map_err(Into::into)?;
+    let window: RasterWindow = ((0, 0), (cols, rows)).into();
+    let factor = factor.get();
+    let buffer_size = (cols.div_ceil(factor), rows.div_ceil(factor));
+    let array = reader
+        .read_as_array_sized::<f64>(window, buffer_size)
+        .map_err(Into::into)?;
+    save_png(stretch_to_u8(array.view()).view(), path)
+}
+
+/// Stack single band arrays of equal shape into a (band,
+/// row, col) array, eg. to build an RGB composite.
+pub fn stack_bands(bands: &[ArrayView2<u8>]) -> Result<Array3<u8>> {
+    if bands.is_empty() {
+        return Err(ShapeError::from_kind(ErrorKind::IncompatibleShape).into());
+    }
+    Ok(ndarray::stack(Axis(0), bands)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_round_trip() {
+        let gray = array![[0u8, 1, 2], [3, 4, 5]];
+        let image = to_gray_image(gray.view());
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.get_pixel(2, 1), &Luma([5]));
+        assert_eq!(from_gray_image(&image), gray);
+
+        let rgb = stack_bands(&[gray.view(), (&gray + 10).view(), (&gray + 20).view()]).unwrap();
+        let image = to_image(rgb.view()).unwrap().into_rgb8();
+        assert_eq!(image.get_pixel(1, 0), &Rgb([1, 11, 21]));
+        assert_eq!(from_image(&image), rgb);
+    }
+
+    #[test]
+    fn test_stretch() {
+        let array = array![[1., 2.], [3., f64::NAN]];
+        assert_eq!(stretch_to_u8(array.view()), array![[0u8, 128], [255, 0]]);
+    }
+}
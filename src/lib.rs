@@ -1,19 +1,166 @@
 //! Library to efficiently process GDAL rasters.
+//!
+//! # Features
+//!
+//! - `gdal` (default): GDAL readers, writers and utilities.
+//! - `serde` (default): serialization of chunk plans and
+//! persistent job state.
+//! - `use-rayon`: parallel chunk iterators.
+//! - `pipeline`: bounded, std thread based pipeline of
+//! reader, worker and writer threads.
+//! - `tiff`: pure-Rust GeoTIFF reader, usable without GDAL.
+//! - `zarr`: reader for 2D Zarr arrays, usable without GDAL.
+//! - `arrow`: conversion of chunks to Arrow arrays and
+//! record batches.
+//! - `image`: conversions to and from `image` buffers and
+//! PNG previews of chunks and bands.
+//! - `simd`: explicitly vectorized elementwise kernels
+//! in [`ops::simd`], also used to mask nodata in expression
+//! sources and to compute band statistics.
+//! - `gpu`: offload of per-chunk kernels to the GPU with
+//! `wgpu`.
+//! - `checksum`: XXH3 checksums of chunks and bands.
+//! - `tiles`: rendering of web mercator XYZ tiles into a
+//! directory; `mbtiles` also writes MBTiles files.
+//! - `cache`: LZ4-compressed in-memory cache of chunk
+//! buffers.
+//! - `fft`: large-kernel filtering of chunks by FFT in
+//! [`ops::fft`].
+//! - `f16`: half-precision pixels (with the `half` crate) in
+//! [`dynamic::DynArray`] and the Zarr reader. They are read
+//! only through `read_dyn`, or converted on read to another
+//! pixel type.
+//! - `cli`: the `raster-utils` binary, with `stats`, `diff`,
+//! `align-check`, `calc` and `copy` subcommands.
+//! - `python`: Python bindings (with `pyo3`) of chunk
+//! planning, chunked statistics and band math, built into
+//! an extension module by the crate in `python/`.
+//! - `tracing`: spans around chunk reads, writes and
+//! per-chunk processing, with the window, band and byte
+//! count as fields. Durations are available from the
+//! subscriber (eg. `FmtSpan::CLOSE`).
 
 pub mod align;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod chain;
+#[cfg(feature = "checksum")]
+pub mod checksum;
 pub mod chunking;
+pub mod contour;
+pub mod dynamic;
+pub mod expr;
 pub mod geometry;
+#[cfg(feature = "tiff")]
+pub mod geotiff;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "image")]
+pub mod imaging;
+pub mod nodata;
+pub mod ops;
+pub mod patches;
+#[cfg(feature = "pipeline")]
+pub mod pipeline;
+pub mod processing;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod reader;
+pub mod resample;
+pub mod sampling;
+#[cfg(feature = "serde")]
+pub mod sidecar;
+pub mod stats;
+pub mod testing;
+#[cfg(feature = "tiles")]
+pub mod tiles;
+#[cfg(feature = "zarr")]
+pub mod zarr;
 
-//#[cfg(feature = "gdal")]
+#[cfg(feature = "gdal")]
 pub mod gdal;
 
 #[derive(thiserror::Error, std::fmt::Debug)]
 pub enum RasterUtilsError {
-    //#[cfg(feature = "gdal")]
+    #[cfg(feature = "gdal")]
     #[error(transparent)]
     Gdal(gdal::error::RasterUtilsGdalError),
+    #[error(transparent)]
+    Alignment(#[from] align::AlignmentError),
+    #[error(transparent)]
+    ChunkConfig(#[from] chunking::builder::ChunkConfigError),
+    #[error(transparent)]
+    Convert(#[from] ops::convert::ConvertError),
     #[error("Encountered an object with zero dimention")]
     ZeroDimention,
+    #[error("Transform is not invertible")]
+    DegenerateTransform,
+    #[error("Saved processing state doesn't match the chunk config")]
+    StateMismatch,
+    #[error("Processing was cancelled")]
+    Cancelled,
+    #[error(transparent)]
+    WindowOutOfBounds(#[from] geometry::WindowOutOfBounds),
+    #[error("Invalid band index {0}: bands are 1-indexed")]
+    InvalidBandIndex(usize),
+    #[error("Band {band} out of range: the raster has {count} bands")]
+    BandOutOfRange { band: usize, count: usize },
+    #[error("Chunk config for a raster of size {config_size:?} exceeds the raster of size {raster_size:?}")]
+    ChunkOutOfRaster {
+        config_size: geometry::Size,
+        raster_size: geometry::Size,
+    },
+    #[error("Type mismatch: expected {expected}, found {found}")]
+    TypeMismatch { expected: String, found: String },
+    #[error("Unsupported operation: {0}")]
+    Unsupported(&'static str),
+    #[error("Invalid histogram: {0}")]
+    InvalidHistogram(&'static str),
+    #[error("Contour interval {0} is not finite and positive")]
+    InvalidContourInterval(f64),
+    #[error("No reader registered for key {0:?}")]
+    UnknownReader(String),
+    #[error("Boundary flows did not converge after {0} passes")]
+    NoConvergence(usize),
+    #[error("Invalid tile {z}/{x}/{y}: zooms go up to 30, indices below 2^zoom")]
+    InvalidTile { z: u8, x: u32, y: u32 },
+    #[error(transparent)]
+    Shape(#[from] ndarray::ShapeError),
+    #[cfg(feature = "tiff")]
+    #[error(transparent)]
+    Tiff(#[from] tiff::TiffError),
+    #[cfg(feature = "arrow")]
+    #[error(transparent)]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[cfg(feature = "image")]
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[cfg(feature = "gpu")]
+    #[error("GPU error: {0}")]
+    Gpu(String),
+    #[cfg(feature = "zarr")]
+    #[error("Zarr error: {0}")]
+    Zarr(String),
+    #[cfg(feature = "mbtiles")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+#[cfg(feature = "gdal")]
+impl From<gdal::error::RasterUtilsGdalError> for RasterUtilsError {
+    fn from(error: gdal::error::RasterUtilsGdalError) -> Self {
+        match error {
+            gdal::error::RasterUtilsGdalError::RasterUtils(error) => *error,
+            error => RasterUtilsError::Gdal(error),
+        }
+    }
 }
 
 /// The `Result` type returned by this crate.
@@ -0,0 +1,94 @@
+//! Comparison of pixel values with nodata.
+//!
+//! Float rasters often store nodata as a large value (eg.
+//! `-3.4e38`, the `f32` minimum rounded for display) that no
+//! longer compares equal after a round trip through another
+//! type or a computation. A [`NodataPolicy`] fixes how values
+//! are compared with nodata, and is accepted by masked reads
+//! ([`ChunkReader::read_chunk_masked_with_policy`]), the
+//! temporal reducers of [`ops::temporal`], band math
+//! processors and writers (with the "gdal" feature).
+//!
+//! [`ChunkReader::read_chunk_masked_with_policy`]: crate::reader::ChunkReader::read_chunk_masked_with_policy
+//! [`ops::temporal`]: crate::ops::temporal
+
+use ndarray::{ArrayBase, DataMut, Dimension};
+use num::ToPrimitive;
+
+/// How values are compared with the nodata value.
+///
+/// Under all policies, `NaN` values are nodata.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NodataPolicy {
+    /// Values equal to the nodata value.
+    #[default]
+    Exact,
+    /// Values within a relative tolerance of the nodata
+    /// value: `|value - nodata| <= epsilon * max(1, |nodata|)`.
+    Epsilon(f64),
+    /// Only `NaN` values; the nodata value is ignored.
+    Nan,
+}
+
+impl NodataPolicy {
+    /// Whether `value` is nodata, given the `nodata` value
+    /// (if any).
+    pub fn is_nodata<T: ToPrimitive>(&self, value: T, nodata: Option<T>) -> bool {
+        let value = value.to_f64().unwrap_or(f64::NAN);
+        if value.is_nan() {
+            return true;
+        }
+        let nodata = match nodata.and_then(|nodata| nodata.to_f64()) {
+            Some(nodata) if !nodata.is_nan() => nodata,
+            _ => return false,
+        };
+        match *self {
+            NodataPolicy::Exact => value == nodata,
+            NodataPolicy::Epsilon(epsilon) => {
+                (value - nodata).abs() <= epsilon * nodata.abs().max(1.)
+            }
+            NodataPolicy::Nan => false,
+        }
+    }
+
+    /// Set the values of `array` that are nodata under this
+    /// policy to exactly `nodata`, so that later exact
+    /// comparisons (eg. by GDAL readers of a written raster)
+    /// agree with the policy.
+    pub fn normalize<S, D, T>(&self, array: &mut ArrayBase<S, D>, nodata: T)
+    where
+        S: DataMut<Elem = T>,
+        D: Dimension,
+        T: ToPrimitive + Copy,
+    {
+        array.mapv_inplace(|value| {
+            if self.is_nodata(value, Some(nodata)) {
+                nodata
+            } else {
+                value
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_policies() {
+        let nodata = Some(-3.4e38);
+        let stored = f32::MIN as f64;
+        assert!(!NodataPolicy::Exact.is_nodata(stored, nodata));
+        assert!(NodataPolicy::Epsilon(1e-3).is_nodata(stored, nodata));
+        assert!(!NodataPolicy::Epsilon(1e-3).is_nodata(-3.3e38, nodata));
+        assert!(!NodataPolicy::Nan.is_nodata(-3.4e38, nodata));
+        assert!(NodataPolicy::Nan.is_nodata(f64::NAN, nodata));
+        assert!(NodataPolicy::Exact.is_nodata(-1i16, Some(-1)));
+
+        let mut values = array![[1., 0.001], [f64::NAN, 2.]];
+        NodataPolicy::Epsilon(0.01).normalize(&mut values, 0.);
+        assert_eq!(values, array![[1., 0.], [0., 2.]]);
+    }
+}
@@ -0,0 +1,177 @@
+//! Conversion of chunks between pixel types.
+//!
+//! `as` casts silently wrap or truncate values out of the
+//! range of the target type (eg. `f32` to `u16`), and
+//! [`NumCast`] fails on them. [`convert_chunk`] converts
+//! with an explicit [`ConvertPolicy`] instead, and maps the
+//! nodata value of the source to that of the target. Values
+//! are rounded to the nearest integer for integer targets.
+
+use crate::nodata::NodataPolicy;
+use ndarray::{Array2, ArrayView2};
+use num::{Bounded, NumCast, ToPrimitive};
+
+use std::any::type_name;
+
+/// Errors of [`convert_chunk`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ConvertError {
+    #[error("Value {value} is out of the range of {target}")]
+    OutOfRange { value: f64, target: &'static str },
+    #[error("Cannot scale from the empty range {0:?}")]
+    EmptyRange((f64, f64)),
+}
+
+/// How values out of the range of the target type are
+/// converted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConvertPolicy {
+    /// Clamp to the range of the target type.
+    #[default]
+    Saturate,
+    /// Map the `src` range (min, max) linearly onto the `dst`
+    /// range, and clamp to `dst`.
+    Scale { src: (f64, f64), dst: (f64, f64) },
+    /// Fail on values out of the range of the target type.
+    Error,
+}
+
+/// Options of [`convert_chunk`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConvertOptions<T, U> {
+    pub policy: ConvertPolicy,
+    /// Nodata value of the source, and the value it maps to.
+    pub nodata: Option<(T, U)>,
+    /// How values are compared with the nodata value of the
+    /// source.
+    pub nodata_policy: NodataPolicy,
+}
+
+impl<T, U> ConvertOptions<T, U> {
+    pub fn new(policy: ConvertPolicy) -> Self {
+        ConvertOptions {
+            policy,
+            nodata: None,
+            nodata_policy: NodataPolicy::Exact,
+        }
+    }
+
+    /// Map the nodata value `src` of the source to `dst`.
+    pub fn with_nodata(mut self, src: T, dst: U) -> Self {
+        self.nodata = Some((src, dst));
+        self
+    }
+
+    pub fn with_nodata_policy(mut self, nodata_policy: NodataPolicy) -> Self {
+        self.nodata_policy = nodata_policy;
+        self
+    }
+}
+
+/// Convert `array` to pixel type `U` under `options`.
+///
+/// `NaN` values that are not nodata are kept for float
+/// targets, and fail for integer ones.
+pub fn convert_chunk<T, U>(
+    array: ArrayView2<T>,
+    options: &ConvertOptions<T, U>,
+) -> Result<Array2<U>, ConvertError>
+where
+    T: ToPrimitive + Copy,
+    U: NumCast + Bounded + Copy,
+{
+    let min = U::min_value().to_f64().unwrap_or(f64::NEG_INFINITY);
+    let max = U::max_value().to_f64().unwrap_or(f64::INFINITY);
+    // Integer types don't represent one half.
+    let integer = <U as NumCast>::from(0.5).and_then(|v| v.to_f64()) != Some(0.5);
+    let (min, max, scale) = match options.policy {
+        ConvertPolicy::Scale { src, dst } => {
+            if !(src.0.is_finite() && src.1.is_finite()) || src.0 == src.1 {
+                return Err(ConvertError::EmptyRange(src));
+            }
+            let (lower, upper) = (dst.0.min(dst.1), dst.0.max(dst.1));
+            (min.max(lower), max.min(upper), Some((src, dst)))
+        }
+        _ => (min, max, None),
+    };
+    let src_nodata = options.nodata.map(|(src, _)| src);
+    let out_of_range = |value: f64| ConvertError::OutOfRange {
+        value,
+        target: type_name::<U>(),
+    };
+
+    let mut values = Vec::with_capacity(array.len());
+    for &value in array.iter() {
+        if let Some((_, nodata)) = options.nodata {
+            if options.nodata_policy.is_nodata(value, src_nodata) {
+                values.push(nodata);
+                continue;
+            }
+        }
+        let original = value.to_f64().unwrap_or(f64::NAN);
+        let mut x = match scale {
+            Some(((a, b), (c, d))) => c + (original - a) * (d - c) / (b - a),
+            None => original,
+        };
+        if integer {
+            x = x.round();
+        }
+        if !x.is_nan() && !(min..=max).contains(&x) {
+            if options.policy == ConvertPolicy::Error {
+                return Err(out_of_range(original));
+            }
+            x = x.clamp(min, max);
+        }
+        // The bounds of 64 bit integers round out of range in
+        // `f64`.
+        let converted = <U as NumCast>::from(x).or_else(|| match x {
+            x if x >= max => Some(U::max_value()),
+            x if x <= min => Some(U::min_value()),
+            _ => None,
+        });
+        values.push(converted.ok_or_else(|| out_of_range(original))?);
+    }
+    Ok(Array2::from_shape_vec(array.raw_dim(), values).expect("one value per pixel"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_convert_chunk() {
+        let values = array![[-5.4, 0.6], [300., f32::NAN]];
+        let saturate = ConvertOptions::new(ConvertPolicy::Saturate).with_nodata(f32::NAN, 0u8);
+        let converted = convert_chunk(values.view(), &saturate).unwrap();
+        assert_eq!(converted, array![[0u8, 1], [255, 0]]);
+
+        let error = ConvertOptions::new(ConvertPolicy::Error).with_nodata(f32::NAN, 0u8);
+        assert_eq!(
+            convert_chunk(values.view(), &error),
+            Err(ConvertError::OutOfRange {
+                value: -5.4f32 as f64,
+                target: "u8"
+            })
+        );
+        let no_nodata = ConvertOptions::<f32, u8>::new(ConvertPolicy::Saturate);
+        assert!(convert_chunk(values.view(), &no_nodata).is_err());
+
+        let elevation = array![[-100i16, 0], [1000, 3000]];
+        let scale = ConvertOptions::new(ConvertPolicy::Scale {
+            src: (0., 2000.),
+            dst: (1., 255.),
+        })
+        .with_nodata(-100, 0u8);
+        let converted = convert_chunk(elevation.view(), &scale).unwrap();
+        assert_eq!(converted, array![[0u8, 1], [128, 255]]);
+
+        let floats = convert_chunk(
+            array![[1e40, f64::NAN]].view(),
+            &ConvertOptions::<f64, f32>::new(ConvertPolicy::Saturate),
+        )
+        .unwrap();
+        assert_eq!(floats[[0, 0]], f32::MAX);
+        assert!(floats[[0, 1]].is_nan());
+    }
+}
@@ -0,0 +1,331 @@
+//! Large-kernel filtering of chunks by FFT.
+//!
+//! Direct convolution costs a multiplication per kernel pixel
+//! per output pixel, which is prohibitive for large kernels
+//! (eg. Gaussians of σ ≫ 10 px). [`FftFilter`] convolves a
+//! chunk in the frequency domain instead, by overlap-save:
+//! the padding of the chunks is the overlap, and only the
+//! data (unpadded) part is kept. Rasters of any height are
+//! thus filtered with memory bounded by the chunk size.
+//! [`FftProcessor`] runs a filter over the chunks of a
+//! config.
+//!
+//! Chunks must be padded by at least the
+//! [`radius`][FftFilter::radius] of the kernel along the
+//! chunk direction (see
+//! [`ChunkConfigBuilder::with_padding`][crate::chunking::builder::ChunkConfigBuilder::with_padding]).
+//! Values beyond the other edges are taken as zero, as are
+//! NaN values, unless
+//! [edge normalization][FftFilter::with_edge_normalization]
+//! is enabled.
+//!
+//! This module is only available with the "fft" feature.
+
+use crate::chain::ChunkStage;
+use crate::chunking::{Chunk, Orientation};
+use crate::processing::ChunkProcessor;
+use crate::reader::ChunkReader;
+use crate::{RasterUtilsError, Result};
+use ndarray::{s, Array2, ArrayView2, Zip};
+use num::ToPrimitive;
+use rustfft::{num_complex::Complex, FftDirection, FftPlanner};
+
+/// Convolution with a kernel, computed by FFT.
+///
+/// Plans and the transform of the kernel are cached, so a
+/// filter should be reused across chunks of the same shape.
+pub struct FftFilter {
+    kernel: Array2<f64>,
+    normalize_edges: bool,
+    planner: FftPlanner<f64>,
+    kernel_fft: Option<Array2<Complex<f64>>>,
+}
+
+impl FftFilter {
+    /// Filter with `kernel`, centered on its middle pixel.
+    /// Both dimensions should be odd.
+    pub fn new(kernel: Array2<f64>) -> Self {
+        FftFilter {
+            kernel,
+            normalize_edges: false,
+            planner: FftPlanner::new(),
+            kernel_fft: None,
+        }
+    }
+
+    /// Gaussian blur of standard deviation `sigma` (in
+    /// pixels), truncated at `3 * sigma`.
+    pub fn gaussian(sigma: f64) -> Self {
+        let radius = (3. * sigma).ceil() as usize;
+        Self::new(gaussian_kernel(sigma, radius))
+    }
+
+    /// Band-pass as a difference of Gaussians: keeps features
+    /// between `sigma_low` and `sigma_high` (in pixels, with
+    /// `sigma_low < sigma_high`).
+    pub fn band_pass(sigma_low: f64, sigma_high: f64) -> Self {
+        let radius = (3. * sigma_high).ceil() as usize;
+        Self::new(gaussian_kernel(sigma_low, radius) - gaussian_kernel(sigma_high, radius))
+    }
+
+    /// Divide the output by the weight of the kernel over
+    /// the pixels within the chunk and not NaN, so that
+    /// smoothing does not darken the edges nor spread
+    /// nodata. Only meaningful for kernels summing to one.
+    /// NaN input pixels stay NaN.
+    pub fn with_edge_normalization(mut self, normalize: bool) -> Self {
+        self.normalize_edges = normalize;
+        self
+    }
+
+    pub fn kernel(&self) -> ArrayView2<'_, f64> {
+        self.kernel.view()
+    }
+
+    /// Half size (x, y) of the kernel.
+    pub fn radius(&self) -> (usize, usize) {
+        let (rows, cols) = self.kernel.dim();
+        (cols / 2, rows / 2)
+    }
+
+    /// Filter `chunk`, and return it without `padding` (x, y)
+    /// pixels on either side.
+    pub fn apply<T>(&mut self, chunk: ArrayView2<T>, padding: (usize, usize)) -> Array2<f64>
+    where
+        T: ToPrimitive + Copy,
+    {
+        let (rows, cols) = chunk.dim();
+        let (pad_x, pad_y) = padding;
+        let out_dim = (
+            rows.saturating_sub(2 * pad_y),
+            cols.saturating_sub(2 * pad_x),
+        );
+        if out_dim.0 == 0 || out_dim.1 == 0 {
+            return Array2::zeros(out_dim);
+        }
+
+        let values = chunk.mapv(|v| v.to_f64().unwrap_or(f64::NAN));
+        let filtered = self.convolve(values.mapv(|v| if v.is_nan() { 0. } else { v }).view());
+        let (radius_x, radius_y) = self.radius();
+        let data = s![
+            radius_y + pad_y..radius_y + pad_y + out_dim.0,
+            radius_x + pad_x..radius_x + pad_x + out_dim.1
+        ];
+        let mut output = filtered.slice(data).to_owned();
+        if !self.normalize_edges {
+            return output;
+        }
+
+        let valid = values.mapv(|v| if v.is_nan() { 0. } else { 1. });
+        let weights = self.convolve(valid.view());
+        let input = values.slice(s![pad_y..pad_y + out_dim.0, pad_x..pad_x + out_dim.1]);
+        Zip::from(&mut output)
+            .and(weights.slice(data))
+            .and(input)
+            .for_each(|value, &weight, &input| {
+                *value = if input.is_nan() || weight.abs() < f64::EPSILON {
+                    f64::NAN
+                } else {
+                    *value / weight
+                };
+            });
+        output
+    }
+
+    /// Full (linear) convolution of `values` with the kernel.
+    fn convolve(&mut self, values: ArrayView2<f64>) -> Array2<f64> {
+        let (rows, cols) = values.dim();
+        let (kernel_rows, kernel_cols) = self.kernel.dim();
+        let dim = (rows + kernel_rows - 1, cols + kernel_cols - 1);
+
+        let kernel_fft = match self.kernel_fft.take() {
+            Some(kernel_fft) if kernel_fft.dim() == dim => kernel_fft,
+            _ => {
+                let kernel = self.kernel.clone();
+                self.forward(kernel.view(), dim)
+            }
+        };
+        let mut spectrum = self.forward(values, dim);
+        spectrum *= &kernel_fft;
+        self.kernel_fft = Some(kernel_fft);
+
+        self.fft2(&mut spectrum, FftDirection::Inverse);
+        let scale = (dim.0 * dim.1) as f64;
+        spectrum.mapv(|v| v.re / scale)
+    }
+
+    /// Transform of `values`, zero padded to `dim`.
+    fn forward(&mut self, values: ArrayView2<f64>, dim: (usize, usize)) -> Array2<Complex<f64>> {
+        let (rows, cols) = values.dim();
+        let mut padded = Array2::zeros(dim);
+        padded
+            .slice_mut(s![..rows, ..cols])
+            .assign(&values.mapv(|v| Complex::new(v, 0.)));
+        self.fft2(&mut padded, FftDirection::Forward);
+        padded
+    }
+
+    /// In place 2D transform: along rows, then along columns
+    /// (on the transposed buffer).
+    fn fft2(&mut self, data: &mut Array2<Complex<f64>>, direction: FftDirection) {
+        let (rows, cols) = data.dim();
+        self.planner
+            .plan_fft(cols, direction)
+            .process(data.as_slice_mut().expect("owned arrays are contiguous"));
+        let mut transposed = data.t().as_standard_layout().into_owned();
+        self.planner
+            .plan_fft(rows, direction)
+            .process(transposed.as_slice_mut().expect("standard layout"));
+        *data = transposed.t().as_standard_layout().into_owned();
+    }
+}
+
+/// Normalized Gaussian kernel of side `2 * radius + 1`.
+fn gaussian_kernel(sigma: f64, radius: usize) -> Array2<f64> {
+    let side = 2 * radius + 1;
+    let weights: Vec<f64> = (0..side)
+        .map(|i| {
+            let d = i as f64 - radius as f64;
+            (-d * d / (2. * sigma * sigma)).exp()
+        })
+        .collect();
+    let kernel = Array2::from_shape_fn((side, side), |(i, j)| weights[i] * weights[j]);
+    let total = kernel.sum();
+    kernel / total
+}
+
+/// Chains the filter with other stages (see
+/// [`crate::chain`]).
+impl ChunkStage for FftFilter {
+    type Input = f64;
+    type Output = f64;
+
+    fn radius(&self) -> (usize, usize) {
+        FftFilter::radius(self)
+    }
+
+    fn apply(&mut self, input: ArrayView2<f64>, padding: (usize, usize)) -> Result<Array2<f64>> {
+        Ok(FftFilter::apply(self, input, padding))
+    }
+}
+
+/// A [`ChunkProcessor`] filtering each chunk read from a
+/// reader with an [`FftFilter`], and passing the data
+/// (unpadded) output to a sink.
+pub struct FftProcessor<'a, R, F> {
+    filter: FftFilter,
+    reader: &'a R,
+    sink: F,
+}
+
+impl<'a, R, F> FftProcessor<'a, R, F>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    F: FnMut(usize, Chunk, Array2<f64>) -> Result<()>,
+{
+    pub fn new(filter: FftFilter, reader: &'a R, sink: F) -> Self {
+        FftProcessor {
+            filter,
+            reader,
+            sink,
+        }
+    }
+
+    pub fn filter(&self) -> &FftFilter {
+        &self.filter
+    }
+}
+
+impl<'a, R, F> ChunkProcessor for FftProcessor<'a, R, F>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    F: FnMut(usize, Chunk, Array2<f64>) -> Result<()>,
+{
+    fn process(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        let padding = chunk.config().padding();
+        let (radius_x, radius_y) = self.filter.radius();
+        let (padding, radius) = match chunk.config().orientation() {
+            Orientation::Rows => ((0, padding), radius_y),
+            Orientation::Columns => ((padding, 0), radius_x),
+        };
+        if padding.0.max(padding.1) < radius {
+            return Err(RasterUtilsError::Unsupported(
+                "chunk padding smaller than the FFT kernel radius",
+            ));
+        }
+        let input = self.reader.read_chunk::<f64>(chunk).map_err(Into::into)?;
+        let output = self.filter.apply(input.view(), padding);
+        (self.sink)(index, chunk, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::testing::ArrayReader;
+    use ndarray::{array, concatenate, Axis};
+    use std::num::NonZeroUsize;
+
+    fn assert_close(a: ArrayView2<f64>, b: ArrayView2<f64>) {
+        assert_eq!(a.dim(), b.dim());
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_matches_direct_convolution() {
+        let values = Array2::from_shape_fn((7, 6), |(i, j)| ((i * 5 + j * 3) % 7) as f64);
+        let kernel = array![[0., 1., 0.], [1., -4., 2.], [0., 3., 0.]];
+        let mut filter = FftFilter::new(kernel.clone());
+
+        let direct = Array2::from_shape_fn((5, 4), |(i, j)| {
+            let mut sum = 0.;
+            for ((ki, kj), k) in kernel.indexed_iter() {
+                // Convolution flips the kernel.
+                sum += k * values[[i + 3 - ki - 1, j + 3 - kj - 1]];
+            }
+            sum
+        });
+        assert_close(filter.apply(values.view(), (1, 1)).view(), direct.view());
+
+        let gaussian = FftFilter::gaussian(2.);
+        assert_eq!(gaussian.radius(), (6, 6));
+        assert!((gaussian.kernel().sum() - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_chunked_matches_whole() {
+        let values = Array2::from_shape_fn((20, 6), |(i, j)| ((i * 7 + j * 3) % 11) as f64);
+        let filter = FftFilter::gaussian(1.).with_edge_normalization(true);
+        let (_, padding) = filter.radius();
+
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(6).unwrap(),
+            NonZeroUsize::new(20).unwrap(),
+        )
+        .with_data_height(NonZeroUsize::new(4).unwrap())
+        .with_padding(padding)
+        .build();
+        let reader = ArrayReader(values.clone());
+        let mut chunks = vec![];
+        let mut processor = FftProcessor::new(filter, &reader, |_, _, output| {
+            chunks.push(output);
+            Ok(())
+        });
+        for (index, chunk) in cfg.iter().enumerate() {
+            processor.process(index, chunk).unwrap();
+        }
+        drop(processor);
+        let views: Vec<_> = chunks.iter().map(|chunk| chunk.view()).collect();
+        let chunked = concatenate(Axis(0), &views).unwrap();
+
+        let whole = FftFilter::gaussian(1.)
+            .with_edge_normalization(true)
+            .apply(values.view(), (0, padding));
+        assert_close(chunked.view(), whole.view());
+    }
+}
@@ -0,0 +1,192 @@
+//! Hydrology on elevation and flow direction chunks.
+//!
+//! Flow directions use the D8 encoding of ESRI: one bit per
+//! neighbour, clockwise from east (see [`D8`]), and
+//! [`NO_FLOW`] for sinks, flats and nodata.
+//!
+//! [`accumulate`] computes the flow accumulation within a
+//! chunk given the inflow across its first and last rows,
+//! and [`outflow`] the flow leaving it; iterating both over
+//! the chunks until the boundary flows settle gives the
+//! accumulation of the whole raster (see
+//! `gdal::hydrology::flow_accumulation`).
+
+use ndarray::{Array2, ArrayView2};
+use num::ToPrimitive;
+
+use std::collections::VecDeque;
+
+/// Direction code of cells without a downslope neighbour.
+pub const NO_FLOW: u8 = 0;
+
+/// D8 direction codes with their (row, col) offsets, in
+/// order E, SE, S, SW, W, NW, N, NE.
+pub const D8: [(u8, (isize, isize)); 8] = [
+    (1, (0, 1)),
+    (2, (1, 1)),
+    (4, (1, 0)),
+    (8, (1, -1)),
+    (16, (0, -1)),
+    (32, (-1, -1)),
+    (64, (-1, 0)),
+    (128, (-1, 1)),
+];
+
+/// (row, col) offset of the neighbour a cell with direction
+/// `code` flows into, if any.
+pub fn d8_offset(code: u8) -> Option<(isize, isize)> {
+    D8.iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, offset)| *offset)
+}
+
+/// D8 flow direction of a chunk padded by one row on either
+/// side: each cell flows to the neighbour with the steepest
+/// drop.
+///
+/// The output has the shape of the data (unpadded) rows.
+/// Neighbours beyond the left and right edges and undefined
+/// (eg. `NaN`) neighbours are ignored. Ties go to the first
+/// direction in [`D8`] order.
+pub fn flow_direction<T>(dem: ArrayView2<T>, cell_size: (f64, f64)) -> Array2<u8>
+where
+    T: ToPrimitive + Copy,
+{
+    let (rows, cols) = dem.dim();
+    if rows < 3 {
+        return Array2::zeros((rows.saturating_sub(2), cols));
+    }
+
+    let z = |i: usize, j: usize| dem[[i, j]].to_f64().unwrap_or(f64::NAN);
+    let (size_x, size_y) = (cell_size.0.abs(), cell_size.1.abs());
+
+    Array2::from_shape_fn((rows - 2, cols), |(row, col)| {
+        let i = row + 1;
+        let center = z(i, col);
+        if center.is_nan() {
+            return NO_FLOW;
+        }
+        let mut best = (NO_FLOW, 0.);
+        for &(code, (di, dj)) in &D8 {
+            let j = col as isize + dj;
+            if j < 0 || j >= cols as isize {
+                continue;
+            }
+            let neighbour = z((i as isize + di) as usize, j as usize);
+            let distance = (di as f64 * size_y).hypot(dj as f64 * size_x);
+            let drop = (center - neighbour) / distance;
+            if drop > best.1 {
+                best = (code, drop);
+            }
+        }
+        best.0
+    })
+}
+
+/// Target (row, col) of cell (i, j) within a chunk of
+/// `directions`, if it flows into the chunk.
+fn target(directions: &ArrayView2<u8>, i: usize, j: usize) -> Option<(usize, usize)> {
+    let (rows, cols) = directions.dim();
+    let (di, dj) = d8_offset(directions[[i, j]])?;
+    let (ti, tj) = (i as isize + di, j as isize + dj);
+    if ti < 0 || tj < 0 || ti >= rows as isize || tj >= cols as isize {
+        return None;
+    }
+    Some((ti as usize, tj as usize))
+}
+
+/// Flow accumulation within a chunk of `directions`: the
+/// number of cells (including itself) draining through each
+/// cell.
+///
+/// `top` and `bottom` (one value per column) are added to
+/// the cells of the first and last rows respectively, for
+/// the flow entering the chunk from the rows above and
+/// below. Cells on direction cycles are left partially
+/// accumulated.
+pub fn accumulate(directions: ArrayView2<u8>, top: &[f64], bottom: &[f64]) -> Array2<f64> {
+    let (rows, cols) = directions.dim();
+    let mut acc = Array2::from_elem((rows, cols), 1.);
+    if rows == 0 {
+        return acc;
+    }
+    for j in 0..cols {
+        acc[[0, j]] += top[j];
+        acc[[rows - 1, j]] += bottom[j];
+    }
+
+    let mut indegree = Array2::<u8>::zeros((rows, cols));
+    for ((i, j), _) in directions.indexed_iter() {
+        if let Some(t) = target(&directions, i, j) {
+            indegree[t] += 1;
+        }
+    }
+    let mut queue: VecDeque<(usize, usize)> = indegree
+        .indexed_iter()
+        .filter(|(_, &degree)| degree == 0)
+        .map(|(index, _)| index)
+        .collect();
+    while let Some((i, j)) = queue.pop_front() {
+        if let Some(t) = target(&directions, i, j) {
+            let value = acc[[i, j]];
+            acc[t] += value;
+            indegree[t] -= 1;
+            if indegree[t] == 0 {
+                queue.push_back(t);
+            }
+        }
+    }
+    acc
+}
+
+/// Flow leaving a chunk of `directions` with accumulation
+/// `acc`, as (up, down): per column of the row above and
+/// the row below the chunk.
+pub fn outflow(directions: ArrayView2<u8>, acc: ArrayView2<f64>) -> (Vec<f64>, Vec<f64>) {
+    let (rows, cols) = directions.dim();
+    let (mut up, mut down) = (vec![0.; cols], vec![0.; cols]);
+    if rows == 0 {
+        return (up, down);
+    }
+    for (i, out) in [(0, &mut up), (rows - 1, &mut down)] {
+        for j in 0..cols {
+            let (di, dj) = match d8_offset(directions[[i, j]]) {
+                Some(offset) => offset,
+                None => continue,
+            };
+            let (ti, tj) = (i as isize + di, j as isize + dj);
+            if (ti < 0 || ti >= rows as isize) && tj >= 0 && tj < cols as isize {
+                out[tj as usize] += acc[[i, j]];
+            }
+        }
+    }
+    (up, down)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_flow_direction() {
+        let dem = array![[9., 9., 9.], [5., 4., 3.], [9., 9., 9.]];
+        let directions = flow_direction(dem.view(), (1., 1.));
+        assert_eq!(directions, array![[1, 1, NO_FLOW]]);
+    }
+
+    #[test]
+    fn test_accumulate() {
+        // Two columns draining south, then east along the
+        // last row; the top row receives 2 cells of inflow.
+        let directions = array![[4, 4], [4, 4], [1, NO_FLOW]];
+        let acc = accumulate(directions.view(), &[2., 0.], &[0., 0.]);
+        assert_eq!(acc, array![[3., 1.], [4., 2.], [5., 8.]]);
+
+        let (up, down) = outflow(
+            array![[64, 4], [2, 4]].view(),
+            array![[1., 1.], [1., 2.]].view(),
+        );
+        assert_eq!((up, down), (vec![1., 0.], vec![0., 3.]));
+    }
+}
@@ -0,0 +1,18 @@
+//! Per-chunk raster operators.
+//!
+//! Operators in this module work on in-memory chunks (as
+//! read via [`ChunkReader`][crate::reader::ChunkReader])
+//! and are independent of GDAL.
+
+pub mod convert;
+#[cfg(feature = "fft")]
+pub mod fft;
+pub mod hydrology;
+pub mod morphology;
+pub mod reclassify;
+#[cfg(feature = "simd")]
+pub mod simd;
+pub mod sparse;
+pub mod spectral;
+pub mod temporal;
+pub mod terrain;
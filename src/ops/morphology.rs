@@ -0,0 +1,236 @@
+//! Morphological operators on boolean or classified chunks.
+//!
+//! Erosion and dilation take the minimum and maximum over a
+//! [`StructuringElement`], so they apply to boolean masks
+//! (`false < true`) as well as to class or integer rasters.
+//! Opening removes features smaller than the element (eg.
+//! speckle in a cloud mask), closing fills holes.
+//!
+//! Like [`hillshade`][super::terrain::hillshade], operators
+//! take chunks padded by [`MorphologyOp::padding`] rows on
+//! either side (see
+//! [`ChunkConfigBuilder::with_padding`][crate::chunking::builder::ChunkConfigBuilder::with_padding])
+//! and return the data (unpadded) rows. Neighbours beyond
+//! the left and right edges are ignored.
+
+use ndarray::{Array2, ArrayView2};
+
+/// Neighbourhood of a pixel, as (row, col) offsets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructuringElement {
+    offsets: Vec<(isize, isize)>,
+}
+
+impl StructuringElement {
+    /// Square of side `2 * radius + 1`.
+    pub fn square(radius: usize) -> Self {
+        Self::from_predicate(radius, |_, _| true)
+    }
+
+    /// Cross (plus sign) of arms of length `radius`.
+    pub fn cross(radius: usize) -> Self {
+        Self::from_predicate(radius, |i, j| i == 0 || j == 0)
+    }
+
+    /// Disk of the given `radius`.
+    pub fn disk(radius: usize) -> Self {
+        let radius_sq = (radius * radius) as isize;
+        Self::from_predicate(radius, |i, j| i * i + j * j <= radius_sq)
+    }
+
+    /// Element of the `true` pixels of `mask`, centered on
+    /// its middle pixel. Both dimensions should be odd.
+    pub fn from_mask(mask: ArrayView2<bool>) -> Self {
+        let (rows, cols) = mask.dim();
+        let (center_i, center_j) = ((rows / 2) as isize, (cols / 2) as isize);
+        let offsets = mask
+            .indexed_iter()
+            .filter(|(_, set)| **set)
+            .map(|((i, j), _)| (i as isize - center_i, j as isize - center_j))
+            .collect();
+        StructuringElement { offsets }
+    }
+
+    fn from_predicate<F: Fn(isize, isize) -> bool>(radius: usize, f: F) -> Self {
+        let radius = radius as isize;
+        let offsets = (-radius..=radius)
+            .flat_map(|i| (-radius..=radius).map(move |j| (i, j)))
+            .filter(|&(i, j)| f(i, j))
+            .collect();
+        StructuringElement { offsets }
+    }
+
+    /// Largest row offset of the element.
+    pub fn radius(&self) -> usize {
+        self.offsets
+            .iter()
+            .map(|(i, _)| i.unsigned_abs())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Reduce the neighbourhood of each data row of `chunk`
+    /// (padded by `padding` rows) with `pick`.
+    fn filter<T, F>(&self, chunk: ArrayView2<T>, padding: usize, pick: F) -> Array2<T>
+    where
+        T: Copy,
+        F: Fn(T, T) -> T,
+    {
+        let (rows, cols) = chunk.dim();
+        let data_rows = rows.saturating_sub(2 * padding);
+        let mut out = chunk
+            .slice(ndarray::s![padding..padding + data_rows, ..])
+            .to_owned();
+        for ((row, col), value) in out.indexed_iter_mut() {
+            let (i, j) = ((row + padding) as isize, col as isize);
+            let neighbours = self.offsets.iter().filter_map(|&(di, dj)| {
+                let (i, j) = (i + di, j + dj);
+                let inside = i >= 0 && (i as usize) < rows && j >= 0 && (j as usize) < cols;
+                inside.then(|| chunk[[i as usize, j as usize]])
+            });
+            if let Some(picked) = neighbours.reduce(&pick) {
+                *value = picked;
+            }
+        }
+        out
+    }
+}
+
+fn min<T: PartialOrd>(a: T, b: T) -> T {
+    if b < a {
+        b
+    } else {
+        a
+    }
+}
+
+fn max<T: PartialOrd>(a: T, b: T) -> T {
+    if b > a {
+        b
+    } else {
+        a
+    }
+}
+
+/// Erosion (minimum over `element`) of a chunk padded by
+/// `element.radius()` rows.
+pub fn erode<T>(chunk: ArrayView2<T>, element: &StructuringElement) -> Array2<T>
+where
+    T: Copy + PartialOrd,
+{
+    element.filter(chunk, element.radius(), min)
+}
+
+/// Dilation (maximum over `element`) of a chunk padded by
+/// `element.radius()` rows.
+pub fn dilate<T>(chunk: ArrayView2<T>, element: &StructuringElement) -> Array2<T>
+where
+    T: Copy + PartialOrd,
+{
+    element.filter(chunk, element.radius(), max)
+}
+
+/// Opening (erosion, then dilation) of a chunk padded by
+/// `2 * element.radius()` rows.
+pub fn open<T>(chunk: ArrayView2<T>, element: &StructuringElement) -> Array2<T>
+where
+    T: Copy + PartialOrd,
+{
+    dilate(erode(chunk, element).view(), element)
+}
+
+/// Closing (dilation, then erosion) of a chunk padded by
+/// `2 * element.radius()` rows.
+pub fn close<T>(chunk: ArrayView2<T>, element: &StructuringElement) -> Array2<T>
+where
+    T: Copy + PartialOrd,
+{
+    erode(dilate(chunk, element).view(), element)
+}
+
+/// A morphological operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MorphologyOp {
+    Erode,
+    Dilate,
+    Open,
+    Close,
+}
+
+impl MorphologyOp {
+    /// Padding (rows) needed by the operator with `element`.
+    pub fn padding(&self, element: &StructuringElement) -> usize {
+        match self {
+            MorphologyOp::Erode | MorphologyOp::Dilate => element.radius(),
+            MorphologyOp::Open | MorphologyOp::Close => 2 * element.radius(),
+        }
+    }
+
+    /// Apply the operator to a chunk padded by
+    /// [`padding`][Self::padding] rows.
+    pub fn apply<T>(&self, chunk: ArrayView2<T>, element: &StructuringElement) -> Array2<T>
+    where
+        T: Copy + PartialOrd,
+    {
+        match self {
+            MorphologyOp::Erode => erode(chunk, element),
+            MorphologyOp::Dilate => dilate(chunk, element),
+            MorphologyOp::Open => open(chunk, element),
+            MorphologyOp::Close => close(chunk, element),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::reader::ChunkReader;
+    use crate::testing::ArrayReader;
+    use ndarray::{concatenate, s, Axis};
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn test_open_removes_speckle() {
+        let mut mask = Array2::from_elem((9, 9), false);
+        mask.slice_mut(s![4..8, 1..5]).fill(true);
+        mask[[2, 7]] = true;
+        let element = StructuringElement::square(1);
+
+        let opened = open(mask.view(), &element);
+        assert_eq!(opened.dim(), (5, 9));
+        // Rows 2..7 of the mask: the speckle is gone, the
+        // block is kept.
+        assert!(!opened[[0, 7]]);
+        assert_eq!(opened.slice(s![2..5, 1..5]), mask.slice(s![4..7, 1..5]));
+        assert_eq!(opened.iter().filter(|&&set| set).count(), 12);
+
+        let closed = close(opened.view(), &StructuringElement::cross(0));
+        assert_eq!(closed, opened);
+    }
+
+    #[test]
+    fn test_chunked_matches_whole() {
+        let classes = Array2::from_shape_fn((12, 5), |(i, j)| ((i * 7 + j * 3) % 4) as u8);
+        let element = StructuringElement::disk(1);
+        let op = MorphologyOp::Close;
+        let padding = op.padding(&element);
+
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(5).unwrap(),
+            NonZeroUsize::new(12).unwrap(),
+        )
+        .with_data_height(NonZeroUsize::new(3).unwrap())
+        .with_padding(padding)
+        .build();
+        let reader = ArrayReader(classes.clone());
+        let chunks: Vec<Array2<u8>> = cfg
+            .iter()
+            .map(|chunk| op.apply(reader.read_chunk::<u8>(chunk).unwrap().view(), &element))
+            .collect();
+        let views: Vec<_> = chunks.iter().map(|chunk| chunk.view()).collect();
+        let chunked = concatenate(Axis(0), &views).unwrap();
+
+        assert_eq!(chunked, op.apply(classes.view(), &element));
+    }
+}
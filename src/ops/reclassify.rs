@@ -0,0 +1,218 @@
+//! Reclassify and threshold chunks.
+//!
+//! Class boundaries on float rasters are sensitive to
+//! representation error (eg. `0.1 + 0.2 != 0.3`). Ranges are
+//! thus matched with an explicit [`Bound`] on each side, and
+//! an `epsilon` tolerance applied in the direction of the
+//! bound: inclusive bounds are widened by `epsilon`, and
+//! exclusive bounds are narrowed by it.
+
+use ndarray::{Array2, ArrayView2};
+use num::ToPrimitive;
+
+/// Whether a range includes its endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Inclusive,
+    Exclusive,
+}
+
+/// A range of values `min..max` with configurable bounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClassRange {
+    pub min: f64,
+    pub min_bound: Bound,
+    pub max: f64,
+    pub max_bound: Bound,
+}
+
+impl ClassRange {
+    /// Range `[min, max)`, the usual convention for
+    /// consecutive classes.
+    pub fn new(min: f64, max: f64) -> Self {
+        ClassRange {
+            min,
+            min_bound: Bound::Inclusive,
+            max,
+            max_bound: Bound::Exclusive,
+        }
+    }
+
+    /// Set the bound of the lower end of the range.
+    pub fn with_min_bound(mut self, bound: Bound) -> Self {
+        self.min_bound = bound;
+        self
+    }
+
+    /// Set the bound of the upper end of the range.
+    pub fn with_max_bound(mut self, bound: Bound) -> Self {
+        self.max_bound = bound;
+        self
+    }
+
+    /// Whether `value` falls within the range, with
+    /// `epsilon` tolerance. `NaN` is never contained.
+    pub fn contains(&self, value: f64, epsilon: f64) -> bool {
+        let above_min = match self.min_bound {
+            Bound::Inclusive => value >= self.min - epsilon,
+            Bound::Exclusive => value > self.min + epsilon,
+        };
+        let below_max = match self.max_bound {
+            Bound::Inclusive => value <= self.max + epsilon,
+            Bound::Exclusive => value < self.max - epsilon,
+        };
+        above_min && below_max
+    }
+}
+
+/// Map ranges of values to classes.
+///
+/// Ranges are tested in insertion order; values matching no
+/// range (including `NaN`) are mapped to the default class.
+#[derive(Clone, Debug)]
+pub struct Reclassify<U> {
+    classes: Vec<(ClassRange, U)>,
+    default: U,
+    epsilon: f64,
+}
+
+impl<U: Copy> Reclassify<U> {
+    /// Create a reclassifier mapping all values to `default`.
+    pub fn new(default: U) -> Self {
+        Reclassify {
+            classes: vec![],
+            default,
+            epsilon: 0.,
+        }
+    }
+
+    /// Add a class for values within `range`.
+    pub fn with_class(mut self, range: ClassRange, class: U) -> Self {
+        self.classes.push((range, class));
+        self
+    }
+
+    /// Set the tolerance used when matching ranges.
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon.abs();
+        self
+    }
+
+    /// Class of a single value.
+    pub fn classify<T: ToPrimitive>(&self, value: T) -> U {
+        let value = value.to_f64().unwrap_or(f64::NAN);
+        self.classes
+            .iter()
+            .find(|(range, _)| range.contains(value, self.epsilon))
+            .map_or(self.default, |&(_, class)| class)
+    }
+
+    /// Reclassify a chunk.
+    pub fn apply<T: ToPrimitive + Copy>(&self, chunk: ArrayView2<T>) -> Array2<U> {
+        chunk.map(|&value| self.classify(value))
+    }
+}
+
+/// Binary classification of values against a threshold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Threshold {
+    pub value: f64,
+    /// Whether values equal to the threshold pass.
+    pub bound: Bound,
+    pub epsilon: f64,
+}
+
+impl Threshold {
+    /// Threshold passing values `>= value`.
+    pub fn new(value: f64) -> Self {
+        Threshold {
+            value,
+            bound: Bound::Inclusive,
+            epsilon: 0.,
+        }
+    }
+
+    /// Set whether values equal to the threshold pass.
+    pub fn with_bound(mut self, bound: Bound) -> Self {
+        self.bound = bound;
+        self
+    }
+
+    /// Set the tolerance used when comparing values.
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon.abs();
+        self
+    }
+
+    /// Whether a single value passes the threshold.
+    pub fn test<T: ToPrimitive>(&self, value: T) -> bool {
+        let value = value.to_f64().unwrap_or(f64::NAN);
+        ClassRange {
+            min: self.value,
+            min_bound: self.bound,
+            max: f64::INFINITY,
+            max_bound: Bound::Inclusive,
+        }
+        .contains(value, self.epsilon)
+    }
+
+    /// Threshold a chunk.
+    pub fn apply<T: ToPrimitive + Copy>(&self, chunk: ArrayView2<T>) -> Array2<bool> {
+        chunk.map(|&value| self.test(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_bounds() {
+        let range = ClassRange::new(0.3, 0.6);
+        assert!(range.contains(0.3, 0.));
+        assert!(!range.contains(0.6, 0.));
+
+        let range = range
+            .with_min_bound(Bound::Exclusive)
+            .with_max_bound(Bound::Inclusive);
+        assert!(!range.contains(0.3, 0.));
+        assert!(range.contains(0.6, 0.));
+    }
+
+    #[test]
+    fn test_epsilon_at_boundary() {
+        let value = 0.1 + 0.2;
+        let range = ClassRange::new(0.0, 0.3);
+        // Representation error puts `value` just above 0.3.
+        assert!(!range.with_max_bound(Bound::Inclusive).contains(value, 0.));
+        assert!(range.with_max_bound(Bound::Inclusive).contains(value, 1e-9));
+        assert!(!range.contains(value - 1e-12, 1e-9));
+    }
+
+    #[test]
+    fn test_reclassify() {
+        let classes = Reclassify::new(0u8)
+            .with_class(ClassRange::new(0., 0.3), 1)
+            .with_class(ClassRange::new(0.3, 1.).with_max_bound(Bound::Inclusive), 2)
+            .with_epsilon(1e-9);
+        let chunk = array![[0.1f32, 0.3], [1.0, f32::NAN]];
+        assert_eq!(classes.apply(chunk.view()), array![[1, 2], [2, 0]]);
+        assert_eq!(classes.classify(0.1 + 0.2), 2);
+    }
+
+    #[test]
+    fn test_threshold() {
+        let chunk = array![[1i16, 2], [3, 4]];
+        let inclusive = Threshold::new(2.);
+        let exclusive = inclusive.with_bound(Bound::Exclusive);
+        assert_eq!(
+            inclusive.apply(chunk.view()),
+            array![[false, true], [true, true]]
+        );
+        assert_eq!(
+            exclusive.apply(chunk.view()),
+            array![[false, false], [true, true]]
+        );
+    }
+}
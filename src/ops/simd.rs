@@ -0,0 +1,525 @@
+//! Explicitly vectorized, nodata-aware elementwise kernels.
+//!
+//! Branches on nodata defeat the autovectorization of
+//! ndarray's `mapv` / `Zip`, so these kernels process 8
+//! lanes at a time with [`wide`] and select results with
+//! masks instead. The tail of a slice is handled by the
+//! equivalent scalar code.
+//!
+//! Values that are nodata under a [`NodataPolicy`] (and
+//! `NaN`) are invalid and map to `NaN` in the output. With
+//! [`NodataPolicy::Epsilon`], lanes are compared in the
+//! precision of the values.
+//!
+//! The `f64` kernels [`mask_nodata`] and [`moments`] back
+//! the nodata masking of [`ExprGraph`] sources and the band
+//! statistics of [`stats`] when the feature is enabled.
+//!
+//! This module is only available with the "simd" feature.
+//!
+//! [`ExprGraph`]: crate::expr::ExprGraph
+//! [`stats`]: crate::stats
+
+use crate::nodata::NodataPolicy;
+use crate::stats::ChunkStats;
+use ndarray::{Array2, ArrayView2, ArrayViewMut2};
+use wide::{f32x8, f64x4};
+
+use std::{borrow::Cow, convert::TryFrom};
+
+const LANES: usize = 8;
+const LANES_F64: usize = 4;
+
+/// Nodata test of a [`NodataPolicy`], on lanes and scalars.
+#[derive(Clone, Copy)]
+struct Invalid {
+    /// Value compared with, `None` if only `NaN` is nodata.
+    nodata: Option<f64>,
+    /// Largest distance of nodata values to `nodata`.
+    tolerance: f64,
+    policy: NodataPolicy,
+}
+
+impl Invalid {
+    fn new(nodata: Option<f64>, policy: NodataPolicy) -> Self {
+        let nodata = nodata.filter(|nodata| !nodata.is_nan() && policy != NodataPolicy::Nan);
+        let tolerance = match (policy, nodata) {
+            (NodataPolicy::Epsilon(epsilon), Some(nodata)) => epsilon * nodata.abs().max(1.),
+            _ => 0.,
+        };
+        Invalid {
+            nodata,
+            tolerance,
+            policy,
+        }
+    }
+
+    /// Mask of the invalid lanes of `values`.
+    #[inline]
+    fn f32x8(&self, values: f32x8) -> f32x8 {
+        match self.nodata {
+            None => values.is_nan(),
+            Some(nodata) if self.tolerance == 0. => {
+                values.is_nan() | values.cmp_eq(f32x8::splat(nodata as f32))
+            }
+            Some(nodata) => {
+                let distance = (values - f32x8::splat(nodata as f32)).abs();
+                values.is_nan() | distance.cmp_le(f32x8::splat(self.tolerance as f32))
+            }
+        }
+    }
+
+    /// Mask of the invalid lanes of `values`.
+    #[inline]
+    fn f64x4(&self, values: f64x4) -> f64x4 {
+        match self.nodata {
+            None => values.is_nan(),
+            Some(nodata) if self.tolerance == 0. => {
+                values.is_nan() | values.cmp_eq(f64x4::splat(nodata))
+            }
+            Some(nodata) => {
+                let distance = (values - f64x4::splat(nodata)).abs();
+                values.is_nan() | distance.cmp_le(f64x4::splat(self.tolerance))
+            }
+        }
+    }
+
+    #[inline]
+    fn scalar(&self, value: f64) -> bool {
+        self.policy.is_nodata(value, self.nodata)
+    }
+}
+
+#[inline]
+fn load(values: &[f32]) -> f32x8 {
+    f32x8::from(<[f32; LANES]>::try_from(values).expect("slice of LANES values"))
+}
+
+#[inline]
+fn load_f64(values: &[f64]) -> f64x4 {
+    f64x4::from(<[f64; LANES_F64]>::try_from(values).expect("slice of LANES_F64 values"))
+}
+
+/// Apply `simd` to full lanes of `values` and `scalar` to
+/// the tail, writing into `out`. Invalid values map to `NaN`.
+fn map_unary<S, F>(values: &[f32], out: &mut [f32], invalid: Invalid, simd: S, scalar: F)
+where
+    S: Fn(f32x8) -> f32x8,
+    F: Fn(f32) -> f32,
+{
+    assert_eq!(values.len(), out.len(), "input and output lengths differ");
+    let nan = f32x8::splat(f32::NAN);
+    let mut src = values.chunks_exact(LANES);
+    let mut dst = out.chunks_exact_mut(LANES);
+    for (src, dst) in (&mut src).zip(&mut dst) {
+        let values = load(src);
+        let result = invalid.f32x8(values).blend(nan, simd(values));
+        dst.copy_from_slice(&result.to_array());
+    }
+    for (value, out) in src.remainder().iter().zip(dst.into_remainder()) {
+        *out = if invalid.scalar(*value as f64) {
+            f32::NAN
+        } else {
+            scalar(*value)
+        };
+    }
+}
+
+/// `values * scale + offset`.
+pub fn scale(
+    values: &[f32],
+    out: &mut [f32],
+    scale: f32,
+    offset: f32,
+    nodata: Option<f32>,
+    policy: NodataPolicy,
+) {
+    let (scale_v, offset_v) = (f32x8::splat(scale), f32x8::splat(offset));
+    map_unary(
+        values,
+        out,
+        Invalid::new(nodata.map(f64::from), policy),
+        |v| v.mul_add(scale_v, offset_v),
+        |v| v.mul_add(scale, offset),
+    );
+}
+
+/// `1.` where `values > threshold`, `0.` elsewhere.
+pub fn threshold(
+    values: &[f32],
+    out: &mut [f32],
+    threshold: f32,
+    nodata: Option<f32>,
+    policy: NodataPolicy,
+) {
+    let (threshold_v, one, zero) = (f32x8::splat(threshold), f32x8::splat(1.), f32x8::splat(0.));
+    map_unary(
+        values,
+        out,
+        Invalid::new(nodata.map(f64::from), policy),
+        |v| v.cmp_gt(threshold_v).blend(one, zero),
+        |v| if v > threshold { 1. } else { 0. },
+    );
+}
+
+/// Normalized difference `(a - b) / (a + b)` (eg. NDVI with
+/// NIR and red bands). Pixels where either value is invalid,
+/// or the sum is zero, map to `NaN`.
+pub fn normalized_difference(
+    a: &[f32],
+    b: &[f32],
+    out: &mut [f32],
+    nodata: Option<f32>,
+    policy: NodataPolicy,
+) {
+    assert_eq!(a.len(), b.len(), "input lengths differ");
+    assert_eq!(a.len(), out.len(), "input and output lengths differ");
+    let invalid = Invalid::new(nodata.map(f64::from), policy);
+    let (nan, zero) = (f32x8::splat(f32::NAN), f32x8::splat(0.));
+    let mut a_lanes = a.chunks_exact(LANES);
+    let mut b_lanes = b.chunks_exact(LANES);
+    let mut dst = out.chunks_exact_mut(LANES);
+    for ((a, b), dst) in (&mut a_lanes).zip(&mut b_lanes).zip(&mut dst) {
+        let (a, b) = (load(a), load(b));
+        let sum = a + b;
+        let mask = invalid.f32x8(a) | invalid.f32x8(b) | sum.cmp_eq(zero);
+        let result = mask.blend(nan, (a - b) / sum);
+        dst.copy_from_slice(&result.to_array());
+    }
+    let tail = a_lanes.remainder().iter().zip(b_lanes.remainder());
+    for ((a, b), out) in tail.zip(dst.into_remainder()) {
+        let sum = a + b;
+        *out = if invalid.scalar(*a as f64) || invalid.scalar(*b as f64) || sum == 0. {
+            f32::NAN
+        } else {
+            (a - b) / sum
+        };
+    }
+}
+
+/// Sum and count of the valid values, eg. to accumulate
+/// mean statistics over chunks.
+pub fn sum_count(values: &[f32], nodata: Option<f32>, policy: NodataPolicy) -> (f64, usize) {
+    let invalid = Invalid::new(nodata.map(f64::from), policy);
+    let zero = f32x8::splat(0.);
+    let mut lanes = values.chunks_exact(LANES);
+    let (mut sum, mut count) = (0f64, 0usize);
+    for lane in &mut lanes {
+        let values = load(lane);
+        let mask = invalid.f32x8(values);
+        // Accumulate each lane in `f64` to limit rounding
+        // errors over large rasters.
+        sum += mask
+            .blend(zero, values)
+            .to_array()
+            .iter()
+            .map(|v| *v as f64)
+            .sum::<f64>();
+        count += LANES - (mask.move_mask() as u32).count_ones() as usize;
+    }
+    for value in lanes.remainder() {
+        if !invalid.scalar(*value as f64) {
+            sum += *value as f64;
+            count += 1;
+        }
+    }
+    (sum, count)
+}
+
+/// Set the values that are nodata under `policy` to `NaN`.
+pub fn mask_nodata(values: &mut [f64], nodata: Option<f64>, policy: NodataPolicy) {
+    let invalid = Invalid::new(nodata, policy);
+    let nan = f64x4::splat(f64::NAN);
+    let mut lanes = values.chunks_exact_mut(LANES_F64);
+    for lane in &mut lanes {
+        let values = load_f64(lane);
+        lane.copy_from_slice(&invalid.f64x4(values).blend(nan, values).to_array());
+    }
+    for value in lanes.into_remainder() {
+        if invalid.scalar(*value) {
+            *value = f64::NAN;
+        }
+    }
+}
+
+/// Count, range and moments of the values that are not
+/// nodata under `policy`, as computed by
+/// [`ChunkStats::from_array`].
+///
+/// The moments are computed in two passes (the mean, then
+/// the squared deviations from it), which vectorize unlike
+/// the updates of `from_array`.
+pub fn moments(values: &[f64], nodata: Option<f64>, policy: NodataPolicy) -> ChunkStats {
+    let invalid = Invalid::new(nodata, policy);
+    let zero = f64x4::splat(0.);
+    let (inf, neg_inf) = (f64x4::splat(f64::INFINITY), f64x4::splat(f64::NEG_INFINITY));
+    let (mut sum, mut min, mut max) = (zero, inf, neg_inf);
+    let mut count = 0usize;
+    let mut lanes = values.chunks_exact(LANES_F64);
+    for lane in &mut lanes {
+        let values = load_f64(lane);
+        let mask = invalid.f64x4(values);
+        sum += mask.blend(zero, values);
+        min = min.min(mask.blend(inf, values));
+        max = max.max(mask.blend(neg_inf, values));
+        count += LANES_F64 - (mask.move_mask() as u32).count_ones() as usize;
+    }
+    let mut sum: f64 = sum.to_array().iter().sum();
+    let mut min = min.to_array().iter().copied().fold(f64::INFINITY, f64::min);
+    let mut max = max
+        .to_array()
+        .iter()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max);
+    for &value in lanes.remainder() {
+        if !invalid.scalar(value) {
+            sum += value;
+            min = min.min(value);
+            max = max.max(value);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return ChunkStats::default();
+    }
+
+    let mean = sum / count as f64;
+    let mean_v = f64x4::splat(mean);
+    let mut m2 = zero;
+    let mut lanes = values.chunks_exact(LANES_F64);
+    for lane in &mut lanes {
+        let values = load_f64(lane);
+        let deviation = invalid.f64x4(values).blend(zero, values - mean_v);
+        m2 = deviation.mul_add(deviation, m2);
+    }
+    let mut m2: f64 = m2.to_array().iter().sum();
+    for &value in lanes.remainder() {
+        if !invalid.scalar(value) {
+            m2 += (value - mean) * (value - mean);
+        }
+    }
+    ChunkStats {
+        count: count as u64,
+        min,
+        max,
+        mean,
+        m2,
+    }
+}
+
+/// Contiguous row-major values of `array`, copying only if
+/// needed.
+fn contiguous<'a, T: Copy>(array: ArrayView2<'a, T>) -> Cow<'a, [T]> {
+    match array.to_slice() {
+        Some(values) => Cow::Borrowed(values),
+        None => Cow::Owned(array.iter().copied().collect()),
+    }
+}
+
+/// [`scale`] over a chunk.
+pub fn scale_array(
+    array: ArrayView2<f32>,
+    factor: f32,
+    offset: f32,
+    nodata: Option<f32>,
+    policy: NodataPolicy,
+) -> Array2<f32> {
+    let mut out = Array2::zeros(array.dim());
+    let values = contiguous(array);
+    scale(
+        &values,
+        out.as_slice_mut().expect("new array is contiguous"),
+        factor,
+        offset,
+        nodata,
+        policy,
+    );
+    out
+}
+
+/// [`threshold`] over a chunk.
+pub fn threshold_array(
+    array: ArrayView2<f32>,
+    value: f32,
+    nodata: Option<f32>,
+    policy: NodataPolicy,
+) -> Array2<f32> {
+    let mut out = Array2::zeros(array.dim());
+    let values = contiguous(array);
+    threshold(
+        &values,
+        out.as_slice_mut().expect("new array is contiguous"),
+        value,
+        nodata,
+        policy,
+    );
+    out
+}
+
+/// [`normalized_difference`] over a pair of chunks of the
+/// same shape.
+///
+/// # Panics
+///
+/// If the shapes of `a` and `b` differ.
+pub fn normalized_difference_array(
+    a: ArrayView2<f32>,
+    b: ArrayView2<f32>,
+    nodata: Option<f32>,
+    policy: NodataPolicy,
+) -> Array2<f32> {
+    assert_eq!(a.dim(), b.dim(), "chunk shapes differ");
+    let mut out = Array2::zeros(a.dim());
+    let (a, b) = (contiguous(a), contiguous(b));
+    normalized_difference(
+        &a,
+        &b,
+        out.as_slice_mut().expect("new array is contiguous"),
+        nodata,
+        policy,
+    );
+    out
+}
+
+/// [`sum_count`] over a chunk.
+pub fn sum_count_array(
+    array: ArrayView2<f32>,
+    nodata: Option<f32>,
+    policy: NodataPolicy,
+) -> (f64, usize) {
+    sum_count(&contiguous(array), nodata, policy)
+}
+
+/// [`mask_nodata`] over a chunk.
+pub fn mask_nodata_array(mut array: ArrayViewMut2<f64>, nodata: Option<f64>, policy: NodataPolicy) {
+    match array.as_slice_mut() {
+        Some(values) => mask_nodata(values, nodata, policy),
+        None => {
+            let invalid = Invalid::new(nodata, policy);
+            array.mapv_inplace(|value| {
+                if invalid.scalar(value) {
+                    f64::NAN
+                } else {
+                    value
+                }
+            });
+        }
+    }
+}
+
+/// [`moments`] over a chunk.
+pub fn moments_array(
+    array: ArrayView2<f64>,
+    nodata: Option<f64>,
+    policy: NodataPolicy,
+) -> ChunkStats {
+    moments(&contiguous(array), nodata, policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, Zip};
+
+    /// Scalar reference of [`normalized_difference`].
+    fn reference_nd(a: ArrayView2<f32>, b: ArrayView2<f32>, nodata: f32) -> Array2<f32> {
+        Zip::from(a).and(b).map_collect(|&a, &b| {
+            if a == nodata || b == nodata || a + b == 0. {
+                f32::NAN
+            } else {
+                (a - b) / (a + b)
+            }
+        })
+    }
+
+    fn same(a: &Array2<f32>, b: &Array2<f32>) -> bool {
+        a.iter()
+            .zip(b)
+            .all(|(a, b)| a == b || (a.is_nan() && b.is_nan()))
+    }
+
+    #[test]
+    fn test_normalized_difference() {
+        let nir = Array2::from_shape_fn((3, 7), |(i, j)| (i * 7 + j) as f32);
+        let red = Array2::from_shape_fn((3, 7), |(i, j)| ((i + j) % 4) as f32);
+        let out =
+            normalized_difference_array(nir.view(), red.view(), Some(3.), NodataPolicy::Exact);
+        assert!(same(&out, &reference_nd(nir.view(), red.view(), 3.)));
+    }
+
+    #[test]
+    fn test_policies() {
+        // Nodata drifted through a conversion, in and out of
+        // full lanes.
+        let values = Array2::from_shape_fn((3, 7), |(i, j)| {
+            if (i + j) % 5 == 0 {
+                -9999.001
+            } else {
+                (i * 7 + j) as f32
+            }
+        });
+        let exact = scale_array(values.view(), 1., 0., Some(-9999.), NodataPolicy::Exact);
+        assert!(exact.iter().all(|v| !v.is_nan()));
+        let epsilon = NodataPolicy::Epsilon(1e-6);
+        let scaled = scale_array(values.view(), 1., 0., Some(-9999.), epsilon);
+        for (value, scaled) in values.iter().zip(&scaled) {
+            assert_eq!(scaled.is_nan(), *value < 0.);
+        }
+        let ignored = scale_array(values.view(), 1., 0., Some(-9999.001), NodataPolicy::Nan);
+        assert!(ignored.iter().all(|v| !v.is_nan()));
+        assert_eq!(sum_count_array(values.view(), Some(-9999.), epsilon).1, 17);
+    }
+
+    #[test]
+    fn test_f64_kernels() {
+        let mut values = Array2::from_shape_fn((3, 7), |(i, j)| {
+            if (i + j) % 5 == 0 {
+                -9999.0001
+            } else {
+                1e6 + (i * 7 + j) as f64
+            }
+        });
+        let policy = NodataPolicy::Epsilon(1e-6);
+        let stats = moments_array(values.view(), Some(-9999.), policy);
+        let reference = ChunkStats::from_array(values.view(), Some(-9999.), policy);
+        assert_eq!(stats.count, reference.count);
+        assert_eq!((stats.min, stats.max), (reference.min, reference.max));
+        assert!((stats.mean - reference.mean).abs() < 1e-9);
+        assert!((stats.m2 - reference.m2).abs() < 1e-6 * reference.m2);
+        assert_eq!(moments(&[f64::NAN], None, policy), ChunkStats::default());
+
+        // A strided view takes the scalar path.
+        mask_nodata_array(
+            values.slice_mut(ndarray::s![.., ..;2]),
+            Some(-9999.),
+            policy,
+        );
+        mask_nodata_array(
+            values.slice_mut(ndarray::s![.., 1..;2]),
+            Some(-9999.),
+            policy,
+        );
+        for ((i, j), value) in values.indexed_iter() {
+            assert_eq!(value.is_nan(), (i + j) % 5 == 0);
+        }
+        let mut values = vec![1., -9999., 3., 4., -9999., 6.];
+        mask_nodata(&mut values, Some(-9999.), NodataPolicy::Exact);
+        assert!(values[1].is_nan() && values[4].is_nan());
+        assert_eq!(values[5], 6.);
+    }
+
+    #[test]
+    fn test_scale_threshold() {
+        let values = array![[1., -9., 3., 4., 5.], [6., 7., 8., f32::NAN, 10.]];
+        let policy = NodataPolicy::Exact;
+        let scaled = scale_array(values.view(), 2., 1., Some(-9.), policy);
+        assert_eq!(scaled[[1, 4]], 21.);
+        assert!(scaled[[0, 1]].is_nan() && scaled[[1, 3]].is_nan());
+
+        let mask = threshold_array(values.t(), 5., Some(-9.), policy);
+        assert_eq!(mask[[4, 1]], 1.);
+        assert_eq!(mask[[2, 0]], 0.);
+
+        assert_eq!(sum_count_array(values.view(), Some(-9.), policy), (44., 8));
+    }
+}
@@ -0,0 +1,285 @@
+//! Sparse chunks for mostly-nodata data.
+//!
+//! Over oceans or deserts most pixels of a chunk may be
+//! nodata, and dense [`Array2`] chunks waste both memory and
+//! compute. A [`SparseChunk`] only stores runs of
+//! consecutive valid pixels (in row-major order), and
+//! operators on it skip the invalid runs entirely.
+//! [`MaskedChunk`] selects between the dense and sparse
+//! representations based on the fraction of nodata pixels.
+//! Pixels are compared with nodata under a [`NodataPolicy`].
+
+use crate::nodata::NodataPolicy;
+use ndarray::{Array2, ArrayView2};
+use num::ToPrimitive;
+
+/// Run-length representation of the valid pixels of a chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseChunk<T> {
+    shape: (usize, usize),
+    nodata: T,
+    policy: NodataPolicy,
+    /// Runs of valid pixels as (flat index, length), sorted
+    /// by index.
+    runs: Vec<(usize, usize)>,
+    /// Values of all runs, concatenated.
+    values: Vec<T>,
+}
+
+impl<T: Copy + ToPrimitive> SparseChunk<T> {
+    /// Collect the runs of `data` that are not nodata under
+    /// `policy`.
+    pub fn from_dense(data: ArrayView2<T>, nodata: T, policy: NodataPolicy) -> Self {
+        let mut runs: Vec<(usize, usize)> = vec![];
+        let mut values = vec![];
+        for (index, &value) in data.iter().enumerate() {
+            if policy.is_nodata(value, Some(nodata)) {
+                continue;
+            }
+            match runs.last_mut() {
+                Some((start, len)) if *start + *len == index => *len += 1,
+                _ => runs.push((index, 1)),
+            }
+            values.push(value);
+        }
+
+        SparseChunk {
+            shape: data.dim(),
+            nodata,
+            policy,
+            runs,
+            values,
+        }
+    }
+
+    /// Expand into a dense array, with invalid pixels set
+    /// to nodata.
+    pub fn to_dense(&self) -> Array2<T> {
+        let mut data = Array2::from_elem(self.shape, self.nodata);
+        let flat = data
+            .as_slice_mut()
+            .expect("freshly allocated array is contiguous");
+        for (start, values) in self.runs() {
+            flat[start..start + values.len()].copy_from_slice(values);
+        }
+        data
+    }
+
+    /// Shape (rows, cols) of the chunk.
+    pub fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    /// Nodata value of the chunk.
+    pub fn nodata(&self) -> T {
+        self.nodata
+    }
+
+    /// Policy the pixels were compared with nodata under.
+    pub fn policy(&self) -> NodataPolicy {
+        self.policy
+    }
+
+    /// Number of valid pixels.
+    pub fn valid_count(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Iterate over runs as (flat index, values).
+    pub fn runs(&self) -> impl Iterator<Item = (usize, &[T])> + '_ {
+        let mut offset = 0;
+        self.runs.iter().map(move |&(start, len)| {
+            let values = &self.values[offset..offset + len];
+            offset += len;
+            (start, values)
+        })
+    }
+
+    /// Iterate over valid values in row-major order.
+    pub fn values(&self) -> impl Iterator<Item = T> + '_ {
+        self.values.iter().copied()
+    }
+
+    /// Apply `f` to each valid pixel, keeping the run
+    /// structure.
+    pub fn map<U, F>(&self, nodata: U, f: F) -> SparseChunk<U>
+    where
+        F: Fn(T) -> U,
+    {
+        SparseChunk {
+            shape: self.shape,
+            nodata,
+            policy: self.policy,
+            runs: self.runs.clone(),
+            values: self.values.iter().map(|&value| f(value)).collect(),
+        }
+    }
+}
+
+/// A chunk along with its nodata value, stored densely or
+/// sparsely.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaskedChunk<T> {
+    Dense {
+        data: Array2<T>,
+        nodata: T,
+        policy: NodataPolicy,
+    },
+    Sparse(SparseChunk<T>),
+}
+
+impl<T: Copy + ToPrimitive> MaskedChunk<T> {
+    /// Wrap `data`, switching to the sparse representation
+    /// if the fraction of pixels that are nodata under
+    /// `policy` exceeds `sparse_fraction`.
+    pub fn new(data: Array2<T>, nodata: T, sparse_fraction: f64, policy: NodataPolicy) -> Self {
+        let invalid = data
+            .iter()
+            .filter(|&&value| policy.is_nodata(value, Some(nodata)))
+            .count();
+        if data.is_empty() || (invalid as f64) / (data.len() as f64) <= sparse_fraction {
+            MaskedChunk::Dense {
+                data,
+                nodata,
+                policy,
+            }
+        } else {
+            MaskedChunk::Sparse(SparseChunk::from_dense(data.view(), nodata, policy))
+        }
+    }
+
+    /// Whether `value` is nodata in this chunk.
+    pub fn is_nodata(&self, value: T) -> bool {
+        self.policy().is_nodata(value, Some(self.nodata()))
+    }
+
+    /// Whether the sparse representation is used.
+    pub fn is_sparse(&self) -> bool {
+        matches!(self, MaskedChunk::Sparse(_))
+    }
+
+    /// Nodata value of the chunk.
+    pub fn nodata(&self) -> T {
+        match self {
+            MaskedChunk::Dense { nodata, .. } => *nodata,
+            MaskedChunk::Sparse(sparse) => sparse.nodata(),
+        }
+    }
+
+    /// Policy the pixels are compared with nodata under.
+    pub fn policy(&self) -> NodataPolicy {
+        match self {
+            MaskedChunk::Dense { policy, .. } => *policy,
+            MaskedChunk::Sparse(sparse) => sparse.policy(),
+        }
+    }
+
+    /// Number of valid pixels.
+    pub fn valid_count(&self) -> usize {
+        match self {
+            MaskedChunk::Dense { data, .. } => {
+                data.iter().filter(|&&value| !self.is_nodata(value)).count()
+            }
+            MaskedChunk::Sparse(sparse) => sparse.valid_count(),
+        }
+    }
+
+    /// Apply `f` to each valid pixel; invalid pixels are
+    /// mapped to `nodata`. The representation is preserved.
+    pub fn map<U, F>(&self, nodata: U, f: F) -> MaskedChunk<U>
+    where
+        U: Copy + ToPrimitive,
+        F: Fn(T) -> U,
+    {
+        match self {
+            MaskedChunk::Dense { data, policy, .. } => MaskedChunk::Dense {
+                data: data.map(|&value| {
+                    if self.is_nodata(value) {
+                        nodata
+                    } else {
+                        f(value)
+                    }
+                }),
+                nodata,
+                policy: *policy,
+            },
+            MaskedChunk::Sparse(sparse) => MaskedChunk::Sparse(sparse.map(nodata, f)),
+        }
+    }
+
+    /// Fold over valid pixels in row-major order.
+    pub fn fold<A, F>(&self, init: A, f: F) -> A
+    where
+        F: FnMut(A, T) -> A,
+    {
+        match self {
+            MaskedChunk::Dense { data, .. } => data
+                .iter()
+                .copied()
+                .filter(|&value| !self.is_nodata(value))
+                .fold(init, f),
+            MaskedChunk::Sparse(sparse) => sparse.values().fold(init, f),
+        }
+    }
+
+    /// Convert into a dense array with invalid pixels set
+    /// to nodata.
+    pub fn into_dense(self) -> Array2<T> {
+        match self {
+            MaskedChunk::Dense { data, .. } => data,
+            MaskedChunk::Sparse(sparse) => sparse.to_dense(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_round_trip() {
+        let data = array![[0, 1, 2, 0], [3, 0, 0, 4], [5, 6, 0, 0]];
+        let sparse = SparseChunk::from_dense(data.view(), 0, NodataPolicy::Exact);
+        assert_eq!(sparse.valid_count(), 6);
+        assert_eq!(
+            sparse
+                .runs()
+                .map(|(start, v)| (start, v.len()))
+                .collect::<Vec<_>>(),
+            vec![(1, 2), (4, 1), (7, 3)]
+        );
+        assert_eq!(sparse.to_dense(), data);
+    }
+
+    #[test]
+    fn test_nan_nodata() {
+        let data = array![[f32::NAN, 1.], [2., f32::NAN]];
+        let chunk = MaskedChunk::new(data, f32::NAN, 0.25, NodataPolicy::Exact);
+        assert!(chunk.is_sparse());
+        assert_eq!(chunk.fold(0., |acc, v| acc + v), 3.);
+    }
+
+    #[test]
+    fn test_map_selects_representation() {
+        let data = array![[0u8, 0, 0, 1], [0, 0, 0, 0]];
+        let sparse = MaskedChunk::new(data.clone(), 0, 0.5, NodataPolicy::Exact);
+        let dense = MaskedChunk::new(data, 0, 0.9, NodataPolicy::Exact);
+        assert!(sparse.is_sparse() && !dense.is_sparse());
+
+        let double = |v: u8| v as u16 * 2;
+        let expected = array![[9u16, 9, 9, 2], [9, 9, 9, 9]];
+        assert_eq!(sparse.map(9, double).into_dense(), expected);
+        assert_eq!(dense.map(9, double).into_dense(), expected);
+    }
+
+    #[test]
+    fn test_policy() {
+        let data = array![[-3.4e38f32, 1.], [-3.3999e38, 2.]];
+        let exact = MaskedChunk::new(data.clone(), -3.4e38, 1., NodataPolicy::Exact);
+        assert_eq!(exact.valid_count(), 3);
+        let epsilon = MaskedChunk::new(data, -3.4e38, 0.25, NodataPolicy::Epsilon(1e-3));
+        assert!(epsilon.is_sparse());
+        assert_eq!(epsilon.fold(0., |acc, v| acc + v), 3.);
+    }
+}
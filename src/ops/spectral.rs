@@ -0,0 +1,54 @@
+//! Spectral indices of pixels.
+//!
+//! [`NormalizedDifferenceIndex`] computes the index of the
+//! GDAL `processors::NormalizedDifference` processor on
+//! single pixels, so in-memory chunks (eg. NumPy arrays in
+//! the Python bindings) get the same nodata handling.
+
+use crate::nodata::NodataPolicy;
+
+/// `(a - b) / (a + b)` of two pixels, eg. NDVI with `a` NIR
+/// and `b` red.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NormalizedDifferenceIndex {
+    pub nodata_a: Option<f64>,
+    pub nodata_b: Option<f64>,
+    pub policy: NodataPolicy,
+}
+
+impl NormalizedDifferenceIndex {
+    pub fn new(nodata_a: Option<f64>, nodata_b: Option<f64>, policy: NodataPolicy) -> Self {
+        NormalizedDifferenceIndex {
+            nodata_a,
+            nodata_b,
+            policy,
+        }
+    }
+
+    /// The index, or `None` if either input is nodata (or
+    /// `NaN`), or `a + b` is zero.
+    pub fn value(&self, a: f64, b: f64) -> Option<f64> {
+        let sum = a + b;
+        if self.policy.is_nodata(a, self.nodata_a)
+            || self.policy.is_nodata(b, self.nodata_b)
+            || sum == 0.
+        {
+            return None;
+        }
+        Some((a - b) / sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalized_difference() {
+        let index = NormalizedDifferenceIndex::new(Some(0.), None, NodataPolicy::Exact);
+        assert_eq!(index.value(3., 1.), Some(0.5));
+        assert_eq!(index.value(0., 1.), None);
+        assert_eq!(index.value(1., -1.), None);
+        assert_eq!(index.value(1., f64::NAN), None);
+    }
+}
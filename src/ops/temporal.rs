@@ -0,0 +1,115 @@
+//! Per-pixel reducers over time-series stacks.
+//!
+//! Stacks are arrays of shape (time, rows, cols), eg. as read
+//! by [`RasterStack`][crate::gdal::stack::RasterStack]. Values
+//! that are nodata under the given [`NodataPolicy`] (and
+//! `NaN`) are ignored; pixels without any valid value reduce
+//! to `NaN`.
+
+use crate::nodata::NodataPolicy;
+use ndarray::{Array2, ArrayView1, ArrayView3, Axis};
+use num::ToPrimitive;
+
+/// Valid values of a pixel's time series.
+fn valid_values<T>(lane: ArrayView1<T>, nodata: Option<T>, policy: NodataPolicy) -> Vec<f64>
+where
+    T: Copy + PartialEq + ToPrimitive,
+{
+    lane.iter()
+        .filter(|value| !policy.is_nodata(**value, nodata))
+        .filter_map(|value| value.to_f64())
+        .collect()
+}
+
+/// Reduce each pixel's valid values with `f`.
+fn reduce<T, F>(stack: ArrayView3<T>, nodata: Option<T>, policy: NodataPolicy, f: F) -> Array2<f64>
+where
+    T: Copy + PartialEq + ToPrimitive,
+    F: Fn(Vec<f64>) -> f64,
+{
+    stack.map_axis(Axis(0), |lane| {
+        let values = valid_values(lane, nodata, policy);
+        if values.is_empty() {
+            f64::NAN
+        } else {
+            f(values)
+        }
+    })
+}
+
+/// Per-pixel mean over time.
+pub fn mean<T>(stack: ArrayView3<T>, nodata: Option<T>, policy: NodataPolicy) -> Array2<f64>
+where
+    T: Copy + PartialEq + ToPrimitive,
+{
+    reduce(stack, nodata, policy, |values| {
+        values.iter().sum::<f64>() / values.len() as f64
+    })
+}
+
+/// Per-pixel median over time. For an even number of valid
+/// values, the mean of the two middle ones.
+pub fn median<T>(stack: ArrayView3<T>, nodata: Option<T>, policy: NodataPolicy) -> Array2<f64>
+where
+    T: Copy + PartialEq + ToPrimitive,
+{
+    reduce(stack, nodata, policy, |mut values| {
+        values.sort_by(f64::total_cmp);
+        let mid = values.len() / 2;
+        if values.len() % 2 == 0 {
+            (values[mid - 1] + values[mid]) / 2.
+        } else {
+            values[mid]
+        }
+    })
+}
+
+/// Per-pixel maximum over time (max-value composite).
+pub fn max_composite<T>(
+    stack: ArrayView3<T>,
+    nodata: Option<T>,
+    policy: NodataPolicy,
+) -> Array2<f64>
+where
+    T: Copy + PartialEq + ToPrimitive,
+{
+    reduce(stack, nodata, policy, |values| {
+        values.into_iter().fold(f64::NEG_INFINITY, f64::max)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    fn test_stack() -> Array3<i16> {
+        // Three dates of a 1x3 raster, `-1` is nodata.
+        Array3::from_shape_vec((3, 1, 3), vec![1, -1, -1, 4, 2, -1, 7, 6, -1]).unwrap()
+    }
+
+    #[test]
+    fn test_reducers() {
+        let stack = test_stack();
+
+        let mean = mean(stack.view(), Some(-1), NodataPolicy::Exact);
+        assert_eq!(mean[[0, 0]], 4.);
+        assert_eq!(mean[[0, 1]], 4.);
+        assert!(mean[[0, 2]].is_nan());
+
+        let median = median(stack.view(), Some(-1), NodataPolicy::Exact);
+        assert_eq!(median[[0, 0]], 4.);
+        assert_eq!(median[[0, 1]], 4.);
+
+        let max = max_composite(stack.view(), Some(-1), NodataPolicy::Exact);
+        assert_eq!(max[[0, 0]], 7.);
+        assert_eq!(max[[0, 1]], 6.);
+        assert!(max[[0, 2]].is_nan());
+    }
+
+    #[test]
+    fn test_without_nodata() {
+        let max = max_composite(test_stack().view(), None, NodataPolicy::Exact);
+        assert_eq!(max[[0, 2]], -1.);
+    }
+}
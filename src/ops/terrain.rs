@@ -0,0 +1,108 @@
+//! Terrain analysis on elevation chunks.
+
+use ndarray::{Array2, ArrayView2};
+use num::ToPrimitive;
+
+/// Parameters of [`hillshade`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HillshadeParams {
+    /// Direction of the light source, in degrees clockwise
+    /// from north.
+    pub azimuth: f64,
+    /// Angle of the light source above the horizon, in
+    /// degrees.
+    pub altitude: f64,
+    /// Multiplier applied to elevations.
+    pub z_factor: f64,
+    /// Pixel size (x, y) in the units of the elevations.
+    pub cell_size: (f64, f64),
+}
+
+impl Default for HillshadeParams {
+    fn default() -> Self {
+        HillshadeParams {
+            azimuth: 315.,
+            altitude: 45.,
+            z_factor: 1.,
+            cell_size: (1., 1.),
+        }
+    }
+}
+
+/// Hillshade of a chunk padded by one row on either side,
+/// using Horn's method.
+///
+/// The output has the shape of the data (unpadded) rows.
+/// Neighbours beyond the left and right edges are clamped
+/// to the edge. Values are in `1..=255`, with `0` where the
+/// elevation is undefined (eg. `NaN`).
+pub fn hillshade<T>(dem: ArrayView2<T>, params: &HillshadeParams) -> Array2<u8>
+where
+    T: ToPrimitive + Copy,
+{
+    let (rows, cols) = dem.dim();
+    if rows < 3 || cols == 0 {
+        return Array2::zeros((rows.saturating_sub(2), cols));
+    }
+
+    let z = |i: usize, j: isize| {
+        let j = j.clamp(0, cols as isize - 1) as usize;
+        dem[[i, j]].to_f64().unwrap_or(f64::NAN) * params.z_factor
+    };
+    let zenith = (90. - params.altitude).to_radians();
+    let azimuth = (360. - params.azimuth + 90.).to_radians();
+    let (size_x, size_y) = (params.cell_size.0.abs(), params.cell_size.1.abs());
+
+    Array2::from_shape_fn((rows - 2, cols), |(row, col)| {
+        let (i, j) = (row + 1, col as isize);
+        let (a, b, c) = (z(i - 1, j - 1), z(i - 1, j), z(i - 1, j + 1));
+        let (d, f) = (z(i, j - 1), z(i, j + 1));
+        let (g, h, k) = (z(i + 1, j - 1), z(i + 1, j), z(i + 1, j + 1));
+
+        let dz_dx = ((c + 2. * f + k) - (a + 2. * d + g)) / (8. * size_x);
+        let dz_dy = ((g + 2. * h + k) - (a + 2. * b + c)) / (8. * size_y);
+        let slope = dz_dx.hypot(dz_dy).atan();
+        let aspect = dz_dy.atan2(-dz_dx);
+
+        let shade = 255.
+            * (zenith.cos() * slope.cos() + zenith.sin() * slope.sin() * (azimuth - aspect).cos());
+        if shade.is_nan() {
+            0
+        } else {
+            shade.clamp(1., 255.) as u8
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_flat() {
+        let dem = Array2::from_elem((5, 4), 100i16);
+        let shade = hillshade(dem.view(), &HillshadeParams::default());
+        // Flat terrain is lit at cos(zenith).
+        assert_eq!(shade, Array2::from_elem((3, 4), 180));
+    }
+
+    #[test]
+    fn test_slopes_facing_light() {
+        // Elevation increasing to the south-east faces
+        // north-west, towards the default light source.
+        let dem = array![[0., 1., 2.], [1., 2., 3.], [2., 3., 4.]];
+        let lit = hillshade(dem.view(), &HillshadeParams::default());
+        let shaded = hillshade(dem.map(|v| -v).view(), &HillshadeParams::default());
+        assert!(lit[[0, 1]] > 180 && shaded[[0, 1]] < 180);
+    }
+
+    #[test]
+    fn test_nan() {
+        let dem = array![[1., 1.], [f64::NAN, 1.], [1., 1.]];
+        assert_eq!(
+            hillshade(dem.view(), &HillshadeParams::default()),
+            array![[0, 0]]
+        );
+    }
+}
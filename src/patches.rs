@@ -0,0 +1,194 @@
+//! Labeled patch extraction, eg. for machine learning.
+//!
+//! [`PatchExtractor`] cuts fixed-size patches of one or more
+//! bands around labeled points and polygons. Each patch is
+//! read directly from its window, so patches may straddle
+//! chunk boundaries, and the part of a patch outside the
+//! raster is set to a fill value.
+//!
+//! For bands of separate GDAL datasets, pass
+//! [`MultiReader::readers`][crate::gdal::multi::MultiReader::readers]
+//! (with the "gdal" feature), which ensures a common grid.
+
+use super::geometry::{PixelWorldTransform, RasterWindow, Size};
+use super::reader::{ChunkReader, Pixel};
+use super::{RasterUtilsError, Result};
+use geo::{AffineOps, BoundingRect, Coord, Intersects, Polygon, Rect};
+use ndarray::{Array3, Axis};
+use num::NumCast;
+
+/// Feature to extract patches at, in world coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchFeature {
+    /// A single patch, centered on the point.
+    Point(Coord<f64>),
+    /// The patches of a grid centered on the polygon and
+    /// covering its bounding box, that intersect the polygon.
+    Polygon(Polygon<f64>),
+}
+
+/// Extracts patches of `size` (x, y) pixels from `bands`,
+/// which share the grid of `transform`.
+///
+/// Patches are arrays of shape (bands, rows, cols).
+pub struct PatchExtractor<'a, R> {
+    bands: &'a [R],
+    transform: PixelWorldTransform,
+    size: Size,
+    fill: f64,
+}
+
+impl<'a, R> PatchExtractor<'a, R>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    pub fn new(bands: &'a [R], transform: PixelWorldTransform, size: Size) -> Self {
+        PatchExtractor {
+            bands,
+            transform,
+            size,
+            fill: 0.,
+        }
+    }
+
+    /// Value of the pixels of a patch outside the raster
+    /// (default: `0`).
+    pub fn with_fill(mut self, fill: f64) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Windows of the patches of `feature`.
+    ///
+    /// The patch of a point has the pixel containing it at
+    /// (`size.0 / 2`, `size.1 / 2`).
+    pub fn windows(&self, feature: &PatchFeature) -> Result<Vec<RasterWindow>> {
+        let (size_x, size_y) = self.size;
+        match feature {
+            PatchFeature::Point(coord) => {
+                let (x, y) = self.transform.world_to_pixel(coord.x_y())?;
+                let offset = (
+                    x.floor() as isize - (size_x / 2) as isize,
+                    y.floor() as isize - (size_y / 2) as isize,
+                );
+                Ok(vec![RasterWindow::from_signed(offset, self.size)])
+            }
+            PatchFeature::Polygon(polygon) => {
+                let polygon = polygon.affine_transform(&self.transform.inverse()?);
+                let bounds = match polygon.bounding_rect() {
+                    Some(bounds) => bounds,
+                    None => return Ok(vec![]),
+                };
+                let count_x = (bounds.width() / size_x as f64).ceil().max(1.) as usize;
+                let count_y = (bounds.height() / size_y as f64).ceil().max(1.) as usize;
+                let center = bounds.center();
+                let start_x = (center.x - (count_x * size_x) as f64 / 2.).round() as isize;
+                let start_y = (center.y - (count_y * size_y) as f64 / 2.).round() as isize;
+
+                let mut windows = vec![];
+                for j in 0..count_y {
+                    for i in 0..count_x {
+                        let offset = (
+                            start_x + (i * size_x) as isize,
+                            start_y + (j * size_y) as isize,
+                        );
+                        let min = Coord::from((offset.0 as f64, offset.1 as f64));
+                        let max = min + Coord::from((size_x as f64, size_y as f64));
+                        if polygon.intersects(&Rect::new(min, max)) {
+                            windows.push(RasterWindow::from_signed(offset, self.size));
+                        }
+                    }
+                }
+                Ok(windows)
+            }
+        }
+    }
+
+    /// Read the patch of `window` from every band.
+    pub fn read<T: Pixel>(&self, window: RasterWindow) -> Result<Array3<T>> {
+        let fill = <T as NumCast>::from(self.fill).ok_or(RasterUtilsError::Unsupported(
+            "fill value not representable in the requested type",
+        ))?;
+        let (rows, cols) = window.shape();
+        let mut patch = Array3::from_elem((self.bands.len(), rows, cols), fill);
+        for (band, mut out) in self.bands.iter().zip(patch.axis_iter_mut(Axis(0))) {
+            let data = band
+                .read_chunk_or_fill(window.clone(), fill)
+                .map_err(Into::into)?;
+            out.assign(&data);
+        }
+        Ok(patch)
+    }
+
+    /// Iterate over the patches of `features`, with their
+    /// labels, in the order of the features.
+    pub fn patches<'b, T, L>(
+        &'b self,
+        features: &'b [(PatchFeature, L)],
+    ) -> impl Iterator<Item = Result<(Array3<T>, L)>> + 'b
+    where
+        T: Pixel + 'b,
+        L: Clone + 'b,
+    {
+        features.iter().flat_map(move |(feature, label)| {
+            let windows = match self.windows(feature) {
+                Ok(windows) => windows.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            windows
+                .into_iter()
+                .map(move |window| Ok((self.read(window?)?, label.clone())))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ArrayReader;
+    use geo::{polygon, AffineTransform};
+    use ndarray::Array2;
+
+    #[test]
+    fn test_patches() {
+        let bands = [
+            ArrayReader(Array2::from_shape_fn((6, 6), |(i, j)| (i * 6 + j) as f64)),
+            ArrayReader(Array2::from_elem((6, 6), 1.)),
+        ];
+        let transform = PixelWorldTransform::new(AffineTransform::identity());
+        let extractor = PatchExtractor::new(&bands, transform, (2, 2)).with_fill(-1.);
+
+        let features = vec![
+            (PatchFeature::Point(Coord { x: 0.5, y: 0.5 }), "corner"),
+            (
+                PatchFeature::Polygon(polygon![
+                    (x: 2.2, y: 2.2),
+                    (x: 5.8, y: 2.2),
+                    (x: 5.8, y: 3.8),
+                    (x: 2.2, y: 3.8),
+                ]),
+                "field",
+            ),
+        ];
+        let patches: Vec<(Array3<f64>, &str)> =
+            extractor.patches(&features).collect::<Result<_>>().unwrap();
+
+        assert_eq!(patches.len(), 3);
+        let (corner, label) = &patches[0];
+        assert_eq!(*label, "corner");
+        assert_eq!(corner.shape(), &[2, 2, 2]);
+        assert_eq!(
+            corner.index_axis(Axis(0), 0),
+            ndarray::array![[-1., -1.], [-1., 0.]]
+        );
+        assert_eq!(
+            corner.index_axis(Axis(0), 1),
+            ndarray::array![[-1., -1.], [-1., 1.]]
+        );
+
+        assert!(patches[1..].iter().all(|(_, label)| *label == "field"));
+        assert_eq!(patches[1].0[[0, 0, 0]], 14.);
+        assert_eq!(patches[2].0[[0, 0, 0]], 16.);
+    }
+}
@@ -0,0 +1,596 @@
+//! Thread pipeline for chunked processing, without rayon.
+//!
+//! [`Pipeline`] runs three stages on std threads, connected
+//! by bounded channels:
+//!
+//! 1. reader threads, each with its own reader from a
+//!    [`ReaderFactory`], read chunks in order;
+//! 2. worker threads process the chunks;
+//! 3. the calling thread writes the results, in chunk
+//!    order.
+//!
+//! At most `capacity` chunks are in flight (read but not yet
+//! written) at any time, so slow writers apply backpressure
+//! to the readers and memory use stays bounded.
+//!
+//! With the "use-rayon" feature, the chunks may be processed
+//! on a rayon pool instead of worker threads (see
+//! [`Pipeline::with_compute_pool`]): reads then stay on the
+//! few reader threads, so I/O and compute are sized
+//! independently and don't starve each other.
+//!
+//! [`BandPipeline`] instead processes many bands
+//! independently (eg. of hyperspectral rasters): each
+//! (band, chunk) pair is a work item, and consecutive items
+//! are of different bands, so that workers read and write
+//! different bands in parallel. Each worker opens its own
+//! reader per band, and progress is reported per band.
+//!
+//! This module is only available with the "pipeline" feature.
+
+use super::chunking::progress::{Progress, ProgressSink, ProgressTracker};
+use super::chunking::{Chunk, ChunkConfig};
+use super::reader::{ChunkReader, Pixel, ReaderFactory};
+use super::{RasterUtilsError, Result};
+use crossbeam_channel::{bounded, select, Receiver, Sender};
+use ndarray::Array2;
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+    thread,
+};
+
+/// First error raised by any stage.
+struct Failure {
+    failed: AtomicBool,
+    error: Mutex<Option<RasterUtilsError>>,
+    /// Dropped on failure, to wake up blocked stages.
+    stop: Mutex<Option<Sender<()>>>,
+}
+
+impl Failure {
+    /// New failure state and the receiver of its stop
+    /// signal, which disconnects on failure.
+    fn new() -> (Self, Receiver<()>) {
+        let (stop_tx, stop_rx) = bounded(0);
+        let failure = Failure {
+            failed: AtomicBool::new(false),
+            error: Mutex::new(None),
+            stop: Mutex::new(Some(stop_tx)),
+        };
+        (failure, stop_rx)
+    }
+
+    fn set(&self, error: RasterUtilsError) {
+        let mut first = self.error.lock().unwrap_or_else(PoisonError::into_inner);
+        if first.is_none() {
+            *first = Some(error);
+        }
+        self.failed.store(true, Ordering::Relaxed);
+        self.stop
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+
+    fn is_set(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    fn take(self) -> Option<RasterUtilsError> {
+        self.error
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Read, process and write the chunks of a config on a
+/// pipeline of threads.
+pub struct Pipeline<'a> {
+    config: &'a ChunkConfig,
+    readers: usize,
+    workers: usize,
+    capacity: usize,
+    #[cfg(feature = "use-rayon")]
+    compute_pool: Option<&'a rayon::ThreadPool>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Pipeline with one reader thread, one worker per
+    /// available core, and up to twice as many chunks in
+    /// flight as workers.
+    pub fn new(config: &'a ChunkConfig) -> Self {
+        let workers = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        Pipeline {
+            config,
+            readers: 1,
+            workers,
+            capacity: 2 * workers,
+            #[cfg(feature = "use-rayon")]
+            compute_pool: None,
+        }
+    }
+
+    /// Number of reader threads.
+    pub fn with_readers(mut self, readers: NonZeroUsize) -> Self {
+        self.readers = readers.get();
+        self
+    }
+
+    /// Number of worker threads.
+    pub fn with_workers(mut self, workers: NonZeroUsize) -> Self {
+        self.workers = workers.get();
+        self
+    }
+
+    /// Process the chunks on `pool` instead of worker
+    /// threads. The number of workers is then ignored: size
+    /// the pool instead (eg. with
+    /// `rayon::ThreadPoolBuilder::num_threads`), and the
+    /// reader threads with [`with_readers`][Self::with_readers].
+    ///
+    /// This function is only available with the "use-rayon" feature.
+    #[cfg(feature = "use-rayon")]
+    pub fn with_compute_pool(mut self, pool: &'a rayon::ThreadPool) -> Self {
+        self.compute_pool = Some(pool);
+        self
+    }
+
+    /// Maximum number of chunks in flight.
+    pub fn with_capacity(mut self, capacity: NonZeroUsize) -> Self {
+        self.capacity = capacity.get();
+        self
+    }
+
+    /// Run the pipeline: read each chunk with a reader from
+    /// `factory`, `process` it on a worker thread and `write`
+    /// the output on the calling thread, in chunk order.
+    ///
+    /// Stops at the first error of any stage; the chunks
+    /// written until then are in order without gaps.
+    pub fn run<RF, T, P, O, W>(&self, factory: &RF, process: P, mut write: W) -> Result<()>
+    where
+        RF: ReaderFactory,
+        <RF::Reader as ChunkReader>::Error: Into<RasterUtilsError>,
+        T: Pixel + Send,
+        P: Fn(usize, Chunk, Array2<T>) -> Result<O> + Sync,
+        O: Send,
+        W: FnMut(usize, Chunk, O) -> Result<()>,
+    {
+        let chunks: Vec<Chunk> = self.config.iter().collect();
+        let next = AtomicUsize::new(0);
+        let (failure_state, stop_rx) = Failure::new();
+        let (chunks, next, failure, process) = (&chunks, &next, &failure_state, &process);
+
+        // Each chunk takes a token before it's read; tokens
+        // are returned once it's written.
+        let (token_tx, token_rx) = bounded(self.capacity);
+        for _ in 0..self.capacity {
+            token_tx.send(()).expect("channel has capacity");
+        }
+        let (read_tx, read_rx) = bounded::<(usize, Array2<T>)>(self.capacity);
+        let (done_tx, done_rx) = bounded::<(usize, O)>(self.capacity);
+
+        thread::scope(|scope| {
+            for _ in 0..self.readers {
+                let (token_rx, read_tx, stop_rx) =
+                    (token_rx.clone(), read_tx.clone(), stop_rx.clone());
+                scope.spawn(move || {
+                    let reader = match factory.create() {
+                        Ok(reader) => reader,
+                        Err(e) => return failure.set(e.into()),
+                    };
+                    loop {
+                        select! {
+                            recv(token_rx) -> token => if token.is_err() { break },
+                            recv(stop_rx) -> _ => break,
+                        }
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(chunk) = chunks.get(index) else {
+                            break;
+                        };
+                        match reader.read_chunk::<T>(*chunk) {
+                            Ok(data) => {
+                                if read_tx.send((index, data)).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => return failure.set(e.into()),
+                        }
+                    }
+                });
+            }
+            // Process a chunk, returning whether to go on.
+            let work = move |index: usize, data: Array2<T>, done_tx: &Sender<(usize, O)>| {
+                if failure.is_set() {
+                    return false;
+                }
+                match process(index, chunks[index], data) {
+                    Ok(output) => done_tx.send((index, output)).is_ok(),
+                    Err(e) => {
+                        failure.set(e);
+                        false
+                    }
+                }
+            };
+            #[cfg(feature = "use-rayon")]
+            let on_pool = self.compute_pool.is_some();
+            #[cfg(not(feature = "use-rayon"))]
+            let on_pool = false;
+            #[cfg(feature = "use-rayon")]
+            if let Some(pool) = self.compute_pool {
+                // A single thread hands the chunks over to the
+                // pool. Tokens bound the chunks in flight, so
+                // the pool tasks never block on sends.
+                let (read_rx, done_tx) = (read_rx.clone(), done_tx.clone());
+                scope.spawn(move || {
+                    pool.in_place_scope(|pool_scope| {
+                        for (index, data) in read_rx {
+                            if failure.is_set() {
+                                break;
+                            }
+                            let done_tx = done_tx.clone();
+                            pool_scope.spawn(move |_| {
+                                work(index, data, &done_tx);
+                            });
+                        }
+                    })
+                });
+            }
+            if !on_pool {
+                for _ in 0..self.workers {
+                    let (read_rx, done_tx) = (read_rx.clone(), done_tx.clone());
+                    scope.spawn(move || {
+                        for (index, data) in read_rx {
+                            if !work(index, data, &done_tx) {
+                                break;
+                            }
+                        }
+                    });
+                }
+            }
+            // Only the threads hold the channel ends from here
+            // on, so that stages see disconnects.
+            drop((token_rx, read_tx, read_rx, done_tx));
+
+            let mut pending = BTreeMap::new();
+            let mut next_write = 0;
+            'write: loop {
+                let (index, output) = select! {
+                    recv(done_rx) -> done => match done {
+                        Ok(done) => done,
+                        Err(_) => break,
+                    },
+                    recv(stop_rx) -> _ => break,
+                };
+                pending.insert(index, output);
+                while let Some(output) = pending.remove(&next_write) {
+                    if let Err(e) = write(next_write, chunks[next_write], output) {
+                        failure.set(e);
+                        break 'write;
+                    }
+                    next_write += 1;
+                    let _ = token_tx.send(());
+                }
+                if failure.is_set() {
+                    break;
+                }
+            }
+            drop((done_rx, token_tx));
+        });
+
+        match failure_state.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Receives the progress of each band of a
+/// [`BandPipeline`], with the band position (from `0`).
+///
+/// Updates may arrive concurrently from several threads.
+pub trait BandProgressSink: Send + Sync {
+    fn on_band_progress(&self, band: usize, progress: Progress);
+}
+
+impl<F> BandProgressSink for F
+where
+    F: Fn(usize, Progress) + Send + Sync,
+{
+    fn on_band_progress(&self, band: usize, progress: Progress) {
+        self(band, progress)
+    }
+}
+
+/// Forwards the progress of one band.
+struct BandSink<'a> {
+    band: usize,
+    sink: &'a dyn BandProgressSink,
+}
+
+impl<'a> ProgressSink for BandSink<'a> {
+    fn on_progress(&self, progress: Progress) {
+        self.sink.on_band_progress(self.band, progress);
+    }
+}
+
+/// Read, process and write the chunks of a config for many
+/// independent bands on a pool of worker threads.
+pub struct BandPipeline<'a> {
+    config: &'a ChunkConfig,
+    workers: usize,
+    progress: Option<&'a dyn BandProgressSink>,
+}
+
+impl<'a> BandPipeline<'a> {
+    /// Pipeline with one worker per available core.
+    pub fn new(config: &'a ChunkConfig) -> Self {
+        BandPipeline {
+            config,
+            workers: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            progress: None,
+        }
+    }
+
+    /// Number of worker threads.
+    pub fn with_workers(mut self, workers: NonZeroUsize) -> Self {
+        self.workers = workers.get();
+        self
+    }
+
+    /// Report the progress of each band to `sink`.
+    pub fn with_progress(mut self, sink: &'a dyn BandProgressSink) -> Self {
+        self.progress = Some(sink);
+        self
+    }
+
+    /// Run the pipeline over one band per writer: each
+    /// worker reads the chunks of band `band` (from `0`) with
+    /// its own reader from `open(band)`, `process`es them and
+    /// writes the output with `writers[band]`.
+    ///
+    /// The chunks of a band are written in any order, and
+    /// the writers of different bands concurrently. Stops at
+    /// the first error, and otherwise returns the writers.
+    pub fn run<RF, R, T, P, O, W>(&self, open: RF, process: P, writers: Vec<W>) -> Result<Vec<W>>
+    where
+        RF: Fn(usize) -> std::result::Result<R, R::Error> + Sync,
+        R: ChunkReader,
+        R::Error: Into<RasterUtilsError>,
+        T: Pixel,
+        P: Fn(usize, Chunk, Array2<T>) -> Result<O> + Sync,
+        W: FnMut(Chunk, O) -> Result<()> + Send,
+    {
+        let chunks: Vec<Chunk> = self.config.iter().collect();
+        let bands = writers.len();
+        let total = chunks.len() * bands;
+        let writer_state: Vec<Mutex<W>> = writers.into_iter().map(Mutex::new).collect();
+        let sinks: Vec<BandSink> = match self.progress {
+            Some(sink) => (0..bands).map(|band| BandSink { band, sink }).collect(),
+            None => vec![],
+        };
+        let trackers: Vec<ProgressTracker> = sinks
+            .iter()
+            .map(|sink| ProgressTracker::new(sink, chunks.len()))
+            .collect();
+        let next = AtomicUsize::new(0);
+        let (failure_state, _) = Failure::new();
+        let (chunks, trackers, writers, failure) =
+            (&chunks, &trackers, &writer_state, &failure_state);
+        let (open, process, next) = (&open, &process, &next);
+
+        thread::scope(|scope| {
+            for _ in 0..self.workers.min(total) {
+                scope.spawn(move || {
+                    let mut readers = HashMap::new();
+                    while !failure.is_set() {
+                        let item = next.fetch_add(1, Ordering::Relaxed);
+                        if item >= total {
+                            break;
+                        }
+                        // Consecutive items are of different
+                        // bands.
+                        let (band, chunk) = (item % bands, chunks[item / bands]);
+                        let mut work = || -> Result<u64> {
+                            let reader = match readers.entry(band) {
+                                Entry::Occupied(entry) => entry.into_mut(),
+                                Entry::Vacant(entry) => {
+                                    entry.insert(open(band).map_err(Into::into)?)
+                                }
+                            };
+                            let data = reader.read_chunk::<T>(chunk).map_err(Into::into)?;
+                            let bytes = (data.len() * std::mem::size_of::<T>()) as u64;
+                            let output = process(band, chunk, data)?;
+                            let mut writer =
+                                writers[band].lock().unwrap_or_else(PoisonError::into_inner);
+                            (*writer)(chunk, output)?;
+                            Ok(bytes)
+                        };
+                        match work() {
+                            Ok(bytes) => {
+                                if let Some(tracker) = trackers.get(band) {
+                                    tracker.add_bytes(bytes);
+                                    tracker.chunk_done();
+                                }
+                            }
+                            Err(e) => failure.set(e),
+                        }
+                    }
+                });
+            }
+        });
+
+        match failure_state.take() {
+            Some(e) => Err(e),
+            None => Ok(writer_state
+                .into_iter()
+                .map(|writer| writer.into_inner().unwrap_or_else(PoisonError::into_inner))
+                .collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::geometry::{RasterWindow, Size};
+
+    /// Reads the row index of each pixel.
+    struct RowReader;
+
+    impl ChunkReader for RowReader {
+        type Error = RasterUtilsError;
+
+        fn raster_size(&self) -> Result<Size> {
+            Ok((10, 100))
+        }
+
+        fn read_into_slice_sized<T: Pixel>(
+            &self,
+            out: &mut [T],
+            raster_window: RasterWindow,
+            _buffer_size: Size,
+        ) -> Result<()> {
+            let (cols, _) = raster_window.size();
+            let (_, off_y) = raster_window.validate((10, 100))?;
+            for (i, out) in out.iter_mut().enumerate() {
+                *out = <T as num::NumCast>::from(off_y + i / cols).unwrap();
+            }
+            Ok(())
+        }
+    }
+
+    fn test_cfg() -> ChunkConfig {
+        ChunkConfigBuilder::new(
+            NonZeroUsize::new(10).unwrap(),
+            NonZeroUsize::new(100).unwrap(),
+        )
+        .add_block_size(NonZeroUsize::new(5).unwrap())
+        .build()
+    }
+
+    #[test]
+    fn test_ordered_writes() {
+        let cfg = test_cfg();
+        let mut written = vec![];
+        Pipeline::new(&cfg)
+            .with_readers(NonZeroUsize::new(2).unwrap())
+            .with_workers(NonZeroUsize::new(3).unwrap())
+            .with_capacity(NonZeroUsize::new(4).unwrap())
+            .run(
+                &|| Ok::<_, RasterUtilsError>(RowReader),
+                |_index, _chunk, data: Array2<u32>| Ok(data[[0, 0]] as usize),
+                |index, chunk, start| {
+                    assert_eq!(start, chunk.start());
+                    written.push(index);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(written, (0..cfg.iter().len()).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "use-rayon")]
+    #[test]
+    fn test_compute_pool() {
+        let cfg = test_cfg();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .build()
+            .unwrap();
+        let mut written = vec![];
+        Pipeline::new(&cfg)
+            .with_readers(NonZeroUsize::new(1).unwrap())
+            .with_compute_pool(&pool)
+            .with_capacity(NonZeroUsize::new(4).unwrap())
+            .run(
+                &|| Ok::<_, RasterUtilsError>(RowReader),
+                |_index, _chunk, data: Array2<u32>| {
+                    assert!(rayon::current_thread_index().is_some());
+                    Ok(data[[0, 0]] as usize)
+                },
+                |index, chunk, start| {
+                    assert_eq!(start, chunk.start());
+                    written.push(index);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(written, (0..cfg.iter().len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_band_pipeline() {
+        let cfg = test_cfg();
+        let chunks = cfg.iter().len();
+        let done = Mutex::new(vec![0; 3]);
+        let sink = |band: usize, progress: Progress| {
+            assert_eq!(progress.chunks_total, chunks);
+            let mut done = done.lock().unwrap();
+            done[band] = done[band].max(progress.chunks_done);
+        };
+        let writers = (0..3)
+            .map(|_| {
+                let mut starts = vec![];
+                move |chunk: Chunk, start: usize| -> Result<()> {
+                    assert_eq!(start, chunk.start());
+                    starts.push(start);
+                    Ok(())
+                }
+            })
+            .collect();
+        let writers = BandPipeline::new(&cfg)
+            .with_workers(NonZeroUsize::new(4).unwrap())
+            .with_progress(&sink)
+            .run(
+                |_band| Ok::<_, RasterUtilsError>(RowReader),
+                |band, _chunk, data: Array2<u32>| {
+                    assert!(band < 3);
+                    Ok(data[[0, 0]] as usize)
+                },
+                writers,
+            )
+            .unwrap();
+        assert_eq!(writers.len(), 3);
+        assert_eq!(*done.lock().unwrap(), vec![chunks; 3]);
+
+        let result = BandPipeline::new(&cfg).run(
+            |band| match band {
+                1 => Err(RasterUtilsError::ZeroDimention),
+                _ => Ok(RowReader),
+            },
+            |_band, _chunk, _data: Array2<u8>| Ok(()),
+            vec![|_: Chunk, _: ()| -> Result<()> { Ok(()) }; 2],
+        );
+        assert!(matches!(result, Err(RasterUtilsError::ZeroDimention)));
+    }
+
+    #[test]
+    fn test_error_stops() {
+        let cfg = test_cfg();
+        let mut written = vec![];
+        let result = Pipeline::new(&cfg).run(
+            &|| Ok::<_, RasterUtilsError>(RowReader),
+            |index, _chunk, _data: Array2<u8>| {
+                if index == 5 {
+                    return Err(RasterUtilsError::ZeroDimention);
+                }
+                Ok(())
+            },
+            |index, _chunk, _output| {
+                written.push(index);
+                Ok(())
+            },
+        );
+        assert!(matches!(result, Err(RasterUtilsError::ZeroDimention)));
+        assert_eq!(written, (0..written.len()).collect::<Vec<_>>());
+        assert!(written.len() <= 5);
+    }
+}
@@ -0,0 +1,509 @@
+//! Drive chunked processing jobs.
+//!
+//! [`ProcessingDriver`] runs a [`ChunkProcessor`] over the
+//! chunks of a [`ChunkConfig`]. Completed chunks are
+//! recorded in a [`ProcessingState`], which may be
+//! periodically persisted to a [`StateStore`]. A job that
+//! dies halfway through can then be restarted in _resume_
+//! mode, skipping the chunks that were already completed.
+
+use super::chunking::{
+    cancel::CancellationToken,
+    progress::{ProgressSink, ProgressTracker},
+    Chunk, ChunkConfig,
+};
+use super::geometry::RasterWindow;
+use super::reader::{Coverage, CoverageReader};
+use super::{RasterUtilsError, Result};
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use std::{collections::BTreeSet, num::NonZeroUsize};
+
+/// Processes a single chunk, identified by its index in the
+/// iteration order of the [`ChunkConfig`].
+pub trait ChunkProcessor {
+    fn process(&mut self, index: usize, chunk: Chunk) -> Result<()>;
+
+    /// Called instead of [`process`][Self::process] for
+    /// chunks without data, when the driver skips empty
+    /// chunks (see [`ProcessingDriver::with_skip_empty`]).
+    /// Eg. write the fill value to the output. Does nothing
+    /// by default.
+    fn process_empty(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        let _ = (index, chunk);
+        Ok(())
+    }
+}
+
+impl<F> ChunkProcessor for F
+where
+    F: FnMut(usize, Chunk) -> Result<()>,
+{
+    fn process(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        self(index, chunk)
+    }
+}
+
+/// Progress of a chunked job.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProcessingState {
+    /// Config the job was started with.
+    config: ChunkConfig,
+    /// Indices of the completed chunks.
+    completed: BTreeSet<usize>,
+}
+
+impl ProcessingState {
+    /// Fresh state for a job over `config`.
+    pub fn new(config: &ChunkConfig) -> Self {
+        ProcessingState {
+            config: config.clone(),
+            completed: BTreeSet::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ChunkConfig {
+        &self.config
+    }
+
+    /// Whether chunk `index` is completed.
+    pub fn is_completed(&self, index: usize) -> bool {
+        self.completed.contains(&index)
+    }
+
+    /// Record chunk `index` as completed.
+    pub fn mark_completed(&mut self, index: usize) {
+        self.completed.insert(index);
+    }
+
+    /// Indices of completed chunks, in increasing order.
+    pub fn completed(&self) -> impl Iterator<Item = usize> + '_ {
+        self.completed.iter().copied()
+    }
+
+    /// Number of completed chunks.
+    pub fn num_completed(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Whether all chunks of the job are completed.
+    pub fn is_finished(&self) -> bool {
+        self.num_completed() == self.config.iter().len()
+    }
+}
+
+/// Persists [`ProcessingState`] across runs.
+pub trait StateStore {
+    /// Load the last saved state, if any.
+    fn load(&self) -> Result<Option<ProcessingState>>;
+    /// Save `state`, replacing the previous one.
+    fn save(&mut self, state: &ProcessingState) -> Result<()>;
+}
+
+#[cfg(feature = "serde")]
+pub use self::sidecar_store::SidecarStateStore;
+
+#[cfg(feature = "serde")]
+mod sidecar_store {
+    use super::{ProcessingState, StateStore};
+    use crate::sidecar::{Sidecar, SourceFingerprint};
+    use crate::Result;
+    use serde_derive::{Deserialize, Serialize};
+    use std::path::{Path, PathBuf};
+
+    /// Name of the sidecar section holding the state.
+    const SECTION: &str = "processing";
+
+    /// Section of the sidecar, with the raster the job runs
+    /// over.
+    #[derive(Serialize, Deserialize)]
+    struct ProcessingSection {
+        source: Option<SourceFingerprint>,
+        state: ProcessingState,
+    }
+
+    /// Stores the state as JSON in the
+    /// [sidecar][crate::sidecar] of a raster.
+    ///
+    /// The state is discarded if the raster changed since it
+    /// was saved. Other sections of the sidecar are kept.
+    pub struct SidecarStateStore {
+        path: PathBuf,
+        source: Option<SourceFingerprint>,
+    }
+
+    impl SidecarStateStore {
+        /// Store next to the raster at `path`.
+        pub fn new(path: &Path) -> Self {
+            SidecarStateStore {
+                path: Sidecar::path_for(path),
+                source: SourceFingerprint::from_path(path).ok(),
+            }
+        }
+    }
+
+    impl StateStore for SidecarStateStore {
+        fn load(&self) -> Result<Option<ProcessingState>> {
+            let section = match Sidecar::read(&self.path)? {
+                Some(sidecar) => sidecar.section::<ProcessingSection>(SECTION)?,
+                None => None,
+            };
+            Ok(section
+                .filter(|section| section.source == self.source)
+                .map(|section| section.state))
+        }
+
+        fn save(&mut self, state: &ProcessingState) -> Result<()> {
+            let mut sidecar = Sidecar::read(&self.path)?.unwrap_or_default();
+            let section = ProcessingSection {
+                source: self.source.clone(),
+                state: state.clone(),
+            };
+            sidecar.set_section(SECTION, &section)?;
+            sidecar.write(&self.path)
+        }
+    }
+}
+
+/// Runs a [`ChunkProcessor`] over all chunks of a config.
+pub struct ProcessingDriver<'a> {
+    config: &'a ChunkConfig,
+    store: Option<Box<dyn StateStore + 'a>>,
+    resume: bool,
+    checkpoint_interval: usize,
+    progress: Option<&'a dyn ProgressSink>,
+    cancellation: Option<CancellationToken>,
+    is_empty: Option<Box<dyn Fn(&RasterWindow) -> Result<bool> + 'a>>,
+}
+
+impl<'a> ProcessingDriver<'a> {
+    pub fn new(config: &'a ChunkConfig) -> Self {
+        ProcessingDriver {
+            config,
+            store: None,
+            resume: false,
+            checkpoint_interval: 1,
+            progress: None,
+            cancellation: None,
+            is_empty: None,
+        }
+    }
+
+    /// Persist progress to `store`.
+    pub fn with_store<S: StateStore + 'a>(mut self, store: S) -> Self {
+        self.store = Some(Box::new(store));
+        self
+    }
+
+    /// Resume from the state saved in the store (if any),
+    /// skipping completed chunks.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Save state after every `interval` completed chunks
+    /// (default: `1`). State is always saved when the run
+    /// ends, successfully or not.
+    pub fn with_checkpoint_interval(mut self, interval: NonZeroUsize) -> Self {
+        self.checkpoint_interval = interval.get();
+        self
+    }
+
+    /// Report progress to `sink`. Chunks completed in a
+    /// previous run count as done.
+    pub fn with_progress(mut self, sink: &'a dyn ProgressSink) -> Self {
+        self.progress = Some(sink);
+        self
+    }
+
+    /// Check `token` before each chunk and stop with
+    /// [`RasterUtilsError::Cancelled`] once it is cancelled.
+    /// Progress made so far is saved, so the run may be
+    /// resumed later.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Skip the chunks whose window (incl. padding)
+    /// `coverage` reports as [`Coverage::Empty`], calling
+    /// [`ChunkProcessor::process_empty`] instead of reading
+    /// and processing them. Skipped chunks count as
+    /// completed.
+    pub fn with_skip_empty<C>(mut self, coverage: &'a C) -> Self
+    where
+        C: CoverageReader,
+        C::Error: Into<RasterUtilsError>,
+    {
+        self.is_empty = Some(Box::new(move |window| {
+            let coverage = coverage.coverage(window).map_err(Into::into)?;
+            Ok(coverage == Coverage::Empty)
+        }));
+        self
+    }
+
+    /// Whether `chunk` should be skipped as empty.
+    fn is_empty(&self, chunk: &Chunk) -> Result<bool> {
+        match &self.is_empty {
+            Some(is_empty) => is_empty(&chunk.window()),
+            None => Ok(false),
+        }
+    }
+
+    /// Initial state of the run.
+    fn initial_state(&self) -> Result<ProcessingState> {
+        let saved = match (&self.store, self.resume) {
+            (Some(store), true) => store.load()?,
+            _ => None,
+        };
+        match saved {
+            Some(state) if state.config != *self.config => Err(RasterUtilsError::StateMismatch),
+            Some(state) => Ok(state),
+            None => Ok(ProcessingState::new(self.config)),
+        }
+    }
+
+    fn save(&mut self, state: &ProcessingState) -> Result<()> {
+        match &mut self.store {
+            Some(store) => store.save(state),
+            None => Ok(()),
+        }
+    }
+
+    /// Process all chunks that are not yet completed, in
+    /// order, and return the final state.
+    ///
+    /// Stops at the first error, after saving the progress
+    /// made so far.
+    pub fn run<P: ChunkProcessor>(&mut self, processor: &mut P) -> Result<ProcessingState> {
+        let mut state = self.initial_state()?;
+        let config = self.config;
+        let tracker = self.progress.map(|sink| {
+            let tracker = ProgressTracker::new(sink, config.iter().len());
+            tracker.skip_chunks(state.num_completed());
+            tracker
+        });
+        let mut unsaved = 0;
+        for (index, chunk) in config.iter().enumerate() {
+            if state.is_completed(index) {
+                continue;
+            }
+            if let Some(Err(e)) = self.cancellation.as_ref().map(CancellationToken::check) {
+                self.save(&state)?;
+                return Err(e);
+            }
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!(
+                "process_chunk",
+                index,
+                start = chunk.start(),
+                size = chunk.size(),
+            )
+            .entered();
+
+            let result = match self.is_empty(&chunk) {
+                Ok(true) => processor.process_empty(index, chunk),
+                Ok(false) => processor.process(index, chunk),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                self.save(&state)?;
+                return Err(e);
+            }
+            state.mark_completed(index);
+            if let Some(tracker) = &tracker {
+                tracker.chunk_done();
+            }
+
+            unsaved += 1;
+            if unsaved == self.checkpoint_interval {
+                self.save(&state)?;
+                unsaved = 0;
+            }
+        }
+        self.save(&state)?;
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Clone, Default)]
+    struct MemoryStore(Rc<RefCell<Option<ProcessingState>>>);
+
+    impl StateStore for MemoryStore {
+        fn load(&self) -> Result<Option<ProcessingState>> {
+            Ok(self.0.borrow().clone())
+        }
+        fn save(&mut self, state: &ProcessingState) -> Result<()> {
+            *self.0.borrow_mut() = Some(state.clone());
+            Ok(())
+        }
+    }
+
+    fn test_cfg() -> ChunkConfig {
+        ChunkConfigBuilder::new(
+            NonZeroUsize::new(10).unwrap(),
+            NonZeroUsize::new(100).unwrap(),
+        )
+        .add_block_size(NonZeroUsize::new(10).unwrap())
+        .build()
+    }
+
+    #[test]
+    fn test_resume() {
+        let cfg = test_cfg();
+        let total = cfg.iter().len();
+        let store = MemoryStore::default();
+
+        let mut first = vec![];
+        let result = ProcessingDriver::new(&cfg).with_store(store.clone()).run(
+            &mut |index, _chunk: Chunk| {
+                if index == 4 {
+                    return Err(RasterUtilsError::ZeroDimention);
+                }
+                first.push(index);
+                Ok(())
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(first, vec![0, 1, 2, 3]);
+        assert_eq!(store.load().unwrap().unwrap().num_completed(), 4);
+
+        let mut second = vec![];
+        let state = ProcessingDriver::new(&cfg)
+            .with_store(store.clone())
+            .with_resume(true)
+            .run(&mut |index, _chunk: Chunk| {
+                second.push(index);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(second, (4..total).collect::<Vec<_>>());
+        assert!(state.is_finished());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let cfg = test_cfg();
+        let store = MemoryStore::default();
+        let token = CancellationToken::new();
+
+        let result = ProcessingDriver::new(&cfg)
+            .with_store(store.clone())
+            .with_cancellation(token.clone())
+            .run(&mut |index, _chunk: Chunk| {
+                if index == 2 {
+                    token.cancel();
+                }
+                Ok(())
+            });
+        assert!(matches!(result, Err(RasterUtilsError::Cancelled)));
+        assert_eq!(store.load().unwrap().unwrap().num_completed(), 3);
+    }
+
+    #[test]
+    fn test_resume_mismatch() {
+        let cfg = test_cfg();
+        let store = MemoryStore::default();
+        *store.0.borrow_mut() = Some(ProcessingState::new(
+            &ChunkConfigBuilder::new(
+                NonZeroUsize::new(10).unwrap(),
+                NonZeroUsize::new(50).unwrap(),
+            )
+            .build(),
+        ));
+
+        let result = ProcessingDriver::new(&cfg)
+            .with_store(store)
+            .with_resume(true)
+            .run(&mut |_index, _chunk: Chunk| Ok(()));
+        assert!(matches!(result, Err(RasterUtilsError::StateMismatch)));
+    }
+
+    /// Data only in the top half of the raster.
+    struct TopHalf;
+
+    impl CoverageReader for TopHalf {
+        type Error = RasterUtilsError;
+
+        fn coverage(&self, raster_window: &RasterWindow) -> Result<Coverage> {
+            let (_, off_y) = raster_window.signed_offset();
+            Ok(if off_y >= 50 {
+                Coverage::Empty
+            } else {
+                Coverage::Data
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        processed: Vec<usize>,
+        empty: Vec<usize>,
+    }
+
+    impl ChunkProcessor for Recorder {
+        fn process(&mut self, index: usize, _chunk: Chunk) -> Result<()> {
+            self.processed.push(index);
+            Ok(())
+        }
+        fn process_empty(&mut self, index: usize, _chunk: Chunk) -> Result<()> {
+            self.empty.push(index);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_skip_empty() {
+        let cfg = test_cfg();
+        let mut recorder = Recorder::default();
+        let state = ProcessingDriver::new(&cfg)
+            .with_skip_empty(&TopHalf)
+            .run(&mut recorder)
+            .unwrap();
+        assert!(state.is_finished());
+        assert_eq!(recorder.processed, vec![0, 1, 2, 3, 4]);
+        assert_eq!(recorder.empty, vec![5, 6, 7, 8, 9]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_sidecar_shared_with_stats() {
+        use crate::stats::{SidecarStatsStore, StatsCache, StatsStore};
+
+        let path = std::env::temp_dir().join("raster_utils_processing_sidecar.tif");
+        std::fs::write(&path, b"raster").unwrap();
+        let sidecar = crate::sidecar::Sidecar::path_for(&path);
+        let _ = std::fs::remove_file(&sidecar);
+
+        let mut cache = StatsCache::new();
+        cache
+            .insert("query", &((0, 0), (10, 10)).into(), &1usize)
+            .unwrap();
+        let mut stats = SidecarStatsStore::new(&path, 1);
+        stats.save(&cache).unwrap();
+
+        let cfg = test_cfg();
+        let mut state = ProcessingState::new(&cfg);
+        state.mark_completed(2);
+        let mut store = SidecarStateStore::new(&path);
+        store.save(&state).unwrap();
+
+        // Each store keeps the section of the other.
+        assert_eq!(store.load().unwrap(), Some(state.clone()));
+        assert_eq!(stats.load().unwrap(), Some(cache.clone()));
+        stats.save(&cache).unwrap();
+        assert_eq!(store.load().unwrap(), Some(state));
+
+        std::fs::remove_file(&sidecar).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+//! Python bindings.
+//!
+//! Exposes chunk planning, chunked statistics and band math
+//! as the `raster_utils` Python module, with NumPy arrays in
+//! and out. The extension module itself is built from the
+//! `python` directory of the repository, which calls
+//! [`register`], with eg. `maturin develop -m
+//! python/Cargo.toml`. Reads and writes of files release
+//! the GIL.
+//!
+//! ```python
+//! import raster_utils
+//!
+//! config = raster_utils.ChunkConfig(10980, 10980, block_size=512, padding=1)
+//! for x, y, width, height in config.windows():
+//!     ...
+//! ```
+//!
+//! This module is only available with the "python" feature.
+
+use super::chunking::{builder::ChunkConfigBuilder, ChunkConfig};
+use super::geometry::RasterWindow;
+use super::nodata::NodataPolicy;
+use super::ops::spectral::NormalizedDifferenceIndex;
+use super::stats::ChunkStats;
+use super::RasterUtilsError;
+use numpy::{IntoPyArray, PyArray2, PyReadonlyArray2};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+
+use std::num::NonZeroUsize;
+
+impl From<RasterUtilsError> for PyErr {
+    fn from(e: RasterUtilsError) -> Self {
+        PyValueError::new_err(e.to_string())
+    }
+}
+
+#[cfg(feature = "gdal")]
+impl From<super::gdal::RasterUtilsGdalError> for PyErr {
+    fn from(e: super::gdal::RasterUtilsGdalError) -> Self {
+        PyValueError::new_err(e.to_string())
+    }
+}
+
+/// Plan of the chunks of a raster (see [`ChunkConfig`]).
+#[pyclass(name = "ChunkConfig", frozen)]
+pub struct PyChunkConfig(ChunkConfig);
+
+#[pymethods]
+impl PyChunkConfig {
+    #[new]
+    #[pyo3(signature = (width, height, block_size=1, data_height=1, padding=0, start=None, end=None))]
+    fn new(
+        width: usize,
+        height: usize,
+        block_size: usize,
+        data_height: usize,
+        padding: usize,
+        start: Option<usize>,
+        end: Option<usize>,
+    ) -> PyResult<Self> {
+        let non_zero = |value| NonZeroUsize::new(value).ok_or(RasterUtilsError::ZeroDimention);
+        let mut builder = ChunkConfigBuilder::new(non_zero(width)?, non_zero(height)?)
+            .add_block_size(non_zero(block_size)?)
+            .with_data_height(non_zero(data_height)?)
+            .with_padding(padding);
+        if let Some(start) = start {
+            builder = builder.with_start(start);
+        }
+        if let Some(end) = end {
+            builder = builder.with_end(end);
+        }
+        let config = builder.try_build().map_err(RasterUtilsError::from)?;
+        Ok(PyChunkConfig(config))
+    }
+
+    #[getter]
+    fn width(&self) -> usize {
+        self.0.width()
+    }
+
+    #[getter]
+    fn height(&self) -> usize {
+        self.0.height()
+    }
+
+    #[getter]
+    fn data_height(&self) -> usize {
+        self.0.data_height()
+    }
+
+    #[getter]
+    fn padding(&self) -> usize {
+        self.0.padding()
+    }
+
+    /// Windows (x, y, width, height) of the chunks, incl.
+    /// padding.
+    fn windows(&self) -> Vec<(usize, usize, usize, usize)> {
+        self.0
+            .iter()
+            .map(|chunk| window_tuple(&chunk.window()))
+            .collect()
+    }
+
+    /// Windows (x, y, width, height) of the data of the
+    /// chunks, without padding.
+    fn data_windows(&self) -> Vec<(usize, usize, usize, usize)> {
+        self.0
+            .iter()
+            .map(|chunk| window_tuple(&chunk.data_window()))
+            .collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.iter().len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+fn window_tuple(window: &RasterWindow) -> (usize, usize, usize, usize) {
+    let (off_x, off_y) = window
+        .offset()
+        .expect("chunk windows start within the raster");
+    let (size_x, size_y) = window.size();
+    (off_x, off_y, size_x, size_y)
+}
+
+fn stats_dict<'py>(py: Python<'py>, stats: &ChunkStats) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("count", stats.count)?;
+    dict.set_item("min", stats.min)?;
+    dict.set_item("max", stats.max)?;
+    dict.set_item("mean", stats.mean())?;
+    dict.set_item("std_dev", stats.std_dev())?;
+    Ok(dict)
+}
+
+/// Statistics of the valid pixels of `array`.
+#[pyfunction]
+#[pyo3(signature = (array, nodata=None))]
+fn stats<'py>(
+    py: Python<'py>,
+    array: PyReadonlyArray2<'py, f64>,
+    nodata: Option<f64>,
+) -> PyResult<Bound<'py, PyDict>> {
+    stats_dict(
+        py,
+        &ChunkStats::from_array(array.as_array(), nodata, NodataPolicy::Exact),
+    )
+}
+
+/// `(a - b) / (a + b)` of two arrays, `NaN` where either is
+/// nodata or the sum is zero.
+#[pyfunction]
+#[pyo3(signature = (a, b, nodata_a=None, nodata_b=None))]
+fn normalized_difference<'py>(
+    py: Python<'py>,
+    a: PyReadonlyArray2<'py, f64>,
+    b: PyReadonlyArray2<'py, f64>,
+    nodata_a: Option<f64>,
+    nodata_b: Option<f64>,
+) -> PyResult<Bound<'py, PyArray2<f32>>> {
+    let (a, b) = (a.as_array(), b.as_array());
+    if a.dim() != b.dim() {
+        return Err(PyValueError::new_err("arrays of different shapes"));
+    }
+    let index = NormalizedDifferenceIndex::new(nodata_a, nodata_b, NodataPolicy::Exact);
+    let output = ndarray::Zip::from(&a)
+        .and(&b)
+        .map_collect(|&a, &b| index.value(a, b).map_or(f32::NAN, |value| value as f32));
+    Ok(output.into_pyarray(py))
+}
+
+#[cfg(feature = "gdal")]
+mod gdal_functions {
+    use super::stats_dict;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::gdal::{
+        processors,
+        readers::{BandIndex, DatasetReader},
+        utils::create_like,
+        writers::DatasetWriter,
+        RasterUtilsGdalError,
+    };
+    use crate::nodata::NodataPolicy;
+    use crate::processing::ProcessingDriver;
+    use crate::stats::{band_stats as chunked_stats, ChunkStats};
+    use gdal::Dataset;
+    use pyo3::{prelude::*, types::PyDict};
+
+    use std::convert::TryFrom;
+
+    fn open(path: &str) -> Result<Dataset, RasterUtilsGdalError> {
+        Ok(Dataset::open(path)?)
+    }
+
+    /// Statistics of a band of the raster at `path`, computed
+    /// chunk by chunk.
+    #[pyfunction]
+    #[pyo3(signature = (path, band=1))]
+    pub(super) fn band_stats<'py>(
+        py: Python<'py>,
+        path: &str,
+        band: usize,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let stats = py.allow_threads(|| -> PyResult<ChunkStats> {
+            let dataset = open(path)?;
+            let nodata = dataset
+                .rasterband(band)
+                .map_err(RasterUtilsGdalError::from)?
+                .no_data_value();
+            let config = ChunkConfigBuilder::from_dataset(&dataset)?
+                .with_auto_data_height(&dataset)?
+                .build();
+            let reader = DatasetReader(dataset, BandIndex::try_from(band)?);
+            Ok(chunked_stats(
+                &reader,
+                &config,
+                nodata,
+                NodataPolicy::Exact,
+            )?)
+        })?;
+        stats_dict(py, &stats)
+    }
+
+    /// Normalized difference of band `band_a` of the raster at
+    /// `path_a` and band `band_b` of the one at `path_b`,
+    /// written chunk by chunk to a `Float32` GeoTIFF at
+    /// `output`.
+    #[pyfunction]
+    #[pyo3(signature = (path_a, path_b, output, band_a=1, band_b=1))]
+    pub(super) fn normalized_difference_file(
+        py: Python<'_>,
+        path_a: &str,
+        path_b: &str,
+        output: &str,
+        band_a: usize,
+        band_b: usize,
+    ) -> PyResult<()> {
+        py.allow_threads(|| -> PyResult<()> {
+            let (a, b) = (open(path_a)?, open(path_b)?);
+            let dataset = create_like::<f32>(&a, "GTiff", output.as_ref(), 1)?;
+            let writer = DatasetWriter(dataset, BandIndex::try_from(1)?);
+            let config = ChunkConfigBuilder::from_dataset(&a)?
+                .with_auto_data_height(&a)?
+                .build();
+            let mut processor = processors::normalized_difference(
+                &a,
+                BandIndex::try_from(band_a)?,
+                &b,
+                BandIndex::try_from(band_b)?,
+                writer,
+            )?;
+            ProcessingDriver::new(&config).run(&mut processor)?;
+            Ok(())
+        })
+    }
+}
+
+/// Add the classes and functions of the bindings to `m`,
+/// the `raster_utils` extension module.
+pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyChunkConfig>()?;
+    m.add_function(wrap_pyfunction!(stats, m)?)?;
+    m.add_function(wrap_pyfunction!(normalized_difference, m)?)?;
+    #[cfg(feature = "gdal")]
+    {
+        m.add_function(wrap_pyfunction!(gdal_functions::band_stats, m)?)?;
+        m.add_function(wrap_pyfunction!(
+            gdal_functions::normalized_difference_file,
+            m
+        )?)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,604 @@
+//! Backend independent chunk reading.
+//!
+//! [`ChunkReader`] abstracts reading windows of a raster
+//! band into buffers and ndarrays. Pixel types are bounded
+//! by [`Pixel`], which (with the `gdal` feature) implies
+//! [`GdalType`][gdal::raster::GdalType]. [`ArrayReader`]
+//! reads in-memory arrays.
+
+use crate::align::bilinear;
+use crate::chunking::Chunk;
+use crate::geometry::{FractionalWindow, RasterWindow, Size};
+use crate::nodata::NodataPolicy;
+use crate::ops::sparse::MaskedChunk;
+use crate::RasterUtilsError;
+use ndarray::{s, Array2, ShapeBuilder, ShapeError};
+use num::{NumCast, ToPrimitive};
+
+use std::num::NonZeroUsize;
+
+/// Primitive types that can be read from a raster.
+#[cfg(feature = "gdal")]
+pub trait Pixel: gdal::raster::GdalType + NumCast + Copy {}
+
+/// Primitive types that can be read from a raster.
+#[cfg(not(feature = "gdal"))]
+pub trait Pixel: NumCast + Copy {}
+
+impl Pixel for u8 {}
+impl Pixel for i8 {}
+impl Pixel for u16 {}
+impl Pixel for i16 {}
+impl Pixel for u32 {}
+impl Pixel for i32 {}
+impl Pixel for u64 {}
+impl Pixel for i64 {}
+impl Pixel for f32 {}
+impl Pixel for f64 {}
+// `f16` is not a `Pixel`, as GDAL has no `GdalType` for it
+// and features must stay additive: half-precision chunks are
+// read as `DynArray` (see `read_dyn`), or converted to
+// another pixel type on read.
+
+/// Memory layout of arrays produced by [`ChunkReader`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryOrder {
+    /// Row-major (C) order, as read by the backend.
+    #[default]
+    Standard,
+    /// Column-major (Fortran) order.
+    ColumnMajor,
+}
+
+/// Resampling algorithm of reads into a buffer of a
+/// different size than the window, mirroring GDAL's
+/// `GDALRIOResampleAlg`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResampleAlg {
+    #[default]
+    Nearest,
+    Bilinear,
+    Cubic,
+    CubicSpline,
+    Lanczos,
+    Average,
+    Mode,
+    Gauss,
+}
+
+/// Abstracts reading chunks from raster.
+///
+/// Implemented by the GDAL readers in
+/// [`crate::gdal::readers`], as well as by other backends.
+pub trait ChunkReader {
+    /// Error returned by reads.
+    type Error: From<ShapeError>;
+
+    /// Size (x, y) of the underlying raster.
+    fn raster_size(&self) -> Result<Size, Self::Error>;
+
+    /// Emulate GDAL's `RasterBand::read_into_slice`.
+    ///
+    /// The window is read into a buffer of `buffer_size`
+    /// (x, y), which may differ from the window size, in which
+    /// case the backend resamples on read (if supported).
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<(), Self::Error>
+    where
+        T: Pixel;
+
+    /// Same as [`read_into_slice_sized`][Self::read_into_slice_sized],
+    /// resampling with `alg` if `buffer_size` differs from
+    /// the window size.
+    ///
+    /// Backends without native resampling ignore `alg`, and
+    /// resample as in `read_into_slice_sized`.
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<(), Self::Error>
+    where
+        T: Pixel,
+    {
+        let _ = alg;
+        self.read_into_slice_sized(out, raster_window, buffer_size)
+    }
+
+    /// Emulate GDAL's `RasterBand::read_into_slice` with buffer
+    /// size equal to the window size.
+    fn read_into_slice<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+    ) -> Result<(), Self::Error>
+    where
+        T: Pixel,
+    {
+        let size = raster_window.size();
+        self.read_into_slice_sized(out, raster_window, size)
+    }
+
+    /// Helper to read into an ndarray.
+    fn read_as_array<T>(&self, raster_window: RasterWindow) -> Result<Array2<T>, Self::Error>
+    where
+        T: Pixel,
+    {
+        let mut buf = zeroed(raster_window.num_pixels());
+        let array_shape = raster_window.shape();
+        self.read_into_slice(&mut buf[..], raster_window)?;
+        Array2::from_shape_vec(array_shape, buf).map_err(Self::Error::from)
+    }
+
+    /// Helper to read a [`MaskedChunk`] from output of
+    /// [`ChunkConfig`] iterator.
+    ///
+    /// The sparse representation is selected if the fraction
+    /// of `nodata` pixels exceeds `sparse_fraction`.
+    fn read_chunk_masked<T>(
+        &self,
+        chunk: Chunk,
+        nodata: T,
+        sparse_fraction: f64,
+    ) -> Result<MaskedChunk<T>, Self::Error>
+    where
+        T: Pixel + PartialEq,
+    {
+        let data = self.read_chunk(chunk)?;
+        Ok(MaskedChunk::new(
+            data,
+            nodata,
+            sparse_fraction,
+            NodataPolicy::Exact,
+        ))
+    }
+
+    /// Like [`read_chunk_masked`][Self::read_chunk_masked],
+    /// comparing values with `nodata` under `policy`: values
+    /// that are nodata under the policy are set to `nodata`.
+    fn read_chunk_masked_with_policy<T>(
+        &self,
+        chunk: Chunk,
+        nodata: T,
+        sparse_fraction: f64,
+        policy: NodataPolicy,
+    ) -> Result<MaskedChunk<T>, Self::Error>
+    where
+        T: Pixel + PartialEq,
+    {
+        let mut data = self.read_chunk(chunk)?;
+        policy.normalize(&mut data, nodata);
+        Ok(MaskedChunk::new(data, nodata, sparse_fraction, policy))
+    }
+
+    /// Helper to read a window that may extend outside the
+    /// raster (eg. boundary chunks in alignment workflows).
+    ///
+    /// The window is clipped to the valid extent, the
+    /// existing data is read and the remainder is set to
+    /// `fill`.
+    fn read_chunk_or_fill<T, W>(&self, window: W, fill: T) -> Result<Array2<T>, Self::Error>
+    where
+        T: Pixel,
+        W: Into<RasterWindow>,
+    {
+        let raster_window = window.into();
+        let mut out = Array2::from_elem(raster_window.shape(), fill);
+
+        if let Some((offset, size, (col, row))) = raster_window.read_window(self.raster_size()?) {
+            let (cols, rows) = size;
+            let data = self.read_as_array::<T>((offset, size).into())?;
+            out.slice_mut(s![row..row + rows, col..col + cols])
+                .assign(&data);
+        }
+        Ok(out)
+    }
+
+    /// Helper to read into an ndarray with the given memory
+    /// `order`.
+    ///
+    /// Column-major arrays are produced by a single
+    /// cache-blocked transposing copy of the read buffer.
+    fn read_as_array_ordered<T>(
+        &self,
+        raster_window: RasterWindow,
+        order: MemoryOrder,
+    ) -> Result<Array2<T>, Self::Error>
+    where
+        T: Pixel,
+    {
+        match order {
+            MemoryOrder::Standard => self.read_as_array(raster_window),
+            MemoryOrder::ColumnMajor => {
+                let (rows, cols) = raster_window.shape();
+                let mut buf = zeroed(rows * cols);
+                let mut transposed = zeroed(rows * cols);
+                self.read_into_slice(&mut buf[..], raster_window)?;
+                transpose_into(&buf, &mut transposed, (rows, cols));
+                Array2::from_shape_vec((rows, cols).f(), transposed).map_err(Self::Error::from)
+            }
+        }
+    }
+
+    /// Helper to read into an ndarray of `buffer_size` (x,
+    /// y), e.g. to downsample a window on read.
+    fn read_as_array_sized<T>(
+        &self,
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<Array2<T>, Self::Error>
+    where
+        T: Pixel,
+    {
+        self.read_as_array_resampled(raster_window, buffer_size, ResampleAlg::Nearest)
+    }
+
+    /// Helper to read into an ndarray of `buffer_size` (x,
+    /// y), resampling with `alg` on read (eg.
+    /// [`ResampleAlg::Average`] to decimate, or
+    /// [`ResampleAlg::Cubic`] to upsample).
+    fn read_as_array_resampled<T>(
+        &self,
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<Array2<T>, Self::Error>
+    where
+        T: Pixel,
+    {
+        let (cols, rows) = buffer_size;
+        let mut buf = zeroed(cols * rows);
+        self.read_into_slice_resampled(&mut buf[..], raster_window, buffer_size, alg)?;
+        Array2::from_shape_vec((rows, cols), buf).map_err(Self::Error::from)
+    }
+
+    /* /// Helper to read into slice from output of
+    /// [`ChunkConfig`] iterator
+    fn read_chunk_into_slice<T>(
+        &self,
+        out: &mut [T],
+        chunk: Chunk,
+    ) -> Result<(), Self::Error>
+    where
+        T: Pixel,
+    {
+        self.read_into_slice(out, chunk.into())
+    } */
+
+    /// Helper to read ndarray from output of
+    /// [`ChunkConfig`] iterator
+    fn read_chunk<T>(&self, chunk: Chunk) -> Result<Array2<T>, Self::Error>
+    where
+        T: Pixel,
+    {
+        self.read_as_array(chunk.into())
+    }
+
+    /// Helper to read ndarray with the given memory `order`
+    /// from output of [`ChunkConfig`] iterator
+    fn read_chunk_ordered<T>(
+        &self,
+        chunk: Chunk,
+        order: MemoryOrder,
+    ) -> Result<Array2<T>, Self::Error>
+    where
+        T: Pixel,
+    {
+        self.read_as_array_ordered(chunk.into(), order)
+    }
+
+    /// Helper to read a decimated ndarray from output of
+    /// [`ChunkConfig`] iterator.
+    ///
+    /// Each axis is shrunk by `factor` (rounding up), so a
+    /// factor of `4` reads 1/16th of the data.
+    fn read_chunk_decimated<T>(
+        &self,
+        chunk: Chunk,
+        factor: NonZeroUsize,
+    ) -> Result<Array2<T>, Self::Error>
+    where
+        T: Pixel,
+    {
+        let raster_window: RasterWindow = chunk.into();
+        let (cols, rows) = raster_window.size();
+        let factor = factor.get();
+        let buffer_size = (cols.div_ceil(factor), rows.div_ceil(factor));
+        self.read_as_array_sized(raster_window, buffer_size)
+    }
+
+    /// Read a [`FractionalWindow`], bilinearly resampled to
+    /// its exact grid.
+    ///
+    /// The enclosing integer window is read and interpolated
+    /// at the center of each output pixel. Sample points
+    /// within half a pixel of the raster edge use the edge
+    /// pixels; points outside the raster are `NaN`.
+    fn read_fractional(&self, window: &FractionalWindow) -> Result<Array2<f64>, Self::Error> {
+        let enclosing = window.enclosing();
+        let (off_x, off_y) = enclosing.signed_offset();
+        let (cols, rows) = self.raster_size()?;
+        let data = self.read_chunk_or_fill(enclosing, f64::NAN)?;
+
+        let (buf_x, buf_y) = window.buffer_size();
+        Ok(Array2::from_shape_fn((buf_y, buf_x), |(row, col)| {
+            let (x, y) = window.sample_point(col, row);
+            if x < 0. || y < 0. || x > cols as f64 || y > rows as f64 {
+                return f64::NAN;
+            }
+            let x = (x - 0.5).clamp(0., cols.saturating_sub(1) as f64);
+            let y = (y - 0.5).clamp(0., rows.saturating_sub(1) as f64);
+            bilinear(&data, y - off_y as f64, x - off_x as f64).unwrap_or(f64::NAN)
+        }))
+    }
+
+    // TODO: read using gdal read_chunk faster?
+}
+
+/// Whether a window of a raster holds data, as known to the
+/// format without reading it (eg. unwritten blocks of sparse
+/// files).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coverage {
+    /// No data: the window reads as nodata (or zero).
+    Empty,
+    /// Some data, but not everywhere.
+    Partial,
+    /// Data everywhere.
+    Data,
+    /// The format can't tell.
+    Unknown,
+}
+
+/// Reports the data [`Coverage`] of windows of a raster.
+///
+/// Used by
+/// [`ProcessingDriver::with_skip_empty`][crate::processing::ProcessingDriver::with_skip_empty]
+/// to skip chunks without data.
+pub trait CoverageReader {
+    type Error;
+
+    fn coverage(&self, raster_window: &RasterWindow) -> Result<Coverage, Self::Error>;
+}
+
+impl<R: CoverageReader + ?Sized> CoverageReader for &R {
+    type Error = R::Error;
+
+    fn coverage(&self, raster_window: &RasterWindow) -> Result<Coverage, Self::Error> {
+        (**self).coverage(raster_window)
+    }
+}
+
+/// Hints a reader about windows that will be read soon.
+///
+/// Advice lets backends (eg. GDAL's `AdviseRead` on network
+/// datasets) start fetching data ahead of the reads. It is
+/// only a hint: backends may ignore it, and callers should
+/// not fail on its errors. See
+/// [`ChunkConfig::iter_advised`][crate::chunking::ChunkConfig::iter_advised].
+pub trait AdviseReader: ChunkReader {
+    /// Advise that `raster_window` will be read.
+    fn advise_read(&self, raster_window: &RasterWindow) -> Result<(), Self::Error>;
+}
+
+impl<R: AdviseReader + ?Sized> AdviseReader for &R {
+    fn advise_read(&self, raster_window: &RasterWindow) -> Result<(), Self::Error> {
+        (**self).advise_read(raster_window)
+    }
+}
+
+/// Creates independent readers of the same raster.
+///
+/// Handles such as GDAL's `Dataset` aren't `Sync`, so
+/// parallel workflows need one reader per worker: see
+/// `ChunkConfig::par_map_with_reader` (with the "use-rayon"
+/// feature).
+pub trait ReaderFactory: Sync {
+    type Reader: ChunkReader;
+
+    /// Create a new reader.
+    fn create(&self) -> Result<Self::Reader, <Self::Reader as ChunkReader>::Error>;
+}
+
+impl<F, R> ReaderFactory for F
+where
+    F: Fn() -> Result<R, R::Error> + Sync,
+    R: ChunkReader,
+{
+    type Reader = R;
+
+    fn create(&self) -> Result<R, R::Error> {
+        self()
+    }
+}
+
+impl<R: ChunkReader + ?Sized> ChunkReader for &R {
+    type Error = R::Error;
+
+    fn raster_size(&self) -> Result<Size, Self::Error> {
+        (**self).raster_size()
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<(), Self::Error>
+    where
+        T: Pixel,
+    {
+        (**self).read_into_slice_sized(out, raster_window, buffer_size)
+    }
+
+    fn read_into_slice_resampled<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+        alg: ResampleAlg,
+    ) -> Result<(), Self::Error>
+    where
+        T: Pixel,
+    {
+        (**self).read_into_slice_resampled(out, raster_window, buffer_size, alg)
+    }
+}
+
+/// A [`ChunkReader`] over an in-memory array of (rows,
+/// cols), eg. a raster read decimated to be regridded.
+/// Resampling on read is not supported.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrayReader<T>(pub Array2<T>);
+
+impl<T: ToPrimitive + Copy> ChunkReader for ArrayReader<T> {
+    type Error = RasterUtilsError;
+
+    fn raster_size(&self) -> crate::Result<Size> {
+        let (rows, cols) = self.0.dim();
+        Ok((cols, rows))
+    }
+
+    fn read_into_slice_sized<P>(
+        &self,
+        out: &mut [P],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> crate::Result<()>
+    where
+        P: Pixel,
+    {
+        let (size_x, size_y) = raster_window.size();
+        if buffer_size != (size_x, size_y) {
+            return Err(RasterUtilsError::Unsupported(
+                "resampled reads of in-memory arrays",
+            ));
+        }
+        let (off_x, off_y) = raster_window.validate(self.raster_size()?)?;
+        let window = self
+            .0
+            .slice(s![off_y..off_y + size_y, off_x..off_x + size_x]);
+        for (out, value) in out.iter_mut().zip(window.iter()) {
+            *out = <P as NumCast>::from(*value).ok_or(RasterUtilsError::Unsupported(
+                "sample value not representable in the requested type",
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// A buffer of `len` zeros to read into. Readers are safe
+/// code and may read from `out`, so it must be initialized.
+fn zeroed<T: Pixel>(len: usize) -> Vec<T> {
+    vec![<T as NumCast>::from(0).expect("zero fits every pixel type"); len]
+}
+
+/// Copy row-major `src` of `shape` (rows, cols) into `dst` in
+/// column-major order, one tile at a time to stay cache
+/// friendly on both sides.
+pub(crate) fn transpose_into<T: Copy>(src: &[T], dst: &mut [T], shape: (usize, usize)) {
+    const TILE: usize = 64;
+    let (rows, cols) = shape;
+    for row_tile in (0..rows).step_by(TILE) {
+        for col_tile in (0..cols).step_by(TILE) {
+            for row in row_tile..(row_tile + TILE).min(rows) {
+                for col in col_tile..(col_tile + TILE).min(cols) {
+                    dst[col * rows + row] = src[row * cols + col];
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::ShapeBuilder;
+
+    #[test]
+    fn test_transpose_into() {
+        let (rows, cols) = (70, 131);
+        let src: Vec<usize> = (0..rows * cols).collect();
+        let mut dst = vec![0; rows * cols];
+        transpose_into(&src, &mut dst, (rows, cols));
+
+        let standard = Array2::from_shape_vec((rows, cols), src).unwrap();
+        let column_major = Array2::from_shape_vec((rows, cols).f(), dst).unwrap();
+        assert_eq!(standard, column_major);
+    }
+
+    #[test]
+    fn test_read_fractional() {
+        // Value is the column index.
+        let reader = ArrayReader(Array2::from_shape_fn((6, 8), |(_, j)| j as f64));
+        let window = FractionalWindow::new((2.25, 1.), (2., 2.), (4, 2));
+        let array = reader.read_fractional(&window).unwrap();
+        assert_eq!(array.row(0).to_vec(), vec![2., 2.5, 3., 3.5]);
+
+        // Beyond the right edge.
+        let window = FractionalWindow::new((7.5, 0.), (1., 1.), (2, 1));
+        let array = reader.read_fractional(&window).unwrap();
+        assert_eq!(array[[0, 0]], 7.);
+        assert!(array[[0, 1]].is_nan());
+    }
+
+    #[test]
+    fn test_read_resampled() {
+        use std::cell::Cell;
+
+        /// Fills reads with the index of the algorithm.
+        struct AlgReader(Cell<Option<ResampleAlg>>);
+
+        impl ChunkReader for AlgReader {
+            type Error = ShapeError;
+
+            fn raster_size(&self) -> Result<Size, ShapeError> {
+                Ok((10, 10))
+            }
+
+            fn read_into_slice_sized<T: Pixel>(
+                &self,
+                out: &mut [T],
+                raster_window: RasterWindow,
+                buffer_size: Size,
+            ) -> Result<(), ShapeError> {
+                self.read_into_slice_resampled(
+                    out,
+                    raster_window,
+                    buffer_size,
+                    ResampleAlg::Nearest,
+                )
+            }
+
+            fn read_into_slice_resampled<T: Pixel>(
+                &self,
+                out: &mut [T],
+                _: RasterWindow,
+                _: Size,
+                alg: ResampleAlg,
+            ) -> Result<(), ShapeError> {
+                self.0.set(Some(alg));
+                out.fill(<T as NumCast>::from(alg as u8).unwrap());
+                Ok(())
+            }
+        }
+
+        let reader = AlgReader(Cell::new(None));
+        let window: RasterWindow = ((0, 0), (10, 10)).into();
+        let array = (&reader)
+            .read_as_array_resampled::<u8>(window.clone(), (5, 4), ResampleAlg::Average)
+            .unwrap();
+        assert_eq!(array.dim(), (4, 5));
+        assert_eq!(reader.0.get(), Some(ResampleAlg::Average));
+        assert!(array.iter().all(|&v| v == ResampleAlg::Average as u8));
+
+        reader.read_as_array_sized::<u8>(window, (5, 4)).unwrap();
+        assert_eq!(reader.0.get(), Some(ResampleAlg::Nearest));
+    }
+}
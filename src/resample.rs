@@ -0,0 +1,271 @@
+//! Regrid a raster to another resolution in the same CRS.
+//!
+//! [`resample_to_grid`] wraps a reader into a
+//! [`GridResampler`], itself a [`ChunkReader`] over the
+//! target grid: each read maps the requested window onto the
+//! source grid with the pixel to pixel transform, reads the
+//! covering source window and resamples it. Use it with a
+//! [`ChunkConfig`][crate::chunking::ChunkConfig] over the
+//! target size to regrid chunk by chunk.
+
+use super::align::bilinear;
+use super::geometry::{PixelPixelTransform, PixelWorldTransform, RasterWindow, Size};
+use super::reader::{ChunkReader, Pixel};
+use super::{RasterUtilsError, Result};
+use geo::Coord;
+use ndarray::Array2;
+use num::NumCast;
+
+/// Resampling method of [`GridResampler`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResampleMethod {
+    /// Value of the source pixel containing the center of
+    /// the target pixel.
+    #[default]
+    Nearest,
+    /// Bilinear interpolation at the center of the target
+    /// pixel.
+    Bilinear,
+    /// Mean of the valid source pixels whose centers fall
+    /// within the target pixel (for downsampling). Falls back
+    /// to nearest if there are none.
+    Average,
+}
+
+/// A [`ChunkReader`] over a target grid, resampling from a
+/// source reader on another grid of the same CRS.
+///
+/// Target pixels without source data are set to the fill
+/// value (`NaN` by default: set it to a nodata value to read
+/// integer types).
+pub struct GridResampler<R> {
+    reader: R,
+    /// Target to source pixel transform.
+    transform: PixelPixelTransform,
+    size: Size,
+    method: ResampleMethod,
+    fill: f64,
+}
+
+/// Resample the raster read by `reader`, with pixel to world
+/// transform `src_transform`, to the grid of
+/// `target_transform` and `target_size`.
+pub fn resample_to_grid<R: ChunkReader>(
+    reader: R,
+    src_transform: &PixelWorldTransform,
+    target_transform: &PixelWorldTransform,
+    target_size: Size,
+    method: ResampleMethod,
+) -> Result<GridResampler<R>> {
+    Ok(GridResampler {
+        reader,
+        transform: target_transform.transform_to(src_transform)?,
+        size: target_size,
+        method,
+        fill: f64::NAN,
+    })
+}
+
+impl<R: ChunkReader> GridResampler<R> {
+    /// Value of target pixels without source data.
+    pub fn with_fill(mut self, fill: f64) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    pub fn method(&self) -> ResampleMethod {
+        self.method
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn map(&self, pixel: (f64, f64)) -> (f64, f64) {
+        self.transform.apply(Coord::from(pixel)).x_y()
+    }
+}
+
+impl<R> GridResampler<R>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    /// Resample the target `window` as `f64`.
+    fn resample(&self, window: &RasterWindow) -> Result<Array2<f64>> {
+        let (off_x, off_y) = window.signed_offset();
+        let (size_x, size_y) = window.size();
+        let (src_cols, src_rows) = self.reader.raster_size().map_err(Into::into)?;
+
+        // Source window covering the target window, with a
+        // margin for interpolation.
+        let covering = window.affine_transform(&self.transform);
+        let (min_x, min_y) = covering.signed_offset();
+        let (cover_x, cover_y) = covering.size();
+        let src_offset = (min_x - 1, min_y - 1);
+        let src_window = RasterWindow::from_signed(src_offset, (cover_x + 3, cover_y + 3));
+        let data = self
+            .reader
+            .read_chunk_or_fill(src_window, f64::NAN)
+            .map_err(Into::into)?;
+        let (src_off_x, src_off_y) = (src_offset.0 as f64, src_offset.1 as f64);
+        let inside =
+            |x: f64, y: f64| x >= 0. && y >= 0. && x < src_cols as f64 && y < src_rows as f64;
+        let at = |x: f64, y: f64| data[[(y - src_off_y) as usize, (x - src_off_x) as usize]];
+
+        let nearest = |(x, y): (f64, f64)| {
+            let (x, y) = (x.floor(), y.floor());
+            if inside(x, y) {
+                at(x, y)
+            } else {
+                f64::NAN
+            }
+        };
+        let bilinear_at = |(x, y): (f64, f64)| {
+            if !inside(x, y) {
+                return f64::NAN;
+            }
+            let x = (x - 0.5).clamp(0., (src_cols - 1) as f64);
+            let y = (y - 0.5).clamp(0., (src_rows - 1) as f64);
+            bilinear(&data, y - src_off_y, x - src_off_x).unwrap_or(f64::NAN)
+        };
+        let average = |col: f64, row: f64| {
+            let (x_1, y_1) = self.map((col, row));
+            let (x_2, y_2) = self.map((col + 1., row + 1.));
+            let (x_1, x_2) = (x_1.min(x_2), x_1.max(x_2));
+            let (y_1, y_2) = (y_1.min(y_2), y_1.max(y_2));
+            let (mut sum, mut count) = (0., 0);
+            // Source pixels whose centers lie in [x_1, x_2).
+            let mut y = (y_1 - 0.5).ceil();
+            while y + 0.5 < y_2 {
+                let mut x = (x_1 - 0.5).ceil();
+                while x + 0.5 < x_2 {
+                    if inside(x, y) {
+                        let value = at(x, y);
+                        if value.is_finite() {
+                            sum += value;
+                            count += 1;
+                        }
+                    }
+                    x += 1.;
+                }
+                y += 1.;
+            }
+            if count > 0 {
+                sum / count as f64
+            } else {
+                nearest(self.map((col + 0.5, row + 0.5)))
+            }
+        };
+
+        Ok(Array2::from_shape_fn((size_y, size_x), |(i, j)| {
+            let (col, row) = ((off_x + j as isize) as f64, (off_y + i as isize) as f64);
+            let value = match self.method {
+                ResampleMethod::Nearest => nearest(self.map((col + 0.5, row + 0.5))),
+                ResampleMethod::Bilinear => bilinear_at(self.map((col + 0.5, row + 0.5))),
+                ResampleMethod::Average => average(col, row),
+            };
+            if value.is_nan() {
+                self.fill
+            } else {
+                value
+            }
+        }))
+    }
+}
+
+impl<R> ChunkReader for GridResampler<R>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    type Error = RasterUtilsError;
+
+    fn raster_size(&self) -> Result<Size> {
+        Ok(self.size)
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        raster_window.validate(self.size)?;
+        if buffer_size != raster_window.size() {
+            return Err(RasterUtilsError::Unsupported(
+                "resampled reads of a GridResampler",
+            ));
+        }
+        let values = self.resample(&raster_window)?;
+        for (out, value) in out.iter_mut().zip(values.iter()) {
+            *out = <T as NumCast>::from(*value).ok_or(RasterUtilsError::Unsupported(
+                "sample value not representable in the requested type",
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ArrayReader;
+    use geo::AffineTransform;
+
+    #[test]
+    fn test_downsample() {
+        // 10 m source of 4x4, 20 m target of 2x2.
+        let source = ArrayReader(Array2::from_shape_fn((4, 4), |(i, j)| (i * 4 + j) as f64));
+        let src_t = PixelWorldTransform::new(AffineTransform::new(10., 0., 0., 0., -10., 40.));
+        let dst_t = PixelWorldTransform::new(AffineTransform::new(20., 0., 0., 0., -20., 40.));
+
+        let average = resample_to_grid(
+            source.clone(),
+            &src_t,
+            &dst_t,
+            (2, 2),
+            ResampleMethod::Average,
+        )
+        .unwrap()
+        .read_as_array::<f64>(((0, 0), (2, 2)).into())
+        .unwrap();
+        assert_eq!(average[[0, 0]], (0. + 1. + 4. + 5.) / 4.);
+        assert_eq!(average[[1, 1]], (10. + 11. + 14. + 15.) / 4.);
+
+        let nearest = resample_to_grid(
+            source.clone(),
+            &src_t,
+            &dst_t,
+            (2, 2),
+            ResampleMethod::Nearest,
+        )
+        .unwrap()
+        .read_as_array::<f64>(((0, 0), (2, 2)).into())
+        .unwrap();
+        // Center of the first target pixel is on the corner
+        // of source pixels 0, 1, 4 and 5.
+        assert_eq!(nearest[[0, 0]], 5.);
+    }
+
+    #[test]
+    fn test_upsample_bilinear() {
+        let source = ArrayReader(Array2::from_shape_fn((2, 2), |(_, j)| j as f64 * 10.));
+        let src_t = PixelWorldTransform::new(AffineTransform::new(20., 0., 0., 0., -20., 40.));
+        let dst_t = PixelWorldTransform::new(AffineTransform::new(10., 0., 0., 0., -10., 40.));
+        let array = resample_to_grid(
+            source.clone(),
+            &src_t,
+            &dst_t,
+            (4, 4),
+            ResampleMethod::Bilinear,
+        )
+        .unwrap()
+        .read_as_array::<f64>(((0, 0), (4, 4)).into())
+        .unwrap();
+        assert_eq!(array.row(1).to_vec(), vec![0., 2.5, 7.5, 10.]);
+    }
+}
@@ -0,0 +1,573 @@
+//! Random sampling of valid pixels.
+//!
+//! [`sample_pixels`] draws a uniform random sample of the
+//! valid pixels of a raster in one pass over the chunks of a
+//! [`ChunkConfig`], with reservoir sampling: memory is
+//! bounded by the sample size, not the raster size.
+//! [`sample_pixels_stratified`] draws one sample per class
+//! of a class raster on the same grid.
+//!
+//! [`sample_points`] instead reads the values at given
+//! coordinates (eg. to drape field plots over a raster), and
+//! [`sample_points_interpolated`] interpolates them (eg. for
+//! DEMs).
+//!
+//! Samples are reproducible: the same seed, raster and
+//! config give the same samples. The generator is
+//! SplitMix64, so results don't depend on external crates.
+
+use super::chunking::ChunkConfig;
+use super::geometry::{PixelWorldTransform, RasterWindow, Size};
+use super::nodata::NodataPolicy;
+use super::reader::{ChunkReader, Pixel};
+use super::{RasterUtilsError, Result};
+use geo::{AffineTransform, Coord, Point};
+use ndarray::Array2;
+
+use std::collections::BTreeMap;
+
+/// Options of [`sample_pixels`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplingOptions {
+    /// Number of pixels to sample (per class, if
+    /// stratified).
+    pub count: usize,
+    /// Seed of the random generator.
+    pub seed: u64,
+    /// Nodata value of the raster, if any. `NaN` pixels are
+    /// always skipped.
+    pub nodata: Option<f64>,
+    /// Nodata value of the class raster, if any.
+    pub class_nodata: Option<f64>,
+    /// How values are compared with the nodata values.
+    pub nodata_policy: NodataPolicy,
+}
+
+impl SamplingOptions {
+    pub fn new(count: usize, seed: u64) -> Self {
+        SamplingOptions {
+            count,
+            seed,
+            nodata: None,
+            class_nodata: None,
+            nodata_policy: NodataPolicy::Exact,
+        }
+    }
+}
+
+/// A sampled pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    /// Pixel position (x, y).
+    pub pixel: (usize, usize),
+    /// World coordinates of the pixel center.
+    pub coord: Coord<f64>,
+    pub value: f64,
+}
+
+/// SplitMix64 generator.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform integer in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+/// Reservoir of at most `count` items (Algorithm R).
+struct Reservoir {
+    items: Vec<Sample>,
+    seen: u64,
+}
+
+impl Reservoir {
+    fn new() -> Self {
+        Reservoir {
+            items: vec![],
+            seen: 0,
+        }
+    }
+
+    fn offer(&mut self, count: usize, rng: &mut SplitMix64, sample: Sample) {
+        self.seen += 1;
+        if self.items.len() < count {
+            self.items.push(sample);
+        } else {
+            let j = rng.below(self.seen) as usize;
+            if j < count {
+                self.items[j] = sample;
+            }
+        }
+    }
+}
+
+/// Call `f` with the pixel position and value of the valid
+/// pixels of `reader` (and their class, read from `classes`),
+/// chunk by chunk.
+fn for_each_valid<R, C, F>(
+    reader: &R,
+    classes: Option<&C>,
+    config: &ChunkConfig,
+    options: &SamplingOptions,
+    mut f: F,
+) -> Result<()>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    C: ChunkReader,
+    C::Error: Into<RasterUtilsError>,
+    F: FnMut((usize, usize), f64, Option<i64>),
+{
+    if config.padding() > 0 {
+        return Err(RasterUtilsError::Unsupported("sampling over padded chunks"));
+    }
+    let is_nodata =
+        |value: f64, nodata: Option<f64>| options.nodata_policy.is_nodata(value, nodata);
+    for chunk in config.iter() {
+        let (off_x, off_y) = chunk.offset();
+        let values = reader.read_chunk::<f64>(chunk).map_err(Into::into)?;
+        let class_values = match classes {
+            Some(classes) => Some(classes.read_chunk::<f64>(chunk).map_err(Into::into)?),
+            None => None,
+        };
+        for ((row, col), &value) in values.indexed_iter() {
+            if is_nodata(value, options.nodata) {
+                continue;
+            }
+            let class = match &class_values {
+                Some(class_values) => {
+                    let class = class_values[[row, col]];
+                    if is_nodata(class, options.class_nodata) {
+                        continue;
+                    }
+                    Some(class as i64)
+                }
+                None => None,
+            };
+            f((off_x + col, off_y + row), value, class);
+        }
+    }
+    Ok(())
+}
+
+fn to_sample(transform: &PixelWorldTransform, (x, y): (usize, usize), value: f64) -> Sample {
+    let center = (x as f64 + 0.5, y as f64 + 0.5);
+    Sample {
+        pixel: (x, y),
+        coord: Coord::from(transform.pixel_to_world(center)),
+        value,
+    }
+}
+
+/// Sample `options.count` valid pixels of the raster read by
+/// `reader`, with pixel to world `transform`, uniformly at
+/// random over the chunks of `config` (without padding).
+///
+/// Returns all valid pixels if there are fewer.
+pub fn sample_pixels<R>(
+    reader: &R,
+    transform: &PixelWorldTransform,
+    config: &ChunkConfig,
+    options: &SamplingOptions,
+) -> Result<Vec<Sample>>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    let mut rng = SplitMix64(options.seed);
+    let mut reservoir = Reservoir::new();
+    for_each_valid::<R, R, _>(reader, None, config, options, |pixel, value, _| {
+        reservoir.offer(options.count, &mut rng, to_sample(transform, pixel, value))
+    })?;
+    Ok(reservoir.items)
+}
+
+/// Like [`sample_pixels`], drawing `options.count` pixels per
+/// class, as read (and truncated to an integer) from
+/// `classes` on the same grid.
+///
+/// Pixels whose class is nodata are skipped.
+pub fn sample_pixels_stratified<R, C>(
+    reader: &R,
+    classes: &C,
+    transform: &PixelWorldTransform,
+    config: &ChunkConfig,
+    options: &SamplingOptions,
+) -> Result<BTreeMap<i64, Vec<Sample>>>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    C: ChunkReader,
+    C::Error: Into<RasterUtilsError>,
+{
+    let mut rng = SplitMix64(options.seed);
+    let mut reservoirs = BTreeMap::new();
+    for_each_valid(
+        reader,
+        Some(classes),
+        config,
+        options,
+        |pixel, value, class| {
+            reservoirs
+                .entry(class.expect("classes are read"))
+                .or_insert_with(Reservoir::new)
+                .offer(options.count, &mut rng, to_sample(transform, pixel, value))
+        },
+    )?;
+    Ok(reservoirs
+        .into_iter()
+        .map(|(class, reservoir)| (class, reservoir.items))
+        .collect())
+}
+
+/// Side of the tiles points are grouped by in
+/// [`sample_points`].
+const POINT_TILE: usize = 256;
+
+/// Margin around the points read by
+/// [`sample_points_interpolated`], enough for the 4x4
+/// neighbourhood of cubic interpolation.
+const POINT_MARGIN: usize = 2;
+
+/// Interpolation of [`sample_points_interpolated`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PointInterpolation {
+    /// Value of the pixel containing the point.
+    #[default]
+    Nearest,
+    /// Bilinear interpolation of the 2x2 nearest pixel
+    /// centers.
+    Bilinear,
+    /// Bicubic (Catmull-Rom) interpolation of the 4x4
+    /// nearest pixel centers.
+    Cubic,
+}
+
+/// Fractional pixel positions (x, y) of the `points` within
+/// the raster, with their index, grouped by tile.
+fn group_points(
+    transform: &AffineTransform,
+    points: &[Point<f64>],
+    (cols, rows): Size,
+) -> Result<BTreeMap<(usize, usize), Vec<(usize, (f64, f64))>>> {
+    let inverse = PixelWorldTransform::new(*transform).inverse()?;
+    let mut tiles: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for (index, point) in points.iter().enumerate() {
+        let pixel = inverse.apply(point.0);
+        // Also skips NaN coordinates.
+        if !(pixel.x >= 0. && pixel.y >= 0. && pixel.x < cols as f64 && pixel.y < rows as f64) {
+            continue;
+        }
+        let (x, y) = (pixel.x as usize, pixel.y as usize);
+        tiles
+            .entry((y / POINT_TILE, x / POINT_TILE))
+            .or_default()
+            .push((index, pixel.x_y()));
+    }
+    Ok(tiles)
+}
+
+/// Window spanning the pixels containing `pixels`, extended
+/// by `margin` on every side.
+fn points_window(pixels: &[(usize, (f64, f64))], margin: usize) -> RasterWindow {
+    let xs = pixels.iter().map(|(_, (x, _))| *x as usize);
+    let ys = pixels.iter().map(|(_, (_, y))| *y as usize);
+    let (min_x, max_x) = (xs.clone().min().unwrap_or(0), xs.max().unwrap_or(0));
+    let (min_y, max_y) = (ys.clone().min().unwrap_or(0), ys.max().unwrap_or(0));
+    let offset = (
+        min_x as isize - margin as isize,
+        min_y as isize - margin as isize,
+    );
+    let size = (
+        max_x - min_x + 1 + 2 * margin,
+        max_y - min_y + 1 + 2 * margin,
+    );
+    RasterWindow::from_signed(offset, size)
+}
+
+/// Values of the raster read by `reader`, with pixel to world
+/// `transform`, at `points` (in input order), or `None` for
+/// points outside the raster.
+///
+/// Points are grouped by tiles of the raster, and the window
+/// spanning the points of each tile is read once.
+pub fn sample_points<R, T>(
+    reader: &R,
+    transform: &AffineTransform,
+    points: &[Point<f64>],
+) -> Result<Vec<Option<T>>>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    T: Pixel,
+{
+    let size = reader.raster_size().map_err(Into::into)?;
+    let mut values = vec![None; points.len()];
+    for pixels in group_points(transform, points, size)?.values() {
+        let window = points_window(pixels, 0);
+        let (off_x, off_y) = window.validate(size)?;
+        let data = reader.read_as_array::<T>(window).map_err(Into::into)?;
+        for &(index, (x, y)) in pixels {
+            values[index] = Some(data[[y as usize - off_y, x as usize - off_x]]);
+        }
+    }
+    Ok(values)
+}
+
+/// Like [`sample_points`], interpolating the values of the
+/// pixels around each point with `method`.
+///
+/// `nodata` pixels under `policy` (and `NaN` ones) are left
+/// out of the interpolation: bilinear weights are renormalized over the
+/// valid pixels, and cubic interpolation falls back to
+/// bilinear when a pixel of its neighbourhood is invalid (eg.
+/// near the raster edge). Points whose neighbourhood has no
+/// valid pixel get `None`.
+pub fn sample_points_interpolated<R>(
+    reader: &R,
+    transform: &AffineTransform,
+    points: &[Point<f64>],
+    method: PointInterpolation,
+    nodata: Option<f64>,
+    policy: NodataPolicy,
+) -> Result<Vec<Option<f64>>>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    let (cols, rows) = reader.raster_size().map_err(Into::into)?;
+    let mut values = vec![None; points.len()];
+    for pixels in group_points(transform, points, (cols, rows))?.values() {
+        let window = points_window(pixels, POINT_MARGIN);
+        let (off_x, off_y) = window.signed_offset();
+        let mut data = reader
+            .read_chunk_or_fill(window, f64::NAN)
+            .map_err(Into::into)?;
+        if nodata.is_some() {
+            data.mapv_inplace(|v| {
+                if policy.is_nodata(v, nodata) {
+                    f64::NAN
+                } else {
+                    v
+                }
+            });
+        }
+        for &(index, (x, y)) in pixels {
+            // Fractional index of the pixel centers, within the
+            // raster.
+            let col = (x - 0.5).clamp(0., cols.saturating_sub(1) as f64) - off_x as f64;
+            let row = (y - 0.5).clamp(0., rows.saturating_sub(1) as f64) - off_y as f64;
+            values[index] = match method {
+                PointInterpolation::Nearest => {
+                    let value = data[[
+                        (y.floor() as isize - off_y) as usize,
+                        (x.floor() as isize - off_x) as usize,
+                    ]];
+                    value.is_finite().then_some(value)
+                }
+                PointInterpolation::Bilinear => bilinear_valid(&data, row, col),
+                PointInterpolation::Cubic => {
+                    cubic(&data, row, col).or_else(|| bilinear_valid(&data, row, col))
+                }
+            };
+        }
+    }
+    Ok(values)
+}
+
+/// Bilinear interpolation at (`row`, `col`) over the finite
+/// samples of the 2x2 neighbourhood.
+fn bilinear_valid(data: &Array2<f64>, row: f64, col: f64) -> Option<f64> {
+    let (i, j) = (row.floor() as usize, col.floor() as usize);
+    let (di, dj) = (row - i as f64, col - j as f64);
+    let neighbours = [
+        (i, j, (1. - di) * (1. - dj)),
+        (i, j + 1, (1. - di) * dj),
+        (i + 1, j, di * (1. - dj)),
+        (i + 1, j + 1, di * dj),
+    ];
+    let (mut sum, mut total) = (0., 0.);
+    for (i, j, weight) in neighbours {
+        let value = data.get([i, j]).copied().unwrap_or(f64::NAN);
+        if weight > 0. && value.is_finite() {
+            sum += weight * value;
+            total += weight;
+        }
+    }
+    (total > 0.).then(|| sum / total)
+}
+
+/// Catmull-Rom weights of the samples at offsets -1, 0, 1
+/// and 2 from `t`.
+fn cubic_weights(t: f64) -> [f64; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        (-t3 + 2. * t2 - t) / 2.,
+        (3. * t3 - 5. * t2 + 2.) / 2.,
+        (-3. * t3 + 4. * t2 + t) / 2.,
+        (t3 - t2) / 2.,
+    ]
+}
+
+/// Bicubic interpolation at (`row`, `col`), or `None` if a
+/// sample of the 4x4 neighbourhood is not finite.
+fn cubic(data: &Array2<f64>, row: f64, col: f64) -> Option<f64> {
+    let (i, j) = (row.floor() as usize, col.floor() as usize);
+    if i == 0 || j == 0 {
+        return None;
+    }
+    let weights_i = cubic_weights(row - i as f64);
+    let weights_j = cubic_weights(col - j as f64);
+    let mut sum = 0.;
+    for (di, weight_i) in weights_i.iter().enumerate() {
+        for (dj, weight_j) in weights_j.iter().enumerate() {
+            let value = *data.get([i + di - 1, j + dj - 1])?;
+            if !value.is_finite() {
+                return None;
+            }
+            sum += weight_i * weight_j * value;
+        }
+    }
+    Some(sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::testing::ArrayReader;
+    use std::num::NonZeroUsize;
+
+    fn test_cfg() -> ChunkConfig {
+        ChunkConfigBuilder::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(6).unwrap())
+            .add_block_size(NonZeroUsize::new(2).unwrap())
+            .build()
+    }
+
+    #[test]
+    fn test_sample_pixels() {
+        let values = Array2::from_shape_fn((6, 4), |(i, j)| if i < 3 { -1. } else { j as f64 });
+        let reader = ArrayReader(values);
+        let transform = PixelWorldTransform::new(AffineTransform::identity());
+        let options = SamplingOptions {
+            nodata: Some(-1.),
+            ..SamplingOptions::new(5, 42)
+        };
+
+        let samples = sample_pixels(&reader, &transform, &test_cfg(), &options).unwrap();
+        assert_eq!(samples.len(), 5);
+        assert!(samples.iter().all(|s| s.pixel.1 >= 3));
+        assert!(samples
+            .iter()
+            .all(|s| s.coord.x == s.pixel.0 as f64 + 0.5 && s.value == s.pixel.0 as f64));
+        let mut pixels: Vec<_> = samples.iter().map(|s| s.pixel).collect();
+        pixels.sort();
+        pixels.dedup();
+        assert_eq!(pixels.len(), 5);
+
+        let again = sample_pixels(&reader, &transform, &test_cfg(), &options).unwrap();
+        assert_eq!(samples, again);
+    }
+
+    #[test]
+    fn test_stratified() {
+        let reader = ArrayReader(Array2::from_shape_fn((6, 4), |(i, j)| (i * 4 + j) as f64));
+        // Class 1 on two pixels only.
+        let classes = ArrayReader(Array2::from_shape_fn((6, 4), |(i, j)| {
+            if (i, j) == (0, 0) || (i, j) == (5, 3) {
+                1
+            } else {
+                0
+            }
+        }));
+        let transform = PixelWorldTransform::new(AffineTransform::identity());
+        let samples = sample_pixels_stratified(
+            &reader,
+            &classes,
+            &transform,
+            &test_cfg(),
+            &SamplingOptions::new(3, 7),
+        )
+        .unwrap();
+        assert_eq!(samples[&0].len(), 3);
+        assert_eq!(
+            samples[&1].iter().map(|s| s.value).collect::<Vec<_>>(),
+            vec![0., 23.]
+        );
+    }
+
+    #[test]
+    fn test_sample_points() {
+        let values = Array2::from_shape_fn((300, 600), |(i, j)| (i * 1000 + j) as u32);
+        let reader = ArrayReader(values);
+        // 10 m pixels, origin at (1000, 5000).
+        let transform = AffineTransform::new(10., 0., 1000., 0., -10., 5000.);
+        let points = [
+            Point::new(5005., 2005.),
+            Point::new(1015., 4995.),
+            Point::new(999., 4995.),
+            Point::new(1025., 4985.),
+            Point::new(f64::NAN, 0.),
+        ];
+        let sampled = sample_points::<_, u32>(&reader, &transform, &points).unwrap();
+        assert_eq!(
+            sampled,
+            vec![Some(299_400), Some(1), None, Some(1002), None]
+        );
+    }
+
+    #[test]
+    fn test_sample_points_interpolated() {
+        // A plane is reproduced by both interpolations.
+        let plane = |x: f64, y: f64| 2. * x + 3. * y;
+        let mut values = Array2::from_shape_fn((8, 8), |(i, j)| plane(j as f64, i as f64));
+        let transform = AffineTransform::identity();
+        let points = [
+            Point::new(3.2, 4.7),
+            Point::new(0.2, 0.2),
+            Point::new(9., 1.),
+        ];
+        for method in [PointInterpolation::Bilinear, PointInterpolation::Cubic] {
+            let reader = ArrayReader(values.clone());
+            let sampled = sample_points_interpolated(
+                &reader,
+                &transform,
+                &points,
+                method,
+                None,
+                NodataPolicy::Exact,
+            )
+            .unwrap();
+            assert!((sampled[0].unwrap() - plane(2.7, 4.2)).abs() < 1e-9);
+            // Clamped to the edge pixel center.
+            assert!((sampled[1].unwrap() - plane(0., 0.)).abs() < 1e-9);
+            assert_eq!(sampled[2], None);
+        }
+
+        // Nodata neighbours are left out, here within a
+        // tolerance.
+        values[[4, 3]] = -9999.0001;
+        let reader = ArrayReader(values);
+        let sampled = sample_points_interpolated(
+            &reader,
+            &transform,
+            &[Point::new(3.5, 4.5), Point::new(4., 5.)],
+            PointInterpolation::Cubic,
+            Some(-9999.),
+            NodataPolicy::Epsilon(1e-6),
+        )
+        .unwrap();
+        assert_eq!(sampled[0], None);
+        let expected = (plane(4., 4.) + plane(3., 5.) + plane(4., 5.)) / 3.;
+        assert!((sampled[1].unwrap() - expected).abs() < 1e-9);
+    }
+}
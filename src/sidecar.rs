@@ -0,0 +1,234 @@
+//! Versioned sidecar files for persistent state.
+//!
+//! Subsystems that keep state next to a raster (job
+//! checkpoints, caches, ...) share a single sidecar schema so
+//! that jobs started by older versions of this crate can be
+//! resumed by newer ones.
+//!
+//! # Compatibility
+//!
+//! - Each subsystem stores its state in its own named
+//! _section_. Sections are kept as opaque JSON values, so
+//! sections unknown to this version (eg. written by a newer
+//! version) survive a read-modify-write cycle untouched.
+//!
+//! - Unknown top-level fields are preserved as well.
+//!
+//! - Fields are only ever added with defaults; breaking
+//! changes bump [`SIDECAR_VERSION`] and get a migration step
+//! in [`Sidecar::migrate`].
+
+use super::{RasterUtilsError, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Current version of the sidecar schema.
+pub const SIDECAR_VERSION: u32 = 1;
+
+/// Extension appended to the raster path to obtain the
+/// sidecar path.
+pub const SIDECAR_EXTENSION: &str = "ru.json";
+
+/// Identity of the raster a sidecar was written for, used to
+/// detect stale state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFingerprint {
+    pub path: PathBuf,
+    /// File size in bytes.
+    pub len: u64,
+    /// Modification time in seconds since the Unix epoch.
+    #[serde(default)]
+    pub modified: Option<u64>,
+}
+
+impl SourceFingerprint {
+    /// Fingerprint the file at `path`.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+        Ok(SourceFingerprint {
+            path: path.to_path_buf(),
+            len: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// Contents of a sidecar file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sidecar {
+    /// Schema version the sidecar was written with.
+    pub version: u32,
+    /// Raster the state belongs to.
+    #[serde(default)]
+    pub source: Option<SourceFingerprint>,
+    /// State of each subsystem, keyed by section name.
+    #[serde(default)]
+    pub sections: BTreeMap<String, Value>,
+    /// Top-level fields unknown to this version.
+    #[serde(flatten)]
+    extra: BTreeMap<String, Value>,
+}
+
+impl Default for Sidecar {
+    fn default() -> Self {
+        Sidecar {
+            version: SIDECAR_VERSION,
+            source: None,
+            sections: BTreeMap::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+impl Sidecar {
+    /// Create an empty sidecar for the given source.
+    pub fn new(source: Option<SourceFingerprint>) -> Self {
+        Sidecar {
+            source,
+            ..Default::default()
+        }
+    }
+
+    /// Path of the sidecar for the raster at `path`.
+    pub fn path_for(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".");
+        name.push(SIDECAR_EXTENSION);
+        PathBuf::from(name)
+    }
+
+    /// Upgrade a raw sidecar value written by any version.
+    ///
+    /// Sidecars written by newer versions are read as-is:
+    /// known fields are interpreted and everything else is
+    /// preserved.
+    pub fn migrate(mut value: Value) -> Result<Self> {
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+        if version == 0 {
+            // Unversioned sidecars only held sections.
+            if let Some(object) = value.as_object_mut() {
+                object.insert("version".into(), Value::from(SIDECAR_VERSION));
+            }
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Parse a sidecar from JSON.
+    pub fn from_json(json: &str) -> Result<Self> {
+        Self::migrate(serde_json::from_str(json)?)
+    }
+
+    /// Serialize the sidecar to JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Read the sidecar at `path`, if it exists.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(json) => Self::from_json(&json).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the sidecar to `path`.
+    ///
+    /// The file is written to a temporary path first and then
+    /// renamed, so a crash never leaves a truncated sidecar.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, self.to_json()?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Whether the sidecar was written for `source`.
+    pub fn matches(&self, source: &SourceFingerprint) -> bool {
+        self.source.as_ref() == Some(source)
+    }
+
+    /// Deserialize section `name`, if present.
+    pub fn section<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>> {
+        self.sections
+            .get(name)
+            .map(|value| serde_json::from_value(value.clone()).map_err(RasterUtilsError::from))
+            .transpose()
+    }
+
+    /// Serialize `state` into section `name`, replacing any
+    /// previous state.
+    pub fn set_section<T: Serialize>(&mut self, name: &str, state: &T) -> Result<()> {
+        self.sections
+            .insert(name.to_owned(), serde_json::to_value(state)?);
+        Ok(())
+    }
+
+    /// Remove section `name`.
+    pub fn remove_section(&mut self, name: &str) -> Option<Value> {
+        self.sections.remove(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut sidecar = Sidecar::default();
+        sidecar
+            .set_section("checkpoint", &vec![1usize, 2, 3])
+            .unwrap();
+        let read = Sidecar::from_json(&sidecar.to_json().unwrap()).unwrap();
+        assert_eq!(read, sidecar);
+        assert_eq!(
+            read.section::<Vec<usize>>("checkpoint").unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(read.section::<Vec<usize>>("stats").unwrap(), None);
+    }
+
+    #[test]
+    fn test_preserves_unknown_fields() {
+        let json = r#"{
+            "version": 7,
+            "sections": { "future": { "a": 1 } },
+            "added_later": [1, 2]
+        }"#;
+        let sidecar = Sidecar::from_json(json).unwrap();
+        assert_eq!(sidecar.version, 7);
+
+        let written: Value = serde_json::from_str(&sidecar.to_json().unwrap()).unwrap();
+        assert_eq!(written["sections"]["future"]["a"], 1);
+        assert_eq!(written["added_later"][1], 2);
+    }
+
+    #[test]
+    fn test_migrate_unversioned() {
+        let sidecar = Sidecar::from_json(r#"{ "sections": {} }"#).unwrap();
+        assert_eq!(sidecar.version, SIDECAR_VERSION);
+    }
+
+    #[test]
+    fn test_path_for() {
+        assert_eq!(
+            Sidecar::path_for(Path::new("/data/dem.tif")),
+            PathBuf::from("/data/dem.tif.ru.json")
+        );
+    }
+}
@@ -0,0 +1,957 @@
+//! Summary statistics and histograms of bands, by chunk.
+//!
+//! [`ChunkStats`] and [`Histogram`] are computed per chunk
+//! and merged, so a band is summarized with the memory of a
+//! single chunk. With the "serde" feature, the per-chunk
+//! results may be kept in a [`StatsCache`], persisted by a
+//! [`StatsStore`] (eg. the [sidecar][crate::sidecar] of the
+//! raster with [`SidecarStatsStore`]), so that later queries
+//! only compute the chunks that are not cached yet.
+//!
+//! [`PairedStats`] accumulates the covariance, correlation
+//! and differences of two aligned bands (eg. a model against
+//! a reference) in the same way, see [`paired_stats`].
+//!
+//! [`WeightedStats`] weights each pixel, eg. by its area
+//! with [`AreaWeights`][crate::geometry::AreaWeights] for
+//! rasters in geographic coordinates, see
+//! [`weighted_band_stats`] and [`zonal_stats`].
+
+use super::chunking::{Chunk, ChunkConfig};
+use super::geometry::WeightProvider;
+use super::nodata::NodataPolicy;
+use super::reader::ChunkReader;
+use super::{RasterUtilsError, Result};
+use ndarray::ArrayView2;
+use num::ToPrimitive;
+#[cfg(feature = "use-rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "serde")]
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+/// Count, range and moments of the valid pixels of a chunk
+/// or band.
+///
+/// `min` and `max` are zero if there are no valid pixels.
+/// As in [`PairedStats`], the variance is kept as centered
+/// moments merged with the pairwise update of Chan et al.,
+/// so that it stays accurate for values far from zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChunkStats {
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    /// Mean of the pixels, zero if there are none.
+    pub mean: f64,
+    /// Sum of squared deviations from the mean.
+    pub m2: f64,
+}
+
+impl ChunkStats {
+    /// Statistics of the pixels of `data` that are not
+    /// `nodata` under `policy` (nor NaN).
+    pub fn from_array<T>(data: ArrayView2<T>, nodata: Option<f64>, policy: NodataPolicy) -> Self
+    where
+        T: ToPrimitive + Copy,
+    {
+        let mut stats = ChunkStats::default();
+        for value in data.iter().filter_map(|v| v.to_f64()) {
+            if policy.is_nodata(value, nodata) {
+                continue;
+            }
+            if stats.count == 0 {
+                stats.min = value;
+                stats.max = value;
+            }
+            stats.count += 1;
+            stats.min = stats.min.min(value);
+            stats.max = stats.max.max(value);
+            let delta = value - stats.mean;
+            stats.mean += delta / stats.count as f64;
+            stats.m2 += delta * (value - stats.mean);
+        }
+        stats
+    }
+
+    /// Statistics of the union of the pixels of `self` and
+    /// `other`.
+    pub fn merge(&self, other: &Self) -> Self {
+        if self.count == 0 {
+            return *other;
+        }
+        if other.count == 0 {
+            return *self;
+        }
+        let count = self.count + other.count;
+        let (n1, n2, n) = (self.count as f64, other.count as f64, count as f64);
+        let delta = other.mean - self.mean;
+        ChunkStats {
+            count,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            mean: self.mean + delta * n2 / n,
+            m2: self.m2 + other.m2 + delta * delta * n1 * n2 / n,
+        }
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.mean * self.count as f64
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Population variance.
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.m2 / self.count as f64)
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+}
+
+/// Weighted count, range and moments of the valid pixels of
+/// a chunk or band.
+///
+/// Pixels with a zero, negative or `NaN` weight are skipped.
+/// The moments are merged as in [`ChunkStats`], with the
+/// weights in place of the counts.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WeightedStats {
+    pub count: u64,
+    /// Sum of the weights of the pixels.
+    pub weight: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Weighted mean of the pixels, zero if there are none.
+    pub mean: f64,
+    /// Weighted sum of squared deviations from the mean.
+    pub m2: f64,
+}
+
+impl WeightedStats {
+    /// Statistics of the pixels of `data` that are not
+    /// `nodata` under `policy` (nor NaN), weighted by
+    /// `weights`.
+    ///
+    /// Panics if `data` and `weights` have different shapes.
+    pub fn from_arrays<T>(
+        data: ArrayView2<T>,
+        weights: ArrayView2<f64>,
+        nodata: Option<f64>,
+        policy: NodataPolicy,
+    ) -> Self
+    where
+        T: ToPrimitive + Copy,
+    {
+        assert_eq!(data.dim(), weights.dim(), "weights of a different shape");
+        let mut stats = WeightedStats::default();
+        for (value, &weight) in data.iter().zip(weights.iter()) {
+            let Some(value) = value.to_f64() else {
+                continue;
+            };
+            stats.add(value, weight, nodata, policy);
+        }
+        stats
+    }
+
+    fn add(&mut self, value: f64, weight: f64, nodata: Option<f64>, policy: NodataPolicy) {
+        if policy.is_nodata(value, nodata) || weight.is_nan() || weight <= 0. {
+            return;
+        }
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        }
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.weight += weight;
+        let delta = value - self.mean;
+        self.mean += delta * weight / self.weight;
+        self.m2 += weight * delta * (value - self.mean);
+    }
+
+    /// Statistics of the union of the pixels of `self` and
+    /// `other`.
+    pub fn merge(&self, other: &Self) -> Self {
+        if self.count == 0 {
+            return *other;
+        }
+        if other.count == 0 {
+            return *self;
+        }
+        let weight = self.weight + other.weight;
+        let delta = other.mean - self.mean;
+        WeightedStats {
+            count: self.count + other.count,
+            weight,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            mean: self.mean + delta * other.weight / weight,
+            m2: self.m2 + other.m2 + delta * delta * self.weight * other.weight / weight,
+        }
+    }
+
+    /// Sum of the values of the pixels times their weights,
+    /// eg. a total over the area for area weights.
+    pub fn weighted_sum(&self) -> f64 {
+        self.mean * self.weight
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Weighted population variance.
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.m2 / self.weight)
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+}
+
+/// [`ChunkStats::from_array`] of an `f64` chunk, as read by
+/// the band statistics, vectorized with the "simd" feature.
+fn f64_chunk_stats(data: ArrayView2<f64>, nodata: Option<f64>, policy: NodataPolicy) -> ChunkStats {
+    #[cfg(feature = "simd")]
+    {
+        crate::ops::simd::moments_array(data, nodata, policy)
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        ChunkStats::from_array(data, nodata, policy)
+    }
+}
+
+/// Histogram of equal width bins over `[min, max]`.
+///
+/// Values outside the range are not counted.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Histogram {
+    pub min: f64,
+    pub max: f64,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    /// Empty histogram of `bins` bins over `[min, max]`.
+    ///
+    /// Errors if there are no bins, or if the range is not
+    /// finite or `min > max`.
+    pub fn new(min: f64, max: f64, bins: usize) -> Result<Self> {
+        if bins == 0 {
+            return Err(RasterUtilsError::InvalidHistogram("no bins"));
+        }
+        if !(min.is_finite() && max.is_finite() && min <= max) {
+            return Err(RasterUtilsError::InvalidHistogram("invalid range"));
+        }
+        Ok(Histogram {
+            min,
+            max,
+            counts: vec![0; bins],
+        })
+    }
+
+    /// Count the pixels of `data` that are not `nodata` under
+    /// `policy` (nor NaN).
+    pub fn add_array<T>(&mut self, data: ArrayView2<T>, nodata: Option<f64>, policy: NodataPolicy)
+    where
+        T: ToPrimitive + Copy,
+    {
+        let bins = self.counts.len();
+        let width = (self.max - self.min) / bins as f64;
+        for value in data.iter().filter_map(|v| v.to_f64()) {
+            if policy.is_nodata(value, nodata) || value < self.min || value > self.max {
+                continue;
+            }
+            // The last bin includes `max`.
+            let bin = (((value - self.min) / width) as usize).min(bins - 1);
+            self.counts[bin] += 1;
+        }
+    }
+
+    /// Add the counts of `other`, which should have the same
+    /// bins.
+    pub fn merge(&mut self, other: &Self) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+    }
+
+    /// Edges of the bins (one more than the bins).
+    pub fn bin_edges(&self) -> Vec<f64> {
+        let bins = self.counts.len();
+        (0..=bins)
+            .map(|i| self.min + (self.max - self.min) * i as f64 / bins as f64)
+            .collect()
+    }
+}
+
+/// Moments of the pixels of two aligned chunks (or bands)
+/// `a` and `b`, where both are valid.
+///
+/// The moments are centered, and merged with the pairwise
+/// update of Chan et al., which keeps them accurate on
+/// large bands, unlike sums of squares.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PairedStats {
+    pub count: u64,
+    pub mean_a: f64,
+    pub mean_b: f64,
+    /// Sum of squared deviations of `a` from its mean.
+    pub m2_a: f64,
+    /// Sum of squared deviations of `b` from its mean.
+    pub m2_b: f64,
+    /// Sum of the products of the deviations of `a` and `b`.
+    pub co_moment: f64,
+}
+
+impl PairedStats {
+    /// Statistics of the pixels where neither `a` is
+    /// `nodata_a` nor `b` is `nodata_b` under `policy` (nor
+    /// NaN).
+    ///
+    /// Panics if `a` and `b` have different shapes.
+    pub fn from_arrays<T, U>(
+        a: ArrayView2<T>,
+        b: ArrayView2<U>,
+        nodata_a: Option<f64>,
+        nodata_b: Option<f64>,
+        policy: NodataPolicy,
+    ) -> Self
+    where
+        T: ToPrimitive + Copy,
+        U: ToPrimitive + Copy,
+    {
+        assert_eq!(a.dim(), b.dim(), "chunks of different shapes");
+        let valid = |value: Option<f64>, nodata: Option<f64>| {
+            value.filter(|v| !policy.is_nodata(*v, nodata))
+        };
+        let mut stats = PairedStats::default();
+        for (a, b) in a.iter().zip(b.iter()) {
+            let (Some(a), Some(b)) = (valid(a.to_f64(), nodata_a), valid(b.to_f64(), nodata_b))
+            else {
+                continue;
+            };
+            stats.count += 1;
+            let n = stats.count as f64;
+            let delta_a = a - stats.mean_a;
+            stats.mean_a += delta_a / n;
+            let delta_b = b - stats.mean_b;
+            stats.mean_b += delta_b / n;
+            stats.m2_a += delta_a * (a - stats.mean_a);
+            stats.m2_b += delta_b * (b - stats.mean_b);
+            stats.co_moment += delta_a * (b - stats.mean_b);
+        }
+        stats
+    }
+
+    /// Statistics of the union of the pixels of `self` and
+    /// `other`.
+    pub fn merge(&self, other: &Self) -> Self {
+        if self.count == 0 {
+            return *other;
+        }
+        if other.count == 0 {
+            return *self;
+        }
+        let count = self.count + other.count;
+        let (n1, n2, n) = (self.count as f64, other.count as f64, count as f64);
+        let delta_a = other.mean_a - self.mean_a;
+        let delta_b = other.mean_b - self.mean_b;
+        let weight = n1 * n2 / n;
+        PairedStats {
+            count,
+            mean_a: self.mean_a + delta_a * n2 / n,
+            mean_b: self.mean_b + delta_b * n2 / n,
+            m2_a: self.m2_a + other.m2_a + delta_a * delta_a * weight,
+            m2_b: self.m2_b + other.m2_b + delta_b * delta_b * weight,
+            co_moment: self.co_moment + other.co_moment + delta_a * delta_b * weight,
+        }
+    }
+
+    /// Population covariance of `a` and `b`.
+    pub fn covariance(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.co_moment / self.count as f64)
+    }
+
+    /// Pearson correlation of `a` and `b`, `None` if either
+    /// is constant.
+    pub fn correlation(&self) -> Option<f64> {
+        let denominator = (self.m2_a * self.m2_b).sqrt();
+        (self.count > 0 && denominator > 0.).then(|| (self.co_moment / denominator).clamp(-1., 1.))
+    }
+
+    /// Mean of `a - b`.
+    pub fn bias(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.mean_a - self.mean_b)
+    }
+
+    /// Root mean square of `a - b`.
+    pub fn rmse(&self) -> Option<f64> {
+        let bias = self.bias()?;
+        // Variance of the differences, plus the squared bias.
+        let variance = (self.m2_a + self.m2_b - 2. * self.co_moment) / self.count as f64;
+        Some((variance.max(0.) + bias * bias).sqrt())
+    }
+}
+
+/// Statistics of the data (unpadded) windows of the chunks of
+/// `config`, merged. Pixels that are `nodata` under `policy`
+/// are skipped.
+pub fn band_stats<R>(
+    reader: &R,
+    config: &ChunkConfig,
+    nodata: Option<f64>,
+    policy: NodataPolicy,
+) -> Result<ChunkStats>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    config.check_raster_size(reader.raster_size().map_err(Into::into)?)?;
+    let mut stats = ChunkStats::default();
+    for chunk in config {
+        let data = reader
+            .read_as_array::<f64>(chunk.data_window())
+            .map_err(Into::into)?;
+        stats = stats.merge(&f64_chunk_stats(data.view(), nodata, policy));
+    }
+    Ok(stats)
+}
+
+/// [`PairedStats`] of the data (unpadded) windows of the
+/// chunks of `config`, read from the aligned bands `a` and
+/// `b`, merged. Pixels that are nodata under `policy` in
+/// either band are skipped.
+///
+/// With the "use-rayon" feature, the chunks are read in
+/// parallel.
+pub fn paired_stats<A, B>(
+    a: &A,
+    b: &B,
+    config: &ChunkConfig,
+    nodata_a: Option<f64>,
+    nodata_b: Option<f64>,
+    policy: NodataPolicy,
+) -> Result<PairedStats>
+where
+    A: ChunkReader + Sync,
+    A::Error: Into<RasterUtilsError>,
+    B: ChunkReader + Sync,
+    B::Error: Into<RasterUtilsError>,
+{
+    config.check_raster_size(a.raster_size().map_err(Into::into)?)?;
+    config.check_raster_size(b.raster_size().map_err(Into::into)?)?;
+    let chunk_stats = |chunk: Chunk| -> Result<PairedStats> {
+        let window = chunk.data_window();
+        let data_a = a.read_as_array::<f64>(window.clone()).map_err(Into::into)?;
+        let data_b = b.read_as_array::<f64>(window).map_err(Into::into)?;
+        Ok(PairedStats::from_arrays(
+            data_a.view(),
+            data_b.view(),
+            nodata_a,
+            nodata_b,
+            policy,
+        ))
+    };
+    #[cfg(feature = "use-rayon")]
+    let chunks = config
+        .par_iter()
+        .map(chunk_stats)
+        .collect::<Result<Vec<_>>>()?;
+    #[cfg(not(feature = "use-rayon"))]
+    let chunks = config.iter().map(chunk_stats).collect::<Result<Vec<_>>>()?;
+    Ok(chunks
+        .iter()
+        .fold(PairedStats::default(), |stats, chunk| stats.merge(chunk)))
+}
+
+/// [`WeightedStats`] of the data (unpadded) windows of the
+/// chunks of `config`, with the weights of `weights`,
+/// merged.
+pub fn weighted_band_stats<R>(
+    reader: &R,
+    config: &ChunkConfig,
+    nodata: Option<f64>,
+    policy: NodataPolicy,
+    weights: &dyn WeightProvider,
+) -> Result<WeightedStats>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    config.check_raster_size(reader.raster_size().map_err(Into::into)?)?;
+    let mut stats = WeightedStats::default();
+    for chunk in config {
+        let window = chunk.data_window();
+        let weights = weights.weights(&window);
+        let data = reader.read_as_array::<f64>(window).map_err(Into::into)?;
+        let chunk_stats = WeightedStats::from_arrays(data.view(), weights.view(), nodata, policy);
+        stats = stats.merge(&chunk_stats);
+    }
+    Ok(stats)
+}
+
+/// Statistics of the pixels of `reader` by zone, the zones
+/// being the values of the aligned band `zones`, over the
+/// data (unpadded) windows of the chunks of `config`.
+///
+/// Pixels are weighted by `weights`, or all weigh one. Every
+/// zone value gets an entry, so the zones band should have
+/// its nodata value removed from the result if it has one.
+pub fn zonal_stats<R, Z>(
+    reader: &R,
+    zones: &Z,
+    config: &ChunkConfig,
+    nodata: Option<f64>,
+    policy: NodataPolicy,
+    weights: Option<&dyn WeightProvider>,
+) -> Result<BTreeMap<i64, WeightedStats>>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+    Z: ChunkReader,
+    Z::Error: Into<RasterUtilsError>,
+{
+    config.check_raster_size(reader.raster_size().map_err(Into::into)?)?;
+    config.check_raster_size(zones.raster_size().map_err(Into::into)?)?;
+    let mut stats = BTreeMap::<i64, WeightedStats>::new();
+    for chunk in config {
+        let window = chunk.data_window();
+        let weights = weights.map(|weights| weights.weights(&window));
+        let data = reader
+            .read_as_array::<f64>(window.clone())
+            .map_err(Into::into)?;
+        let zones = zones.read_as_array::<i64>(window).map_err(Into::into)?;
+        for ((index, &value), &zone) in data.indexed_iter().zip(zones.iter()) {
+            let weight = weights.as_ref().map_or(1., |weights| weights[index]);
+            stats
+                .entry(zone)
+                .or_default()
+                .add(value, weight, nodata, policy);
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(feature = "serde")]
+pub use self::cache::{SidecarStatsStore, StatsCache, StatsStore};
+
+#[cfg(feature = "serde")]
+mod cache {
+    use super::{f64_chunk_stats, ChunkStats, Histogram, NodataPolicy};
+    use crate::chunking::{Chunk, ChunkConfig};
+    use crate::geometry::{GdalOffset, RasterWindow, Size};
+    use crate::reader::ChunkReader;
+    use crate::sidecar::{Sidecar, SourceFingerprint};
+    use crate::{RasterUtilsError, Result};
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    };
+
+    /// Per-chunk results of queries over a raster band,
+    /// keyed by query and by the data window of the chunk.
+    ///
+    /// Keying by window (rather than chunk index) lets
+    /// configs with the same chunk geometry share results.
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+    pub struct StatsCache {
+        queries: BTreeMap<String, BTreeMap<String, Value>>,
+    }
+
+    fn window_key(window: &RasterWindow) -> String {
+        let (offset, size): (GdalOffset, Size) = window.clone().into();
+        format!("{},{},{},{}", offset.0, offset.1, size.0, size.1)
+    }
+
+    impl StatsCache {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Number of cached results, over all queries.
+        pub fn len(&self) -> usize {
+            self.queries.values().map(BTreeMap::len).sum()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Cached result of `query` for `window`, if any.
+        pub fn get<V: DeserializeOwned>(&self, query: &str, window: &RasterWindow) -> Option<V> {
+            let value = self.queries.get(query)?.get(&window_key(window))?;
+            serde_json::from_value(value.clone()).ok()
+        }
+
+        pub fn insert<V: Serialize>(
+            &mut self,
+            query: &str,
+            window: &RasterWindow,
+            value: &V,
+        ) -> Result<()> {
+            self.queries
+                .entry(query.to_owned())
+                .or_default()
+                .insert(window_key(window), serde_json::to_value(value)?);
+            Ok(())
+        }
+
+        /// Drop the results of `query`.
+        pub fn remove_query(&mut self, query: &str) {
+            self.queries.remove(query);
+        }
+
+        /// Results of `query` for each chunk of `config`, in
+        /// iteration order. Chunks not cached yet are computed
+        /// with `compute`, and cached.
+        pub fn chunk_results<V, F>(
+            &mut self,
+            query: &str,
+            config: &ChunkConfig,
+            mut compute: F,
+        ) -> Result<Vec<V>>
+        where
+            V: Serialize + DeserializeOwned,
+            F: FnMut(Chunk) -> Result<V>,
+        {
+            let mut results = vec![];
+            for chunk in config {
+                let window = chunk.data_window();
+                let result = match self.get(query, &window) {
+                    Some(result) => result,
+                    None => {
+                        let result = compute(chunk)?;
+                        self.insert(query, &window, &result)?;
+                        result
+                    }
+                };
+                results.push(result);
+            }
+            Ok(results)
+        }
+
+        /// Like [`band_stats`][super::band_stats], reusing and
+        /// filling the cache.
+        pub fn band_stats<R>(
+            &mut self,
+            reader: &R,
+            config: &ChunkConfig,
+            nodata: Option<f64>,
+            policy: NodataPolicy,
+        ) -> Result<ChunkStats>
+        where
+            R: ChunkReader,
+            R::Error: Into<RasterUtilsError>,
+        {
+            let query = format!("stats:{:?}:{:?}", nodata, policy);
+            let chunks = self.chunk_results(&query, config, |chunk| {
+                let data = reader
+                    .read_as_array::<f64>(chunk.data_window())
+                    .map_err(Into::into)?;
+                Ok(f64_chunk_stats(data.view(), nodata, policy))
+            })?;
+            Ok(chunks
+                .iter()
+                .fold(ChunkStats::default(), |stats, chunk| stats.merge(chunk)))
+        }
+
+        /// Histogram of the data windows of the chunks of
+        /// `config`, reusing and filling the cache.
+        pub fn band_histogram<R>(
+            &mut self,
+            reader: &R,
+            config: &ChunkConfig,
+            nodata: Option<f64>,
+            policy: NodataPolicy,
+            range: (f64, f64),
+            bins: usize,
+        ) -> Result<Histogram>
+        where
+            R: ChunkReader,
+            R::Error: Into<RasterUtilsError>,
+        {
+            let query = format!(
+                "histogram:{:?}:{:?}:{}:{:?}:{:?}",
+                range.0, range.1, bins, nodata, policy
+            );
+            let chunks = self.chunk_results(&query, config, |chunk| {
+                let data = reader
+                    .read_as_array::<f64>(chunk.data_window())
+                    .map_err(Into::into)?;
+                let mut histogram = Histogram::new(range.0, range.1, bins)?;
+                histogram.add_array(data.view(), nodata, policy);
+                Ok(histogram)
+            })?;
+            let mut histogram = Histogram::new(range.0, range.1, bins)?;
+            for chunk in &chunks {
+                histogram.merge(chunk);
+            }
+            Ok(histogram)
+        }
+    }
+
+    /// Persists a [`StatsCache`] across runs.
+    pub trait StatsStore {
+        /// Load the last saved cache, if any.
+        fn load(&self) -> Result<Option<StatsCache>>;
+        /// Save `cache`, replacing the previous one.
+        fn save(&mut self, cache: &StatsCache) -> Result<()>;
+    }
+
+    /// Section of the sidecar, with the raster it was
+    /// computed from.
+    #[derive(Serialize, Deserialize)]
+    struct StatsSection {
+        source: Option<SourceFingerprint>,
+        cache: StatsCache,
+    }
+
+    /// Stores the cache of a band as JSON in the
+    /// [sidecar][crate::sidecar] of a raster.
+    ///
+    /// The cache is discarded if the raster (path, size or
+    /// modification time) changed since it was saved.
+    pub struct SidecarStatsStore {
+        path: PathBuf,
+        section: String,
+        source: Option<SourceFingerprint>,
+    }
+
+    impl SidecarStatsStore {
+        /// Store the cache of `band` next to the raster at
+        /// `path`.
+        pub fn new(path: &Path, band: usize) -> Self {
+            SidecarStatsStore {
+                path: Sidecar::path_for(path),
+                section: format!("stats.{}", band),
+                source: SourceFingerprint::from_path(path).ok(),
+            }
+        }
+    }
+
+    impl StatsStore for SidecarStatsStore {
+        fn load(&self) -> Result<Option<StatsCache>> {
+            let section = match Sidecar::read(&self.path)? {
+                Some(sidecar) => sidecar.section::<StatsSection>(&self.section)?,
+                None => None,
+            };
+            Ok(section
+                .filter(|section| section.source == self.source)
+                .map(|section| section.cache))
+        }
+
+        fn save(&mut self, cache: &StatsCache) -> Result<()> {
+            let mut sidecar = Sidecar::read(&self.path)?.unwrap_or_default();
+            let section = StatsSection {
+                source: self.source.clone(),
+                cache: cache.clone(),
+            };
+            sidecar.set_section(&self.section, &section)?;
+            sidecar.write(&self.path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use crate::geometry::{AreaWeights, Crs, PixelWorldTransform};
+    use crate::testing::ArrayReader;
+    use geo::AffineTransform;
+    use ndarray::{array, Array2};
+    use std::num::NonZeroUsize;
+
+    fn test_data() -> (ArrayReader<f64>, ChunkConfig) {
+        let values = Array2::from_shape_fn((12, 5), |(i, j)| (i * 5 + j) as f64);
+        let cfg = ChunkConfigBuilder::new(
+            NonZeroUsize::new(5).unwrap(),
+            NonZeroUsize::new(12).unwrap(),
+        )
+        .with_data_height(NonZeroUsize::new(4).unwrap())
+        .build();
+        (ArrayReader(values), cfg)
+    }
+
+    #[test]
+    fn test_band_stats() {
+        let (reader, cfg) = test_data();
+        let stats = band_stats(&reader, &cfg, Some(0.), NodataPolicy::Exact).unwrap();
+        let whole = ChunkStats::from_array(reader.0.view(), Some(0.), NodataPolicy::Exact);
+        assert_eq!(stats.count, whole.count);
+        assert_eq!((stats.min, stats.max), (whole.min, whole.max));
+        assert!((stats.sum() - whole.sum()).abs() < 1e-9);
+        assert!((stats.variance().unwrap() - whole.variance().unwrap()).abs() < 1e-9);
+
+        let mut histogram = Histogram::new(0., 60., 6).unwrap();
+        histogram.add_array(reader.0.view(), None, NodataPolicy::Exact);
+        assert_eq!(histogram.counts, vec![10; 6]);
+        assert_eq!(histogram.bin_edges()[1], 10.);
+
+        assert!(matches!(
+            Histogram::new(0., 60., 0),
+            Err(RasterUtilsError::InvalidHistogram(_))
+        ));
+        assert!(Histogram::new(60., 0., 6).is_err());
+        assert!(Histogram::new(0., f64::NAN, 6).is_err());
+    }
+
+    #[test]
+    fn test_stats_far_from_zero() {
+        // Sums of squares lose the variance of these values
+        // to cancellation.
+        let values = Array2::from_shape_fn((4, 4), |(i, j)| 1e9 + (i * 4 + j) as f64);
+        let (top, bottom) = values.view().split_at(ndarray::Axis(0), 2);
+        let stats = ChunkStats::from_array(top, None, NodataPolicy::Exact)
+            .merge(&ChunkStats::from_array(bottom, None, NodataPolicy::Exact));
+        assert_eq!(stats.count, 16);
+        assert_eq!(stats.mean(), Some(1e9 + 7.5));
+        // Variance of 0..16.
+        assert!((stats.variance().unwrap() - 21.25).abs() < 1e-9);
+        assert_eq!(ChunkStats::default().variance(), None);
+    }
+
+    #[test]
+    fn test_paired_stats() {
+        let (reader, cfg) = test_data();
+        // b = 2a - 3, except for a nodata pixel.
+        let mut doubled = reader.0.mapv(|v| 2. * v - 3.);
+        doubled[[11, 4]] = -100.;
+        let other = ArrayReader(doubled);
+        let policy = NodataPolicy::Exact;
+        let stats = paired_stats(&reader, &other, &cfg, Some(0.), Some(-100.), policy).unwrap();
+        let whole = PairedStats::from_arrays(
+            reader.0.view(),
+            other.0.view(),
+            Some(0.),
+            Some(-100.),
+            policy,
+        );
+        assert_eq!(stats.count, 58);
+        assert_eq!(whole.count, 58);
+        assert!((stats.co_moment - whole.co_moment).abs() < 1e-6);
+        assert!((stats.correlation().unwrap() - 1.).abs() < 1e-12);
+
+        assert!((stats.covariance().unwrap() - 2. * stats.m2_a / 58.).abs() < 1e-6);
+        assert!((stats.m2_b - 4. * stats.m2_a).abs() < 1e-6);
+        // a - b = 3 - a
+        let bias = stats.bias().unwrap();
+        assert!((bias - (3. - stats.mean_a)).abs() < 1e-9);
+
+        let same = PairedStats::from_arrays(reader.0.view(), reader.0.view(), None, None, policy);
+        assert_eq!(same.rmse(), Some(0.));
+        assert_eq!(same.bias(), Some(0.));
+        assert_eq!(PairedStats::default().correlation(), None);
+
+        // Nodata values that drifted through a float conversion.
+        let mut drifted = other.0.clone();
+        drifted[[11, 4]] = -100.0001;
+        let exact = PairedStats::from_arrays(
+            reader.0.view(),
+            drifted.view(),
+            Some(0.),
+            Some(-100.),
+            NodataPolicy::Exact,
+        );
+        let epsilon = PairedStats::from_arrays(
+            reader.0.view(),
+            drifted.view(),
+            Some(0.),
+            Some(-100.),
+            NodataPolicy::Epsilon(1e-3),
+        );
+        assert_eq!((exact.count, epsilon.count), (59, 58));
+    }
+
+    #[test]
+    fn test_weighted_stats() {
+        let (reader, cfg) = test_data();
+        let policy = NodataPolicy::Exact;
+        let transform = PixelWorldTransform::new(AffineTransform::new(2., 0., 0., 0., -2., 0.));
+        let areas = AreaWeights {
+            transform,
+            crs: Crs::Projected,
+        };
+        // Constant weights give the unweighted statistics.
+        let stats = weighted_band_stats(&reader, &cfg, Some(0.), policy, &areas).unwrap();
+        let whole = ChunkStats::from_array(reader.0.view(), Some(0.), policy);
+        assert_eq!(stats.count, whole.count);
+        assert_eq!(stats.weight, 4. * whole.count as f64);
+        assert!((stats.mean - whole.mean).abs() < 1e-9);
+        assert!((stats.variance().unwrap() - whole.variance().unwrap()).abs() < 1e-9);
+        assert!((stats.weighted_sum() - 4. * whole.sum()).abs() < 1e-9);
+
+        // Weights of one row of the first chunk only.
+        let weights = Array2::from_shape_fn((2, 2), |(i, _)| if i == 0 { 3. } else { 1. });
+        let values = array![[1., 2.], [5., f64::NAN]];
+        let stats = WeightedStats::from_arrays(values.view(), weights.view(), None, policy);
+        assert_eq!((stats.count, stats.weight), (3, 7.));
+        assert_eq!(stats.mean(), Some(2.));
+        assert_eq!(WeightedStats::default().mean(), None);
+    }
+
+    #[test]
+    fn test_zonal_stats() {
+        let (reader, cfg) = test_data();
+        // Zone of each pixel: its column, odd or even.
+        let zones = ArrayReader(Array2::from_shape_fn((12, 5), |(_, j)| (j % 2) as i64));
+        let policy = NodataPolicy::Exact;
+        let stats = zonal_stats(&reader, &zones, &cfg, Some(0.), policy, None).unwrap();
+        assert_eq!(stats.keys().copied().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!((stats[&0].count, stats[&1].count), (35, 24));
+        assert_eq!(stats[&1].mean(), Some(29.5));
+        let total: f64 = stats.values().map(WeightedStats::weighted_sum).sum();
+        assert!((total - (0..60).sum::<usize>() as f64).abs() < 1e-9);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_stats_cache() {
+        let (reader, cfg) = test_data();
+        let mut cache = StatsCache::new();
+        let first = cache
+            .band_stats(&reader, &cfg, None, NodataPolicy::Exact)
+            .unwrap();
+        let chunks = cache.len();
+        assert_eq!(chunks, cfg.iter().len());
+
+        // Cached chunks are not recomputed.
+        let mut computed = 0;
+        let query = format!("stats:{:?}:{:?}", None::<f64>, NodataPolicy::Exact);
+        let again: Vec<ChunkStats> = cache
+            .chunk_results(&query, &cfg, |_| {
+                computed += 1;
+                Ok(ChunkStats::default())
+            })
+            .unwrap();
+        assert_eq!(computed, 0);
+        let merged = again
+            .iter()
+            .fold(ChunkStats::default(), |stats, chunk| stats.merge(chunk));
+        assert_eq!(merged, first);
+
+        let histogram = cache
+            .band_histogram(&reader, &cfg, None, NodataPolicy::Exact, (0., 60.), 6)
+            .unwrap();
+        assert_eq!(histogram.counts.iter().sum::<u64>(), 60);
+        assert_eq!(cache.len(), 2 * chunks);
+    }
+}
@@ -0,0 +1,139 @@
+//! Helpers for tests of raster processing.
+//!
+//! [`assert_rasters_match`] compares two rasters chunk by
+//! chunk, within a [`Tolerance`]. [`ArrayReader`] (re-exported
+//! from [`reader`][super::reader]) serves an in-memory array
+//! through [`ChunkReader`], to build fixtures without
+//! touching the filesystem.
+
+use super::chunking::ChunkConfig;
+use super::geometry::RasterWindow;
+use super::reader::ChunkReader;
+
+use std::fmt::Debug;
+
+pub use super::reader::ArrayReader;
+
+/// Tolerance of [`assert_rasters_match`].
+///
+/// Values `a` and `b` match if `|a - b| <= abs + rel *
+/// max(|a|, |b|)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tolerance {
+    /// Absolute tolerance.
+    pub abs: f64,
+    /// Relative tolerance.
+    pub rel: f64,
+    /// Whether values that are both `NaN`, or both equal to
+    /// `nodata`, match.
+    pub nodata_equal: bool,
+    /// Nodata value of both rasters, if any.
+    pub nodata: Option<f64>,
+}
+
+impl Tolerance {
+    /// Exact comparison, with `NaN`s matching.
+    pub fn exact() -> Self {
+        Tolerance {
+            nodata_equal: true,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_nodata(&self, value: f64) -> bool {
+        value.is_nan() || self.nodata == Some(value)
+    }
+
+    /// Whether `a` and `b` match.
+    pub fn matches(&self, a: f64, b: f64) -> bool {
+        if self.nodata_equal && self.is_nodata(a) && self.is_nodata(b) {
+            return true;
+        }
+        (a - b).abs() <= self.abs + self.rel * a.abs().max(b.abs())
+    }
+}
+
+/// Assert that the rasters read by `a` and `b` match within
+/// `tolerance`, comparing the chunks of `config` (read as
+/// `f64`).
+///
+/// # Panics
+///
+/// If the raster sizes differ, a read fails, or a pixel
+/// differs: the message has the window of the chunk, its
+/// index, the (x, y) position of the first differing pixel
+/// and the values.
+pub fn assert_rasters_match<A, B>(a: &A, b: &B, config: &ChunkConfig, tolerance: Tolerance)
+where
+    A: ChunkReader,
+    B: ChunkReader,
+    A::Error: Debug,
+    B::Error: Debug,
+{
+    let size_a = a.raster_size().expect("raster size of a");
+    let size_b = b.raster_size().expect("raster size of b");
+    assert_eq!(size_a, size_b, "raster sizes differ");
+
+    for (index, chunk) in config.iter().enumerate() {
+        let window = RasterWindow::from(chunk);
+        let values_a = a.read_chunk::<f64>(chunk).expect("read from a");
+        let values_b = b.read_chunk::<f64>(chunk).expect("read from b");
+        let (off_x, off_y) = chunk.offset();
+        for ((row, col), &value_a) in values_a.indexed_iter() {
+            let value_b = values_b[[row, col]];
+            assert!(
+                tolerance.matches(value_a, value_b),
+                "rasters differ in chunk {} (window {:?}) at (x, y) = ({}, {}): {} != {}",
+                index,
+                window,
+                off_x + col,
+                off_y + row,
+                value_a,
+                value_b,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::builder::ChunkConfigBuilder;
+    use ndarray::Array2;
+    use std::num::NonZeroUsize;
+
+    fn test_cfg() -> ChunkConfig {
+        ChunkConfigBuilder::new(NonZeroUsize::new(4).unwrap(), NonZeroUsize::new(6).unwrap())
+            .add_block_size(NonZeroUsize::new(2).unwrap())
+            .build()
+    }
+
+    #[test]
+    fn test_match_within_tolerance() {
+        let a = Array2::from_shape_fn((6, 4), |(i, j)| (i * 4 + j) as f64);
+        let mut b = &a + 1e-9;
+        b[[3, 1]] = f64::NAN;
+        let mut a = a;
+        a[[3, 1]] = f64::NAN;
+
+        let tolerance = Tolerance {
+            abs: 1e-6,
+            ..Tolerance::exact()
+        };
+        assert_rasters_match(&ArrayReader(a), &ArrayReader(b), &test_cfg(), tolerance);
+    }
+
+    #[test]
+    #[should_panic(expected = "at (x, y) = (2, 5): 22 != 23")]
+    fn test_mismatch() {
+        let a = Array2::from_shape_fn((6, 4), |(i, j)| (i * 4 + j) as u16);
+        let mut b = a.clone();
+        b[[5, 2]] += 1;
+        assert_rasters_match(
+            &ArrayReader(a),
+            &ArrayReader(b),
+            &test_cfg(),
+            Tolerance::exact(),
+        );
+    }
+}
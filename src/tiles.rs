@@ -0,0 +1,512 @@
+//! XYZ tiles in web mercator.
+//!
+//! [`TileGenerator`] renders the tiles covering a raster for a
+//! range of zoom levels into RGBA PNGs, styled by a
+//! [`TileStyle`], and writes them to a [`TileSink`]: a
+//! [`DirectorySink`] (`{z}/{x}/{y}.png`) or, with the
+//! "mbtiles" feature, an [`MbTilesSink`].
+//!
+//! The raster must already be in web mercator (EPSG:3857):
+//! tiles are regridded with a
+//! [`GridResampler`][crate::resample::GridResampler], which does
+//! not reproject. At zooms coarser than the raster
+//! resolution, the source is first read decimated (see
+//! [`ChunkReader::read_as_array_sized`]), so the reader
+//! must support resampling on read (GDAL readers do, using
+//! overviews when available).
+//!
+//! This module is only available with the "tiles" feature.
+
+use super::geometry::{PixelWorldTransform, RasterWindow, Size};
+use super::reader::{ArrayReader, ChunkReader};
+use super::resample::{resample_to_grid, ResampleMethod};
+use super::{RasterUtilsError, Result};
+use geo::{AffineTransform, Coord, Rect};
+use image::{ImageFormat, Rgba, RgbaImage};
+use ndarray::{Array2, ArrayView2};
+
+use std::io::Cursor;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+/// Half the extent of the web mercator square, in meters.
+pub const WEB_MERCATOR_EXTENT: f64 = 20_037_508.342_789_244;
+
+/// Deepest supported zoom level: tile indices of zoom `z` run
+/// up to `2^z - 1`, which must fit in a `u32`.
+pub const MAX_ZOOM: u8 = 30;
+
+/// Id of an XYZ tile: `y` runs from the north.
+///
+/// Ids are built by [`TileId::new`] or
+/// [`TileGenerator::tiles`], so they have a zoom of at most
+/// [`MAX_ZOOM`] and indices within it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TileId {
+    z: u8,
+    x: u32,
+    y: u32,
+}
+
+impl TileId {
+    /// Tile `x`, `y` of zoom `z`, or an error if `z` exceeds
+    /// [`MAX_ZOOM`] or an index is out of its range.
+    pub fn new(z: u8, x: u32, y: u32) -> Result<Self> {
+        match last_index(z) {
+            Some(last) if x <= last && y <= last => Ok(TileId { z, x, y }),
+            _ => Err(RasterUtilsError::InvalidTile { z, x, y }),
+        }
+    }
+
+    pub fn z(&self) -> u8 {
+        self.z
+    }
+
+    pub fn x(&self) -> u32 {
+        self.x
+    }
+
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    /// Row of the tile in the TMS scheme, running from the
+    /// south.
+    pub fn tms_y(&self) -> u32 {
+        let last = last_index(self.z).expect("tile ids have a valid zoom");
+        last - self.y
+    }
+
+    /// Size of a tile of zoom `z` in meters.
+    pub fn span(z: u8) -> f64 {
+        2. * WEB_MERCATOR_EXTENT / 2f64.powi(z as i32)
+    }
+
+    /// Bounds of the tile in web mercator.
+    pub fn bounds(&self) -> Rect<f64> {
+        let span = Self::span(self.z);
+        let min_x = -WEB_MERCATOR_EXTENT + self.x as f64 * span;
+        let max_y = WEB_MERCATOR_EXTENT - self.y as f64 * span;
+        Rect::new((min_x, max_y - span), (min_x + span, max_y))
+    }
+
+    /// Pixel to world transform of the tile rendered at
+    /// `tile_size` pixels.
+    pub fn transform(&self, tile_size: u32) -> PixelWorldTransform {
+        let bounds = self.bounds();
+        let res = Self::span(self.z) / tile_size as f64;
+        PixelWorldTransform::new(AffineTransform::new(
+            res,
+            0.,
+            bounds.min().x,
+            0.,
+            -res,
+            bounds.max().y,
+        ))
+    }
+}
+
+/// Last tile index of zoom `z`, if `z` is at most
+/// [`MAX_ZOOM`].
+fn last_index(z: u8) -> Option<u32> {
+    (z <= MAX_ZOOM).then(|| (1u32 << z) - 1)
+}
+
+/// Mapping of raster values to colors.
+///
+/// Values are stretched linearly from `min` to `max` onto the
+/// color ramp (clamping outside it); `NaN` and nodata values
+/// are transparent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileStyle {
+    min: f64,
+    max: f64,
+    nodata: Option<f64>,
+    colors: Vec<[u8; 3]>,
+}
+
+impl TileStyle {
+    /// Grayscale ramp from `min` (black) to `max` (white).
+    pub fn new(min: f64, max: f64) -> Self {
+        TileStyle {
+            min,
+            max,
+            nodata: None,
+            colors: vec![[0, 0, 0], [255, 255, 255]],
+        }
+    }
+
+    pub fn with_nodata(mut self, nodata: f64) -> Self {
+        self.nodata = Some(nodata);
+        self
+    }
+
+    /// Evenly spaced color stops of the ramp, from `min` to
+    /// `max`.
+    ///
+    /// # Panics
+    ///
+    /// If `colors` is empty.
+    pub fn with_colors(mut self, colors: Vec<[u8; 3]>) -> Self {
+        assert!(!colors.is_empty(), "color ramp without colors");
+        self.colors = colors;
+        self
+    }
+
+    /// Color of `value`.
+    pub fn color(&self, value: f64) -> Rgba<u8> {
+        if value.is_nan() || self.nodata == Some(value) {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let range = if self.max > self.min {
+            self.max - self.min
+        } else {
+            1.
+        };
+        let t = ((value - self.min) / range).clamp(0., 1.);
+        let position = t * (self.colors.len() - 1) as f64;
+        let lower = position.floor() as usize;
+        let upper = (lower + 1).min(self.colors.len() - 1);
+        let frac = position - lower as f64;
+        let (a, b) = (self.colors[lower], self.colors[upper]);
+        let lerp = |i: usize| (a[i] as f64 + (b[i] as f64 - a[i] as f64) * frac).round() as u8;
+        Rgba([lerp(0), lerp(1), lerp(2), 255])
+    }
+
+    /// Render `values` of (rows, cols) into an image.
+    pub fn render(&self, values: ArrayView2<f64>) -> RgbaImage {
+        let (rows, cols) = values.dim();
+        RgbaImage::from_fn(cols as u32, rows as u32, |x, y| {
+            self.color(values[[y as usize, x as usize]])
+        })
+    }
+}
+
+/// Destination of rendered tiles.
+pub trait TileSink {
+    /// Write the PNG encoded `tile`.
+    fn write_tile(&mut self, tile: TileId, png: &[u8]) -> Result<()>;
+
+    /// Called once all tiles are written.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes tiles as `{root}/{z}/{x}/{y}.png`.
+#[derive(Clone, Debug)]
+pub struct DirectorySink {
+    root: PathBuf,
+}
+
+impl DirectorySink {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        DirectorySink { root: root.into() }
+    }
+}
+
+impl TileSink for DirectorySink {
+    fn write_tile(&mut self, tile: TileId, png: &[u8]) -> Result<()> {
+        let dir = self
+            .root
+            .join(tile.z().to_string())
+            .join(tile.x().to_string());
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(format!("{}.png", tile.y())), png)?;
+        Ok(())
+    }
+}
+
+/// Writes tiles into an MBTiles (SQLite) file.
+///
+/// Tiles are inserted in a single transaction, committed by
+/// [`TileSink::finish`].
+///
+/// Only available with the "mbtiles" feature.
+#[cfg(feature = "mbtiles")]
+pub struct MbTilesSink {
+    connection: rusqlite::Connection,
+}
+
+#[cfg(feature = "mbtiles")]
+impl MbTilesSink {
+    /// Create the MBTiles file at `path`, with the tileset
+    /// `name`.
+    pub fn create<P: AsRef<std::path::Path>>(path: P, name: &str) -> Result<Self> {
+        let connection = rusqlite::Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE metadata (name TEXT, value TEXT);
+             CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER,
+                                 tile_row INTEGER, tile_data BLOB);
+             CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+             BEGIN;",
+        )?;
+        let sink = MbTilesSink { connection };
+        sink.with_metadata("name", name)?
+            .with_metadata("format", "png")
+    }
+
+    /// Set the metadata `key` to `value`.
+    pub fn with_metadata(self, key: &str, value: &str) -> Result<Self> {
+        self.connection.execute(
+            "INSERT INTO metadata (name, value) VALUES (?1, ?2)",
+            (key, value),
+        )?;
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "mbtiles")]
+impl TileSink for MbTilesSink {
+    fn write_tile(&mut self, tile: TileId, png: &[u8]) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO tiles (zoom_level, tile_column, tile_row, tile_data)
+             VALUES (?1, ?2, ?3, ?4)",
+            (tile.z(), tile.x(), tile.tms_y(), png),
+        )?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.connection.execute_batch("COMMIT;")?;
+        Ok(())
+    }
+}
+
+/// Renders the XYZ tiles of a web mercator raster.
+pub struct TileGenerator<R> {
+    reader: R,
+    transform: PixelWorldTransform,
+    style: TileStyle,
+    method: ResampleMethod,
+    tile_size: u32,
+    skip_empty: bool,
+}
+
+impl<R> TileGenerator<R>
+where
+    R: ChunkReader,
+    R::Error: Into<RasterUtilsError>,
+{
+    /// Tiles of the raster read by `reader`, with pixel to
+    /// web mercator transform `transform`.
+    pub fn new(reader: R, transform: PixelWorldTransform, style: TileStyle) -> Self {
+        TileGenerator {
+            reader,
+            transform,
+            style,
+            method: ResampleMethod::Average,
+            tile_size: 256,
+            skip_empty: true,
+        }
+    }
+
+    /// Resampling method (default: average).
+    pub fn with_method(mut self, method: ResampleMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Tile size in pixels (default: 256).
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    /// Whether to skip tiles without data (default: true).
+    pub fn with_skip_empty(mut self, skip_empty: bool) -> Self {
+        self.skip_empty = skip_empty;
+        self
+    }
+
+    /// Tiles of zoom `z` intersecting the raster, or an error
+    /// if `z` exceeds [`MAX_ZOOM`].
+    pub fn tiles(&self, z: u8) -> Result<impl Iterator<Item = TileId>> {
+        let last = last_index(z).ok_or(RasterUtilsError::InvalidTile { z, x: 0, y: 0 })?;
+        let size = self.reader.raster_size().map_err(Into::into)?;
+        let bounds = RasterWindow::from(((0, 0), size)).to_world(self.transform.affine());
+        let span = TileId::span(z);
+        let first = |v: f64| ((v / span).floor().max(0.) as u32).min(last);
+        // A raster ending on a tile edge doesn't cover the
+        // next tile.
+        let end = |v: f64| (((v / span).ceil() - 1.).max(0.) as u32).min(last);
+        let min_x = first(bounds.min().x + WEB_MERCATOR_EXTENT);
+        let max_x = end(bounds.max().x + WEB_MERCATOR_EXTENT);
+        let min_y = first(WEB_MERCATOR_EXTENT - bounds.max().y);
+        let max_y = end(WEB_MERCATOR_EXTENT - bounds.min().y);
+        Ok((min_y..=max_y).flat_map(move |y| (min_x..=max_x).map(move |x| TileId { z, x, y })))
+    }
+
+    /// Values of `tile`, as (rows, cols) of `f64` with `NaN`
+    /// outside the raster.
+    pub fn tile_values(&self, tile: TileId) -> Result<Array2<f64>> {
+        let tile_size = self.tile_size as usize;
+        let size: Size = (tile_size, tile_size);
+        let target = tile.transform(self.tile_size);
+        let (cols, rows) = self.reader.raster_size().map_err(Into::into)?;
+
+        // Source pixels covered by the tile.
+        let to_source = target.transform_to(&self.transform)?;
+        let edge = tile_size as f64;
+        let corners = [(0., 0.), (edge, 0.), (0., edge), (edge, edge)]
+            .map(|corner| to_source.apply(Coord::from(corner)));
+        let min_x = corners.iter().map(|c| c.x).fold(f64::INFINITY, f64::min);
+        let max_x = corners
+            .iter()
+            .map(|c| c.x)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let min_y = corners.iter().map(|c| c.y).fold(f64::INFINITY, f64::min);
+        let max_y = corners
+            .iter()
+            .map(|c| c.y)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let factor = ((max_x - min_x).max(max_y - min_y) / edge).floor() as usize;
+
+        let values = if factor < 2 {
+            resample_to_grid(&self.reader, &self.transform, &target, size, self.method)?
+                .read_as_array::<f64>(((0, 0), size).into())?
+        } else {
+            // Read the covered source window decimated, then
+            // regrid from memory.
+            let off_x = (min_x.floor().max(0.) as usize).min(cols);
+            let off_y = (min_y.floor().max(0.) as usize).min(rows);
+            let end_x = (max_x.ceil().max(0.) as usize).min(cols);
+            let end_y = (max_y.ceil().max(0.) as usize).min(rows);
+            if end_x <= off_x || end_y <= off_y {
+                return Ok(Array2::from_elem((tile_size, tile_size), f64::NAN));
+            }
+            let window: RasterWindow = ((off_x, off_y), (end_x - off_x, end_y - off_y)).into();
+            let (width, height) = window.size();
+            let buffer_size = (width.div_ceil(factor), height.div_ceil(factor));
+            let decimated = self
+                .reader
+                .read_as_array_sized::<f64>(window, buffer_size)
+                .map_err(Into::into)?;
+            let (scale_x, scale_y) = (
+                width as f64 / buffer_size.0 as f64,
+                height as f64 / buffer_size.1 as f64,
+            );
+            let decimated_transform = PixelWorldTransform::new(
+                AffineTransform::new(scale_x, 0., off_x as f64, 0., scale_y, off_y as f64)
+                    .compose(self.transform.affine()),
+            );
+            resample_to_grid(
+                ArrayReader(decimated),
+                &decimated_transform,
+                &target,
+                size,
+                self.method,
+            )?
+            .read_as_array::<f64>(((0, 0), size).into())?
+        };
+        let nodata = self.style.nodata;
+        Ok(values.mapv(|v| if Some(v) == nodata { f64::NAN } else { v }))
+    }
+
+    /// Render `tile` as a PNG, or `None` if it has no data and
+    /// empty tiles are skipped.
+    pub fn render(&self, tile: TileId) -> Result<Option<Vec<u8>>> {
+        let values = self.tile_values(tile)?;
+        if self.skip_empty && values.iter().all(|v| v.is_nan()) {
+            return Ok(None);
+        }
+        let mut png = Vec::new();
+        self.style
+            .render(values.view())
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Ok(Some(png))
+    }
+
+    /// Render the tiles of the zoom levels `zooms` into
+    /// `sink`, and finish it. Returns the number of tiles
+    /// written.
+    ///
+    /// Zoom levels are validated before any tile is written.
+    pub fn generate<S: TileSink>(&self, zooms: RangeInclusive<u8>, sink: &mut S) -> Result<usize> {
+        if *zooms.end() > MAX_ZOOM {
+            let z = *zooms.end();
+            return Err(RasterUtilsError::InvalidTile { z, x: 0, y: 0 });
+        }
+        let mut written = 0;
+        for z in zooms {
+            for tile in self.tiles(z)? {
+                if let Some(png) = self.render(tile)? {
+                    sink.write_tile(tile, &png)?;
+                    written += 1;
+                }
+            }
+        }
+        sink.finish()?;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MemorySink(Vec<TileId>);
+
+    impl TileSink for MemorySink {
+        fn write_tile(&mut self, tile: TileId, _png: &[u8]) -> Result<()> {
+            self.0.push(tile);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tile_bounds() {
+        let tile = TileId::new(1, 1, 0).unwrap();
+        let bounds = tile.bounds();
+        assert_eq!(bounds.min().x, 0.);
+        assert_eq!(bounds.max().y, WEB_MERCATOR_EXTENT);
+        assert_eq!(tile.tms_y(), 1);
+    }
+
+    #[test]
+    fn test_invalid_tile() {
+        assert!(TileId::new(MAX_ZOOM, (1 << 30) - 1, 0).is_ok());
+        assert!(matches!(
+            TileId::new(MAX_ZOOM + 1, 0, 0),
+            Err(RasterUtilsError::InvalidTile { z: 31, .. })
+        ));
+        assert!(TileId::new(1, 2, 0).is_err());
+        assert_eq!(TileId::span(32), 2. * WEB_MERCATOR_EXTENT / 2f64.powi(32));
+    }
+
+    #[test]
+    fn test_generate() {
+        // The north-east quarter of the world, on the grid of
+        // the zoom 1 tile at 4 pixels.
+        let res = TileId::span(1) / 4.;
+        let transform = PixelWorldTransform::new(AffineTransform::new(
+            res,
+            0.,
+            0.,
+            0.,
+            -res,
+            WEB_MERCATOR_EXTENT,
+        ));
+        let reader = ArrayReader(Array2::from_shape_fn((4, 4), |(i, j)| (i * 4 + j) as f64));
+        let generator = TileGenerator::new(reader, transform, TileStyle::new(0., 15.))
+            .with_method(ResampleMethod::Nearest)
+            .with_tile_size(4);
+
+        let values = generator
+            .tile_values(TileId::new(1, 1, 0).unwrap())
+            .unwrap();
+        assert_eq!(values, generator.reader.0);
+
+        let mut sink = MemorySink::default();
+        let written = generator.generate(1..=2, &mut sink).unwrap();
+        assert_eq!(written, 1 + 4);
+        assert_eq!(sink.0[0], TileId::new(1, 1, 0).unwrap());
+        assert!(sink.0[1..].iter().all(|t| t.x() >= 2 && t.y() <= 1));
+
+        assert!(generator.tiles(MAX_ZOOM + 1).is_err());
+        let mut sink = MemorySink::default();
+        assert!(generator.generate(0..=MAX_ZOOM + 1, &mut sink).is_err());
+        assert!(sink.0.is_empty());
+    }
+}
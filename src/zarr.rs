@@ -0,0 +1,344 @@
+//! Zarr backend.
+//!
+//! [`ZarrReader`] implements [`ChunkReader`] for 2D Zarr
+//! arrays (shape `[rows, cols]`) with the [`zarrs`] crate.
+//! Any readable store is supported (local filesystem, object
+//! storage, ...).
+//!
+//! This module is only available with the "zarr" feature.
+
+use super::chunking::builder::ChunkConfigBuilder;
+use super::dynamic::DynArray;
+use super::geometry::{RasterWindow, Size};
+use super::reader::{ChunkReader, Pixel};
+use super::{RasterUtilsError, Result};
+use ndarray::Array2;
+use num::{complex::Complex, NumCast, ToPrimitive};
+use zarrs::{
+    array::{Array, DataType, ElementOwned},
+    array_subset::ArraySubset,
+    filesystem::FilesystemStore,
+    storage::ReadableStorageTraits,
+};
+
+use std::{num::NonZeroUsize, path::Path, sync::Arc};
+
+/// A [`ChunkReader`] over a 2D Zarr array.
+///
+/// Values are read in the native data type of the array and
+/// converted to the requested pixel type. Resampling on read
+/// is not supported.
+pub struct ZarrReader<S: ?Sized> {
+    array: Array<S>,
+}
+
+impl ZarrReader<FilesystemStore> {
+    /// Open the array at `array_path` (eg. `"/B04"`) of the
+    /// store in directory `store`.
+    pub fn open(store: &Path, array_path: &str) -> Result<Self> {
+        let store = Arc::new(
+            FilesystemStore::new(store).map_err(|e| RasterUtilsError::Zarr(e.to_string()))?,
+        );
+        let array =
+            Array::open(store, array_path).map_err(|e| RasterUtilsError::Zarr(e.to_string()))?;
+        Self::new(array)
+    }
+}
+
+impl<S: ?Sized + ReadableStorageTraits + 'static> ZarrReader<S> {
+    /// Read `array`, which must be two dimensional.
+    pub fn new(array: Array<S>) -> Result<Self> {
+        if array.shape().len() != 2 {
+            return Err(RasterUtilsError::Unsupported("Zarr arrays that are not 2D"));
+        }
+        Ok(ZarrReader { array })
+    }
+
+    pub fn array(&self) -> &Array<S> {
+        &self.array
+    }
+
+    /// Size (x, y) of the native chunks, from the chunk at
+    /// the origin.
+    pub fn chunk_size(&self) -> Result<Size> {
+        let shape = self
+            .array
+            .chunk_shape(&[0, 0])
+            .map_err(|e| RasterUtilsError::Zarr(e.to_string()))?
+            .to_array_shape();
+        Ok((shape[1] as usize, shape[0] as usize))
+    }
+
+    /// Create a [`ChunkConfigBuilder`] with the dimensions of
+    /// the array and the shape of its native chunks as block
+    /// shape.
+    pub fn chunk_config_builder(&self) -> Result<ChunkConfigBuilder> {
+        let (cols, rows) = self.raster_size()?;
+        let (width, height) = NonZeroUsize::new(cols)
+            .zip(NonZeroUsize::new(rows))
+            .ok_or(RasterUtilsError::ZeroDimention)?;
+        let mut builder = ChunkConfigBuilder::new(width, height);
+        let (block_x, block_y) = self.chunk_size()?;
+        if let Some(block_shape) = NonZeroUsize::new(block_x).zip(NonZeroUsize::new(block_y)) {
+            builder = builder.add_block_shape(block_shape);
+        }
+        Ok(builder)
+    }
+
+    /// Read `raster_window` in the native data type of the
+    /// array, including complex (and with the "f16" feature,
+    /// half-precision) types.
+    pub fn read_dyn(&self, raster_window: RasterWindow) -> Result<DynArray> {
+        let (off_x, off_y) = raster_window.validate(self.raster_size()?)?;
+        let (size_x, size_y) = raster_window.size();
+        let subset = ArraySubset::new_with_ranges(&[
+            off_y as u64..(off_y + size_y) as u64,
+            off_x as u64..(off_x + size_x) as u64,
+        ]);
+        Ok(match self.array.data_type() {
+            DataType::UInt8 => self.read_elements::<u8>(&subset)?.into(),
+            DataType::UInt16 => self.read_elements::<u16>(&subset)?.into(),
+            DataType::UInt32 => self.read_elements::<u32>(&subset)?.into(),
+            DataType::UInt64 => self.read_elements::<u64>(&subset)?.into(),
+            DataType::Int8 => self.read_elements::<i8>(&subset)?.into(),
+            DataType::Int16 => self.read_elements::<i16>(&subset)?.into(),
+            DataType::Int32 => self.read_elements::<i32>(&subset)?.into(),
+            DataType::Int64 => self.read_elements::<i64>(&subset)?.into(),
+            #[cfg(feature = "f16")]
+            DataType::Float16 => self.read_elements::<half::f16>(&subset)?.into(),
+            DataType::Float32 => self.read_elements::<f32>(&subset)?.into(),
+            DataType::Float64 => self.read_elements::<f64>(&subset)?.into(),
+            DataType::Complex64 => self.read_elements::<Complex<f32>>(&subset)?.into(),
+            DataType::Complex128 => self.read_elements::<Complex<f64>>(&subset)?.into(),
+            _ => return Err(RasterUtilsError::Unsupported("Zarr data type")),
+        })
+    }
+
+    /// Read `subset` as an array of elements of type `E`.
+    fn read_elements<E: ElementOwned>(&self, subset: &ArraySubset) -> Result<Array2<E>> {
+        let shape = subset.shape();
+        let elements = self
+            .array
+            .retrieve_array_subset_elements::<E>(subset)
+            .map_err(|e| RasterUtilsError::Zarr(e.to_string()))?;
+        Ok(
+            Array2::from_shape_vec((shape[0] as usize, shape[1] as usize), elements)
+                .expect("one element per pixel of the subset"),
+        )
+    }
+
+    /// Read `subset` as elements of type `E` into `out`.
+    fn read_as<E, T>(&self, subset: &ArraySubset, out: &mut [T]) -> Result<()>
+    where
+        E: ElementOwned + ToPrimitive,
+        T: Pixel,
+    {
+        let elements = self
+            .array
+            .retrieve_array_subset_elements::<E>(subset)
+            .map_err(|e| RasterUtilsError::Zarr(e.to_string()))?;
+        for (out, element) in out.iter_mut().zip(elements) {
+            *out = <T as NumCast>::from(element).ok_or(RasterUtilsError::Unsupported(
+                "sample value not representable in the requested type",
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: ?Sized + ReadableStorageTraits + 'static> ChunkReader for ZarrReader<S> {
+    type Error = RasterUtilsError;
+
+    fn raster_size(&self) -> Result<Size> {
+        let shape = self.array.shape();
+        Ok((shape[1] as usize, shape[0] as usize))
+    }
+
+    fn read_into_slice_sized<T>(
+        &self,
+        out: &mut [T],
+        raster_window: RasterWindow,
+        buffer_size: Size,
+    ) -> Result<()>
+    where
+        T: Pixel,
+    {
+        let (size_x, size_y) = raster_window.size();
+        if buffer_size != (size_x, size_y) {
+            return Err(RasterUtilsError::Unsupported(
+                "resampled reads of Zarr arrays",
+            ));
+        }
+        let (off_x, off_y) = raster_window.validate(self.raster_size()?)?;
+
+        let subset = ArraySubset::new_with_ranges(&[
+            off_y as u64..(off_y + size_y) as u64,
+            off_x as u64..(off_x + size_x) as u64,
+        ]);
+        match self.array.data_type() {
+            DataType::UInt8 => self.read_as::<u8, T>(&subset, out),
+            DataType::UInt16 => self.read_as::<u16, T>(&subset, out),
+            DataType::UInt32 => self.read_as::<u32, T>(&subset, out),
+            DataType::UInt64 => self.read_as::<u64, T>(&subset, out),
+            DataType::Int8 => self.read_as::<i8, T>(&subset, out),
+            DataType::Int16 => self.read_as::<i16, T>(&subset, out),
+            DataType::Int32 => self.read_as::<i32, T>(&subset, out),
+            DataType::Int64 => self.read_as::<i64, T>(&subset, out),
+            #[cfg(feature = "f16")]
+            DataType::Float16 => self.read_as::<half::f16, T>(&subset, out),
+            DataType::Float32 => self.read_as::<f32, T>(&subset, out),
+            DataType::Float64 => self.read_as::<f64, T>(&subset, out),
+            _ => Err(RasterUtilsError::Unsupported("Zarr data type")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+    use std::convert::TryInto;
+    use zarrs::{
+        array::{ArrayBuilder, FillValue},
+        storage::store::MemoryStore,
+    };
+
+    /// A (3, 4) array of `data_type` in 2x2 chunks, in memory.
+    fn memory_array<E: zarrs::array::Element>(
+        data_type: DataType,
+        fill_value: FillValue,
+        elements: &[E],
+    ) -> ZarrReader<MemoryStore> {
+        let store = Arc::new(MemoryStore::new());
+        let array = ArrayBuilder::new(
+            vec![3, 4],
+            data_type,
+            vec![2, 2].try_into().unwrap(),
+            fill_value,
+        )
+        .build(store, "/band")
+        .unwrap();
+        array.store_metadata().unwrap();
+        let subset = ArraySubset::new_with_ranges(&[0..3, 0..4]);
+        array
+            .store_array_subset_elements::<E>(&subset, elements)
+            .unwrap();
+        ZarrReader::new(array).unwrap()
+    }
+
+    #[test]
+    fn test_read() {
+        let elements: Vec<u16> = (0..12).collect();
+        let reader = memory_array(DataType::UInt16, FillValue::from(0u16), &elements);
+        assert_eq!(reader.raster_size().unwrap(), (4, 3));
+        assert_eq!(reader.chunk_size().unwrap(), (2, 2));
+        let window: RasterWindow = ((1, 1), (3, 2)).into();
+        let values = reader.read_as_array::<f32>(window.clone()).unwrap();
+        assert_eq!(values, array![[5., 6., 7.], [9., 10., 11.]]);
+        assert_eq!(
+            reader.read_dyn(window).unwrap(),
+            DynArray::from(array![[5u16, 6, 7], [9, 10, 11]])
+        );
+    }
+
+    #[test]
+    fn test_read_dyn_complex() {
+        let elements: Vec<Complex<f32>> = (0..12)
+            .map(|i| Complex::new(i as f32, -(i as f32)))
+            .collect();
+        let fill_value = FillValue::from(Complex::<f32>::new(0., 0.));
+        let reader = memory_array(DataType::Complex64, fill_value, &elements);
+        let chunk = reader.read_dyn(((2, 1), (2, 2)).into()).unwrap();
+        assert!(chunk.is_complex());
+        assert_eq!(
+            chunk,
+            DynArray::from(array![
+                [Complex::new(6f32, -6.), Complex::new(7., -7.)],
+                [Complex::new(10., -10.), Complex::new(11., -11.)]
+            ])
+        );
+        // Outside the array.
+        assert!(reader.read_dyn(((3, 0), (2, 2)).into()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_config_and_errors() {
+        let elements: Vec<u8> = (0..12).collect();
+        let reader = memory_array(DataType::UInt8, FillValue::from(0u8), &elements);
+        let config = reader.chunk_config_builder().unwrap().build();
+        assert_eq!((config.width(), config.height()), (4, 3));
+        assert_eq!(config.block_size(), 2);
+        let sum: u32 = config
+            .iter()
+            .map(|chunk| reader.read_chunk::<u32>(chunk).unwrap().sum())
+            .sum();
+        assert_eq!(sum, (0..12).sum());
+
+        assert!(matches!(
+            reader.read_as_array::<u8>(((0, 0), (5, 1)).into()),
+            Err(RasterUtilsError::WindowOutOfBounds(_))
+        ));
+        assert!(matches!(
+            reader.read_as_array_sized::<u8>(((0, 0), (4, 2)).into(), (2, 1)),
+            Err(RasterUtilsError::Unsupported(_))
+        ));
+
+        let store = Arc::new(MemoryStore::new());
+        let cube = ArrayBuilder::new(
+            vec![2, 3, 4],
+            DataType::UInt8,
+            vec![1, 2, 2].try_into().unwrap(),
+            FillValue::from(0u8),
+        )
+        .build(store, "/cube")
+        .unwrap();
+        assert!(ZarrReader::new(cube).is_err());
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = std::env::temp_dir().join("raster_utils_zarr_open");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = Arc::new(FilesystemStore::new(&dir).unwrap());
+        let array = ArrayBuilder::new(
+            vec![3, 4],
+            DataType::Float32,
+            vec![2, 2].try_into().unwrap(),
+            FillValue::from(f32::NAN),
+        )
+        .build(store, "/band")
+        .unwrap();
+        array.store_metadata().unwrap();
+        let subset = ArraySubset::new_with_ranges(&[1..2, 0..4]);
+        array
+            .store_array_subset_elements::<f32>(&subset, &[1., 2., 3., 4.])
+            .unwrap();
+
+        let reader = ZarrReader::open(&dir, "/band").unwrap();
+        let values = reader
+            .read_as_array::<f64>(((0, 0), (4, 3)).into())
+            .unwrap();
+        assert_eq!(values.row(1).to_vec(), vec![1., 2., 3., 4.]);
+        // Chunks never written hold the fill value.
+        assert!(values[[0, 0]].is_nan() && values[[2, 3]].is_nan());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "f16")]
+    #[test]
+    fn test_read_dyn_f16() {
+        let elements: Vec<half::f16> = (0..12).map(|i| half::f16::from_f32(i as f32)).collect();
+        let fill_value = FillValue::from(half::f16::ZERO);
+        let reader = memory_array(DataType::Float16, fill_value, &elements);
+        let window: RasterWindow = ((0, 2), (2, 1)).into();
+        assert_eq!(
+            reader.read_dyn(window.clone()).unwrap(),
+            DynArray::from(array![[half::f16::from_f32(8.), half::f16::from_f32(9.)]])
+        );
+        // Converted on read to another pixel type.
+        assert_eq!(
+            reader.read_as_array::<f32>(window).unwrap(),
+            array![[8., 9.]]
+        );
+    }
+}